@@ -0,0 +1,35 @@
+//! Criterion benchmarks for the operations `tars bench` times: the file
+//! tools against a generated tree, and request serialization.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tars::bench::Workload;
+
+/// Files in the generated tree.
+const FILES: usize = 2000;
+
+fn tools(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let workload = Workload::new(FILES).unwrap();
+    c.bench_function("read_file", |b| {
+        b.iter(|| runtime.block_on(workload.read_file()).unwrap())
+    });
+    c.bench_function("list_files", |b| {
+        b.iter(|| runtime.block_on(workload.list_files()).unwrap())
+    });
+    c.bench_function("search_files", |b| {
+        b.iter(|| runtime.block_on(workload.search_files()).unwrap())
+    });
+}
+
+fn messages(c: &mut Criterion) {
+    let workload = Workload::new(1).unwrap();
+    c.bench_function("request (200 turns)", |b| {
+        b.iter(|| workload.serialize_request().unwrap())
+    });
+    c.bench_function("truncate (200 turns, 8k)", |b| {
+        b.iter(|| black_box(workload.truncate()))
+    });
+}
+
+criterion_group!(benches, tools, messages);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The first byte says how many of the following bytes are chunk sizes; the
+// rest is the stream body.
+fuzz_target!(|data: &[u8]| {
+    let Some((&count, data)) = data.split_first() else {
+        return;
+    };
+    let (chunk_sizes, body) = data.split_at((count as usize % 8).min(data.len()));
+    tars::fuzz::sse_stream(chunk_sizes, body);
+});
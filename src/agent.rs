@@ -1,33 +1,355 @@
 use crate::ai_sdk::{
-    ContentBlock, MessageParam, MessageRequest, MessageResponse, ToolDefinitionApi,
+    assistant_content_from_response, Anthropic, Backend, ContentBlock, MessageRequest,
+    MessageResponse, Provider, ResponseContentBlock, StopReason, ToolDefinitionApi, Vertex,
 };
-use crate::tools::{get_all_tools, ToolDefinition};
-use reqwest::Client;
+use crate::conversation::Conversation;
+use crate::index;
+use crate::key_ring::KeyRing;
+use crate::prompt::SystemPrompt;
+use crate::protocol::{ApprovalDecision, ResponseStats, StreamEvent};
+#[cfg(feature = "server")]
+use crate::protocol::{ToolStats, UsageTotals};
+use crate::response_cache::ResponseCache;
+use crate::telemetry;
+use crate::tools::{
+    check_links, denial, format_after_edit, get_all_tools, guard_refusal, normalize_path,
+    validate_input, Approval, Change, NetworkAccess, OptIn, ToolContext, ToolDefinition, ToolUsage,
+    TurnCache, GUARD, PRIVATE, TODO_TOOL,
+};
+use crate::config::{
+    self, Config, ContextConfig, FormatConfig, GuardConfig, NetworkConfig, PromptConfig,
+    ReviewConfig, ToolsConfig,
+};
+#[cfg(feature = "server")]
+use crate::config::ProfileConfig;
+#[cfg(feature = "server")]
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::field::Empty;
+use tracing::Span;
 
-pub struct Agent {
-    client: Client,
-    api_key: String,
+pub(crate) const DEFAULT_MODEL: &str = "claude-haiku-4-5-20251001";
+/// Short names for the current models, accepted wherever a model is.
+pub(crate) const MODEL_ALIASES: &[(&str, &str)] = &[
+    ("haiku", DEFAULT_MODEL),
+    ("sonnet", "claude-sonnet-4-5-20250929"),
+    ("opus", "claude-opus-4-1-20250805"),
+];
+pub(crate) const DEFAULT_MAX_TOKENS: u32 = 4096;
+/// How many times a reply cut off at the token limit is continued before
+/// it is kept as is.
+const MAX_CONTINUATIONS: usize = 3;
+/// What the planner of a profile is told, unless the profile says
+/// otherwise.
+#[cfg(feature = "server")]
+const PLANNER_PROMPT: &str = "You are the planner in a two-agent setup. An executor agent with tools to read, search, edit and run code in the workspace will carry out your plan; you have no tools yourself. Reply to the user's latest message with a short numbered list of concrete steps, one per line, and nothing else.";
+/// Heads the plan in the executor's system prompt.
+#[cfg(feature = "server")]
+const PLAN_INTRO: &str = "A planner has broken the user's latest message into the steps below. Carry them out in order; if one turns out to be wrong or unnecessary, say so rather than forcing it.";
+/// What the reviewer is told about its job, ahead of the policy.
+const REVIEWER_PROMPT: &str = "You check the tool calls of a coding agent before they run, often in sessions nobody is watching. Hold the call you are shown to the policy below. Reply with a single line: ALLOW if it may run, FLAG: <reason> if it may run but someone should look at it afterwards, or BLOCK: <reason> if it must not run.";
+/// The policy calls are held to unless `[review]` sets one.
+const REVIEW_POLICY: &str = "Block calls that delete or overwrite anything outside the working directory, read or send credentials and other secrets, push to a remote, publish a package, install software system-wide, change system settings, or run code fetched from the internet. Flag calls that would be hard to undo within the working directory, such as deleting many files or rewriting git history. Allow the rest.";
+/// Heads the repo map in the system prompt.
+const REPO_MAP_INTRO: &str = "A map of the workspace, kept up to date as files change. It lists the most used source files and their main definitions; use code_outline, search_files and read_file for the rest.";
+
+/// Runs turns against the model behind `P`.
+pub struct Agent<P: Provider = Backend> {
+    provider: P,
+    /// How tools that go online reach the network; the provider has its
+    /// own client.
+    network: NetworkConfig,
+    /// Built from `network` for the first network tool call, once
+    /// `[tools]` has said where the tools may go.
+    network_access: OnceLock<NetworkAccess>,
     tools: Vec<ToolDefinition>,
+    model: String,
+    /// The most tokens a reply may take.
+    max_tokens: u32,
+    context_window: usize,
+    temperature: Option<f32>,
+    /// Formatters run on files after a successful edit.
+    format: Option<FormatConfig>,
+    /// How much one turn may change before the model has to ask.
+    guard: GuardConfig,
+    /// The model that checks calls which change the workspace, if any.
+    review: ReviewConfig,
+    /// Which opt-in tools are offered, and how far network tools may go.
+    tools_config: ToolsConfig,
+    /// What each request tells the model about the workspace.
+    context: ContextConfig,
+    /// The segments of the system prompt before the repo map.
+    prompt: SystemPrompt,
+    /// Planner and executor pairs, by name.
+    #[cfg(feature = "server")]
+    profiles: BTreeMap<String, ProfileConfig>,
+    /// Answers repeated requests from disk, with `--cache-llm`.
+    cache: Option<ResponseCache>,
+    /// Totals over every successful request this agent has made.
+    requests: AtomicU64,
+    input_tokens: AtomicU64,
+    output_tokens: AtomicU64,
+    /// How every tool call this agent has run went.
+    tool_usage: std::sync::Mutex<ToolUsage>,
 }
 
 impl Agent {
-    pub fn new(api_key: String) -> Self {
-        let client = Client::new();
-        let tools = get_all_tools();
+    /// An agent for the config's `provider` and model, with its network
+    /// settings applied. Anthropic needs `keys`; Vertex AI signs in with
+    /// Google credentials instead.
+    pub fn from_config(
+        settings: &Config,
+        keys: KeyRing,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if settings.provider == "anthropic" && keys.is_empty() {
+            return Err(config::MISSING_API_KEY.into());
+        }
+        Ok(Self::with_provider(backend(settings, keys)?)
+            .with_network(&settings.network)?
+            .with_request_timeout(settings.network.request_timeout())
+            .with_model(settings.model())
+            .with_max_tokens(settings.max_tokens()))
+    }
+
+    /// Sends requests, the provider's and the network tools', through
+    /// clients built from `network`, e.g. with a proxy or extra root
+    /// certificates.
+    pub fn with_network(
+        mut self,
+        network: &NetworkConfig,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        self.provider = self.provider.with_http_client(network.client()?);
+        self.network = network.clone();
+        Ok(self)
+    }
+
+    /// Fails an inference request that has not finished within `timeout`,
+    /// rather than letting a hung connection stall the turn.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.provider = self.provider.with_request_timeout(timeout);
+        self
+    }
+}
+
+/// The provider for the config's `provider`, without its network settings.
+/// Anthropic needs `keys` before it can send anything.
+pub(crate) fn backend(
+    settings: &Config,
+    keys: KeyRing,
+) -> Result<Backend, Box<dyn std::error::Error + Send + Sync>> {
+    match settings.provider.as_str() {
+        "anthropic" => Ok(Backend::Anthropic(
+            Anthropic::new(keys).with_base_url(settings.base_url()),
+        )),
+        "vertex" => Ok(Backend::Vertex(Vertex::new(
+            settings.vertex.project()?,
+            settings.vertex.region(),
+            settings.vertex.base_url(),
+            settings.vertex.credentials.clone(),
+        ))),
+        other => Err(format!("Unsupported provider {:?} in config", other).into()),
+    }
+}
+
+impl<P: Provider> Agent<P> {
+    pub(crate) fn with_provider(provider: P) -> Self {
+        let tools_config = ToolsConfig::default();
+        let tools = offered_tools(&tools_config);
         Self {
-            client,
-            api_key,
+            provider,
+            network: NetworkConfig::default(),
+            network_access: OnceLock::new(),
             tools,
+            model: DEFAULT_MODEL.to_string(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+            context_window: context_window_for(DEFAULT_MODEL),
+            temperature: None,
+            format: None,
+            guard: GuardConfig::default(),
+            review: ReviewConfig::default(),
+            tools_config,
+            context: ContextConfig::default(),
+            prompt: SystemPrompt::new(PromptConfig::default()),
+            #[cfg(feature = "server")]
+            profiles: BTreeMap::new(),
+            cache: None,
+            requests: AtomicU64::new(0),
+            input_tokens: AtomicU64::new(0),
+            output_tokens: AtomicU64::new(0),
+            tool_usage: std::sync::Mutex::new(ToolUsage::default()),
         }
     }
 
-    pub(crate) async fn run_inference(
+    /// The model behind this agent.
+    pub(crate) fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = resolve_model(&model.into()).to_string();
+        self.context_window = context_window_for(&self.model);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Runs the configured formatter on each file a tool edits, when the
+    /// config turns that on.
+    pub fn with_format(mut self, format: FormatConfig) -> Self {
+        self.format = format.on_edit.then_some(format);
+        self
+    }
+
+    pub fn with_guard(mut self, guard: GuardConfig) -> Self {
+        self.guard = guard;
+        self
+    }
+
+    /// Has the `[review]` model check each call that would change the
+    /// workspace before it runs, when the config names one.
+    pub fn with_review(mut self, review: ReviewConfig) -> Self {
+        self.review = review;
+        self
+    }
+
+    /// Offers the opt-in tools `config` turns on.
+    pub fn with_tools(mut self, config: ToolsConfig) -> Self {
+        self.tools = offered_tools(&config);
+        self.tools_config = config;
+        self
+    }
+
+    pub fn with_context(mut self, context: ContextConfig) -> Self {
+        self.context = context;
+        self
+    }
+
+    pub fn with_prompt(mut self, prompt: PromptConfig) -> Self {
+        self.prompt = SystemPrompt::new(prompt);
+        self
+    }
+
+    #[cfg(feature = "server")]
+    pub fn with_profiles(mut self, profiles: BTreeMap<String, ProfileConfig>) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn has_profile(&self, name: &str) -> bool {
+        self.profiles.contains_key(name)
+    }
+
+    /// Answers a request the cache has seen from it, and saves each new
+    /// response there.
+    #[cfg(feature = "server")]
+    pub fn with_response_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// What this agent's API key has been used for so far.
+    #[cfg(feature = "server")]
+    pub(crate) fn usage(&self) -> UsageTotals {
+        UsageTotals {
+            requests: self.requests.load(Ordering::Relaxed),
+            input_tokens: self.input_tokens.load(Ordering::Relaxed),
+            output_tokens: self.output_tokens.load(Ordering::Relaxed),
+        }
+    }
+
+    /// How the tool calls this agent has run went, most called first.
+    #[cfg(feature = "server")]
+    pub(crate) fn tool_stats(&self) -> Vec<ToolStats> {
+        self.tool_usage.lock().unwrap().stats()
+    }
+
+    /// Whether the model's API answers at all.
+    #[cfg(feature = "server")]
+    pub(crate) async fn api_reachable(&self) -> bool {
+        self.provider.reachable().await
+    }
+
+    /// The system prompt: the configured segments, then the repo map.
+    async fn system_prompt(&self) -> Option<String> {
+        let root = std::env::current_dir().unwrap_or_default();
+        let mut segments = self.prompt.segments(&root);
+        segments.extend(self.repo_map().await);
+        (!segments.is_empty()).then(|| segments.join("\n\n"))
+    }
+
+    /// The repo map, from the index brought up to date first, so it follows
+    /// the files as the turn edits them.
+    async fn repo_map(&self) -> Option<String> {
+        if !self.context.repo_map {
+            return None;
+        }
+        let max_chars = self.context.repo_map_tokens * 4;
+        let map = match tokio::task::spawn_blocking(move || index::current_map(max_chars)).await {
+            Ok(map) => map,
+            Err(err) => Err(err.into()),
+        };
+        match map {
+            Ok(map) if !map.is_empty() => Some(format!("{}\n\n{}", REPO_MAP_INTRO, map)),
+            Ok(_) => None,
+            Err(err) => {
+                tracing::warn!("Could not map the workspace: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Tokens available for conversation history with `model`, once the
+    /// reply, the system prompt and the tool schemas have been accounted
+    /// for.
+    fn history_budget(
         &self,
-        conversation: &[MessageParam],
+        model: &str,
+        system: Option<&str>,
+        tools: &[ToolDefinitionApi],
+    ) -> usize {
+        let tools_tokens = serde_json::to_string(tools).map_or(0, |json| json.len()) / 4;
+        let system_tokens = system.map_or(0, str::len) / 4;
+        let context_window = if model == self.model {
+            self.context_window
+        } else {
+            context_window_for(model)
+        };
+        context_window
+            .saturating_sub(self.max_tokens as usize)
+            .saturating_sub(system_tokens)
+            .saturating_sub(tools_tokens)
+    }
+
+    #[tracing::instrument(
+        name = "inference",
+        skip_all,
+        fields(model = %model, input_tokens = Empty, output_tokens = Empty, cached = Empty),
+        err
+    )]
+    async fn run_inference(
+        &self,
+        model: &str,
+        access: ToolAccess,
+        system: Option<String>,
+        conversation: &Conversation,
     ) -> Result<MessageResponse, Box<dyn std::error::Error + Send + Sync>> {
+        let model = resolve_model(model);
         let tools_api: Vec<ToolDefinitionApi> = self
             .tools
             .iter()
+            .filter(|t| access.offers(t))
             .map(|t| ToolDefinitionApi {
                 name: t.name.to_string(),
                 description: t.description.to_string(),
@@ -35,46 +357,1293 @@ impl Agent {
             })
             .collect();
 
+        let capabilities = self.provider.capabilities(model);
+        let mut messages =
+            conversation.truncated(self.history_budget(model, system.as_deref(), &tools_api));
+        if !capabilities.prefill && conversation.prefill().is_some() {
+            messages.pop();
+        }
         let request = MessageRequest {
-            model: "claude-haiku-4-5-20251001".to_string(),
-            max_tokens: 4096,
-            messages: conversation.to_vec(),
+            model: model.to_string(),
+            max_tokens: self.max_tokens,
+            system,
+            messages,
             tools: tools_api,
+            temperature: self.temperature,
+            tool_choice: conversation
+                .tool_choice()
+                .filter(|_| capabilities.tool_choice)
+                .cloned(),
         };
 
+        let cache_key = match &self.cache {
+            Some(_) => Some(ResponseCache::key(&request)?),
+            None => None,
+        };
+        if let Some(cache) = &self.cache
+            && let Some(key) = &cache_key
+            && let Some(mut response) = cache.get(key)
+        {
+            // Cached replies cost nothing, so they aren't counted as usage.
+            Span::current().record("cached", true);
+            if capabilities.prefill
+                && let Some(prefill) = conversation.prefill()
+            {
+                prepend_prefill(&mut response, prefill.trim_end());
+            }
+            return Ok(response);
+        }
+
+        let started = Instant::now();
+        let mut response = self.provider.run_inference(&request).await?;
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.input_tokens
+            .fetch_add(response.usage.input_tokens, Ordering::Relaxed);
+        self.output_tokens
+            .fetch_add(response.usage.output_tokens, Ordering::Relaxed);
+        let span = Span::current();
+        span.record("input_tokens", response.usage.input_tokens);
+        span.record("output_tokens", response.usage.output_tokens);
+        telemetry::record_inference(
+            &response.model,
+            started.elapsed(),
+            response.usage.input_tokens,
+            response.usage.output_tokens,
+        );
+        if let Some(cache) = &self.cache
+            && let Some(key) = &cache_key
+            && let Err(err) = cache.put(key, &response)
+        {
+            tracing::warn!("Could not cache the response: {}", err);
+        }
+        if capabilities.prefill
+            && let Some(prefill) = conversation.prefill()
+        {
+            prepend_prefill(&mut response, prefill.trim_end());
+        }
+        Ok(response)
+    }
+
+    /// Answers a single prompt without tools or history, for one-off jobs
+    /// such as writing a commit message.
+    pub(crate) async fn complete(
+        &self,
+        prompt: String,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        self.complete_with(&self.model, None, prompt).await
+    }
+
+    /// Answers a single prompt with `model` and `system`, without tools or
+    /// history.
+    async fn complete_with(
+        &self,
+        model: &str,
+        system: Option<String>,
+        prompt: String,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let mut conversation = Conversation::new();
+        conversation.begin_turn(prompt)?;
         let response = self
-            .client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&request)
-            .send()
+            .run_inference(model, ToolAccess::None, system, &conversation)
             .await?;
+        Ok(reply_text(&response))
+    }
+
+    /// Has `model` hold a call to `tool` against the review policy. A
+    /// reviewer that can't be reached blocks the call, since nothing
+    /// vouched for it.
+    async fn review(
+        &self,
+        model: &str,
+        tool: &ToolDefinition,
+        input: &serde_json::Value,
+    ) -> Verdict {
+        let policy = self.review.policy.as_deref().unwrap_or(REVIEW_POLICY);
+        let system = format!("{}\n\nPolicy:\n{}", REVIEWER_PROMPT, policy);
+        let call = format!(
+            "Working directory: {}\nTool: {}\nSummary: {}\nInput:\n{}",
+            std::env::current_dir().unwrap_or_default().display(),
+            tool.name,
+            (tool.summarize)(input, None),
+            serde_json::to_string_pretty(input).unwrap_or_default()
+        );
+        match self.complete_with(model, Some(system), call).await {
+            Ok(reply) => verdict(&reply),
+            Err(err) => {
+                tracing::warn!("Could not reach the reviewer: {}", err);
+                Verdict::Block(format!("the reviewer could not be reached ({})", err))
+            }
+        }
+    }
+
+    /// Runs inference and tool calls until the model stops asking for tools.
+    ///
+    /// The caller must have started a turn on `conversation`; progress is
+    /// reported through `emit` as it happens. `context` is the state the
+    /// session's tools keep between turns.
+    pub(crate) async fn run_turn<F>(
+        &self,
+        conversation: &Mutex<Conversation>,
+        context: &ToolContext,
+        emit: F,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: FnMut(StreamEvent),
+    {
+        self.run_turn_with(
+            &self.model,
+            ToolAccess::All,
+            None,
+            conversation,
+            context,
+            emit,
+        )
+        .await
+    }
+
+    /// Runs a turn with `model` instead of the agent's own, such as one the
+    /// user picked with `/model`.
+    #[cfg(feature = "server")]
+    pub(crate) async fn run_turn_on<F>(
+        &self,
+        model: &str,
+        conversation: &Mutex<Conversation>,
+        context: &ToolContext,
+        emit: F,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: FnMut(StreamEvent),
+    {
+        self.run_turn_with(model, ToolAccess::All, None, conversation, context, emit)
+            .await
+    }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await?;
-            return Err(format!("API error: {} - {}", status, error_text).into());
+    /// Runs a turn through the profile `name`: its planner model drafts the
+    /// steps, without tools, and the executor carries them out with the
+    /// plan in its system prompt. The plan is reported as a
+    /// `StreamEvent::Plan` before the executor starts.
+    #[cfg(feature = "server")]
+    pub(crate) async fn run_planned_turn<F>(
+        &self,
+        name: &str,
+        conversation: &Mutex<Conversation>,
+        context: &ToolContext,
+        mut emit: F,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: FnMut(StreamEvent),
+    {
+        let profile = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| format!("No profile named {:?} in the config", name))?;
+        let snapshot = conversation.lock().await.clone();
+        let mut system = profile
+            .planner_prompt
+            .as_deref()
+            .unwrap_or(PLANNER_PROMPT)
+            .to_string();
+        if let Some(context) = self.system_prompt().await {
+            system = format!("{}\n\n{}", system, context);
         }
+        let started = Instant::now();
+        let response = self
+            .run_inference(&profile.planner, ToolAccess::None, Some(system), &snapshot)
+            .await?;
+        let steps = plan_steps(&reply_text(&response));
+        if steps.is_empty() {
+            return Err("The planner replied without a plan".into());
+        }
+        emit(StreamEvent::Plan {
+            steps: steps.clone(),
+            stats: ResponseStats {
+                model: response.model.clone(),
+                elapsed_ms: started.elapsed().as_millis() as u64,
+                input_tokens: response.usage.input_tokens,
+                output_tokens: response.usage.output_tokens,
+            },
+        });
+
+        let plan: Vec<String> = steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| format!("{}. {}", index + 1, step))
+            .collect();
+        let plan = format!("{}\n\n{}", PLAN_INTRO, plan.join("\n"));
+        let executor = profile.executor.as_deref().unwrap_or(&self.model);
+        self.run_turn_with(
+            executor,
+            ToolAccess::All,
+            Some(&plan),
+            conversation,
+            context,
+            emit,
+        )
+        .await
+    }
+
+    /// Runs a turn with `model` instead of the agent's own, offering only
+    /// the read-only tools, so nothing it does changes the workspace. Used
+    /// by `/compare`.
+    #[cfg(feature = "server")]
+    pub(crate) async fn run_read_only_turn<F>(
+        &self,
+        model: &str,
+        conversation: &Mutex<Conversation>,
+        context: &ToolContext,
+        emit: F,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: FnMut(StreamEvent),
+    {
+        self.run_turn_with(
+            model,
+            ToolAccess::ReadOnly,
+            None,
+            conversation,
+            context,
+            emit,
+        )
+        .await
+    }
+
+    /// Runs a turn with `model`, adding `plan` to the system prompt of each
+    /// request when there is one.
+    async fn run_turn_with<F>(
+        &self,
+        model: &str,
+        access: ToolAccess,
+        plan: Option<&str>,
+        conversation: &Mutex<Conversation>,
+        context: &ToolContext,
+        mut emit: F,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: FnMut(StreamEvent),
+    {
+        let mut cache = TurnCache::default();
+        context.turn_edits.lock().unwrap().reset();
+        let mut continuations = 0;
+        // Time and output spent on earlier parts of a continued reply.
+        let mut carried_ms = 0;
+        let mut carried_tokens = 0;
+        loop {
+            // Reminders go out with this request and are then dropped.
+            let snapshot = {
+                let mut conversation = conversation.lock().await;
+                let snapshot = conversation.clone();
+                conversation.clear_reminders();
+                snapshot
+            };
+            let system = match (self.system_prompt().await, plan) {
+                (Some(system), Some(plan)) => Some(format!("{}\n\n{}", system, plan)),
+                (system, plan) => system.or(plan.map(str::to_string)),
+            };
+            let started = Instant::now();
+            let response = self.run_inference(model, access, system, &snapshot).await?;
+            let elapsed_ms = started.elapsed().as_millis() as u64;
+
+            // A text-only reply cut off by the token limit is sent back as
+            // prefill so the model picks up where it stopped. Replies with
+            // tool calls can't be resumed that way.
+            if response.stop_reason == Some(StopReason::MaxTokens)
+                && self.provider.capabilities(model).prefill
+                && continuations < MAX_CONTINUATIONS
+                && let Some(partial) = continuable_text(&response)
+            {
+                continuations += 1;
+                carried_ms += elapsed_ms;
+                carried_tokens += response.usage.output_tokens;
+                emit(StreamEvent::Info {
+                    message: format!(
+                        "Reply reached the {}-token limit; continuing ({}/{})",
+                        self.max_tokens, continuations, MAX_CONTINUATIONS
+                    ),
+                });
+                conversation.lock().await.set_prefill(partial);
+                continue;
+            }
+
+            let stats = ResponseStats {
+                model: response.model.clone(),
+                elapsed_ms: carried_ms + elapsed_ms,
+                input_tokens: response.usage.input_tokens,
+                output_tokens: carried_tokens + response.usage.output_tokens,
+            };
+            carried_ms = 0;
+            carried_tokens = 0;
+            continuations = 0;
+            let last_text = response
+                .content
+                .iter()
+                .rposition(|content| matches!(content, ResponseContentBlock::Text { .. }));
+            let mut tool_results: Vec<ContentBlock> = Vec::new();
+
+            for (index, content) in response.content.iter().enumerate() {
+                match content {
+                    ResponseContentBlock::Text { text } => {
+                        emit(StreamEvent::Assistant {
+                            text: text.clone(),
+                            stats: (Some(index) == last_text).then(|| stats.clone()),
+                        });
+                    }
+                    ResponseContentBlock::ToolUse { id, name, input } => {
+                        emit(StreamEvent::ToolCall {
+                            tool_use_id: id.clone(),
+                            name: name.clone(),
+                            input: input.clone(),
+                        });
+
+                        let result = self
+                            .execute_tool(
+                                &mut cache,
+                                context,
+                                model,
+                                access,
+                                id.clone(),
+                                name.clone(),
+                                input.clone(),
+                                &mut emit,
+                            )
+                            .await;
 
-        response.json().await.map_err(|e| e.into())
+                        let (content, is_error) = match &result {
+                            ContentBlock::ToolResult {
+                                content,
+                                is_error,
+                                ..
+                            } => (content.text(), is_error.unwrap_or(false)),
+                            _ => (String::new(), false),
+                        };
+
+                        emit(StreamEvent::ToolResult {
+                            tool_use_id: id.clone(),
+                            name: name.clone(),
+                            content,
+                            is_error,
+                        });
+                        if name == TODO_TOOL && !is_error {
+                            let items = context.todos.lock().unwrap().clone();
+                            emit(StreamEvent::TodoUpdate { items });
+                        }
+                        let update = context.changes.lock().unwrap().take_update();
+                        if let Some(files) = update {
+                            emit(StreamEvent::DiffUpdate { files });
+                        }
+                        tool_results.push(result);
+                    }
+                }
+            }
+
+            match response.stop_reason {
+                Some(StopReason::MaxTokens) => emit(StreamEvent::Warning {
+                    message: format!(
+                        "The reply was cut off at the {}-token limit and may be incomplete.",
+                        self.max_tokens
+                    ),
+                }),
+                Some(StopReason::Refusal) => emit(StreamEvent::Warning {
+                    message: format!(
+                        "{} declined to continue with this request.",
+                        model
+                    ),
+                }),
+                _ => {}
+            }
+
+            let assistant_content = assistant_content_from_response(&response);
+            let done = tool_results.is_empty();
+            // A refusal can come back empty, and the API rejects empty
+            // assistant messages in later requests.
+            if !assistant_content.is_empty() || !done {
+                conversation
+                    .lock()
+                    .await
+                    .push_exchange(assistant_content, tool_results);
+            }
+
+            if done {
+                return Ok(());
+            }
+        }
     }
 
-    pub(crate) async fn execute_tool(
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(name = "tool", skip_all, fields(tool = %name, is_error = Empty))]
+    async fn execute_tool<F>(
         &self,
+        cache: &mut TurnCache,
+        context: &ToolContext,
+        model: &str,
+        access: ToolAccess,
         id: String,
         name: String,
         input: serde_json::Value,
-    ) -> ContentBlock {
-        let tool_def = self.tools.iter().find(|t| t.name == name);
+        emit: &mut F,
+    ) -> ContentBlock
+    where
+        F: FnMut(StreamEvent),
+    {
+        let Some(tool) = self
+            .tools
+            .iter()
+            .find(|t| t.name == name && access.offers(t))
+        else {
+            return ContentBlock::tool_result(id, "tool not found".to_string(), true);
+        };
+        if let Err(problems) = validate_input(&tool.input_schema, &input) {
+            self.tool_usage.lock().unwrap().record_invalid(&name);
+            context.usage.lock().unwrap().record_invalid(&name);
+            return ContentBlock::tool_result(
+                id,
+                format!("invalid parameters: {}", problems),
+                true,
+            );
+        }
+        if let Some(path) = input["path"].as_str()
+            && let Ok(root) = std::env::current_dir()
+            && let Err(refusal) = check_links(&root, path)
+        {
+            return ContentBlock::tool_result(id, refusal, true);
+        }
+        if tool.changes_workspace()
+            && let Some(recorded) = &context.recorded
+        {
+            let (content, is_error) = recorded.lock().unwrap().take(&name, &input);
+            return ContentBlock::tool_result(id, content, is_error);
+        }
+        if tool.changes_workspace()
+            && let Some(model) = &self.review.model
+        {
+            match self.review(model, tool, &input).await {
+                Verdict::Allow => {}
+                Verdict::Flag(reason) => emit(StreamEvent::Warning {
+                    message: format!(
+                        "The reviewer flagged {}: {}",
+                        (tool.summarize)(&input, None),
+                        reason
+                    ),
+                }),
+                Verdict::Block(reason) => {
+                    emit(StreamEvent::Warning {
+                        message: format!(
+                            "The reviewer blocked {}: {}",
+                            (tool.summarize)(&input, None),
+                            reason
+                        ),
+                    });
+                    return ContentBlock::tool_result(
+                        id,
+                        format!("The reviewer blocked this call: {}. Don't retry it or work around it; say what you meant to do instead.", reason),
+                        true,
+                    );
+                }
+            }
+        }
+        let change = (!tool.read_only)
+            .then(|| Change::of(&name, &input))
+            .flatten();
+        // A change past the `[guard]` limits waits for the user even when
+        // `[tools]` auto-approves the tool.
+        let breach = change.and_then(|change| {
+            context
+                .turn_edits
+                .lock()
+                .unwrap()
+                .check(&self.guard, change)
+                .err()
+        });
+        // The tool's own approval first, then the user's say-so for going
+        // past the limits, which is never kept for the session.
+        if tool.needs_approval()
+            && !self.auto_approves(tool)
+            && let Err(refusal) = self
+                .approve(context, tool, &id, &name, &input, None, emit)
+                .await
+        {
+            return ContentBlock::tool_result(id, refusal, true);
+        }
+        if let Some(reason) = &breach
+            && let Err(refusal) = self
+                .approve(context, tool, &id, GUARD, &input, Some(reason), emit)
+                .await
+        {
+            return ContentBlock::tool_result(id, refusal, true);
+        }
 
-        match tool_def {
-            Some(tool) => match (tool.handler)(input).await {
-                Ok(result) => ContentBlock::tool_result(id, result, false),
-                Err(e) => ContentBlock::tool_result(id, e.to_string(), true),
+        if !tool.read_only {
+            // Anything may have changed on disk; earlier reads are stale.
+            cache.clear();
+        } else if let Some((content, is_error)) = cache.get(&name, &input) {
+            return ContentBlock::tool_result(id, content, is_error);
+        }
+        if let Some(change) = change {
+            context.turn_edits.lock().unwrap().record(change);
+        }
+        if !tool.read_only
+            && let Some(path) = input["path"].as_str()
+        {
+            context.changes.lock().unwrap().track(path);
+        }
+
+        *context.progress.lock().unwrap() = None;
+        context.images.lock().unwrap().clear();
+        let network = match self.tools_config.network {
+            true => match self.network_access() {
+                Ok(network) => Some(network),
+                Err(err) => {
+                    let err = format!("Network tools can't be set up: {}", err);
+                    return ContentBlock::tool_result(id, err, true);
+                }
             },
-            None => ContentBlock::tool_result(id, "tool not found".to_string(), true),
+            false => None,
+        };
+        let tool_context = ToolContext {
+            network,
+            env_allowlist: self.tools_config.env.clone(),
+            bash_timeout_secs: Some(self.tools_config.bash_timeout_secs),
+            ..context.clone()
+        };
+        let started = Instant::now();
+        let (mut content, is_error) = match (tool.handler)(input.clone(), tool_context).await {
+            Ok(result) => (result, false),
+            Err(e) => (e.to_string(), true),
+        };
+        let elapsed = started.elapsed();
+        Span::current().record("is_error", is_error);
+        telemetry::record_tool(&name, elapsed, is_error, content.len() as u64 / 4);
+        for usage in [&self.tool_usage, &*context.usage] {
+            usage
+                .lock()
+                .unwrap()
+                .record(&name, elapsed, is_error, &content);
+        }
+        if !tool.read_only
+            && !is_error
+            && let Some(format) = &self.format
+            && let Some(path) = input["path"].as_str()
+            && let Some(report) = format_after_edit(format, path).await
+        {
+            content.push_str("\n\n");
+            content.push_str(&report);
         }
+        if tool.read_only {
+            cache.insert(&name, &input, content.clone(), is_error);
+            if !is_error && let Some(path) = input["path"].as_str() {
+                context.reads.lock().unwrap().insert(normalize_path(path));
+            }
+        }
+        let mut images = std::mem::take(&mut *context.images.lock().unwrap());
+        if !images.is_empty() && !self.provider.capabilities(model).images {
+            images.clear();
+            content.push_str("\n\n(The image can't be shown to this model.)");
+        }
+        ContentBlock::tool_result_with_images(id, content, images, is_error)
+    }
+
+    fn network_access(&self) -> Result<NetworkAccess, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(network) = self.network_access.get() {
+            return Ok(network.clone());
+        }
+        let network = NetworkAccess::new(
+            self.network.client_builder()?,
+            self.tools_config.allowed_hosts.clone(),
+            self.tools_config.max_download_mb * 1024 * 1024,
+        )?;
+        Ok(self.network_access.get_or_init(|| network).clone())
+    }
+
+    /// Waits for the user to approve the call `id` under `key`, the tool's
+    /// name or `GUARD` for a change past the limits, which `breach`
+    /// describes. The error is what the model is told when it may not run.
+    #[allow(clippy::too_many_arguments)]
+    async fn approve<F>(
+        &self,
+        context: &ToolContext,
+        tool: &ToolDefinition,
+        id: &str,
+        key: &str,
+        input: &serde_json::Value,
+        breach: Option<&str>,
+        emit: &mut F,
+    ) -> Result<(), String>
+    where
+        F: FnMut(StreamEvent),
+    {
+        let approval = context.approvals.lock().unwrap().request(id, key);
+        let answer = match approval {
+            Approval::Granted => return Ok(()),
+            Approval::Refused(refusal) => return Err(breach.map_or(refusal, guard_refusal)),
+            Approval::Pending(answer) => answer,
+        };
+        let mut summary = (tool.summarize)(input, None);
+        if let Some(reason) = breach {
+            summary = format!("{} ({})", summary, reason);
+        }
+        emit(StreamEvent::ApprovalNeeded {
+            tool_use_id: id.to_string(),
+            name: tool.name.to_string(),
+            summary,
+        });
+        match answer.await {
+            Ok(answer) if answer.decision == ApprovalDecision::Deny => Err(match breach {
+                Some(reason) if answer.message.is_none() => guard_refusal(reason),
+                _ => denial(&answer),
+            }),
+            Ok(_) => Ok(()),
+            Err(_) => Err("The call was never approved".to_string()),
+        }
+    }
+
+    /// Whether `[tools]` lets `tool` run without asking.
+    fn auto_approves(&self, tool: &ToolDefinition) -> bool {
+        self.tools_config.auto_approve.iter().any(|allowed| {
+            allowed == tool.name || (allowed == "*" && !PRIVATE.contains(&tool.name))
+        })
+    }
+}
+
+/// Every tool, less the opt-in ones `config` leaves off.
+fn offered_tools(config: &ToolsConfig) -> Vec<ToolDefinition> {
+    get_all_tools()
+        .into_iter()
+        .filter(|tool| match tool.opt_in {
+            None => true,
+            Some(OptIn::Network) => config.network,
+            Some(OptIn::Clipboard) => config.clipboard,
+            Some(OptIn::Screenshot) => config.screenshot,
+            Some(OptIn::Processes) => config.processes,
+        })
+        .collect()
+}
+
+/// Which tools a request offers the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ToolAccess {
+    All,
+    /// Only tools that can't change the workspace, for `/compare`.
+    #[cfg(feature = "server")]
+    ReadOnly,
+    None,
+}
+
+impl ToolAccess {
+    fn offers(self, tool: &ToolDefinition) -> bool {
+        #[cfg(not(feature = "server"))]
+        let _ = tool;
+        match self {
+            ToolAccess::All => true,
+            #[cfg(feature = "server")]
+            ToolAccess::ReadOnly => tool.read_only,
+            ToolAccess::None => false,
+        }
+    }
+}
+
+/// The text of a reply, without its tool calls.
+fn reply_text(response: &MessageResponse) -> String {
+    let text: Vec<&str> = response
+        .content
+        .iter()
+        .filter_map(|content| match content {
+            ResponseContentBlock::Text { text } => Some(text.as_str()),
+            ResponseContentBlock::ToolUse { .. } => None,
+        })
+        .collect();
+    text.join("\n").trim().to_string()
+}
+
+/// The steps of a planner's reply: its numbered or bulleted lines, or the
+/// whole reply as one step when it has none.
+#[cfg(feature = "server")]
+fn plan_steps(reply: &str) -> Vec<String> {
+    let steps: Vec<String> = reply
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+            let step = if rest.len() < line.len() {
+                rest.strip_prefix('.').or_else(|| rest.strip_prefix(')'))?
+            } else {
+                line.strip_prefix("- ")
+                    .or_else(|| line.strip_prefix("* "))?
+            };
+            Some(step.trim().to_string()).filter(|step| !step.is_empty())
+        })
+        .collect();
+    if steps.is_empty() && !reply.trim().is_empty() {
+        return vec![reply.trim().to_string()];
+    }
+    steps
+}
+
+/// What the reviewer made of a call.
+#[derive(Debug, PartialEq, Eq)]
+enum Verdict {
+    Allow,
+    /// The call runs, and the reason is passed on as a warning.
+    Flag(String),
+    Block(String),
+}
+
+/// The verdict on the first line of a reviewer's reply. Anything but a
+/// verdict blocks the call.
+fn verdict(reply: &str) -> Verdict {
+    let line = reply.trim().lines().next().unwrap_or_default();
+    let (word, reason) = line
+        .split_once(|c: char| c == ':' || c.is_whitespace())
+        .unwrap_or((line, ""));
+    let reason = reason.trim_start_matches([':', ' ']).trim();
+    let reason = if reason.is_empty() {
+        "no reason given".to_string()
+    } else {
+        reason.to_string()
+    };
+    match word
+        .trim_matches(|c: char| !c.is_ascii_alphabetic())
+        .to_ascii_uppercase()
+        .as_str()
+    {
+        "ALLOW" => Verdict::Allow,
+        "FLAG" => Verdict::Flag(reason),
+        "BLOCK" => Verdict::Block(reason),
+        _ => Verdict::Block(format!("the reviewer replied {:?}", line)),
+    }
+}
+
+/// Whether a failed turn never reached the API, as opposed to the API
+/// answering with an error.
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) fn is_network_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    err.downcast_ref::<reqwest::Error>()
+        .is_some_and(|err| err.is_connect() || err.is_timeout() || err.is_request())
+}
+
+/// The model `name` stands for: the one an alias maps to, or `name` itself.
+pub(crate) fn resolve_model(name: &str) -> &str {
+    MODEL_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(name.trim()))
+        .map_or(name, |(_, model)| model)
+}
+
+/// Context window, in tokens, for a model. Unknown models get a conservative
+/// default that suits small local models; set `TARS_CONTEXT_WINDOW` to
+/// override it.
+fn context_window_for(model: &str) -> usize {
+    if let Some(window) = std::env::var("TARS_CONTEXT_WINDOW")
+        .ok()
+        .and_then(|value| value.parse().ok())
+    {
+        window
+    } else if model.starts_with("claude-") {
+        200_000
+    } else if model.starts_with("gemini-") {
+        1_048_576
+    } else {
+        8_192
+    }
+}
+
+/// The text of a reply that consists only of text, for continuing it.
+fn continuable_text(response: &MessageResponse) -> Option<String> {
+    let mut text = String::new();
+    for content in &response.content {
+        match content {
+            ResponseContentBlock::Text { text: block } => text.push_str(block),
+            ResponseContentBlock::ToolUse { .. } => return None,
+        }
+    }
+    (!text.trim().is_empty()).then_some(text)
+}
+
+/// Folds a prefill back into the reply so the stored and displayed text
+/// reads as one message.
+fn prepend_prefill(response: &mut MessageResponse, prefill: &str) {
+    match response.content.first_mut() {
+        Some(ResponseContentBlock::Text { text }) => text.insert_str(0, prefill),
+        _ => response.content.insert(
+            0,
+            ResponseContentBlock::Text {
+                text: prefill.to_string(),
+            },
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_sdk::{Capabilities, ProviderResult, Usage};
+    #[cfg(feature = "server")]
+    use crate::ai_sdk::ToolChoice;
+    #[cfg(feature = "server")]
+    use crate::protocol::ApprovalRequest;
+    use crate::test_support::{Scripted, TempDir};
+    #[cfg(feature = "server")]
+    use crate::tools::Approvals;
+
+    /// Answers every request with a text reply cut off at the token limit,
+    /// and keeps the requests.
+    #[derive(Default)]
+    struct Truncating {
+        requests: std::sync::Mutex<Vec<serde_json::Value>>,
+    }
+
+    impl Provider for Truncating {
+        fn capabilities(&self, _model: &str) -> Capabilities {
+            Capabilities {
+                prefill: false,
+                images: false,
+                tool_choice: false,
+            }
+        }
+
+        fn convert_request(&self, request: &MessageRequest) -> ProviderResult<serde_json::Value> {
+            Ok(serde_json::to_value(request)?)
+        }
+
+        fn convert_response(&self, body: serde_json::Value) -> ProviderResult<MessageResponse> {
+            Ok(serde_json::from_value(body)?)
+        }
+
+        async fn run_inference(&self, request: &MessageRequest) -> ProviderResult<MessageResponse> {
+            let body = self.convert_request(request)?;
+            self.requests.lock().unwrap().push(body);
+            Ok(MessageResponse {
+                id: "msg_1".to_string(),
+                content: vec![ResponseContentBlock::Text {
+                    text: "Partial".to_string(),
+                }],
+                stop_reason: Some(StopReason::MaxTokens),
+                model: "local".to_string(),
+                usage: Usage::default(),
+            })
+        }
+
+        #[cfg(feature = "server")]
+        async fn reachable(&self) -> bool {
+            true
+        }
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn turns_leave_out_what_the_provider_cannot_do() {
+        let agent = Agent::with_provider(Truncating::default())
+            .with_model("local")
+            .with_context(ContextConfig {
+                repo_map: false,
+                ..ContextConfig::default()
+            });
+        let mut conversation = Conversation::new();
+        conversation.begin_turn("Go on".to_string()).unwrap();
+        conversation.set_prefill("Sure".to_string());
+        conversation.set_tool_choice(ToolChoice::None);
+        let conversation = Mutex::new(conversation);
+        let mut events = Vec::new();
+        agent
+            .run_turn(&conversation, &ToolContext::default(), |event| {
+                events.push(event)
+            })
+            .await
+            .unwrap();
+
+        let requests = agent.provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1, "a cut-off reply isn't continued");
+        assert_eq!(requests[0]["messages"].as_array().unwrap().len(), 1);
+        assert!(requests[0].get("tool_choice").is_none());
+        assert!(matches!(
+            &events[..],
+            [
+                StreamEvent::Assistant { text, .. },
+                StreamEvent::Warning { .. },
+            ] if text == "Partial"
+        ));
+        assert_eq!(agent.usage().requests, 1);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn planned_turns_hand_the_plan_to_the_executor() {
+        assert_eq!(
+            plan_steps("The plan:\n1. Read main.rs\n2) Add a flag\n- Run the tests"),
+            ["Read main.rs", "Add a flag", "Run the tests"]
+        );
+
+        let profile = ProfileConfig {
+            planner: "planner".to_string(),
+            executor: None,
+            planner_prompt: None,
+        };
+        let agent = Agent::with_provider(Truncating::default())
+            .with_model("local")
+            .with_context(ContextConfig {
+                repo_map: false,
+                ..ContextConfig::default()
+            })
+            .with_profiles(BTreeMap::from([("review".to_string(), profile)]));
+        let mut conversation = Conversation::new();
+        conversation.begin_turn("Tidy up".to_string()).unwrap();
+        let conversation = Mutex::new(conversation);
+        let mut events = Vec::new();
+        agent
+            .run_planned_turn("review", &conversation, &ToolContext::default(), |event| {
+                events.push(event)
+            })
+            .await
+            .unwrap();
+        assert!(agent
+            .run_planned_turn("nope", &conversation, &ToolContext::default(), |_| {})
+            .await
+            .is_err());
+
+        let requests = agent.provider.requests.lock().unwrap();
+        assert_eq!(requests[0]["model"], "planner");
+        assert_eq!(requests[0]["tools"], serde_json::json!([]));
+        assert_eq!(requests[1]["model"], "local");
+        assert!(requests[1]["system"]
+            .as_str()
+            .unwrap()
+            .ends_with(&format!("{}\n\n1. Partial", PLAN_INTRO)));
+        assert!(matches!(
+            &events[0],
+            StreamEvent::Plan { steps, stats } if steps == &["Partial"] && stats.model == "local"
+        ));
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn turns_run_on_the_model_picked_for_them() {
+        assert_eq!(resolve_model(" Sonnet"), "claude-sonnet-4-5-20250929");
+        assert_eq!(resolve_model("haiku"), DEFAULT_MODEL);
+        assert_eq!(resolve_model("claude-sonnet-4-5"), "claude-sonnet-4-5");
+
+        let reply = |text: &str| {
+            vec![ResponseContentBlock::Text {
+                text: text.to_string(),
+            }]
+        };
+        let agent = Agent::with_provider(Scripted::new(vec![reply("One"), reply("Two")]))
+            .with_model("opus")
+            .with_context(ContextConfig {
+                repo_map: false,
+                ..ContextConfig::default()
+            });
+        let conversation = Mutex::new(Conversation::new());
+        conversation
+            .lock()
+            .await
+            .begin_turn("First".to_string())
+            .unwrap();
+        agent
+            .run_turn_on("sonnet", &conversation, &ToolContext::default(), |_| {})
+            .await
+            .unwrap();
+        let mut turn = conversation.lock().await;
+        turn.finish_turn();
+        turn.begin_turn("Second".to_string()).unwrap();
+        drop(turn);
+        agent
+            .run_turn(&conversation, &ToolContext::default(), |_| {})
+            .await
+            .unwrap();
+
+        let requests = agent.provider().requests();
+        assert_eq!(requests[0]["model"], "claude-sonnet-4-5-20250929");
+        assert_eq!(requests[1]["model"], "claude-opus-4-1-20250805");
+    }
+
+    #[tokio::test]
+    async fn the_reviewer_blocks_calls_it_does_not_allow() {
+        assert_eq!(verdict("ALLOW"), Verdict::Allow);
+        assert_eq!(
+            verdict("**FLAG**: deletes the build cache\nIt can be rebuilt."),
+            Verdict::Flag("deletes the build cache".to_string())
+        );
+        assert_eq!(
+            verdict("block pushes to main"),
+            Verdict::Block("pushes to main".to_string())
+        );
+
+        let agent = Agent::with_provider(Truncating::default())
+            .with_model("local")
+            .with_review(ReviewConfig {
+                model: Some("reviewer".to_string()),
+                policy: Some("Never push.".to_string()),
+            });
+        let mut events = Vec::new();
+        let result = agent
+            .execute_tool(
+                &mut TurnCache::default(),
+                &ToolContext::default(),
+                "local",
+                ToolAccess::All,
+                "call_1".to_string(),
+                "bash".to_string(),
+                serde_json::json!({"command": "git push --force"}),
+                &mut |event| events.push(event),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            ContentBlock::ToolResult { content, is_error: Some(true), .. }
+                if content.text().starts_with("The reviewer blocked this call: the reviewer replied \"Partial\"")
+        ));
+        assert!(matches!(&events[..], [StreamEvent::Warning { .. }]));
+
+        let requests = agent.provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["model"], "reviewer");
+        assert!(requests[0]["system"]
+            .as_str()
+            .unwrap()
+            .ends_with("Policy:\nNever push."));
+        assert!(requests[0]["messages"][0]["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("git push --force"));
+    }
+
+    async fn call(
+        agent: &Agent<Truncating>,
+        cache: &mut TurnCache,
+        context: &ToolContext,
+        name: &str,
+        input: serde_json::Value,
+    ) -> String {
+        let result = agent
+            .execute_tool(
+                cache,
+                context,
+                &agent.model,
+                ToolAccess::All,
+                "call_1".to_string(),
+                name.to_string(),
+                input,
+                &mut |_| {},
+            )
+            .await;
+        match result {
+            ContentBlock::ToolResult { content, .. } => content.text(),
+            other => panic!("expected a tool result, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn repeated_reads_come_from_the_cache_until_something_changes() {
+        let dir = TempDir::new("tars-turn-cache");
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, "first\n").unwrap();
+        let agent = Agent::with_provider(Truncating::default());
+        let context = ToolContext::default();
+        context.approvals.lock().unwrap().allow("edit_file");
+        let mut cache = TurnCache::default();
+        let read = serde_json::json!({ "path": path });
+
+        let output = call(&agent, &mut cache, &context, "read_file", read.clone()).await;
+        assert!(output.contains("first"), "{}", output);
+        std::fs::write(&path, "second\n").unwrap();
+        let output = call(&agent, &mut cache, &context, "read_file", read.clone()).await;
+        assert!(output.contains("first"), "{}", output);
+
+        let edit = serde_json::json!({ "path": path, "old_str": "second", "new_str": "third" });
+        call(&agent, &mut cache, &context, "edit_file", edit).await;
+        let output = call(&agent, &mut cache, &context, "read_file", read).await;
+        assert!(output.contains("third"), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn tool_events_carry_the_call_they_belong_to() {
+        let dir = TempDir::new("tars-tool-events");
+        std::fs::write(dir.join("notes.txt"), "hello\n").unwrap();
+        let read = |id: &str, name: &str| ResponseContentBlock::ToolUse {
+            id: id.to_string(),
+            name: "read_file".to_string(),
+            input: serde_json::json!({ "path": dir.join(name) }),
+        };
+        let agent = Agent::with_provider(Scripted::new(vec![
+            vec![read("call_1", "notes.txt"), read("call_2", "missing.txt")],
+            vec![ResponseContentBlock::Text {
+                text: "Read it.".to_string(),
+            }],
+        ]))
+        .with_context(ContextConfig {
+            repo_map: false,
+            ..ContextConfig::default()
+        });
+        let mut conversation = Conversation::new();
+        conversation
+            .begin_turn("Read my notes".to_string())
+            .unwrap();
+        let conversation = Mutex::new(conversation);
+        let mut events = Vec::new();
+        agent
+            .run_turn(&conversation, &ToolContext::default(), |event| {
+                events.push(event)
+            })
+            .await
+            .unwrap();
+
+        let calls: Vec<(&str, &str, Option<bool>)> = events
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::ToolCall {
+                    tool_use_id, name, ..
+                } => Some((tool_use_id.as_str(), name.as_str(), None)),
+                StreamEvent::ToolResult {
+                    tool_use_id,
+                    name,
+                    is_error,
+                    ..
+                } => Some((tool_use_id.as_str(), name.as_str(), Some(*is_error))),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("call_1", "read_file", None),
+                ("call_1", "read_file", Some(false)),
+                ("call_2", "read_file", None),
+                ("call_2", "read_file", Some(true)),
+            ]
+        );
+        assert_eq!(agent.provider().requests().len(), 2);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn changes_past_the_guard_wait_for_the_user_even_when_auto_approved() {
+        let dir = TempDir::new("tars-guard");
+        let agent = Agent::with_provider(Truncating::default())
+            .with_tools(ToolsConfig {
+                auto_approve: vec!["*".to_string()],
+                ..ToolsConfig::default()
+            })
+            .with_guard(GuardConfig {
+                max_files: 1,
+                max_removed_lines: 0,
+            });
+        let context = ToolContext {
+            approvals: std::sync::Arc::new(std::sync::Mutex::new(Approvals::interactive())),
+            ..ToolContext::default()
+        };
+        let mut cache = TurnCache::default();
+        let create = |name: &str| {
+            let path = dir.join(name);
+            serde_json::json!({ "path": path, "old_str": "", "new_str": "hello" })
+        };
+        async fn answer(context: &ToolContext, decision: ApprovalDecision) {
+            while !context.approvals.lock().unwrap().waiting() {
+                tokio::task::yield_now().await;
+            }
+            let answer = ApprovalRequest {
+                decision,
+                message: None,
+            };
+            assert!(context.approvals.lock().unwrap().answer("call_1", answer));
+        }
+
+        call(&agent, &mut cache, &context, "edit_file", create("a.txt")).await;
+        assert!(dir.join("a.txt").exists());
+
+        let (output, ()) = tokio::join!(
+            call(&agent, &mut cache, &context, "edit_file", create("b.txt")),
+            answer(&context, ApprovalDecision::Approve)
+        );
+        assert!(output.starts_with("Successfully created"), "{}", output);
+
+        let (output, ()) = tokio::join!(
+            call(&agent, &mut cache, &context, "edit_file", create("c.txt")),
+            answer(&context, ApprovalDecision::Deny)
+        );
+        assert!(output.starts_with("Refused: this turn has already changed 2 files"));
+        assert!(!dir.join("c.txt").exists());
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn always_past_the_guard_neither_lasts_nor_approves_the_tool() {
+        let dir = TempDir::new("tars-guard-always");
+        let agent = Agent::with_provider(Truncating::default()).with_guard(GuardConfig {
+            max_files: 1,
+            max_removed_lines: 0,
+        });
+        let context = ToolContext {
+            approvals: std::sync::Arc::new(std::sync::Mutex::new(Approvals::interactive())),
+            ..ToolContext::default()
+        };
+        let mut cache = TurnCache::default();
+        let create = |name: &str| {
+            let path = dir.join(name);
+            serde_json::json!({ "path": path, "old_str": "", "new_str": "hello" })
+        };
+        // Answers the prompts as they come, in order.
+        async fn answer(context: &ToolContext, decisions: &[ApprovalDecision]) {
+            for &decision in decisions {
+                while !context.approvals.lock().unwrap().waiting() {
+                    tokio::task::yield_now().await;
+                }
+                let answer = ApprovalRequest {
+                    decision,
+                    message: None,
+                };
+                assert!(context.approvals.lock().unwrap().answer("call_1", answer));
+            }
+        }
+        use ApprovalDecision::{Always, Approve, Deny};
+
+        let (output, ()) = tokio::join!(
+            call(&agent, &mut cache, &context, "edit_file", create("a.txt")),
+            answer(&context, &[Approve])
+        );
+        assert!(output.starts_with("Successfully created"), "{}", output);
+
+        // The tool's prompt, then the guard's.
+        let (output, ()) = tokio::join!(
+            call(&agent, &mut cache, &context, "edit_file", create("b.txt")),
+            answer(&context, &[Approve, Always])
+        );
+        assert!(output.starts_with("Successfully created"), "{}", output);
+
+        let (output, ()) = tokio::join!(
+            call(&agent, &mut cache, &context, "edit_file", create("c.txt")),
+            answer(&context, &[Deny])
+        );
+        assert!(output.starts_with("The user denied this call"), "{}", output);
+
+        let (output, ()) = tokio::join!(
+            call(&agent, &mut cache, &context, "edit_file", create("d.txt")),
+            answer(&context, &[Always, Deny])
+        );
+        assert!(output.starts_with("Refused: this turn has already changed 2 files"));
+        assert!(!dir.join("c.txt").exists() && !dir.join("d.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn unattended_runs_refuse_commands_past_the_guard() {
+        let agent = Agent::with_provider(Truncating::default())
+            .with_tools(ToolsConfig {
+                auto_approve: vec!["bash".to_string()],
+                ..ToolsConfig::default()
+            })
+            .with_guard(GuardConfig {
+                max_files: 1,
+                max_removed_lines: 0,
+            });
+        let context = ToolContext::default();
+        let mut cache = TurnCache::default();
+        let echo = serde_json::json!({ "command": "echo ran" });
+
+        let output = call(&agent, &mut cache, &context, "bash", echo.clone()).await;
+        assert!(output.contains("ran"), "{}", output);
+        let output = call(&agent, &mut cache, &context, "bash", echo).await;
+        assert!(
+            output.starts_with("Refused: this turn has already changed 0 files or run 1 commands"),
+            "{}",
+            output
+        );
     }
 }
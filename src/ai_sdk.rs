@@ -1,3 +1,11 @@
 mod anthropic;
+mod backend;
+mod messages;
+mod provider;
+mod vertex;
 
 pub(crate) use anthropic::*;
+pub(crate) use backend::*;
+pub(crate) use messages::*;
+pub(crate) use provider::*;
+pub(crate) use vertex::*;
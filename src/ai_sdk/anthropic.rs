@@ -1,156 +1,237 @@
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct MessageRequest {
-    pub(crate) model: String,
-    pub(crate) max_tokens: u32,
-    pub(crate) messages: Vec<MessageParam>,
-    pub(crate) tools: Vec<ToolDefinitionApi>,
+use reqwest::{Client, StatusCode};
+use std::time::Duration;
+
+use super::{Capabilities, MessageRequest, MessageResponse, Provider, ProviderResult};
+use crate::config;
+use crate::key_ring::KeyRing;
+
+pub(crate) const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+const API_VERSION: &str = "2023-06-01";
+
+/// The Anthropic Messages API, or a gateway that speaks it.
+pub struct Anthropic {
+    client: Client,
+    keys: KeyRing,
+    base_url: String,
+    request_timeout: Option<Duration>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum MessageParam {
-    User(UserMessage),
-    Assistant(AssistantMessage),
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UserMessage {
-    role: String,
-    content: Vec<ContentBlock>,
-}
-
-impl UserMessage {
-    pub(crate) fn new(content: Vec<ContentBlock>) -> Self {
+impl Anthropic {
+    pub fn new(keys: KeyRing) -> Self {
         Self {
-            role: "user".to_string(),
-            content,
+            client: Client::new(),
+            keys,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            request_timeout: None,
         }
     }
 
-    pub(crate) fn from_text(text: String) -> Self {
-        Self::new(vec![ContentBlock::Text { text }])
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
     }
-}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AssistantMessage {
-    role: String,
-    content: Vec<ContentBlock>,
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
 }
 
-impl AssistantMessage {
-    pub(crate) fn new(content: Vec<ContentBlock>) -> Self {
-        Self {
-            role: "assistant".to_string(),
-            content,
+impl Provider for Anthropic {
+    fn capabilities(&self, _model: &str) -> Capabilities {
+        Capabilities {
+            prefill: true,
+            images: true,
+            tool_choice: true,
         }
     }
-}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum ContentBlock {
-    Text { text: String },
-    ToolUse {
-        id: String,
-        name: String,
-        input: serde_json::Value,
-    },
-    ToolResult {
-        tool_use_id: String,
-        content: String,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        is_error: Option<bool>,
-    },
-}
+    /// Conversations are kept in this API's format already.
+    fn convert_request(&self, request: &MessageRequest) -> ProviderResult<serde_json::Value> {
+        Ok(serde_json::to_value(request)?)
+    }
 
-impl ContentBlock {
-    pub(crate) fn tool_result(tool_use_id: String, content: String, is_error: bool) -> Self {
-        Self::ToolResult {
-            tool_use_id,
-            content,
-            is_error: if is_error { Some(true) } else { None },
-        }
+    fn convert_response(&self, body: serde_json::Value) -> ProviderResult<MessageResponse> {
+        Ok(serde_json::from_value(body)?)
     }
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct MessageResponse {
-    pub(crate) id: String,
-    pub(crate) content: Vec<ResponseContentBlock>,
-    pub(crate) stop_reason: String,
-}
+    async fn run_inference(&self, request: &MessageRequest) -> ProviderResult<MessageResponse> {
+        let body = self.convert_request(request)?;
+        // A rate-limited key is rotated out and the request retried with
+        // the next one, trying each key at most once.
+        let mut attempts = 1;
+        let response = loop {
+            let api_key = self.keys.current().ok_or(config::MISSING_API_KEY)?;
+            let mut builder = self
+                .client
+                .post(format!("{}/v1/messages", self.base_url))
+                .header("x-api-key", &api_key)
+                .header("anthropic-version", API_VERSION)
+                .header("content-type", "application/json")
+                .json(&body);
+            if let Some(timeout) = self.request_timeout {
+                builder = builder.timeout(timeout);
+            }
+            let response = builder.send().await?;
+
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                self.keys.rotate(&api_key);
+                if attempts < self.keys.len() {
+                    attempts += 1;
+                    continue;
+                }
+            }
+            break response;
+        };
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum ResponseContentBlock {
-    Text { text: String },
-    ToolUse {
-        id: String,
-        name: String,
-        input: serde_json::Value,
-    },
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await?;
+            return Err(format!("API error: {} - {}", status, error_text).into());
+        }
+        self.convert_response(response.json().await?)
+    }
+
+    /// Any HTTP response counts.
+    #[cfg(feature = "server")]
+    async fn reachable(&self) -> bool {
+        self.client
+            .head(format!("{}/", self.base_url))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .is_ok()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct ToolDefinitionApi {
-    pub(crate) name: String,
-    pub(crate) description: String,
-    pub(crate) input_schema: serde_json::Value,
+/// Outcome of checking an API key before starting a session.
+#[cfg(all(feature = "client", feature = "server"))]
+pub(crate) enum KeyCheck {
+    Valid,
+    /// The API answered and refused the key.
+    Rejected(String),
+    /// The API could not be asked, so the key may still be fine.
+    Unreachable(String),
 }
 
-pub(crate) fn assistant_content_from_response(response: &MessageResponse) -> Vec<ContentBlock> {
-    response
-        .content
-        .iter()
-        .map(|content| match content {
-            ResponseContentBlock::Text { text } => ContentBlock::Text { text: text.clone() },
-            ResponseContentBlock::ToolUse { id, name, input } => ContentBlock::ToolUse {
-                id: id.clone(),
-                name: name.clone(),
-                input: input.clone(),
-            },
-        })
-        .collect()
+/// Checks a key with a cheap authenticated request, telling a bad key apart
+/// from a network or service problem.
+#[cfg(all(feature = "client", feature = "server"))]
+pub(crate) async fn check_api_key(client: &Client, base_url: &str, api_key: &str) -> KeyCheck {
+    let response = client
+        .get(format!("{}/v1/models?limit=1", base_url))
+        .header("x-api-key", api_key)
+        .header("anthropic-version", API_VERSION)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => KeyCheck::Valid,
+        Ok(response) if matches!(response.status().as_u16(), 401 | 403) => {
+            KeyCheck::Rejected(response.status().to_string())
+        }
+        Ok(response) => KeyCheck::Unreachable(format!("API returned {}", response.status())),
+        Err(err) => KeyCheck::Unreachable(err.to_string()),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
-
-    #[test]
-    fn assistant_content_from_response_maps_blocks() {
-        let response = MessageResponse {
-            id: "msg_1".to_string(),
-            stop_reason: "end".to_string(),
-            content: vec![
-                ResponseContentBlock::Text {
-                    text: "hello".to_string(),
-                },
-                ResponseContentBlock::ToolUse {
-                    id: "tool_1".to_string(),
-                    name: "read_file".to_string(),
-                    input: json!({"path": "README.md"}),
-                },
-            ],
-        };
-
-        let content = assistant_content_from_response(&response);
-        assert_eq!(content.len(), 2);
-        match &content[0] {
-            ContentBlock::Text { text } => assert_eq!(text, "hello"),
-            _ => panic!("expected text block"),
-        }
-        match &content[1] {
-            ContentBlock::ToolUse { id, name, input } => {
-                assert_eq!(id, "tool_1");
-                assert_eq!(name, "read_file");
-                assert_eq!(input, &json!({"path": "README.md"}));
-            }
-            _ => panic!("expected tool use block"),
+    use crate::ai_sdk::{MessageParam, UserMessage};
+    use crate::test_support::serve;
+
+    fn request() -> MessageRequest {
+        MessageRequest {
+            model: "claude-haiku-4-5-20251001".to_string(),
+            max_tokens: 1024,
+            system: None,
+            messages: vec![MessageParam::User(UserMessage::from_text("Hi".to_string()))],
+            tools: Vec::new(),
+            temperature: None,
+            tool_choice: None,
         }
     }
+
+    #[cfg(all(feature = "client", feature = "server"))]
+    #[tokio::test]
+    async fn key_checks_tell_a_bad_key_from_an_unreachable_api() {
+        let base_url = serve(|request| {
+            let status = if request.contains("x-api-key: good") {
+                200
+            } else if request.contains("x-api-key: bad") {
+                401
+            } else {
+                500
+            };
+            (status, "{}".to_string())
+        })
+        .await;
+        let client = Client::new();
+        assert!(matches!(
+            check_api_key(&client, &base_url, "good").await,
+            KeyCheck::Valid
+        ));
+        assert!(matches!(
+            check_api_key(&client, &base_url, "bad").await,
+            KeyCheck::Rejected(status) if status.starts_with("401")
+        ));
+        assert!(matches!(
+            check_api_key(&client, &base_url, "other").await,
+            KeyCheck::Unreachable(reason) if reason.contains("500")
+        ));
+
+        // Nothing listens on a port that was just released.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        assert!(matches!(
+            check_api_key(&client, &closed, "good").await,
+            KeyCheck::Unreachable(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn requests_go_to_the_gateway_under_its_path() {
+        let gateway = serve(|request| {
+            let status = if request.starts_with("POST /anthropic/v1/messages ") {
+                200
+            } else {
+                404
+            };
+            let body = serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5-20251001",
+                "content": [{"type": "text", "text": "Hello"}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 3, "output_tokens": 1},
+            });
+            (status, body.to_string())
+        })
+        .await;
+        let anthropic = Anthropic::new(KeyRing::new(vec!["key".to_string()]))
+            .with_base_url(format!("{}/anthropic", gateway));
+        let response = anthropic.run_inference(&request()).await.unwrap();
+        assert_eq!(response.usage.output_tokens, 1);
+    }
+
+    #[tokio::test]
+    async fn a_hung_request_times_out() {
+        // Connections are queued by the OS but never answered.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let anthropic = Anthropic::new(KeyRing::new(vec!["key".to_string()]))
+            .with_base_url(format!("http://{}", listener.local_addr().unwrap()))
+            .with_request_timeout(Duration::from_millis(200));
+        let err = anthropic.run_inference(&request()).await.unwrap_err();
+        assert!(err
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(reqwest::Error::is_timeout));
+    }
 }
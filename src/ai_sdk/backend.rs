@@ -0,0 +1,68 @@
+use reqwest::Client;
+use std::time::Duration;
+
+use super::{
+    Anthropic, Capabilities, MessageRequest, MessageResponse, Provider, ProviderResult, Vertex,
+};
+
+/// The provider the config picks, as one type the agent can hold.
+pub enum Backend {
+    Anthropic(Anthropic),
+    Vertex(Vertex),
+}
+
+impl Backend {
+    pub fn with_http_client(self, client: Client) -> Self {
+        match self {
+            Backend::Anthropic(provider) => Backend::Anthropic(provider.with_http_client(client)),
+            Backend::Vertex(provider) => Backend::Vertex(provider.with_http_client(client)),
+        }
+    }
+
+    pub fn with_request_timeout(self, timeout: Duration) -> Self {
+        match self {
+            Backend::Anthropic(provider) => {
+                Backend::Anthropic(provider.with_request_timeout(timeout))
+            }
+            Backend::Vertex(provider) => Backend::Vertex(provider.with_request_timeout(timeout)),
+        }
+    }
+}
+
+impl Provider for Backend {
+    fn capabilities(&self, model: &str) -> Capabilities {
+        match self {
+            Backend::Anthropic(provider) => provider.capabilities(model),
+            Backend::Vertex(provider) => provider.capabilities(model),
+        }
+    }
+
+    fn convert_request(&self, request: &MessageRequest) -> ProviderResult<serde_json::Value> {
+        match self {
+            Backend::Anthropic(provider) => provider.convert_request(request),
+            Backend::Vertex(provider) => provider.convert_request(request),
+        }
+    }
+
+    fn convert_response(&self, body: serde_json::Value) -> ProviderResult<MessageResponse> {
+        match self {
+            Backend::Anthropic(provider) => provider.convert_response(body),
+            Backend::Vertex(provider) => provider.convert_response(body),
+        }
+    }
+
+    async fn run_inference(&self, request: &MessageRequest) -> ProviderResult<MessageResponse> {
+        match self {
+            Backend::Anthropic(provider) => provider.run_inference(request).await,
+            Backend::Vertex(provider) => provider.run_inference(request).await,
+        }
+    }
+
+    #[cfg(feature = "server")]
+    async fn reachable(&self) -> bool {
+        match self {
+            Backend::Anthropic(provider) => provider.reachable().await,
+            Backend::Vertex(provider) => provider.reachable().await,
+        }
+    }
+}
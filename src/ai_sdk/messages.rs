@@ -0,0 +1,363 @@
+use base64::Engine;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+/// One inference request, in the format conversations are kept and stored
+/// in, which follows the Anthropic Messages API. Each provider converts it
+/// to what its backend expects.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MessageRequest {
+    pub(crate) model: String,
+    pub(crate) max_tokens: u32,
+    /// Context the model gets ahead of the messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) system: Option<String>,
+    pub(crate) messages: Vec<MessageParam>,
+    pub(crate) tools: Vec<ToolDefinitionApi>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tool_choice: Option<ToolChoice>,
+}
+
+/// Whether the model may, must, or must not call tools.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    Auto,
+    /// Call at least one tool, whichever the model picks.
+    Any,
+    /// Call the named tool.
+    Tool {
+        name: String,
+    },
+    /// Reply with text only.
+    None,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum MessageParam {
+    User(UserMessage),
+    Assistant(AssistantMessage),
+}
+
+/// Both kinds of message have the same shape, so the role decides which
+/// one a stored message is.
+impl<'de> Deserialize<'de> for MessageParam {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let UserMessage { role, content } = UserMessage::deserialize(deserializer)?;
+        match role.as_str() {
+            "user" => Ok(MessageParam::User(UserMessage { role, content })),
+            "assistant" => Ok(MessageParam::Assistant(AssistantMessage { role, content })),
+            other => Err(de::Error::unknown_variant(other, &["user", "assistant"])),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserMessage {
+    role: String,
+    content: Vec<ContentBlock>,
+}
+
+impl UserMessage {
+    pub(crate) fn new(content: Vec<ContentBlock>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content,
+        }
+    }
+
+    pub(crate) fn from_text(text: String) -> Self {
+        Self::new(vec![ContentBlock::Text { text }])
+    }
+
+    pub(crate) fn content(&self) -> &[ContentBlock] {
+        &self.content
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssistantMessage {
+    role: String,
+    content: Vec<ContentBlock>,
+}
+
+impl AssistantMessage {
+    pub(crate) fn new(content: Vec<ContentBlock>) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content,
+        }
+    }
+
+    pub(crate) fn content(&self) -> &[ContentBlock] {
+        &self.content
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: ToolResultContent,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        is_error: Option<bool>,
+    },
+}
+
+impl ContentBlock {
+    pub(crate) fn tool_result(tool_use_id: String, content: String, is_error: bool) -> Self {
+        Self::tool_result_with_images(tool_use_id, content, Vec::new(), is_error)
+    }
+
+    /// A tool result that shows the model `images` after its text, such as
+    /// a screenshot.
+    pub(crate) fn tool_result_with_images(
+        tool_use_id: String,
+        content: String,
+        images: Vec<ImageSource>,
+        is_error: bool,
+    ) -> Self {
+        let content = if images.is_empty() {
+            ToolResultContent::Text(content)
+        } else {
+            let text = ToolResultBlock::Text { text: content };
+            let images = images
+                .into_iter()
+                .map(|source| ToolResultBlock::Image { source });
+            ToolResultContent::Blocks(std::iter::once(text).chain(images).collect())
+        };
+        Self::ToolResult {
+            tool_use_id,
+            content,
+            is_error: if is_error { Some(true) } else { None },
+        }
+    }
+}
+
+/// What a tool result holds: text, or text followed by images.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ToolResultContent {
+    Text(String),
+    Blocks(Vec<ToolResultBlock>),
+}
+
+impl ToolResultContent {
+    /// The text, without the images.
+    pub(crate) fn text(&self) -> String {
+        match self {
+            ToolResultContent::Text(text) => text.clone(),
+            ToolResultContent::Blocks(blocks) => blocks
+                .iter()
+                .filter_map(|block| match block {
+                    ToolResultBlock::Text { text } => Some(text.as_str()),
+                    ToolResultBlock::Image { .. } => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    pub(crate) fn images(&self) -> impl Iterator<Item = &ImageSource> {
+        let blocks = match self {
+            ToolResultContent::Text(_) => &[][..],
+            ToolResultContent::Blocks(blocks) => blocks,
+        };
+        blocks.iter().filter_map(|block| match block {
+            ToolResultBlock::Image { source } => Some(source),
+            ToolResultBlock::Text { .. } => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolResultBlock {
+    Text { text: String },
+    Image { source: ImageSource },
+}
+
+/// An image sent inline with the request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageSource {
+    /// Always `base64`.
+    #[serde(rename = "type")]
+    kind: String,
+    pub(crate) media_type: String,
+    pub(crate) data: String,
+}
+
+impl ImageSource {
+    pub(crate) fn base64(media_type: &str, bytes: &[u8]) -> Self {
+        Self {
+            kind: "base64".to_string(),
+            media_type: media_type.to_string(),
+            data: base64::engine::general_purpose::STANDARD.encode(bytes),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MessageResponse {
+    pub(crate) id: String,
+    pub(crate) content: Vec<ResponseContentBlock>,
+    #[serde(default)]
+    pub(crate) stop_reason: Option<StopReason>,
+    #[serde(default)]
+    pub(crate) model: String,
+    #[serde(default)]
+    pub(crate) usage: Usage,
+}
+
+/// Why the model stopped generating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StopReason {
+    EndTurn,
+    /// The reply hit `max_tokens` and is incomplete.
+    MaxTokens,
+    StopSequence,
+    ToolUse,
+    PauseTurn,
+    /// The model declined to continue for safety reasons.
+    Refusal,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Usage {
+    pub(crate) input_tokens: u64,
+    pub(crate) output_tokens: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ToolDefinitionApi {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) input_schema: serde_json::Value,
+}
+
+pub(crate) fn assistant_content_from_response(response: &MessageResponse) -> Vec<ContentBlock> {
+    response
+        .content
+        .iter()
+        .map(|content| match content {
+            ResponseContentBlock::Text { text } => ContentBlock::Text { text: text.clone() },
+            ResponseContentBlock::ToolUse { id, name, input } => ContentBlock::ToolUse {
+                id: id.clone(),
+                name: name.clone(),
+                input: input.clone(),
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn assistant_content_from_response_maps_blocks() {
+        let response = MessageResponse {
+            id: "msg_1".to_string(),
+            stop_reason: Some(StopReason::ToolUse),
+            model: "claude".to_string(),
+            usage: Usage::default(),
+            content: vec![
+                ResponseContentBlock::Text {
+                    text: "hello".to_string(),
+                },
+                ResponseContentBlock::ToolUse {
+                    id: "tool_1".to_string(),
+                    name: "read_file".to_string(),
+                    input: json!({"path": "README.md"}),
+                },
+            ],
+        };
+
+        let content = assistant_content_from_response(&response);
+        assert_eq!(content.len(), 2);
+        match &content[0] {
+            ContentBlock::Text { text } => assert_eq!(text, "hello"),
+            _ => panic!("expected text block"),
+        }
+        match &content[1] {
+            ContentBlock::ToolUse { id, name, input } => {
+                assert_eq!(id, "tool_1");
+                assert_eq!(name, "read_file");
+                assert_eq!(input, &json!({"path": "README.md"}));
+            }
+            _ => panic!("expected tool use block"),
+        }
+    }
+    #[test]
+    fn tool_results_with_images_use_content_blocks() {
+        let plain = ContentBlock::tool_result("t1".to_string(), "ok".to_string(), false);
+        assert_eq!(
+            serde_json::to_value(&plain).unwrap(),
+            json!({"type": "tool_result", "tool_use_id": "t1", "content": "ok"})
+        );
+
+        let image = ImageSource::base64("image/jpeg", b"jpg");
+        let shot = ContentBlock::tool_result_with_images(
+            "t2".to_string(),
+            "Screenshot".to_string(),
+            vec![image],
+            false,
+        );
+        let value = serde_json::to_value(&shot).unwrap();
+        assert_eq!(
+            value["content"][1],
+            json!({"type": "image", "source": {"type": "base64", "media_type": "image/jpeg", "data": "anBn"}})
+        );
+        let ContentBlock::ToolResult { content, .. } = serde_json::from_value(value).unwrap()
+        else {
+            panic!("expected a tool result");
+        };
+        assert_eq!(content.text(), "Screenshot");
+        assert_eq!(content.images().count(), 1);
+    }
+
+    #[test]
+    fn stop_reason_parses_known_and_unknown_values() {
+        let parse = |value: serde_json::Value| {
+            serde_json::from_value::<MessageResponse>(json!({
+                "id": "msg_1",
+                "content": [],
+                "stop_reason": value,
+            }))
+            .unwrap()
+            .stop_reason
+        };
+        assert_eq!(parse(json!("max_tokens")), Some(StopReason::MaxTokens));
+        assert_eq!(parse(json!("refusal")), Some(StopReason::Refusal));
+        assert_eq!(parse(json!("something_new")), Some(StopReason::Other));
+        assert_eq!(parse(json!(null)), None);
+    }
+}
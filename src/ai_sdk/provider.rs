@@ -0,0 +1,43 @@
+use std::future::Future;
+
+use super::{MessageRequest, MessageResponse};
+
+pub(crate) type ProviderResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A model backend the agent runs inference against.
+///
+/// Requests and replies are in the conversation's own format; the provider
+/// converts them to and from its API's.
+pub(crate) trait Provider: Send + Sync {
+    /// What the backend supports for `model`, so the agent can work around
+    /// the rest.
+    fn capabilities(&self, model: &str) -> Capabilities;
+
+    /// The API request body for `request`.
+    fn convert_request(&self, request: &MessageRequest) -> ProviderResult<serde_json::Value>;
+
+    /// The reply in an API response body.
+    fn convert_response(&self, body: serde_json::Value) -> ProviderResult<MessageResponse>;
+
+    /// Sends `request` and waits for the whole reply.
+    fn run_inference(
+        &self,
+        request: &MessageRequest,
+    ) -> impl Future<Output = ProviderResult<MessageResponse>> + Send;
+
+    /// Whether the backend answers at all, for the health check.
+    #[cfg(feature = "server")]
+    fn reachable(&self) -> impl Future<Output = bool> + Send;
+}
+
+/// Features a backend may lack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Capabilities {
+    /// Continues a reply from a trailing assistant message, which is how
+    /// replies cut off at the token limit are resumed.
+    pub(crate) prefill: bool,
+    /// Accepts images in tool results, such as screenshots.
+    pub(crate) images: bool,
+    /// Honours `tool_choice`.
+    pub(crate) tool_choice: bool,
+}
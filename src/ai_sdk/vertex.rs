@@ -0,0 +1,199 @@
+mod auth;
+mod gemini;
+
+use reqwest::Client;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{Capabilities, MessageRequest, MessageResponse, Provider, ProviderResult};
+use auth::GoogleAuth;
+
+/// The version of the Messages API Claude on Vertex AI speaks.
+const ANTHROPIC_VERSION: &str = "vertex-2023-10-16";
+
+/// Google Vertex AI, running Claude or Gemini models in a Google Cloud
+/// project. Claude keeps the Messages API format; Gemini requests are
+/// converted to `generateContent`.
+pub struct Vertex {
+    client: Client,
+    auth: GoogleAuth,
+    project: String,
+    region: String,
+    base_url: String,
+    request_timeout: Option<Duration>,
+}
+
+impl Vertex {
+    /// Signs in with `credentials` when given, else the way Application
+    /// Default Credentials do.
+    pub fn new(
+        project: impl Into<String>,
+        region: impl Into<String>,
+        base_url: impl Into<String>,
+        credentials: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            client: Client::new(),
+            auth: GoogleAuth::new(credentials),
+            project: project.into(),
+            region: region.into(),
+            base_url: base_url.into(),
+            request_timeout: None,
+        }
+    }
+
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    fn endpoint(&self, model: &str) -> String {
+        let (publisher, method) = if is_gemini(model) {
+            ("google", "generateContent")
+        } else {
+            ("anthropic", "rawPredict")
+        };
+        format!(
+            "{}/v1/projects/{}/locations/{}/publishers/{}/models/{}:{}",
+            self.base_url,
+            self.project,
+            self.region,
+            publisher,
+            model_id(model),
+            method
+        )
+    }
+}
+
+fn is_gemini(model: &str) -> bool {
+    model.starts_with("gemini-")
+}
+
+/// Vertex AI names Claude models with an `@` before the date, so
+/// `claude-haiku-4-5-20251001` becomes `claude-haiku-4-5@20251001`.
+fn model_id(model: &str) -> String {
+    match model.rsplit_once('-') {
+        Some((name, date))
+            if model.starts_with("claude-")
+                && date.len() == 8
+                && date.bytes().all(|byte| byte.is_ascii_digit()) =>
+        {
+            format!("{}@{}", name, date)
+        }
+        _ => model.to_string(),
+    }
+}
+
+impl Provider for Vertex {
+    /// Gemini can't continue a reply from a trailing assistant message.
+    fn capabilities(&self, model: &str) -> Capabilities {
+        Capabilities {
+            prefill: !is_gemini(model),
+            images: true,
+            tool_choice: true,
+        }
+    }
+
+    fn convert_request(&self, request: &MessageRequest) -> ProviderResult<serde_json::Value> {
+        if is_gemini(&request.model) {
+            return Ok(gemini::convert_request(request));
+        }
+        // The model is in the URL instead.
+        let mut body = serde_json::to_value(request)?;
+        if let Some(body) = body.as_object_mut() {
+            body.remove("model");
+            body.insert("anthropic_version".to_string(), ANTHROPIC_VERSION.into());
+        }
+        Ok(body)
+    }
+
+    fn convert_response(&self, body: serde_json::Value) -> ProviderResult<MessageResponse> {
+        if body.get("candidates").is_some() || body.get("promptFeedback").is_some() {
+            return Ok(gemini::convert_response(body)?);
+        }
+        Ok(serde_json::from_value(body)?)
+    }
+
+    async fn run_inference(&self, request: &MessageRequest) -> ProviderResult<MessageResponse> {
+        let body = self.convert_request(request)?;
+        let token = self.auth.token(&self.client).await?;
+        let mut builder = self
+            .client
+            .post(self.endpoint(&request.model))
+            .bearer_auth(token)
+            .json(&body);
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+        let response = builder.send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await?;
+            return Err(format!("API error: {} - {}", status, error_text).into());
+        }
+        let mut response = self.convert_response(response.json().await?)?;
+        if response.model.is_empty() {
+            response.model = request.model.clone();
+        }
+        Ok(response)
+    }
+
+    /// Any HTTP response counts.
+    #[cfg(feature = "server")]
+    async fn reachable(&self) -> bool {
+        self.client
+            .head(format!("{}/", self.base_url))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_sdk::{MessageParam, UserMessage};
+
+    #[test]
+    fn claude_requests_name_the_model_in_the_url() {
+        let vertex = Vertex::new(
+            "my-project",
+            "us-east5",
+            "https://us-east5-aiplatform.googleapis.com",
+            None,
+        );
+        assert_eq!(
+            vertex.endpoint("claude-haiku-4-5-20251001"),
+            "https://us-east5-aiplatform.googleapis.com/v1/projects/my-project/locations/us-east5/publishers/anthropic/models/claude-haiku-4-5@20251001:rawPredict"
+        );
+        assert!(vertex
+            .endpoint("gemini-2.5-pro")
+            .ends_with("/publishers/google/models/gemini-2.5-pro:generateContent"));
+        assert_eq!(
+            model_id("claude-sonnet-4-5@20250929"),
+            "claude-sonnet-4-5@20250929"
+        );
+
+        let request = MessageRequest {
+            model: "claude-haiku-4-5-20251001".to_string(),
+            max_tokens: 4096,
+            system: None,
+            messages: vec![MessageParam::User(UserMessage::from_text("Hi".to_string()))],
+            tools: Vec::new(),
+            temperature: None,
+            tool_choice: None,
+        };
+        let body = vertex.convert_request(&request).unwrap();
+        assert!(body.get("model").is_none());
+        assert_eq!(body["anthropic_version"], ANTHROPIC_VERSION);
+        assert!(!vertex.capabilities("gemini-2.5-pro").prefill);
+        assert!(vertex.capabilities("claude-haiku-4-5-20251001").prefill);
+    }
+}
@@ -0,0 +1,211 @@
+use base64::Engine;
+use reqwest::Client;
+use ring::rand::SystemRandom;
+use ring::signature::{RsaKeyPair, RSA_PKCS1_SHA256};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+use crate::ai_sdk::ProviderResult;
+
+const SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+const TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+/// A token this close to expiring is replaced before use.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+const NO_CREDENTIALS: &str = "No Google credentials found for Vertex AI. Run `gcloud auth application-default login`, set GOOGLE_APPLICATION_CREDENTIALS, or set `credentials` under [vertex] in ~/.tars/config.toml.";
+
+/// OAuth access tokens for Google APIs, from the first of Application
+/// Default Credentials' sources that has any. A token is reused until it is
+/// about to expire.
+pub(crate) struct GoogleAuth {
+    /// A key file from the config, ahead of the usual places.
+    credentials: Option<PathBuf>,
+    token: Mutex<Option<AccessToken>>,
+}
+
+struct AccessToken {
+    value: String,
+    expires: Instant,
+}
+
+/// The key files Google tools write.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum KeyFile {
+    ServiceAccount {
+        client_email: String,
+        private_key: String,
+        #[serde(default = "default_token_uri")]
+        token_uri: String,
+    },
+    /// What `gcloud auth application-default login` saves.
+    AuthorizedUser {
+        client_id: String,
+        client_secret: String,
+        refresh_token: String,
+    },
+}
+
+fn default_token_uri() -> String {
+    TOKEN_URI.to_string()
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+impl GoogleAuth {
+    pub(crate) fn new(credentials: Option<PathBuf>) -> Self {
+        Self {
+            credentials,
+            token: Mutex::new(None),
+        }
+    }
+
+    /// A bearer token for the `cloud-platform` scope.
+    pub(crate) async fn token(&self, client: &Client) -> ProviderResult<String> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = &*token
+            && token.expires > Instant::now() + EXPIRY_MARGIN
+        {
+            return Ok(token.value.clone());
+        }
+        let response = match self.key_file() {
+            Some(path) => {
+                let contents = std::fs::read_to_string(&path)
+                    .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+                let key: KeyFile = serde_json::from_str(&contents).map_err(|err| {
+                    format!(
+                        "{}: not a service account key or authorized user file ({})",
+                        path.display(),
+                        err
+                    )
+                })?;
+                exchange(client, key).await?
+            }
+            None => from_metadata_server(client).await?,
+        };
+        let value = response.access_token.clone();
+        *token = Some(AccessToken {
+            value: response.access_token,
+            expires: Instant::now() + Duration::from_secs(response.expires_in),
+        });
+        Ok(value)
+    }
+
+    /// The configured key file, else `GOOGLE_APPLICATION_CREDENTIALS`, else
+    /// gcloud's, if it exists.
+    fn key_file(&self) -> Option<PathBuf> {
+        if let Some(path) = &self.credentials {
+            return Some(path.clone());
+        }
+        if let Some(path) =
+            std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS").filter(|path| !path.is_empty())
+        {
+            return Some(PathBuf::from(path));
+        }
+        let gcloud = match std::env::var_os("CLOUDSDK_CONFIG") {
+            Some(dir) => PathBuf::from(dir),
+            None if cfg!(windows) => PathBuf::from(std::env::var_os("APPDATA")?).join("gcloud"),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config/gcloud"),
+        };
+        Some(gcloud.join("application_default_credentials.json")).filter(|path| path.exists())
+    }
+}
+
+/// Trades a key file for an access token at Google's token endpoint.
+async fn exchange(client: &Client, key: KeyFile) -> ProviderResult<TokenResponse> {
+    let request = match key {
+        KeyFile::ServiceAccount {
+            client_email,
+            private_key,
+            token_uri,
+        } => {
+            let assertion = signed_assertion(&client_email, &private_key, &token_uri)?;
+            client.post(token_uri).form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", assertion.as_str()),
+            ])
+        }
+        KeyFile::AuthorizedUser {
+            client_id,
+            client_secret,
+            refresh_token,
+        } => client.post(TOKEN_URI).form(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", client_id.as_str()),
+            ("client_secret", client_secret.as_str()),
+            ("refresh_token", refresh_token.as_str()),
+        ]),
+    };
+    let response = request.timeout(Duration::from_secs(30)).send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await?;
+        return Err(format!("Google sign-in failed: {} - {}", status, error_text).into());
+    }
+    Ok(response.json().await?)
+}
+
+/// The token of the service account attached to the VM, on Google Cloud.
+async fn from_metadata_server(client: &Client) -> ProviderResult<TokenResponse> {
+    let response = client
+        .get(METADATA_TOKEN_URL)
+        .header("Metadata-Flavor", "Google")
+        .timeout(Duration::from_secs(3))
+        .send()
+        .await
+        .map_err(|_| NO_CREDENTIALS)?;
+    if !response.status().is_success() {
+        return Err(NO_CREDENTIALS.into());
+    }
+    Ok(response.json().await?)
+}
+
+/// A JWT asking for a token for the service account, signed with its key.
+fn signed_assertion(
+    client_email: &str,
+    private_key: &str,
+    token_uri: &str,
+) -> ProviderResult<String> {
+    let base64 = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let issued_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let header = serde_json::json!({"alg": "RS256", "typ": "JWT"});
+    let claims = serde_json::json!({
+        "iss": client_email,
+        "scope": SCOPE,
+        "aud": token_uri,
+        "iat": issued_at,
+        "exp": issued_at + 3600,
+    });
+    let message = format!(
+        "{}.{}",
+        base64.encode(header.to_string()),
+        base64.encode(claims.to_string())
+    );
+
+    let der: String = private_key
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .map(str::trim)
+        .collect();
+    let der = base64::engine::general_purpose::STANDARD
+        .decode(der)
+        .map_err(|err| format!("The service account's private key is not PEM: {}", err))?;
+    let key = RsaKeyPair::from_pkcs8(&der)
+        .map_err(|err| format!("The service account's private key was rejected: {}", err))?;
+    let mut signature = vec![0; key.public().modulus_len()];
+    key.sign(
+        &RSA_PKCS1_SHA256,
+        &SystemRandom::new(),
+        message.as_bytes(),
+        &mut signature,
+    )
+    .map_err(|_| "Could not sign with the service account's private key")?;
+    Ok(format!("{}.{}", message, base64.encode(signature)))
+}
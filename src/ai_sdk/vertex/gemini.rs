@@ -0,0 +1,290 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::ai_sdk::{
+    ContentBlock, MessageParam, MessageRequest, MessageResponse, ResponseContentBlock, StopReason,
+    ToolChoice, Usage,
+};
+
+/// The `generateContent` body for `request`. Gemini doesn't give its
+/// function calls ids, so a tool result names the tool of the call it
+/// answers instead.
+pub(super) fn convert_request(request: &MessageRequest) -> Value {
+    let mut tools_by_id = HashMap::new();
+    let contents: Vec<Value> = request
+        .messages
+        .iter()
+        .map(|message| {
+            let (role, content) = match message {
+                MessageParam::User(message) => ("user", message.content()),
+                MessageParam::Assistant(message) => ("model", message.content()),
+            };
+            let mut parts = Vec::new();
+            for block in content {
+                match block {
+                    ContentBlock::Text { text } if text.is_empty() => {}
+                    ContentBlock::Text { text } => parts.push(json!({"text": text})),
+                    ContentBlock::ToolUse { id, name, input } => {
+                        tools_by_id.insert(id.as_str(), name.as_str());
+                        parts.push(json!({"functionCall": {"name": name, "args": input}}));
+                    }
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        is_error,
+                    } => {
+                        let key = if *is_error == Some(true) {
+                            "error"
+                        } else {
+                            "content"
+                        };
+                        parts.push(json!({"functionResponse": {
+                            "name": tools_by_id.get(tool_use_id.as_str()).copied().unwrap_or_default(),
+                            "response": {key: content.text()},
+                        }}));
+                        for image in content.images() {
+                            parts.push(json!({"inlineData": {
+                                "mimeType": image.media_type,
+                                "data": image.data,
+                            }}));
+                        }
+                    }
+                }
+            }
+            json!({"role": role, "parts": parts})
+        })
+        .collect();
+
+    let mut generation_config = json!({"maxOutputTokens": request.max_tokens});
+    if let Some(temperature) = request.temperature {
+        generation_config["temperature"] = json!(temperature);
+    }
+    let mut body = json!({
+        "contents": contents,
+        "generationConfig": generation_config,
+    });
+    if let Some(system) = &request.system {
+        body["systemInstruction"] = json!({"parts": [{"text": system}]});
+    }
+    if !request.tools.is_empty() {
+        let declarations: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parametersJsonSchema": tool.input_schema,
+                })
+            })
+            .collect();
+        body["tools"] = json!([{"functionDeclarations": declarations}]);
+    }
+    if let Some(choice) = &request.tool_choice {
+        let config = match choice {
+            ToolChoice::Auto => json!({"mode": "AUTO"}),
+            ToolChoice::Any => json!({"mode": "ANY"}),
+            ToolChoice::Tool { name } => json!({"mode": "ANY", "allowedFunctionNames": [name]}),
+            ToolChoice::None => json!({"mode": "NONE"}),
+        };
+        body["toolConfig"] = json!({"functionCallingConfig": config});
+    }
+    body
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentResponse {
+    #[serde(default)]
+    response_id: String,
+    #[serde(default)]
+    model_version: String,
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    #[serde(default)]
+    usage_metadata: UsageMetadata,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    #[serde(default)]
+    content: Option<Content>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Part {
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    function_call: Option<FunctionCall>,
+    /// Set on the model's thinking, which isn't part of the reply.
+    #[serde(default)]
+    thought: bool,
+}
+
+#[derive(Deserialize)]
+struct FunctionCall {
+    name: String,
+    #[serde(default)]
+    args: Value,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+}
+
+/// The reply in a `generateContent` response, with ids made up for its
+/// function calls. A prompt Gemini blocked is a refusal with no content.
+pub(super) fn convert_response(body: Value) -> serde_json::Result<MessageResponse> {
+    let response: GenerateContentResponse = serde_json::from_value(body)?;
+    let candidate = response.candidates.into_iter().next();
+    let finish_reason = candidate
+        .as_ref()
+        .and_then(|candidate| candidate.finish_reason.clone());
+    let content: Vec<ResponseContentBlock> = candidate
+        .and_then(|candidate| candidate.content)
+        .map(|content| content.parts)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|part| !part.thought)
+        .filter_map(|part| match (part.function_call, part.text) {
+            (Some(call), _) => Some(ResponseContentBlock::ToolUse {
+                id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+                name: call.name,
+                input: if call.args.is_null() {
+                    json!({})
+                } else {
+                    call.args
+                },
+            }),
+            (None, Some(text)) => Some(ResponseContentBlock::Text { text }),
+            (None, None) => None,
+        })
+        .collect();
+    let calls_tools = content
+        .iter()
+        .any(|block| matches!(block, ResponseContentBlock::ToolUse { .. }));
+    let stop_reason = match finish_reason.as_deref() {
+        _ if calls_tools => StopReason::ToolUse,
+        Some("STOP") => StopReason::EndTurn,
+        Some("MAX_TOKENS") => StopReason::MaxTokens,
+        Some("SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII") | None => {
+            StopReason::Refusal
+        }
+        Some(_) => StopReason::Other,
+    };
+    Ok(MessageResponse {
+        id: response.response_id,
+        content,
+        stop_reason: Some(stop_reason),
+        model: response.model_version,
+        usage: Usage {
+            input_tokens: response.usage_metadata.prompt_token_count,
+            output_tokens: response.usage_metadata.candidates_token_count,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_sdk::{AssistantMessage, ImageSource, ToolDefinitionApi, UserMessage};
+
+    #[test]
+    fn tool_calls_round_trip_through_gemini_parts() {
+        let request = MessageRequest {
+            model: "gemini-2.5-pro".to_string(),
+            max_tokens: 4096,
+            system: Some("A map of the workspace".to_string()),
+            messages: vec![
+                MessageParam::User(UserMessage::from_text("Take a look".to_string())),
+                MessageParam::Assistant(AssistantMessage::new(vec![ContentBlock::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "screenshot".to_string(),
+                    input: json!({}),
+                }])),
+                MessageParam::User(UserMessage::new(vec![
+                    ContentBlock::tool_result_with_images(
+                        "call_1".to_string(),
+                        "Screenshot".to_string(),
+                        vec![ImageSource::base64("image/png", b"png")],
+                        false,
+                    ),
+                ])),
+            ],
+            tools: vec![ToolDefinitionApi {
+                name: "screenshot".to_string(),
+                description: "Take a screenshot".to_string(),
+                input_schema: json!({"type": "object", "properties": {}}),
+            }],
+            temperature: None,
+            tool_choice: Some(ToolChoice::Tool {
+                name: "screenshot".to_string(),
+            }),
+        };
+        let body = convert_request(&request);
+        assert_eq!(
+            body["systemInstruction"],
+            json!({"parts": [{"text": "A map of the workspace"}]})
+        );
+        assert_eq!(body["contents"][1]["role"], "model");
+        assert_eq!(
+            body["contents"][2]["parts"],
+            json!([
+                {"functionResponse": {"name": "screenshot", "response": {"content": "Screenshot"}}},
+                {"inlineData": {"mimeType": "image/png", "data": "cG5n"}},
+            ])
+        );
+        assert_eq!(
+            body["toolConfig"],
+            json!({"functionCallingConfig": {"mode": "ANY", "allowedFunctionNames": ["screenshot"]}})
+        );
+        assert_eq!(
+            body["tools"][0]["functionDeclarations"][0]["name"],
+            "screenshot"
+        );
+
+        let response = convert_response(json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"text": "Planning", "thought": true},
+                    {"text": "Reading it."},
+                    {"functionCall": {"name": "read_file", "args": {"path": "README.md"}}},
+                ]},
+                "finishReason": "STOP",
+            }],
+            "usageMetadata": {"promptTokenCount": 12, "candidatesTokenCount": 5},
+            "modelVersion": "gemini-2.5-pro",
+            "responseId": "resp_1",
+        }))
+        .unwrap();
+        assert_eq!(response.stop_reason, Some(StopReason::ToolUse));
+        assert_eq!(response.usage.input_tokens, 12);
+        assert!(matches!(
+            &response.content[..],
+            [
+                ResponseContentBlock::Text { text },
+                ResponseContentBlock::ToolUse { name, input, .. },
+            ] if text == "Reading it." && name == "read_file" && input["path"] == "README.md"
+        ));
+
+        let blocked = convert_response(json!({"promptFeedback": {"blockReason": "SAFETY"}}));
+        assert_eq!(blocked.unwrap().stop_reason, Some(StopReason::Refusal));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::ai_sdk::{ContentBlock, MessageRequest, ToolDefinitionApi};
+use crate::conversation::Conversation;
+use crate::tools::{get_all_tools, ToolContext, ToolDefinition};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+type BenchResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+pub struct BenchConfig {
+    pub iterations: usize,
+    pub files: usize,
+}
+
+/// Measures tool execution latency against a generated fixture tree and the
+/// per-iteration overhead of the agent loop (snapshotting, truncation and
+/// request serialization), printing a table of timings.
+pub async fn run(config: BenchConfig) -> BenchResult<()> {
+    let workload = Workload::new(config.files)?;
+    println!(
+        "{:<28} {:>10} {:>10} {:>10} {:>10}",
+        "benchmark", "min", "mean", "p50", "p95"
+    );
+
+    let samples = time_async(config.iterations, || workload.read_file()).await?;
+    print_row("read_file", &samples);
+    let samples = time_async(config.iterations, || workload.list_files()).await?;
+    print_row("list_files", &samples);
+    let samples = time_async(config.iterations, || workload.search_files()).await?;
+    print_row("search_files", &samples);
+    let samples = time_sync(config.iterations, || workload.serialize_request())?;
+    print_row("request (200 turns)", &samples);
+    let samples = time_sync(config.iterations, || {
+        Ok::<_, std::convert::Infallible>(workload.truncate())
+    })?;
+    print_row("truncate (200 turns, 8k)", &samples);
+
+    Ok(())
+}
+
+/// What `tars bench` times: the file tools against a fixture tree, and
+/// serializing and truncating a long conversation. The criterion benchmarks
+/// in `benches/tools.rs` time the same operations.
+pub struct Workload {
+    fixture: Fixture,
+    tools: Vec<ToolDefinition>,
+    tools_api: Vec<ToolDefinitionApi>,
+    conversation: Conversation,
+}
+
+impl Workload {
+    /// Generates a fixture tree of `files` source files and a 200-turn
+    /// conversation.
+    pub fn new(files: usize) -> BenchResult<Self> {
+        let tools = get_all_tools();
+        let tools_api = tools
+            .iter()
+            .map(|t| ToolDefinitionApi {
+                name: t.name.to_string(),
+                description: t.description.to_string(),
+                input_schema: t.input_schema.clone(),
+            })
+            .collect();
+        Ok(Self {
+            fixture: Fixture::create(files)?,
+            tools,
+            tools_api,
+            conversation: sample_conversation(200),
+        })
+    }
+
+    pub async fn read_file(&self) -> BenchResult<String> {
+        let path = self.fixture.root().join("dir_0/file_0.rs");
+        self.call("read_file", serde_json::json!({ "path": path }))
+            .await
+    }
+
+    pub async fn list_files(&self) -> BenchResult<String> {
+        let path = self.fixture.root().join("dir_0");
+        self.call("list_files", serde_json::json!({ "path": path }))
+            .await
+    }
+
+    /// Searches the whole tree for a pattern one line in every file matches.
+    pub async fn search_files(&self) -> BenchResult<String> {
+        let path = self.fixture.root();
+        self.call(
+            "search_files",
+            serde_json::json!({ "pattern": "fn example_7\\b", "path": path }),
+        )
+        .await
+    }
+
+    /// Serializes a request carrying the whole conversation and every tool,
+    /// returning its size in bytes.
+    pub fn serialize_request(&self) -> BenchResult<usize> {
+        let request = MessageRequest {
+            model: "bench".to_string(),
+            max_tokens: 4096,
+            system: None,
+            messages: self.conversation.truncated(usize::MAX),
+            tools: self.tools_api.clone(),
+            temperature: None,
+            tool_choice: None,
+        };
+        Ok(serde_json::to_vec(&request)?.len())
+    }
+
+    /// Trims the conversation to an 8k-token budget, returning how many
+    /// messages are kept.
+    pub fn truncate(&self) -> usize {
+        self.conversation.truncated(8_192).len()
+    }
+
+    async fn call(&self, name: &str, input: serde_json::Value) -> BenchResult<String> {
+        let tool = self
+            .tools
+            .iter()
+            .find(|tool| tool.name == name)
+            .ok_or_else(|| format!("tool {} not registered", name))?;
+        (tool.handler)(input, ToolContext::default()).await
+    }
+}
+
+async fn time_async<F, Fut, T, E>(iterations: usize, mut f: F) -> BenchResult<Vec<Duration>>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    let mut samples = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f().await.map_err(Into::into)?;
+        samples.push(start.elapsed());
+    }
+    Ok(samples)
+}
+
+fn time_sync<F, T, E>(iterations: usize, mut f: F) -> BenchResult<Vec<Duration>>
+where
+    F: FnMut() -> Result<T, E>,
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    let mut samples = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        std::hint::black_box(f().map_err(Into::into)?);
+        samples.push(start.elapsed());
+    }
+    Ok(samples)
+}
+
+fn print_row(name: &str, samples: &[Duration]) {
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+    let mean = sorted.iter().sum::<Duration>() / sorted.len().max(1) as u32;
+    println!(
+        "{:<28} {:>10.2?} {:>10.2?} {:>10.2?} {:>10.2?}",
+        name,
+        sorted[0],
+        mean,
+        percentile(50),
+        percentile(95)
+    );
+}
+
+fn sample_conversation(turns: usize) -> Conversation {
+    let mut conversation = Conversation::new();
+    for i in 0..turns {
+        let _ = conversation.begin_turn(format!("Question {} about the codebase", i));
+        conversation.push_exchange(
+            vec![ContentBlock::ToolUse {
+                id: format!("tool_{}", i),
+                name: "read_file".to_string(),
+                input: serde_json::json!({ "path": format!("src/file_{}.rs", i) }),
+            }],
+            vec![ContentBlock::tool_result(
+                format!("tool_{}", i),
+                "fn main() {}\n".repeat(50),
+                false,
+            )],
+        );
+        conversation.push_exchange(
+            vec![ContentBlock::Text {
+                text: "Here is what I found.".to_string(),
+            }],
+            Vec::new(),
+        );
+        conversation.finish_turn();
+    }
+    conversation
+}
+
+/// A temporary directory tree removed on drop.
+struct Fixture {
+    root: PathBuf,
+}
+
+impl Fixture {
+    fn create(files: usize) -> BenchResult<Self> {
+        let root = std::env::temp_dir().join(format!("tars-bench-{}", uuid::Uuid::new_v4()));
+        let fixture = Self { root };
+        let per_dir = 100;
+        for i in 0..files {
+            let dir = fixture.root.join(format!("dir_{}", i / per_dir));
+            if i.is_multiple_of(per_dir) {
+                std::fs::create_dir_all(&dir)?;
+            }
+            std::fs::write(
+                dir.join(format!("file_{}.rs", i % per_dir)),
+                (0..20)
+                    .map(|n| format!("pub fn example_{}() -> u32 {{\n    {}\n}}\n", n, n))
+                    .collect::<String>(),
+            )?;
+        }
+        Ok(fixture)
+    }
+
+    fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_workload_runs_against_its_fixture() {
+        let workload = Workload::new(150).unwrap();
+        let root = workload.fixture.root().to_path_buf();
+        assert!(root.join("dir_1/file_49.rs").is_file());
+        assert!(!root.join("dir_1/file_50.rs").exists());
+
+        assert!(workload.read_file().await.unwrap().contains("example_19"));
+        assert!(workload.list_files().await.unwrap().contains("file_99.rs"));
+        let matches = workload.search_files().await.unwrap();
+        assert!(matches.contains("file_0.rs"));
+        assert!(!matches.contains("example_17"));
+        assert!(workload.serialize_request().unwrap() > 0);
+
+        drop(workload);
+        assert!(!root.exists());
+    }
+}
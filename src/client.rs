@@ -1,21 +1,37 @@
-use crate::protocol::{SendMessageRequest, SessionCreateResponse, StreamEvent};
-use futures::StreamExt;
+use crate::ai_sdk::MessageParam;
+use crate::config;
+use crate::protocol::{
+    ApprovalRequest, ArchiveResponse, ArtifactInfo, ArtifactListResponse, ComparedAnswer,
+    DescribeRequest, DescribeResponse, EventEnvelope, FileDiff, FilePreviewResponse, ForkRequest,
+    HealthResponse, PinResponse, ResponseStats, RewindResponse, SendMessageRequest,
+    SendMessageResponse, SessionCreateRequest, SessionCreateResponse, SessionListResponse,
+    SessionStatsResponse, StreamEvent, TodoItem, TurnActivity, UsageResponse,
+    WorkspaceFilesResponse,
+};
+use crate::sse::SseDecoder;
+use futures::{stream, Stream, StreamExt};
 use reqwest::Client as HttpClient;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::future::Future;
 use std::path::PathBuf;
+use std::pin::pin;
 
 pub struct ClientConfig {
     pub base_url: String,
     pub token: String,
+    pub http: HttpClient,
+    /// Attached to every session this client creates.
+    pub labels: BTreeMap<String, String>,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct ClientSession {
     base_url: String,
     token: String,
     session_id: String,
     http: HttpClient,
+    labels: BTreeMap<String, String>,
 }
 
 type ClientResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
@@ -33,29 +49,77 @@ pub fn resolve_token(explicit: Option<String>) -> ClientResult<String> {
 
 impl ClientSession {
     pub async fn connect(config: ClientConfig) -> ClientResult<Self> {
+        let (session, _) = Self::open(config, None).await?;
+        Ok(session)
+    }
+
+    /// Continues a session saved on the server, `last` being the most
+    /// recent, and returns its history with it.
+    pub async fn resume(
+        config: ClientConfig,
+        session_id: &str,
+    ) -> ClientResult<(Self, Vec<MessageParam>)> {
+        Self::open(config, Some(session_id)).await
+    }
+
+    async fn open(
+        config: ClientConfig,
+        resume: Option<&str>,
+    ) -> ClientResult<(Self, Vec<MessageParam>)> {
         let base_url = normalize_base_url(&config.base_url);
-        let http = HttpClient::new();
+        let created = create_session(
+            &config.http,
+            &base_url,
+            &config.token,
+            &config.labels,
+            resume,
+        )
+        .await?;
 
-        let response = http
-            .post(format!("{}/sessions", base_url))
-            .bearer_auth(&config.token)
+        let session = Self {
+            base_url,
+            token: config.token,
+            session_id: created.session_id,
+            http: config.http,
+            labels: config.labels,
+        };
+        Ok((session, created.messages))
+    }
+
+    /// Starts another session on the same server with the same credentials.
+    pub async fn new_session(&self) -> ClientResult<Self> {
+        let created =
+            create_session(&self.http, &self.base_url, &self.token, &self.labels, None).await?;
+        Ok(Self {
+            session_id: created.session_id,
+            ..self.clone()
+        })
+    }
+
+    /// A handle to another session on the same server.
+    pub fn attach(&self, session_id: String) -> Self {
+        Self {
+            session_id,
+            ..self.clone()
+        }
+    }
+
+    /// The live sessions this client's token can see.
+    pub async fn list_sessions(&self) -> ClientResult<SessionListResponse> {
+        let response = self
+            .http
+            .get(format!("{}/sessions", self.base_url))
+            .bearer_auth(&self.token)
             .send()
             .await?;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            return Err(format!("Failed to create session: {} - {}", status, body).into());
+            return Err(format!("Failed to list sessions: {} - {}", status, body).into());
         }
 
-        let body: SessionCreateResponse = response.json().await?;
-
-        Ok(Self {
-            base_url,
-            token: config.token,
-            session_id: body.session_id,
-            http,
-        })
+        Ok(response.json().await?)
     }
 
     pub fn base_url(&self) -> &str {
@@ -66,8 +130,9 @@ impl ClientSession {
         &self.session_id
     }
 
-    pub async fn send_message(&self, content: String) -> ClientResult<()> {
-        let request = SendMessageRequest { content };
+    /// Starts a turn and returns its id; the turn's events follow on the
+    /// stream.
+    pub async fn send_message(&self, request: SendMessageRequest) -> ClientResult<String> {
         let response = self
             .http
             .post(format!(
@@ -85,18 +150,116 @@ impl ClientSession {
             return Err(format!("Failed to send message: {} - {}", status, body).into());
         }
 
+        let body: SendMessageResponse = response.json().await?;
+        Ok(body.turn_id)
+    }
+
+    /// Stops the running turn; its `Cancelled` and `Done` events follow on
+    /// the stream.
+    pub async fn cancel(&self) -> ClientResult<()> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/sessions/{}/cancel",
+                self.base_url, self.session_id
+            ))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            return Err("No turn is running".into());
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to cancel: {} - {}", status, body).into());
+        }
+
+        Ok(())
+    }
+
+    /// Answers the approval the call `tool_use_id` is waiting for.
+    pub async fn answer_approval(
+        &self,
+        tool_use_id: &str,
+        request: ApprovalRequest,
+    ) -> ClientResult<()> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/sessions/{}/approvals/{}",
+                self.base_url, self.session_id, tool_use_id
+            ))
+            .bearer_auth(&self.token)
+            .json(&request)
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(format!("No tool call {} is waiting for approval", tool_use_id).into());
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to answer the approval: {} - {}", status, body).into());
+        }
+
+        Ok(())
+    }
+
+    /// Lets the session call `tool`, one of the tools that need approval,
+    /// without asking again.
+    pub async fn allow_tool(&self, tool: &str) -> ClientResult<()> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/sessions/{}/allow/{}",
+                self.base_url, self.session_id, tool
+            ))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(format!("{} isn't a tool that needs approval", tool).into());
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to allow {}: {} - {}", tool, status, body).into());
+        }
+
         Ok(())
     }
 
-    pub async fn stream_events<F, Fut>(&self, mut on_event: F) -> ClientResult<()>
-    where
-        F: FnMut(StreamEvent) -> Fut,
-        Fut: Future<Output = ()>,
-    {
+    pub async fn pin_last_message(&self) -> ClientResult<PinResponse> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/sessions/{}/pin",
+                self.base_url, self.session_id
+            ))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to pin message: {} - {}", status, body).into());
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// The files in the server's workspace, marked with what this session's
+    /// agent read and changed.
+    pub async fn workspace_files(&self) -> ClientResult<WorkspaceFilesResponse> {
         let response = self
             .http
             .get(format!(
-                "{}/sessions/{}/stream",
+                "{}/sessions/{}/files",
                 self.base_url, self.session_id
             ))
             .bearer_auth(&self.token)
@@ -106,55 +269,519 @@ impl ClientSession {
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            return Err(format!("Failed to open stream: {} - {}", status, body).into());
+            return Err(format!("Failed to list files: {} - {}", status, body).into());
         }
 
-        let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
+        Ok(response.json().await?)
+    }
 
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            let chunk = String::from_utf8_lossy(&chunk);
-            if chunk.contains('\r') {
-                buffer.push_str(&chunk.replace("\r\n", "\n"));
-            } else {
-                buffer.push_str(&chunk);
+    /// The contents of a workspace file, `path` being relative to the
+    /// server's working directory.
+    pub async fn preview_file(&self, path: &str) -> ClientResult<FilePreviewResponse> {
+        let mut url = reqwest::Url::parse(&format!(
+            "{}/sessions/{}/files",
+            self.base_url, self.session_id
+        ))?;
+        url.path_segments_mut()
+            .map_err(|_| "Invalid server URL")?
+            .extend(path.split('/'));
+        let response = self.http.get(url).bearer_auth(&self.token).send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to open {}: {} - {}", path, status, body).into());
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Copies this session's history, up to message `up_to` if given, into a
+    /// new session and returns a handle to it.
+    pub async fn fork(&self, up_to: Option<usize>) -> ClientResult<Self> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/sessions/{}/fork",
+                self.base_url, self.session_id
+            ))
+            .bearer_auth(&self.token)
+            .json(&ForkRequest { up_to })
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to fork session: {} - {}", status, body).into());
+        }
+
+        let body: SessionCreateResponse = response.json().await?;
+        Ok(Self {
+            session_id: body.session_id,
+            ..self.clone()
+        })
+    }
+
+    /// Removes the last turn and returns its prompt.
+    pub async fn rewind(&self) -> ClientResult<RewindResponse> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/sessions/{}/rewind",
+                self.base_url, self.session_id
+            ))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err("Nothing to rewind yet".into());
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to rewind: {} - {}", status, body).into());
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Has the server write a commit message or pull request description
+    /// for the changes in its working directory.
+    pub async fn describe(&self, request: DescribeRequest) -> ClientResult<DescribeResponse> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/sessions/{}/describe",
+                self.base_url, self.session_id
+            ))
+            .bearer_auth(&self.token)
+            .json(&request)
+            .send()
+            .await?;
+
+        // The server explains what it couldn't describe, e.g. that nothing
+        // is staged.
+        if response.status() == reqwest::StatusCode::UNPROCESSABLE_ENTITY {
+            return Err(response.text().await.unwrap_or_default().into());
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to describe changes: {} - {}", status, body).into());
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Saves this session on the server and closes it.
+    pub async fn archive(&self) -> ClientResult<ArchiveResponse> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/sessions/{}/archive",
+                self.base_url, self.session_id
+            ))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to archive session: {} - {}", status, body).into());
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// The files this session saved with `save_artifact`.
+    pub async fn artifacts(&self) -> ClientResult<Vec<ArtifactInfo>> {
+        let response = self
+            .http
+            .get(format!(
+                "{}/sessions/{}/artifacts",
+                self.base_url, self.session_id
+            ))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to list artifacts: {} - {}", status, body).into());
+        }
+
+        let body: ArtifactListResponse = response.json().await?;
+        Ok(body.artifacts)
+    }
+
+    /// Asks the server whether it can reach the model API.
+    pub async fn health(&self) -> ClientResult<HealthResponse> {
+        let response = self
+            .http
+            .get(format!("{}/health", self.base_url))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Health check failed: {} - {}", status, body).into());
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// API usage and tool calls since the server started, as far as this
+    /// client's token may see.
+    pub async fn usage(&self) -> ClientResult<UsageResponse> {
+        usage(&self.http, &self.base_url, &self.token).await
+    }
+
+    /// What turn `turn` of the session changed, or the last turn without
+    /// one, as a git patch; `None` when it changed no files.
+    pub async fn turn_patch(&self, turn: Option<usize>) -> ClientResult<Option<String>> {
+        let response = self
+            .http
+            .get(format!(
+                "{}/sessions/{}/turns/{}/patch",
+                self.base_url,
+                self.session_id,
+                turn.map_or("last".to_string(), |turn| turn.to_string())
+            ))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        match response.status() {
+            reqwest::StatusCode::NO_CONTENT => Ok(None),
+            reqwest::StatusCode::NOT_FOUND => Err(match turn {
+                Some(turn) => format!("There is no turn {} in this session", turn).into(),
+                None => "The session has no turns yet".into(),
+            }),
+            status if !status.is_success() => {
+                let body = response.text().await.unwrap_or_default();
+                Err(format!("Failed to get the patch: {} - {}", status, body).into())
             }
+            _ => Ok(Some(response.text().await?)),
+        }
+    }
 
-            while let Some(idx) = buffer.find("\n\n") {
-                let raw_event = buffer[..idx].to_string();
-                buffer = buffer[idx + 2..].to_string();
+    /// The session's transcript as Markdown; `None` before its first
+    /// message.
+    pub async fn export(&self) -> ClientResult<Option<String>> {
+        let response = self
+            .http
+            .get(format!(
+                "{}/sessions/{}/export",
+                self.base_url, self.session_id
+            ))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
 
-                if let Some(data) = extract_sse_data(&raw_event) {
-                    if let Ok(event) = serde_json::from_str::<StreamEvent>(&data) {
-                        on_event(event).await;
-                    }
-                }
+        match response.status() {
+            reqwest::StatusCode::NO_CONTENT => Ok(None),
+            status if !status.is_success() => {
+                let body = response.text().await.unwrap_or_default();
+                Err(format!("Failed to export the session: {} - {}", status, body).into())
             }
+            _ => Ok(Some(response.text().await?)),
+        }
+    }
+
+    /// How this session's tool calls have gone.
+    pub async fn stats(&self) -> ClientResult<SessionStatsResponse> {
+        let response = self
+            .http
+            .get(format!(
+                "{}/sessions/{}/stats",
+                self.base_url, self.session_id
+            ))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to get session stats: {} - {}", status, body).into());
         }
 
+        Ok(response.json().await?)
+    }
+
+    /// The session's events as they arrive. The stream ends when the server
+    /// closes it, and yields an error if it cannot be opened or breaks off.
+    pub fn events(&self) -> impl Stream<Item = ClientResult<EventEnvelope>> + Send + 'static {
+        stream::once(open_stream(self.stream_request())).flat_map(|opened| match opened {
+            Ok(envelopes) => envelopes.left_stream(),
+            Err(err) => stream::iter([Err(err)]).right_stream(),
+        })
+    }
+
+    /// Like `events`, but returns only once the server has subscribed this
+    /// client, so no event emitted afterwards is missed.
+    pub async fn subscribe(
+        &self,
+    ) -> ClientResult<impl Stream<Item = ClientResult<EventEnvelope>> + Send + 'static> {
+        open_stream(self.stream_request()).await
+    }
+
+    fn stream_request(&self) -> reqwest::RequestBuilder {
+        self.http
+            .get(format!(
+                "{}/sessions/{}/stream",
+                self.base_url, self.session_id
+            ))
+            .bearer_auth(&self.token)
+    }
+
+    /// Feeds the session's events to `handler` until the stream ends.
+    pub async fn handle_events<H: EventHandler>(&self, handler: &mut H) -> ClientResult<()> {
+        let mut events = pin!(self.events());
+        while let Some(envelope) = events.next().await {
+            let envelope = envelope?;
+            match envelope.event {
+                StreamEvent::Assistant { text, stats } => handler.on_assistant(text, stats).await,
+                StreamEvent::ToolCall {
+                    tool_use_id,
+                    name,
+                    input,
+                } => handler.on_tool_call(tool_use_id, name, input).await,
+                StreamEvent::ToolResult {
+                    tool_use_id,
+                    name,
+                    content,
+                    is_error,
+                } => {
+                    handler
+                        .on_tool_result(tool_use_id, name, content, is_error)
+                        .await
+                }
+                StreamEvent::ApprovalNeeded {
+                    tool_use_id,
+                    name,
+                    summary,
+                } => handler.on_approval_needed(tool_use_id, name, summary).await,
+                StreamEvent::Info { message } => handler.on_info(message).await,
+                StreamEvent::Warning { message } => handler.on_warning(message).await,
+                StreamEvent::Error { message } => handler.on_error(message).await,
+                StreamEvent::Offline { message } => handler.on_offline(message).await,
+                StreamEvent::Heartbeat {
+                    activity,
+                    elapsed_ms,
+                } => handler.on_heartbeat(activity, elapsed_ms).await,
+                StreamEvent::Plan { steps, stats } => handler.on_plan(steps, stats).await,
+                StreamEvent::TodoUpdate { items } => handler.on_todo_update(items).await,
+                StreamEvent::DiffUpdate { files } => handler.on_diff_update(files).await,
+                StreamEvent::Comparison { answers } => handler.on_comparison(answers).await,
+                StreamEvent::Cancelled => handler.on_cancelled().await,
+                StreamEvent::Done => handler.on_done().await,
+            }
+        }
         Ok(())
     }
 }
 
-fn normalize_base_url(value: &str) -> String {
-    value.trim_end_matches('/').to_string()
+/// Receives a session's events one kind at a time through
+/// `ClientSession::handle_events`. Every method ignores its event unless
+/// overridden.
+pub trait EventHandler: Send {
+    fn on_assistant(
+        &mut self,
+        _text: String,
+        _stats: Option<ResponseStats>,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    fn on_tool_call(
+        &mut self,
+        _tool_use_id: String,
+        _name: String,
+        _input: serde_json::Value,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    fn on_tool_result(
+        &mut self,
+        _tool_use_id: String,
+        _name: String,
+        _content: String,
+        _is_error: bool,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// A call waits until it is answered with
+    /// `ClientSession::answer_approval`.
+    fn on_approval_needed(
+        &mut self,
+        _tool_use_id: String,
+        _name: String,
+        _summary: String,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    fn on_info(&mut self, _message: String) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    fn on_warning(&mut self, _message: String) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    fn on_error(&mut self, _message: String) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// The turn was rolled back because the model API was unreachable.
+    fn on_offline(&mut self, _message: String) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// The turn is still running; see `StreamEvent::Heartbeat`.
+    fn on_heartbeat(
+        &mut self,
+        _activity: TurnActivity,
+        _elapsed_ms: u64,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// A profile's planner drafted the turn's steps.
+    fn on_plan(
+        &mut self,
+        _steps: Vec<String>,
+        _stats: ResponseStats,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// The agent's plan changed; `items` is the whole list.
+    fn on_todo_update(&mut self, _items: Vec<TodoItem>) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// A tool changed files; `files` is the session's whole diff.
+    fn on_diff_update(&mut self, _files: Vec<FileDiff>) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Each model's answer to a `/compare` prompt.
+    fn on_comparison(&mut self, _answers: Vec<ComparedAnswer>) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// The turn was stopped before it finished; `on_done` follows.
+    fn on_cancelled(&mut self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// The turn is over.
+    fn on_done(&mut self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
 }
 
-fn extract_sse_data(raw: &str) -> Option<String> {
-    let mut data_lines = Vec::new();
-    for line in raw.lines() {
-        let line = line.trim_end_matches('\r');
-        if let Some(data) = line.strip_prefix("data:") {
-            data_lines.push(data.trim_start().to_string());
-        }
+async fn open_stream(
+    request: reqwest::RequestBuilder,
+) -> ClientResult<impl Stream<Item = ClientResult<EventEnvelope>> + Send + 'static> {
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to open stream: {} - {}", status, body).into());
+    }
+    Ok(sse_envelopes(response.bytes_stream()))
+}
+
+/// Decodes a server-sent event byte stream into the envelopes it carries,
+/// skipping events that do not parse.
+fn sse_envelopes<S, B>(bytes: S) -> impl Stream<Item = ClientResult<EventEnvelope>> + Send
+where
+    S: Stream<Item = reqwest::Result<B>> + Send + Unpin,
+    B: AsRef<[u8]>,
+{
+    bytes
+        .map(|chunk| chunk.map_err(Into::into))
+        .scan(SseDecoder::new(), |decoder, chunk: ClientResult<B>| {
+            let items: Vec<ClientResult<EventEnvelope>> = match chunk {
+                Ok(chunk) => decoder
+                    .push(chunk.as_ref())
+                    .into_iter()
+                    .filter(|event| event.event == "message")
+                    .filter_map(|event| serde_json::from_str(&event.data).ok())
+                    .map(Ok)
+                    .collect(),
+                Err(err) => vec![Err(err)],
+            };
+            futures::future::ready(Some(stream::iter(items)))
+        })
+        .flatten()
+}
+
+/// API usage and tool calls since the server at `base_url` started, without
+/// opening a session.
+pub async fn usage(http: &HttpClient, base_url: &str, token: &str) -> ClientResult<UsageResponse> {
+    let response = http
+        .get(format!("{}/usage", normalize_base_url(base_url)))
+        .bearer_auth(token)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to get usage: {} - {}", status, body).into());
     }
 
-    if data_lines.is_empty() {
-        None
-    } else {
-        Some(data_lines.join("\n"))
+    Ok(response.json().await?)
+}
+
+async fn create_session(
+    http: &HttpClient,
+    base_url: &str,
+    token: &str,
+    labels: &BTreeMap<String, String>,
+    resume: Option<&str>,
+) -> ClientResult<SessionCreateResponse> {
+    let response = http
+        .post(format!("{}/sessions", base_url))
+        .bearer_auth(token)
+        .json(&SessionCreateRequest {
+            labels: labels.clone(),
+            resume: resume.map(str::to_string),
+        })
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(match (resume, status) {
+            (Some("last"), reqwest::StatusCode::NOT_FOUND) => {
+                "There is no saved session to resume".into()
+            }
+            (Some(id), reqwest::StatusCode::NOT_FOUND) => {
+                format!("There is no saved session {}", id).into()
+            }
+            _ => format!("Failed to create session: {} - {}", status, body).into(),
+        });
     }
+
+    Ok(response.json().await?)
+}
+
+fn normalize_base_url(value: &str) -> String {
+    value.trim_end_matches('/').to_string()
 }
 
 fn read_token_file() -> ClientResult<String> {
@@ -164,9 +791,112 @@ fn read_token_file() -> ClientResult<String> {
 }
 
 fn token_path() -> PathBuf {
-    if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
-        return PathBuf::from(home).join(".tars").join("server.token");
+    config::tars_dir().join("server.token")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::EVENT_VERSION;
+    use crate::test_support::serve;
+
+    /// Notes each event it is given.
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl EventHandler for Log {
+        async fn on_tool_call(
+            &mut self,
+            tool_use_id: String,
+            name: String,
+            _input: serde_json::Value,
+        ) {
+            self.0.push(format!("call {} {}", tool_use_id, name));
+        }
+
+        async fn on_tool_result(
+            &mut self,
+            tool_use_id: String,
+            _name: String,
+            content: String,
+            is_error: bool,
+        ) {
+            self.0
+                .push(format!("result {} {} {}", tool_use_id, content, is_error));
+        }
+
+        async fn on_done(&mut self) {
+            self.0.push("done".to_string());
+        }
     }
 
-    PathBuf::from("tars.token")
+    fn envelope(seq: u64, event: StreamEvent) -> String {
+        let envelope = EventEnvelope {
+            version: EVENT_VERSION,
+            seq,
+            timestamp_ms: 0,
+            session_id: "s1".to_string(),
+            turn_id: None,
+            event,
+        };
+        format!(
+            "id: {}\ndata: {}\n\n",
+            seq,
+            serde_json::to_string(&envelope).unwrap()
+        )
+    }
+
+    #[tokio::test]
+    async fn handlers_get_each_event_by_kind() {
+        let body = [
+            envelope(
+                1,
+                StreamEvent::ToolCall {
+                    tool_use_id: "call_1".to_string(),
+                    name: "bash".to_string(),
+                    input: serde_json::json!({"command": "ls"}),
+                },
+            ),
+            ": keep-alive\n\ndata: not an envelope\n\n".to_string(),
+            envelope(
+                2,
+                StreamEvent::Info {
+                    message: "unhandled".to_string(),
+                },
+            ),
+            envelope(
+                3,
+                StreamEvent::ToolResult {
+                    tool_use_id: "call_1".to_string(),
+                    name: "bash".to_string(),
+                    content: "Cargo.toml".to_string(),
+                    is_error: false,
+                },
+            ),
+            envelope(4, StreamEvent::Done),
+        ]
+        .concat();
+        let base_url = serve(move |request| {
+            if request.starts_with("GET /sessions/s1/stream ") {
+                (200, body.clone())
+            } else {
+                (404, String::new())
+            }
+        })
+        .await;
+        let session = ClientSession {
+            base_url,
+            token: "token".to_string(),
+            session_id: "s1".to_string(),
+            http: HttpClient::new(),
+            labels: BTreeMap::new(),
+        };
+
+        let mut log = Log::default();
+        session.handle_events(&mut log).await.unwrap();
+        assert_eq!(
+            log.0,
+            ["call call_1 bash", "result call_1 Cargo.toml false", "done"]
+        );
+    }
 }
@@ -0,0 +1,67 @@
+use std::time::SystemTime;
+use time::{OffsetDateTime, UtcOffset};
+
+/// The current local date and time as `YYYY-MM-DD HH:MM`.
+pub fn now() -> String {
+    format_minute(local_now())
+}
+
+/// A local time as `YYYY-MM-DD HH:MM`, such as a file's modification time.
+pub fn format_local(time: SystemTime) -> String {
+    let time = OffsetDateTime::from(time);
+    format_minute(time.to_offset(local_offset(time.unix_timestamp())))
+}
+
+/// The current time in the local time zone.
+pub fn local_now() -> OffsetDateTime {
+    let now = OffsetDateTime::now_utc();
+    now.to_offset(local_offset(now.unix_timestamp()))
+}
+
+fn format_minute(time: OffsetDateTime) -> String {
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}",
+        time.year(),
+        u8::from(time.month()),
+        time.day(),
+        time.hour(),
+        time.minute()
+    )
+}
+
+/// The UTC offset in effect at `timestamp`.
+///
+/// `time` declines to look this up once other threads exist, since
+/// `localtime_r` races with `setenv`. tars never changes its environment
+/// after startup, so asking the C library directly is fine.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd"
+))]
+fn local_offset(timestamp: i64) -> UtcOffset {
+    let mut tm = std::mem::MaybeUninit::<libc::tm>::uninit();
+    let timestamp = timestamp as libc::time_t;
+    // Safety: localtime_r only writes to `tm`, and returns null on failure,
+    // in which case `tm` is never read.
+    let tm = unsafe {
+        if libc::localtime_r(&timestamp, tm.as_mut_ptr()).is_null() {
+            return UtcOffset::UTC;
+        }
+        tm.assume_init()
+    };
+    UtcOffset::from_whole_seconds(tm.tm_gmtoff as i32).unwrap_or(UtcOffset::UTC)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd"
+)))]
+fn local_offset(_timestamp: i64) -> UtcOffset {
+    UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC)
+}
@@ -0,0 +1,348 @@
+/// A slash command typed into the TUI input.
+#[derive(Debug, PartialEq)]
+pub enum SlashCommand {
+    /// Pin the last message, or send a new message pinned.
+    Pin(Option<String>),
+    /// Seed the start of the next reply, or clear it when empty.
+    Prefill(Option<String>),
+    /// Archive the session and continue in a fresh one.
+    New,
+    /// Open a fresh session in a new tab, keeping this one running.
+    Tab,
+    /// Close the current tab; its session stays on the server.
+    Close,
+    /// Continue in a copy of the session, optionally cut before a message
+    /// index, leaving the original as it was.
+    Fork(Option<usize>),
+    /// Drop the last turn and send its prompt again.
+    Retry,
+    /// Drop the last turn and load its prompt into the input for editing.
+    Edit,
+    /// Mark the start of a new topic in the scrollback.
+    Topic(String),
+    /// Send a message that must be answered without tools.
+    Chat(String),
+    /// Ask each of the configured models the same thing, with read-only
+    /// tools, and show the answers side by side.
+    Compare(String),
+    /// Write a commit message for the staged changes, or with `pr`, a pull
+    /// request description against `base`.
+    Describe { pr: bool, base: Option<String> },
+    /// Send a message whose first reply must call the named tool, or any
+    /// tool when no name is given.
+    Tool {
+        name: Option<String>,
+        message: String,
+    },
+    /// Send a message for the named profile's planner to plan and its
+    /// executor to carry out.
+    Plan { profile: String, message: String },
+    /// List the files the session saved with `save_artifact`.
+    Artifacts,
+    /// Let the session use a tool that asks first, such as
+    /// `read_clipboard`.
+    Allow(String),
+    /// Show how tool calls have gone in this session and on the server.
+    Stats,
+    /// Copy what a turn changed, the last one unless given, as a git
+    /// patch.
+    Patch(Option<usize>),
+    /// Copy the transcript as Markdown, or write it to a file when given a
+    /// path.
+    Export(Option<String>),
+    /// Send the next messages to another model, or an alias such as
+    /// `sonnet`; show the current one when no name is given.
+    Model(Option<String>),
+}
+
+/// A command's usage line and what it does.
+pub struct CommandHelp {
+    pub usage: &'static str,
+    pub summary: &'static str,
+}
+
+/// Every command, listed when an unknown one is typed and in the command
+/// palette.
+pub const COMMANDS: &[CommandHelp] = &[
+    CommandHelp {
+        usage: "/new",
+        summary: "Archive the session and start a fresh one",
+    },
+    CommandHelp {
+        usage: "/tab",
+        summary: "Open a fresh session in a new tab (Ctrl+N, as Ctrl+T toggles the plan)",
+    },
+    CommandHelp {
+        usage: "/close",
+        summary: "Close the current tab",
+    },
+    CommandHelp {
+        usage: "/fork [message index]",
+        summary: "Continue in a copy of the session",
+    },
+    CommandHelp {
+        usage: "/retry",
+        summary: "Drop the last turn and send its prompt again",
+    },
+    CommandHelp {
+        usage: "/edit",
+        summary: "Drop the last turn and edit its prompt",
+    },
+    CommandHelp {
+        usage: "/pin [message]",
+        summary: "Pin the last message, or send one pinned",
+    },
+    CommandHelp {
+        usage: "/prefill [text]",
+        summary: "Seed the start of the next reply",
+    },
+    CommandHelp {
+        usage: "/topic <title>",
+        summary: "Mark the start of a new topic",
+    },
+    CommandHelp {
+        usage: "/chat <message>",
+        summary: "Send a message answered without tools",
+    },
+    CommandHelp {
+        usage: "/compare <message>",
+        summary: "Ask the configured models and compare answers",
+    },
+    CommandHelp {
+        usage: "/describe [pr [base]]",
+        summary: "Write a commit message or PR description",
+    },
+    CommandHelp {
+        usage: "/tool <name|any> <message>",
+        summary: "Send a message that must call a tool",
+    },
+    CommandHelp {
+        usage: "/plan <profile> <message>",
+        summary: "Have a profile's planner plan and its executor do it",
+    },
+    CommandHelp {
+        usage: "/artifacts",
+        summary: "List the files the session saved",
+    },
+    CommandHelp {
+        usage: "/allow <tool>",
+        summary: "Stop asking to approve a tool in this session",
+    },
+    CommandHelp {
+        usage: "/stats",
+        summary: "Show calls, errors and latency per tool",
+    },
+    CommandHelp {
+        usage: "/patch [turn]",
+        summary: "Copy a turn's changes as a git patch",
+    },
+    CommandHelp {
+        usage: "/export [path]",
+        summary: "Copy the transcript as Markdown, or save it",
+    },
+    CommandHelp {
+        usage: "/model [name|haiku|sonnet|opus]",
+        summary: "Switch models for the next messages",
+    },
+];
+
+/// Parses `input` as a slash command.
+///
+/// Returns `None` when the input is an ordinary message, including ones that
+/// merely start with a path such as `/etc/hosts`.
+pub fn parse(input: &str) -> Option<Result<SlashCommand, String>> {
+    let rest = input.trim().strip_prefix('/')?;
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    let args = args.trim();
+
+    let command = match name {
+        "pin" => SlashCommand::Pin(non_empty(args)),
+        "prefill" => SlashCommand::Prefill(non_empty(unquote(args))),
+        "new" => SlashCommand::New,
+        "tab" => SlashCommand::Tab,
+        "close" => SlashCommand::Close,
+        "fork" if args.is_empty() => SlashCommand::Fork(None),
+        "fork" => match args.parse() {
+            Ok(index) => SlashCommand::Fork(Some(index)),
+            Err(_) => return Some(Err("Usage: /fork [message index]".to_string())),
+        },
+        "retry" => SlashCommand::Retry,
+        "edit" => SlashCommand::Edit,
+        "topic" => match non_empty(args) {
+            Some(title) => SlashCommand::Topic(title),
+            None => return Some(Err("Usage: /topic <title>".to_string())),
+        },
+        "chat" => match non_empty(args) {
+            Some(message) => SlashCommand::Chat(message),
+            None => return Some(Err("Usage: /chat <message>".to_string())),
+        },
+        "compare" => match non_empty(args) {
+            Some(message) => SlashCommand::Compare(message),
+            None => return Some(Err("Usage: /compare <message>".to_string())),
+        },
+        "describe" => match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [] => SlashCommand::Describe {
+                pr: false,
+                base: None,
+            },
+            ["pr"] => SlashCommand::Describe {
+                pr: true,
+                base: None,
+            },
+            ["pr", base] => SlashCommand::Describe {
+                pr: true,
+                base: Some(base.to_string()),
+            },
+            _ => return Some(Err("Usage: /describe [pr [base]]".to_string())),
+        },
+        "tool" => match args.split_once(char::is_whitespace) {
+            Some((name, message)) if !message.trim().is_empty() => SlashCommand::Tool {
+                name: (name != "any").then(|| name.to_string()),
+                message: message.trim().to_string(),
+            },
+            _ => return Some(Err("Usage: /tool <name|any> <message>".to_string())),
+        },
+        "plan" => match args.split_once(char::is_whitespace) {
+            Some((profile, message)) if !message.trim().is_empty() => SlashCommand::Plan {
+                profile: profile.to_string(),
+                message: message.trim().to_string(),
+            },
+            _ => return Some(Err("Usage: /plan <profile> <message>".to_string())),
+        },
+        "artifacts" => SlashCommand::Artifacts,
+        "allow" => match non_empty(args) {
+            Some(tool) => SlashCommand::Allow(tool),
+            None => return Some(Err("Usage: /allow <tool>".to_string())),
+        },
+        "stats" => SlashCommand::Stats,
+        "patch" if args.is_empty() => SlashCommand::Patch(None),
+        "patch" => match args.parse() {
+            Ok(turn) => SlashCommand::Patch(Some(turn)),
+            Err(_) => return Some(Err("Usage: /patch [turn]".to_string())),
+        },
+        "export" => SlashCommand::Export(non_empty(args)),
+        "model" => SlashCommand::Model(non_empty(args)),
+        _ => {
+            return Some(Err(format!(
+                "Unknown command: /{} (available: {})",
+                name,
+                COMMANDS
+                    .iter()
+                    .map(|command| command.usage)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+    };
+    Some(Ok(command))
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Strips one pair of matching surrounding quotes, so `/prefill "```diff"`
+/// seeds the reply with the fence itself.
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|rest| rest.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_distinguishes_commands_from_paths() {
+        assert_eq!(parse("hello"), None);
+        assert_eq!(parse("/etc/hosts looks wrong"), None);
+        assert_eq!(parse("/pin"), Some(Ok(SlashCommand::Pin(None))));
+        assert_eq!(
+            parse("/prefill \"```diff\""),
+            Some(Ok(SlashCommand::Prefill(Some("```diff".to_string()))))
+        );
+        assert_eq!(
+            parse("/chat what does this crate do?"),
+            Some(Ok(SlashCommand::Chat(
+                "what does this crate do?".to_string()
+            )))
+        );
+        assert_eq!(
+            parse("/compare which map type fits?"),
+            Some(Ok(SlashCommand::Compare(
+                "which map type fits?".to_string()
+            )))
+        );
+        assert!(matches!(parse("/compare"), Some(Err(_))));
+        assert_eq!(
+            parse("/describe pr origin/main"),
+            Some(Ok(SlashCommand::Describe {
+                pr: true,
+                base: Some("origin/main".to_string()),
+            }))
+        );
+        assert!(matches!(parse("/describe main"), Some(Err(_))));
+        assert_eq!(
+            parse("/tool list_files what is here?"),
+            Some(Ok(SlashCommand::Tool {
+                name: Some("list_files".to_string()),
+                message: "what is here?".to_string(),
+            }))
+        );
+        assert_eq!(
+            parse("/tool any look around"),
+            Some(Ok(SlashCommand::Tool {
+                name: None,
+                message: "look around".to_string(),
+            }))
+        );
+        assert!(matches!(parse("/tool read_file"), Some(Err(_))));
+        assert_eq!(
+            parse("/plan review tighten the error handling"),
+            Some(Ok(SlashCommand::Plan {
+                profile: "review".to_string(),
+                message: "tighten the error handling".to_string(),
+            }))
+        );
+        assert_eq!(
+            parse("/allow read_clipboard"),
+            Some(Ok(SlashCommand::Allow("read_clipboard".to_string())))
+        );
+        assert!(matches!(parse("/allow"), Some(Err(_))));
+        assert_eq!(parse("/tab"), Some(Ok(SlashCommand::Tab)));
+        assert_eq!(parse("/stats"), Some(Ok(SlashCommand::Stats)));
+        assert_eq!(parse("/patch 3"), Some(Ok(SlashCommand::Patch(Some(3)))));
+        assert!(matches!(parse("/patch last"), Some(Err(_))));
+        assert_eq!(parse("/export"), Some(Ok(SlashCommand::Export(None))));
+        assert_eq!(
+            parse("/export notes/session.md"),
+            Some(Ok(SlashCommand::Export(Some(
+                "notes/session.md".to_string()
+            ))))
+        );
+        assert_eq!(parse("/model"), Some(Ok(SlashCommand::Model(None))));
+        assert_eq!(
+            parse("/model sonnet"),
+            Some(Ok(SlashCommand::Model(Some("sonnet".to_string()))))
+        );
+        assert_eq!(parse("/close"), Some(Ok(SlashCommand::Close)));
+        assert_eq!(parse("/fork 4"), Some(Ok(SlashCommand::Fork(Some(4)))));
+        assert!(matches!(parse("/fork last"), Some(Err(_))));
+        assert_eq!(parse("/artifacts"), Some(Ok(SlashCommand::Artifacts)));
+        assert!(matches!(parse("/nope"), Some(Err(_))));
+    }
+}
@@ -0,0 +1,691 @@
+use crate::ai_sdk::{AssistantMessage, ContentBlock, MessageParam, ToolChoice, UserMessage};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use uuid::Uuid;
+
+/// What an image costs at most, once the API has scaled it to fit within
+/// 1568 pixels.
+const IMAGE_TOKENS: usize = 1600;
+
+/// The transcript of a session, owned by the server and shared with the
+/// agent loop behind a single lock.
+///
+/// Updates follow a small protocol so the transcript can never interleave:
+/// `begin_turn` claims the conversation and records the user message,
+/// `push_exchange` appends an assistant reply together with its tool
+/// results, and `finish_turn` releases the claim. Only the holder of the
+/// turn appends, and every append happens in a single critical section.
+///
+/// Pinned messages are indices into `messages` whose turns are always
+/// retained when the history is truncated to fit a context window. Turn ids
+/// are keyed the same way, by the index of the message that starts the turn.
+///
+/// Reminders are notes for the model alone, such as "the user interrupted
+/// you". They ride along with the next request and are never recorded.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Conversation {
+    messages: Vec<MessageParam>,
+    pinned: BTreeSet<usize>,
+    turn_ids: BTreeMap<usize, String>,
+    prefill: Option<String>,
+    tool_choice: Option<ToolChoice>,
+    reminders: Vec<String>,
+    turn_active: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct TurnInProgress;
+
+impl std::fmt::Display for TurnInProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a turn is already in progress")
+    }
+}
+
+impl std::error::Error for TurnInProgress {}
+
+impl Conversation {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// A conversation continuing from saved `messages`, with `turn_ids`
+    /// keyed by the index of the message each turn starts at.
+    #[cfg(feature = "server")]
+    pub(crate) fn restore(messages: Vec<MessageParam>, turn_ids: BTreeMap<usize, String>) -> Self {
+        Self {
+            messages,
+            turn_ids,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn messages(&self) -> &[MessageParam] {
+        &self.messages
+    }
+
+    /// Starts a turn with the user's message and returns the new turn's id.
+    pub(crate) fn begin_turn(&mut self, text: String) -> Result<String, TurnInProgress> {
+        if self.turn_active {
+            return Err(TurnInProgress);
+        }
+        self.turn_active = true;
+        self.prefill = None;
+        self.tool_choice = None;
+        let turn_id = Uuid::new_v4().to_string();
+        self.turn_ids.insert(self.messages.len(), turn_id.clone());
+        self.messages
+            .push(MessageParam::User(UserMessage::from_text(text)));
+        Ok(turn_id)
+    }
+
+    /// The id of the turn that message `index` belongs to. Messages loaded
+    /// from transcripts written before turns had ids have none.
+    pub(crate) fn turn_id(&self, index: usize) -> Option<&str> {
+        self.turn_ids
+            .range(..=index)
+            .next_back()
+            .map(|(_, turn_id)| turn_id.as_str())
+    }
+
+    /// How many turns the transcript holds.
+    #[cfg(feature = "server")]
+    pub(crate) fn turn_count(&self) -> usize {
+        turn_starts(&self.messages).len()
+    }
+
+    /// The id and prompt of turn `number`, counting from 1, when it has an
+    /// id.
+    #[cfg(feature = "server")]
+    pub(crate) fn turn(&self, number: usize) -> Option<(&str, String)> {
+        let start = *turn_starts(&self.messages).get(number.checked_sub(1)?)?;
+        let prompt = match &self.messages[start] {
+            MessageParam::User(user) => text_content(user.content()),
+            MessageParam::Assistant(_) => String::new(),
+        };
+        Some((self.turn_ids.get(&start)?.as_str(), prompt))
+    }
+
+    pub(crate) fn push_exchange(
+        &mut self,
+        assistant: Vec<ContentBlock>,
+        tool_results: Vec<ContentBlock>,
+    ) {
+        debug_assert!(self.turn_active, "exchange pushed outside of a turn");
+        self.prefill = None;
+        self.tool_choice = None;
+        self.messages
+            .push(MessageParam::Assistant(AssistantMessage::new(assistant)));
+        if !tool_results.is_empty() {
+            self.messages
+                .push(MessageParam::User(UserMessage::new(tool_results)));
+        }
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn turn_active(&self) -> bool {
+        self.turn_active
+    }
+
+    pub(crate) fn finish_turn(&mut self) {
+        self.turn_active = false;
+    }
+
+    /// Ends a turn that produced nothing, removing its user message so the
+    /// prompt can be sent again later. Returns false, leaving the turn in
+    /// place, once any reply has been recorded.
+    #[cfg(feature = "server")]
+    pub(crate) fn abandon_turn(&mut self) -> bool {
+        let untouched = self.turn_active
+            && turn_starts(&self.messages).last() == Some(&(self.messages.len() - 1));
+        if untouched {
+            self.messages.pop();
+            self.pinned.remove(&self.messages.len());
+            self.turn_ids.remove(&self.messages.len());
+            self.prefill = None;
+            self.tool_choice = None;
+            self.turn_active = false;
+        }
+        untouched
+    }
+
+    /// Removes the most recent turn, prompt and replies alike, so it can be
+    /// sent again. Returns the prompt text and whether it was pinned, or
+    /// `None` when there is no turn or one is still running.
+    #[cfg(feature = "server")]
+    pub(crate) fn rewind_last_turn(&mut self) -> Option<(String, bool)> {
+        if self.turn_active {
+            return None;
+        }
+        let start = *turn_starts(&self.messages).last()?;
+        let text = match &self.messages[start] {
+            MessageParam::User(user) => text_content(user.content()),
+            MessageParam::Assistant(_) => return None,
+        };
+        self.messages.truncate(start);
+        self.turn_ids.split_off(&start);
+        let pinned = self.pinned.remove(&start);
+        Some((text, pinned))
+    }
+
+    /// A copy of the history up to message `up_to`, which must fall on a turn
+    /// boundary so no tool call is separated from its result. Without a
+    /// limit, everything but a turn still in progress is copied.
+    #[cfg(feature = "server")]
+    pub(crate) fn fork(&self, up_to: Option<usize>) -> Option<Conversation> {
+        let starts = turn_starts(&self.messages);
+        let end = match up_to {
+            Some(end) if end == self.messages.len() && !self.turn_active => end,
+            Some(end) if starts.contains(&end) => end,
+            Some(_) => return None,
+            None if self.turn_active => starts.last().copied().unwrap_or(0),
+            None => self.messages.len(),
+        };
+        Some(Conversation {
+            messages: self.messages[..end].to_vec(),
+            pinned: self.pinned.range(..end).copied().collect(),
+            turn_ids: self
+                .turn_ids
+                .range(..end)
+                .map(|(&index, turn_id)| (index, turn_id.clone()))
+                .collect(),
+            ..Conversation::default()
+        })
+    }
+
+    /// Seeds the start of the next assistant reply in the current turn.
+    pub(crate) fn set_prefill(&mut self, prefill: String) {
+        self.prefill = Some(prefill);
+    }
+
+    pub(crate) fn prefill(&self) -> Option<&str> {
+        self.prefill.as_deref()
+    }
+
+    /// Constrains tool use for the next assistant reply in the current turn.
+    #[cfg(feature = "server")]
+    pub(crate) fn set_tool_choice(&mut self, tool_choice: ToolChoice) {
+        self.tool_choice = Some(tool_choice);
+    }
+
+    pub(crate) fn tool_choice(&self) -> Option<&ToolChoice> {
+        self.tool_choice.as_ref()
+    }
+
+    /// Queues a note for the model to see with the next request only.
+    #[cfg(feature = "server")]
+    pub(crate) fn add_reminder(&mut self, text: String) {
+        self.reminders.push(text);
+    }
+
+    /// Drops queued reminders once a request carrying them has been built.
+    pub(crate) fn clear_reminders(&mut self) {
+        self.reminders.clear();
+    }
+
+    /// Pins the most recent user prompt and returns its text.
+    #[cfg(feature = "server")]
+    pub(crate) fn pin_last_user_message(&mut self) -> Option<String> {
+        let index = *turn_starts(&self.messages).last()?;
+        self.pinned.insert(index);
+        match &self.messages[index] {
+            MessageParam::User(user) => Some(text_content(user.content())),
+            MessageParam::Assistant(_) => None,
+        }
+    }
+
+    /// The history to send for the next request, trimmed to `budget` tokens.
+    ///
+    /// Queued reminders are appended to the last user message as
+    /// `<system-reminder>` blocks. A pending prefill is sent as a trailing
+    /// assistant message, which the model continues rather than starting a
+    /// fresh reply. Both count against the budget, as a prefill can be most
+    /// of a reply.
+    pub(crate) fn truncated(&self, budget: usize) -> Vec<MessageParam> {
+        let reminders: Vec<ContentBlock> = self
+            .reminders
+            .iter()
+            .map(|text| ContentBlock::Text {
+                text: format!("<system-reminder>\n{}\n</system-reminder>", text),
+            })
+            .collect();
+        // The API rejects assistant prefill that ends in whitespace.
+        let prefill = self.prefill.as_ref().map(|prefill| {
+            MessageParam::Assistant(AssistantMessage::new(vec![ContentBlock::Text {
+                text: prefill.trim_end().to_string(),
+            }]))
+        });
+        let added = serde_json::to_string(&reminders).map_or(0, |json| json.len()) / 4
+            + estimate_tokens(prefill.as_slice());
+
+        let mut messages =
+            truncate_to_budget(&self.messages, &self.pinned, budget.saturating_sub(added));
+        if !reminders.is_empty()
+            && let Some(MessageParam::User(user)) = messages.last_mut()
+        {
+            *user = UserMessage::new(user.content().iter().cloned().chain(reminders).collect());
+        }
+        messages.extend(prefill);
+        messages
+    }
+}
+
+/// Joins the text blocks of a message, ignoring tool calls and results.
+pub(crate) fn text_content(content: &[ContentBlock]) -> String {
+    content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Rough token estimate for a slice of messages, using the common heuristic
+/// of four bytes of serialized JSON per token. Images count as what the API
+/// charges for them rather than by the size of their data.
+pub(crate) fn estimate_tokens(messages: &[MessageParam]) -> usize {
+    let mut bytes = 0;
+    let mut images = 0;
+    for message in messages {
+        bytes += serde_json::to_string(message).map_or(0, |json| json.len());
+        let content = match message {
+            MessageParam::User(message) => message.content(),
+            MessageParam::Assistant(message) => message.content(),
+        };
+        for block in content {
+            if let ContentBlock::ToolResult { content, .. } = block {
+                for image in content.images() {
+                    bytes -= image.data.len();
+                    images += 1;
+                }
+            }
+        }
+    }
+    bytes / 4 + images * IMAGE_TOKENS
+}
+
+/// Drops the oldest turns until the conversation fits in `budget` tokens.
+///
+/// Turns are removed in exponentially growing batches (1, 2, 4, ...) so very
+/// long transcripts converge quickly, and a turn is never split: the result
+/// always starts with a user text message rather than an orphaned tool
+/// result. Turns containing a pinned message and the most recent turn are
+/// always kept, even if they alone exceed the budget.
+pub(crate) fn truncate_to_budget(
+    messages: &[MessageParam],
+    pinned: &BTreeSet<usize>,
+    budget: usize,
+) -> Vec<MessageParam> {
+    if estimate_tokens(messages) <= budget {
+        return messages.to_vec();
+    }
+
+    let turns = turn_ranges(messages);
+    let Some((first, _)) = turns.split_first() else {
+        return messages.to_vec();
+    };
+    let droppable: Vec<usize> = (0..turns.len() - 1)
+        .filter(|&turn| !turns[turn].clone().any(|index| pinned.contains(&index)))
+        .collect();
+
+    let keep = |dropped: usize| -> Vec<MessageParam> {
+        let removed = &droppable[..dropped];
+        // Anything before the first turn (e.g. a leading assistant message)
+        // is dropped along with it.
+        let preamble = if removed.first() == Some(&0) {
+            0..0
+        } else {
+            0..first.start
+        };
+        preamble
+            .chain(
+                turns
+                    .iter()
+                    .enumerate()
+                    .filter(|(turn, _)| !removed.contains(turn))
+                    .flat_map(|(_, range)| range.clone()),
+            )
+            .map(|index| messages[index].clone())
+            .collect()
+    };
+
+    let mut dropped = 0;
+    let mut step = 1;
+    while dropped < droppable.len() {
+        dropped = (dropped + step).min(droppable.len());
+        step *= 2;
+        if estimate_tokens(&keep(dropped)) <= budget {
+            break;
+        }
+    }
+
+    keep(dropped)
+}
+
+/// Splits a transcript into turns, each starting with a user prompt.
+pub(crate) fn split_turns(messages: &[MessageParam]) -> Vec<&[MessageParam]> {
+    turn_ranges(messages)
+        .into_iter()
+        .map(|range| &messages[range])
+        .collect()
+}
+
+fn turn_ranges(messages: &[MessageParam]) -> Vec<Range<usize>> {
+    let starts = turn_starts(messages);
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| start..starts.get(i + 1).copied().unwrap_or(messages.len()))
+        .collect()
+}
+
+fn turn_starts(messages: &[MessageParam]) -> Vec<usize> {
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| match message {
+            MessageParam::User(user) => user
+                .content()
+                .iter()
+                .any(|block| matches!(block, ContentBlock::Text { .. })),
+            MessageParam::Assistant(_) => false,
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn role(message: &MessageParam) -> &'static str {
+        match message {
+            MessageParam::User(_) => "user",
+            MessageParam::Assistant(_) => "assistant",
+        }
+    }
+
+    #[test]
+    fn begin_turn_rejects_overlapping_turns() {
+        let mut conversation = Conversation::new();
+        conversation.begin_turn("first".to_string()).unwrap();
+        assert_eq!(
+            conversation.begin_turn("second".to_string()),
+            Err(TurnInProgress)
+        );
+        assert_eq!(conversation.messages.len(), 1);
+
+        conversation.finish_turn();
+        conversation.begin_turn("second".to_string()).unwrap();
+        assert_eq!(conversation.messages.len(), 2);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn abandon_turn_only_removes_unanswered_prompts() {
+        let mut conversation = Conversation::new();
+        conversation.begin_turn("lost".to_string()).unwrap();
+        conversation.pin_last_user_message();
+        assert!(conversation.abandon_turn());
+        assert!(conversation.messages.is_empty());
+        assert!(conversation.pinned.is_empty());
+
+        conversation.begin_turn("answered".to_string()).unwrap();
+        conversation.push_exchange(
+            vec![ContentBlock::Text {
+                text: "partial".to_string(),
+            }],
+            Vec::new(),
+        );
+        assert!(!conversation.abandon_turn());
+        assert_eq!(conversation.messages.len(), 2);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn rewind_last_turn_removes_prompt_and_replies() {
+        let mut conversation = Conversation::new();
+        push_turn(&mut conversation, 0);
+        push_turn(&mut conversation, 1);
+        conversation.pin_last_user_message();
+
+        let first_turn = conversation.turn_id(0).unwrap().to_string();
+        assert_eq!(conversation.turn_id(3), Some(first_turn.as_str()));
+        assert_ne!(conversation.turn_id(4), Some(first_turn.as_str()));
+
+        let (text, pinned) = conversation.rewind_last_turn().unwrap();
+        assert!(text.starts_with("question 1"));
+        assert!(pinned);
+        assert_eq!(conversation.messages.len(), 4);
+        assert!(conversation.pinned.is_empty());
+        assert_eq!(conversation.turn_ids.len(), 1);
+
+        conversation.begin_turn("running".to_string()).unwrap();
+        assert_eq!(conversation.rewind_last_turn(), None);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn fork_copies_whole_turns_only() {
+        let mut conversation = Conversation::new();
+        push_turn(&mut conversation, 0);
+        conversation.pin_last_user_message();
+        push_turn(&mut conversation, 1);
+
+        let fork = conversation.fork(Some(4)).unwrap();
+        assert_eq!(fork.messages.len(), 4);
+        assert_eq!(fork.pinned, BTreeSet::from([0]));
+        assert!(conversation.fork(Some(2)).is_none());
+        assert_eq!(conversation.fork(None).unwrap().messages.len(), 8);
+
+        conversation.begin_turn("running".to_string()).unwrap();
+        assert_eq!(conversation.fork(None).unwrap().messages.len(), 8);
+        assert!(conversation.fork(Some(9)).is_none());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn tool_choice_applies_to_the_first_reply_only() {
+        let mut conversation = Conversation::new();
+        conversation
+            .begin_turn("what is here?".to_string())
+            .unwrap();
+        conversation.set_tool_choice(ToolChoice::Tool {
+            name: "list_files".to_string(),
+        });
+        assert_eq!(
+            serde_json::to_value(conversation.tool_choice()).unwrap(),
+            serde_json::json!({"type": "tool", "name": "list_files"})
+        );
+
+        conversation.push_exchange(
+            vec![ContentBlock::Text {
+                text: "Looking.".to_string(),
+            }],
+            Vec::new(),
+        );
+        assert_eq!(conversation.tool_choice(), None);
+
+        conversation.set_tool_choice(ToolChoice::None);
+        conversation.finish_turn();
+        conversation.begin_turn("and now?".to_string()).unwrap();
+        assert_eq!(conversation.tool_choice(), None);
+    }
+
+    #[tokio::test]
+    async fn concurrent_turns_keep_exchanges_ordered() {
+        let conversation = Arc::new(Mutex::new(Conversation::new()));
+        let mut handles = Vec::new();
+
+        for i in 0..8 {
+            let conversation = Arc::clone(&conversation);
+            handles.push(tokio::spawn(async move {
+                loop {
+                    if conversation
+                        .lock()
+                        .await
+                        .begin_turn(format!("message {}", i))
+                        .is_ok()
+                    {
+                        break;
+                    }
+                    tokio::task::yield_now().await;
+                }
+
+                tokio::task::yield_now().await;
+                let mut guard = conversation.lock().await;
+                guard.push_exchange(
+                    vec![ContentBlock::ToolUse {
+                        id: format!("tool_{}", i),
+                        name: "read_file".to_string(),
+                        input: serde_json::json!({}),
+                    }],
+                    vec![ContentBlock::tool_result(
+                        format!("tool_{}", i),
+                        "ok".to_string(),
+                        false,
+                    )],
+                );
+                guard.finish_turn();
+            }));
+        }
+
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let mut conversation = conversation.lock().await;
+        let roles: Vec<_> = conversation.messages.iter().map(role).collect();
+        assert_eq!(roles.len(), 24);
+        for chunk in roles.chunks(3) {
+            assert_eq!(chunk, ["user", "assistant", "user"]);
+        }
+        assert!(conversation.begin_turn("after".to_string()).is_ok());
+    }
+
+    fn push_turn(conversation: &mut Conversation, i: usize) {
+        conversation
+            .begin_turn(format!("question {} {}", i, "x".repeat(400)))
+            .unwrap();
+        conversation.push_exchange(
+            vec![ContentBlock::ToolUse {
+                id: format!("tool_{}", i),
+                name: "read_file".to_string(),
+                input: serde_json::json!({"path": "README.md"}),
+            }],
+            vec![ContentBlock::tool_result(
+                format!("tool_{}", i),
+                "y".repeat(400),
+                false,
+            )],
+        );
+        conversation.push_exchange(
+            vec![ContentBlock::Text {
+                text: "answer".to_string(),
+            }],
+            Vec::new(),
+        );
+        conversation.finish_turn();
+    }
+
+    fn conversation_with_turns(count: usize) -> Conversation {
+        let mut conversation = Conversation::new();
+        for i in 0..count {
+            push_turn(&mut conversation, i);
+        }
+        conversation
+    }
+
+    #[test]
+    fn truncate_to_budget_keeps_whole_recent_turns() {
+        let conversation = conversation_with_turns(20);
+        let messages = &conversation.messages;
+        let per_turn = estimate_tokens(&messages[..4]);
+
+        let truncated = truncate_to_budget(messages, &BTreeSet::new(), per_turn * 5);
+        assert!(estimate_tokens(&truncated) <= per_turn * 5);
+        assert!(truncated.len() >= 4);
+        assert_eq!(truncated.len() % 4, 0);
+        assert!(turn_starts(&truncated).first() == Some(&0));
+        assert_eq!(
+            serde_json::to_string(truncated.last().unwrap()).unwrap(),
+            serde_json::to_string(messages.last().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn truncate_to_budget_leaves_small_conversations_alone() {
+        let conversation = conversation_with_turns(2);
+        let truncated = conversation.truncated(usize::MAX);
+        assert_eq!(truncated.len(), conversation.messages.len());
+
+        let truncated = conversation.truncated(0);
+        assert_eq!(truncated.len(), 4);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn truncate_to_budget_retains_pinned_turns() {
+        let mut conversation = conversation_with_turns(1);
+        conversation
+            .begin_turn("remember this".to_string())
+            .unwrap();
+        assert_eq!(
+            conversation.pin_last_user_message().as_deref(),
+            Some("remember this")
+        );
+        conversation.finish_turn();
+        for i in 0..10 {
+            push_turn(&mut conversation, i);
+        }
+
+        let truncated = conversation.truncated(0);
+        assert_eq!(truncated.len(), 5);
+        match &truncated[0] {
+            MessageParam::User(user) => assert_eq!(text_content(user.content()), "remember this"),
+            _ => panic!("expected pinned user message first"),
+        }
+    }
+
+    #[test]
+    fn truncation_leaves_room_for_the_prefill() {
+        let mut conversation = conversation_with_turns(20);
+        let budget = estimate_tokens(&conversation.messages);
+        conversation.begin_turn("go on".to_string()).unwrap();
+        // About a whole reply at the default 4096-token limit.
+        conversation.set_prefill("z".repeat(4 * 4000));
+
+        let truncated = conversation.truncated(budget);
+        assert!(estimate_tokens(&truncated) <= budget);
+        assert!(truncated.len() < conversation.messages.len());
+        assert!(matches!(truncated.last(), Some(MessageParam::Assistant(_))));
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn reminders_reach_the_next_request_only() {
+        let prompt = |message: &MessageParam| match message {
+            MessageParam::User(user) => text_content(user.content()),
+            MessageParam::Assistant(_) => panic!("expected the prompt"),
+        };
+        let mut conversation = Conversation::new();
+        conversation.begin_turn("hello".to_string()).unwrap();
+        conversation.add_reminder("the user interrupted you".to_string());
+        conversation.set_prefill("Sure".to_string());
+
+        let truncated = conversation.truncated(usize::MAX);
+        assert_eq!(truncated.len(), 2);
+        assert_eq!(
+            prompt(&truncated[0]),
+            "hello\n<system-reminder>\nthe user interrupted you\n</system-reminder>"
+        );
+        assert_eq!(prompt(&conversation.messages()[0]), "hello");
+
+        conversation.clear_reminders();
+        assert_eq!(prompt(&conversation.truncated(usize::MAX)[0]), "hello");
+    }
+}
@@ -0,0 +1,141 @@
+use crate::clock;
+use crate::config::{self, ScheduleConfig};
+use crate::schedule::{self, Cron};
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::Write;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+type DaemonResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Runs the configured schedules until killed, starting each one as a
+/// `tars schedule run` child whenever its cron expression matches the
+/// current minute.
+///
+/// The config is read again every minute, so schedules added or removed
+/// with `tars schedule` apply without a restart. A schedule whose previous
+/// run is still going is skipped rather than started twice.
+pub async fn run() -> DaemonResult<()> {
+    let exe = std::env::current_exe()?;
+    let running: Arc<Mutex<HashSet<String>>> = Arc::default();
+    tracing::info!("daemon started");
+    loop {
+        tokio::time::sleep(until_next_minute()).await;
+        let now = clock::local_now();
+        let settings = match config::load() {
+            Ok(settings) => settings,
+            Err(err) => {
+                tracing::error!(error = %err, "could not read the config");
+                continue;
+            }
+        };
+        for schedule in settings.schedules {
+            match Cron::parse(&schedule.cron) {
+                Ok(cron) if cron.matches(now) => {}
+                Ok(_) => continue,
+                Err(err) => {
+                    tracing::warn!(schedule = %schedule.name, error = %err, "invalid cron expression");
+                    continue;
+                }
+            }
+            if !running.lock().unwrap().insert(schedule.name.clone()) {
+                tracing::warn!(schedule = %schedule.name, "previous run still going; skipped");
+                continue;
+            }
+            let exe = exe.clone();
+            let running = running.clone();
+            tokio::spawn(async move {
+                tracing::info!(schedule = %schedule.name, "run started");
+                match start(&exe, &schedule).await {
+                    Ok(()) => tracing::info!(schedule = %schedule.name, "run finished"),
+                    Err(err) => {
+                        tracing::error!(schedule = %schedule.name, error = %err, "run failed");
+                        notify(&schedule, &err.to_string()).await;
+                    }
+                }
+                running.lock().unwrap().remove(&schedule.name);
+            });
+        }
+    }
+}
+
+/// How long until the next minute starts, by the wall clock.
+fn until_next_minute() -> Duration {
+    let now = time::OffsetDateTime::now_utc();
+    let into_minute = Duration::new(now.second() as u64, now.nanosecond());
+    Duration::from_secs(60).saturating_sub(into_minute)
+}
+
+/// Runs one schedule in a child process, appending its output to the
+/// schedule's log. Fails with the last thing the run printed to stderr,
+/// which is the error `tars` exits with.
+async fn start(exe: &std::path::Path, schedule: &ScheduleConfig) -> DaemonResult<()> {
+    let path = schedule::log_path(&schedule.name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    writeln!(log, "=== {} ===", clock::now())?;
+
+    let mut child = tokio::process::Command::new(exe)
+        .args(["schedule", "run", &schedule.name])
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stderr = BufReader::new(child.stderr.take().expect("stderr is piped")).lines();
+    let mut last_error = None;
+    while let Some(line) = stderr.next_line().await? {
+        writeln!(log, "{}", line)?;
+        if !line.trim().is_empty() {
+            last_error = Some(line);
+        }
+    }
+    let status = child.wait().await?;
+    if status.success() {
+        return Ok(());
+    }
+    Err(last_error
+        .map(|line| exit_error(&line))
+        .unwrap_or_else(|| format!("tars exited with {}", status))
+        .into())
+}
+
+/// Unwraps the `Error: "..."` that `main` prints for the error it returns.
+fn exit_error(line: &str) -> String {
+    let Some(debug) = line.strip_prefix("Error: ") else {
+        return line.to_string();
+    };
+    serde_json::from_str(debug).unwrap_or_else(|_| debug.to_string())
+}
+
+/// Runs the schedule's `on_failure` command, if it has one.
+async fn notify(schedule: &ScheduleConfig, error: &str) {
+    let Some(command) = &schedule.on_failure else {
+        return;
+    };
+    let result = tokio::process::Command::new("sh")
+        .args(["-c", command])
+        .env("TARS_SCHEDULE", &schedule.name)
+        .env("TARS_ERROR", error)
+        .env("TARS_RUN_LOG", schedule::log_path(&schedule.name))
+        .stdin(Stdio::null())
+        .status()
+        .await;
+    match result {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            tracing::warn!(schedule = %schedule.name, %status, "on_failure command failed")
+        }
+        Err(err) => {
+            tracing::warn!(schedule = %schedule.name, error = %err, "could not run on_failure")
+        }
+    }
+}
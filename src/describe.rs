@@ -0,0 +1,221 @@
+use crate::agent::Agent;
+use crate::ai_sdk::Provider;
+use crate::config;
+use crate::key_ring::KeyRing;
+use crate::protocol::DescribeRequest;
+use std::error::Error;
+use std::path::Path;
+use std::process::Stdio;
+
+type DescribeResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Diffs longer than this are cut off before they are sent; the stat
+/// summary still covers every file.
+const MAX_DIFF_CHARS: usize = 60_000;
+/// Earlier commit subjects shown to the model so it follows the project's
+/// style.
+const STYLE_SAMPLES: &str = "10";
+
+const COMMIT_PROMPT: &str = "Write a git commit message for the staged changes below. \
+Start with a subject line of at most 72 characters in the imperative mood, matching the \
+style of the recent subjects. For anything beyond a trivial change, follow it with a blank \
+line and a short body, wrapped at 72 columns, saying what changed and why. Reply with the \
+message only, without code fences or commentary.";
+
+const PR_PROMPT: &str = "Write a pull request description for the branch below. Start with \
+a title line, then a blank line and a Markdown body that opens with what the change does and \
+why, followed by anything a reviewer should check. Don't list the files changed. Reply with \
+the description only, without code fences around it or commentary.";
+
+/// Writes a commit message or pull request description for `tars
+/// describe`, printed to stdout so it can be piped into `git commit -F -`.
+pub async fn run(request: DescribeRequest) -> DescribeResult<()> {
+    let settings = config::load()?;
+    let agent = Agent::from_config(&settings, KeyRing::from_config())?;
+    println!("{}", describe(&agent, &request, Path::new(".")).await?);
+    Ok(())
+}
+
+/// Asks the model to describe the staged changes of the repository at
+/// `dir`, or its current branch against the base, in one request without
+/// tools.
+pub(crate) async fn describe<P: Provider>(
+    agent: &Agent<P>,
+    request: &DescribeRequest,
+    dir: &Path,
+) -> DescribeResult<String> {
+    let prompt = if request.pr {
+        let base = match &request.base {
+            Some(base) => base.clone(),
+            None => default_base(dir).await,
+        };
+        let range = format!("{}...HEAD", base);
+        let diff = git(dir, &["diff", &range]).await?;
+        if diff.trim().is_empty() {
+            return Err(format!("No changes between {} and HEAD", base).into());
+        }
+        let commits = git(dir, &["log", "--format=- %s", &format!("{}..HEAD", base)]).await?;
+        let stat = git(dir, &["diff", "--stat", &range]).await?;
+        format!(
+            "{}\n\nCommits:\n{}\nFiles:\n{}\nDiff against {}:\n{}",
+            PR_PROMPT,
+            commits,
+            stat,
+            base,
+            truncate(&diff)
+        )
+    } else {
+        let diff = git(dir, &["diff", "--cached"]).await?;
+        if diff.trim().is_empty() {
+            return Err("No staged changes; stage some with `git add` first".into());
+        }
+        let stat = git(dir, &["diff", "--cached", "--stat"]).await?;
+        // A repository without commits has no history to sample.
+        let recent = git(dir, &["log", "-n", STYLE_SAMPLES, "--format=- %s"])
+            .await
+            .unwrap_or_default();
+        format!(
+            "{}\n\nRecent subjects:\n{}\nFiles:\n{}\nDiff:\n{}",
+            COMMIT_PROMPT,
+            recent,
+            stat,
+            truncate(&diff)
+        )
+    };
+    agent.complete(prompt).await
+}
+
+/// The remote's default branch, such as `origin/main`, or `main` when the
+/// remote doesn't say.
+async fn default_base(dir: &Path) -> String {
+    git(dir, &["rev-parse", "--abbrev-ref", "origin/HEAD"])
+        .await
+        .ok()
+        .map(|base| base.trim().to_string())
+        .filter(|base| !base.is_empty() && base != "origin/HEAD")
+        .unwrap_or_else(|| "main".to_string())
+}
+
+/// Runs git in `dir` and returns its output, or what it printed to stderr
+/// when it fails.
+async fn git(dir: &Path, args: &[&str]) -> DescribeResult<String> {
+    let output = tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|err| format!("Could not run git: {}", err))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git {} failed: {}", args.join(" "), stderr.trim()).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn truncate(diff: &str) -> String {
+    match diff.char_indices().nth(MAX_DIFF_CHARS) {
+        Some((end, _)) => format!("{}\n[diff truncated]", &diff[..end]),
+        None => diff.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_sdk::ResponseContentBlock;
+    use crate::test_support::{Scripted, TempDir};
+
+    /// Runs git in `dir` for the test's setup.
+    fn run_git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// A repository with one commit on `main`.
+    fn repository() -> TempDir {
+        let dir = TempDir::new("tars-describe");
+        run_git(&dir, &["init", "-q", "-b", "main"]);
+        std::fs::write(dir.join("README.md"), "# Demo\n").unwrap();
+        run_git(&dir, &["add", "README.md"]);
+        run_git(&dir, &["commit", "-q", "-m", "Add the readme"]);
+        dir
+    }
+
+    fn answering(text: &str) -> Agent<Scripted> {
+        Agent::with_provider(Scripted::new(vec![vec![ResponseContentBlock::Text {
+            text: text.to_string(),
+        }]]))
+    }
+
+    /// The text of the single prompt `agent` was sent.
+    fn prompt(agent: &Agent<Scripted>) -> String {
+        let requests = agent.provider().requests();
+        assert_eq!(requests.len(), 1);
+        requests[0]["messages"][0]["content"].to_string()
+    }
+
+    #[tokio::test]
+    async fn commit_messages_describe_the_staged_changes() {
+        let dir = repository();
+        std::fs::write(dir.join("hello.txt"), "hello\n").unwrap();
+        std::fs::write(dir.join("unstaged.txt"), "later\n").unwrap();
+        run_git(&dir, &["add", "hello.txt"]);
+
+        let agent = answering("Add a greeting");
+        let text = describe(&agent, &DescribeRequest::default(), &dir)
+            .await
+            .unwrap();
+        assert_eq!(text, "Add a greeting");
+        let prompt = prompt(&agent);
+        assert!(prompt.contains("- Add the readme"), "{}", prompt);
+        assert!(prompt.contains("+hello"), "{}", prompt);
+        assert!(!prompt.contains("unstaged.txt"), "{}", prompt);
+    }
+
+    #[tokio::test]
+    async fn pull_requests_describe_the_branch_against_its_base() {
+        let dir = repository();
+        run_git(&dir, &["checkout", "-q", "-b", "greeting"]);
+        std::fs::write(dir.join("hello.txt"), "hello\n").unwrap();
+        run_git(&dir, &["add", "hello.txt"]);
+        run_git(&dir, &["commit", "-q", "-m", "Add a greeting"]);
+
+        let agent = answering("Greet the reader");
+        let request = DescribeRequest {
+            pr: true,
+            base: None,
+        };
+        let text = describe(&agent, &request, &dir).await.unwrap();
+        assert_eq!(text, "Greet the reader");
+        let prompt = prompt(&agent);
+        assert!(prompt.contains("- Add a greeting"), "{}", prompt);
+        assert!(!prompt.contains("- Add the readme"), "{}", prompt);
+        assert!(prompt.contains("Diff against main"), "{}", prompt);
+        assert!(prompt.contains("+hello"), "{}", prompt);
+    }
+
+    #[tokio::test]
+    async fn nothing_to_describe_is_an_error() {
+        let dir = repository();
+        let agent = answering("unused");
+        let err = describe(&agent, &DescribeRequest::default(), &dir)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("No staged changes"));
+
+        let request = DescribeRequest {
+            pr: true,
+            base: Some("main".to_string()),
+        };
+        let err = describe(&agent, &request, &dir).await.unwrap_err();
+        assert_eq!(err.to_string(), "No changes between main and HEAD");
+        assert!(agent.provider().requests().is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::ai_sdk::{ContentBlock, MessageParam};
+use crate::conversation::split_turns;
+use crate::session_store;
+use crate::show::summarize;
+use crate::tools::get_all_tools;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+type ExportResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Writes a saved session as Markdown, to `output` or else stdout, for
+/// `tars export`.
+pub fn run(session: &str, output: Option<&Path>) -> ExportResult<()> {
+    let path = session_store::resolve(session);
+    let messages = session_store::load(&path)?;
+    if messages.is_empty() {
+        return Err(format!("{} contains no messages", path.display()).into());
+    }
+    let markdown = markdown(&messages);
+    match output {
+        Some(output) => std::fs::write(output, markdown)
+            .map_err(|err| format!("Failed to write {}: {}", output.display(), err).into()),
+        None => {
+            print!("{}", markdown);
+            Ok(())
+        }
+    }
+}
+
+/// The transcript as Markdown: a heading per turn, the prompts and replies
+/// as they were written, and each tool call as a collapsed `<details>`
+/// block holding what it returned.
+pub(crate) fn markdown(messages: &[MessageParam]) -> String {
+    let tools = get_all_tools();
+    let turns = split_turns(messages);
+    let mut calls = HashMap::new();
+    let mut markdown = String::new();
+    for (index, turn) in turns.iter().enumerate() {
+        markdown.push_str(&format!("## Turn {}\n\n", index + 1));
+        for message in turn.iter() {
+            let (content, label) = match message {
+                MessageParam::User(message) => (message.content(), "**You:**"),
+                MessageParam::Assistant(message) => (message.content(), "**Assistant:**"),
+            };
+            for block in content {
+                match block {
+                    ContentBlock::Text { text } if text.trim().is_empty() => {}
+                    ContentBlock::Text { text } => {
+                        markdown.push_str(&format!("{}\n\n{}\n\n", label, text.trim()));
+                    }
+                    ContentBlock::ToolUse { id, name, input } => {
+                        calls.insert(id.as_str(), summarize(&tools, name, input));
+                    }
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        is_error,
+                    } => {
+                        let mut summary = calls
+                            .remove(tool_use_id.as_str())
+                            .unwrap_or_else(|| "Tool result".to_string());
+                        if *is_error == Some(true) {
+                            summary.push_str(" (failed)");
+                        }
+                        let mut output = content.text().trim().to_string();
+                        let images = content.images().count();
+                        if images > 0 {
+                            output.push_str(&format!("\n({} image(s))", images));
+                        }
+                        markdown.push_str(&format!(
+                            "<details>\n<summary>{}</summary>\n\n{}\n\n</details>\n\n",
+                            escape_html(&summary),
+                            fenced(&output)
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    markdown.truncate(markdown.trim_end().len());
+    markdown.push('\n');
+    markdown
+}
+
+/// `text` in a code block whose fence is longer than any run of backticks
+/// inside it.
+fn fenced(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{}\n{}\n{}", fence, text, fence)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_sdk::{AssistantMessage, UserMessage};
+
+    #[test]
+    fn transcripts_export_as_markdown() {
+        let messages = vec![
+            MessageParam::User(UserMessage::from_text("What's in src?".to_string())),
+            MessageParam::Assistant(AssistantMessage::new(vec![ContentBlock::ToolUse {
+                id: "call_1".to_string(),
+                name: "bash".to_string(),
+                input: serde_json::json!({"command": "ls src"}),
+            }])),
+            MessageParam::User(UserMessage::new(vec![ContentBlock::tool_result(
+                "call_1".to_string(),
+                "main.rs\n```".to_string(),
+                false,
+            )])),
+            MessageParam::Assistant(AssistantMessage::new(vec![ContentBlock::Text {
+                text: "Just `main.rs`.".to_string(),
+            }])),
+            MessageParam::User(UserMessage::from_text("Thanks".to_string())),
+        ];
+        assert_eq!(
+            markdown(&messages),
+            "## Turn 1\n\n**You:**\n\nWhat's in src?\n\n\
+             <details>\n<summary>💻 ls src</summary>\n\n````\nmain.rs\n```\n````\n\n</details>\n\n\
+             **Assistant:**\n\nJust `main.rs`.\n\n\
+             ## Turn 2\n\n**You:**\n\nThanks\n"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Entry points for the cargo-fuzz targets in `fuzz/`: each feeds
+//! arbitrary bytes to a parser that reads what servers and providers send,
+//! and checks what it makes of them.
+
+use crate::ai_sdk::{
+    assistant_content_from_response, AssistantMessage, MessageParam, MessageResponse,
+};
+#[cfg(any(feature = "server", feature = "client"))]
+use crate::protocol::EventEnvelope;
+#[cfg(feature = "client")]
+use crate::sse::SseDecoder;
+
+/// Parses a server event the way the client does, and checks that whatever
+/// parses serializes back into something that parses again.
+#[cfg(any(feature = "server", feature = "client"))]
+pub fn stream_event(data: &[u8]) {
+    let Ok(envelope) = serde_json::from_slice::<EventEnvelope>(data) else {
+        return;
+    };
+    let json = serde_json::to_string(&envelope).expect("a parsed event serializes");
+    serde_json::from_str::<EventEnvelope>(&json).expect("a serialized event parses");
+}
+
+/// Decodes `body` as an event stream both whole and split into chunks at
+/// the given sizes, which must yield the same events, then parses each
+/// message event as a server event.
+#[cfg(feature = "client")]
+pub fn sse_stream(chunk_sizes: &[u8], body: &[u8]) {
+    let whole = SseDecoder::new().push(body);
+
+    let mut decoder = SseDecoder::new();
+    let mut chunked = Vec::new();
+    let mut rest = body;
+    // Empty chunks are legal too, as long as some size makes progress.
+    let progress = chunk_sizes.iter().any(|&size| size % 17 != 0);
+    let mut sizes = chunk_sizes.iter().map(|&size| size as usize % 17).cycle();
+    while !rest.is_empty() {
+        let size = match sizes.next() {
+            Some(size) if progress => size,
+            _ => rest.len(),
+        };
+        let (chunk, tail) = rest.split_at(size.min(rest.len()));
+        chunked.extend(decoder.push(chunk));
+        rest = tail;
+    }
+    assert_eq!(whole, chunked, "chunking changed the decoded events");
+
+    for event in whole.iter().filter(|event| event.event == "message") {
+        stream_event(event.data.as_bytes());
+    }
+}
+
+/// Parses a Messages API response body the way the agent does and turns it
+/// into the assistant message sent back with the next request.
+pub fn anthropic_response(data: &[u8]) {
+    let Ok(response) = serde_json::from_slice::<MessageResponse>(data) else {
+        return;
+    };
+    let content = assistant_content_from_response(&response);
+    let message = MessageParam::Assistant(AssistantMessage::new(content));
+    let json = serde_json::to_string(&message).expect("an assistant message serializes");
+    serde_json::from_str::<MessageParam>(&json).expect("a serialized message parses");
+}
@@ -0,0 +1,426 @@
+use crate::ai_sdk::ContentBlock;
+use crate::conversation::{text_content, Conversation};
+use crate::session_store;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+use uuid::Uuid;
+
+type ImportResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// A conversation read from another tool's export.
+struct Imported {
+    title: Option<String>,
+    messages: Vec<Message>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug)]
+struct Message {
+    role: Role,
+    content: Vec<ContentBlock>,
+}
+
+/// Converts a Claude Code transcript (`~/.claude/projects/*/<id>.jsonl`) or
+/// a ChatGPT export (`conversations.json`) into saved tars sessions, one per
+/// conversation.
+pub fn run(path: &Path) -> ImportResult<()> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let imported = parse(&contents).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let mut sessions = 0;
+    for conversation in imported {
+        let title = conversation.title.as_deref().unwrap_or("untitled");
+        let transcript = build(normalize(conversation.messages))?;
+        if transcript.messages().is_empty() {
+            println!("Skipped \"{}\": no messages to import", title);
+            continue;
+        }
+        let session_id = Uuid::new_v4().to_string();
+        session_store::save(&session_id, &transcript)?;
+        println!(
+            "Imported \"{}\" ({} messages) to {}",
+            title,
+            transcript.messages().len(),
+            session_store::session_path(&session_id).display()
+        );
+        sessions += 1;
+    }
+
+    if sessions == 0 {
+        return Err(format!("{} contains no conversations to import", path.display()).into());
+    }
+    Ok(())
+}
+
+/// Detects the export format: ChatGPT writes a JSON array of conversations
+/// (or a single one), Claude Code one JSON record per line.
+fn parse(contents: &str) -> ImportResult<Vec<Imported>> {
+    let trimmed = contents.trim_start();
+    if trimmed.starts_with('[') {
+        let conversations: Vec<ChatGptConversation> = serde_json::from_str(contents)?;
+        return Ok(conversations
+            .into_iter()
+            .map(chatgpt_conversation)
+            .collect());
+    }
+    if let Ok(conversation) = serde_json::from_str::<ChatGptConversation>(contents) {
+        return Ok(vec![chatgpt_conversation(conversation)]);
+    }
+    Ok(vec![claude_code_transcript(contents)?])
+}
+
+#[derive(Deserialize)]
+struct ChatGptConversation {
+    #[serde(default)]
+    title: Option<String>,
+    mapping: BTreeMap<String, ChatGptNode>,
+    #[serde(default)]
+    current_node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptNode {
+    #[serde(default)]
+    message: Option<ChatGptMessage>,
+    #[serde(default)]
+    parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMessage {
+    author: ChatGptAuthor,
+    content: ChatGptContent,
+    /// Who the message is addressed to; anything but `all` is a call to
+    /// one of ChatGPT's own tools.
+    #[serde(default)]
+    recipient: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptAuthor {
+    role: String,
+}
+
+#[derive(Deserialize)]
+struct ChatGptContent {
+    #[serde(default)]
+    parts: Vec<Value>,
+}
+
+/// Follows the branch that ends at the conversation's current node, since
+/// the mapping also holds every edited or regenerated alternative.
+fn chatgpt_conversation(conversation: ChatGptConversation) -> Imported {
+    let parents: HashSet<&str> = conversation
+        .mapping
+        .values()
+        .filter_map(|node| node.parent.as_deref())
+        .collect();
+    let mut current = conversation.current_node.clone().or_else(|| {
+        conversation
+            .mapping
+            .keys()
+            .find(|id| !parents.contains(id.as_str()))
+            .cloned()
+    });
+
+    let mut messages = Vec::new();
+    // Bounded by the node count in case the parent links form a cycle.
+    for _ in 0..conversation.mapping.len() {
+        let Some(node) = current.as_ref().and_then(|id| conversation.mapping.get(id)) else {
+            break;
+        };
+        if let Some(message) = &node.message
+            && message.recipient.as_deref().is_none_or(|to| to == "all")
+        {
+            let role = match message.author.role.as_str() {
+                "user" => Some(Role::User),
+                "assistant" => Some(Role::Assistant),
+                _ => None,
+            };
+            let text = message
+                .content
+                .parts
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join("\n");
+            if let Some(role) = role {
+                messages.push(Message {
+                    role,
+                    content: vec![ContentBlock::Text { text }],
+                });
+            }
+        }
+        current = node.parent.clone();
+    }
+    messages.reverse();
+
+    Imported {
+        title: conversation.title,
+        messages,
+    }
+}
+
+#[derive(Deserialize)]
+struct ClaudeCodeRecord {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    message: Option<ClaudeCodeMessage>,
+    #[serde(default)]
+    summary: Option<String>,
+    /// Set on a subagent's messages, which are not part of the main thread.
+    #[serde(default, rename = "isSidechain")]
+    is_sidechain: bool,
+    /// Set on messages Claude Code injects for the model, such as command
+    /// output caveats.
+    #[serde(default, rename = "isMeta")]
+    is_meta: bool,
+}
+
+#[derive(Deserialize)]
+struct ClaudeCodeMessage {
+    content: Value,
+}
+
+fn claude_code_transcript(contents: &str) -> ImportResult<Imported> {
+    let mut title = None;
+    let mut messages = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: ClaudeCodeRecord = serde_json::from_str(line)
+            .map_err(|err| format!("line {}: not a Claude Code record: {}", index + 1, err))?;
+        let role = match record.kind.as_str() {
+            "summary" => {
+                title = title.or(record.summary);
+                continue;
+            }
+            "user" => Role::User,
+            "assistant" => Role::Assistant,
+            _ => continue,
+        };
+        if record.is_sidechain || record.is_meta {
+            continue;
+        }
+        if let Some(message) = record.message {
+            messages.push(Message {
+                role,
+                content: claude_code_content(message.content),
+            });
+        }
+    }
+    Ok(Imported { title, messages })
+}
+
+/// Keeps text, tool calls and tool results; thinking and images have no
+/// counterpart in a tars transcript.
+fn claude_code_content(content: Value) -> Vec<ContentBlock> {
+    let blocks = match content {
+        Value::String(text) => return vec![ContentBlock::Text { text }],
+        Value::Array(blocks) => blocks,
+        _ => return Vec::new(),
+    };
+    blocks
+        .into_iter()
+        .filter_map(|block| match block["type"].as_str()? {
+            "text" => Some(ContentBlock::Text {
+                text: block["text"].as_str()?.to_string(),
+            }),
+            "tool_use" => Some(ContentBlock::ToolUse {
+                id: block["id"].as_str()?.to_string(),
+                name: block["name"].as_str()?.to_string(),
+                input: block["input"].clone(),
+            }),
+            "tool_result" => Some(ContentBlock::tool_result(
+                block["tool_use_id"].as_str()?.to_string(),
+                match &block["content"] {
+                    Value::String(text) => text.clone(),
+                    Value::Array(parts) => parts
+                        .iter()
+                        .filter_map(|part| part["text"].as_str())
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    _ => String::new(),
+                },
+                block["is_error"].as_bool().unwrap_or(false),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Shapes the messages the way the API expects them: roles alternate, the
+/// first message is the user's, and every tool call is answered by a result
+/// in the very next message. Calls that were interrupted before a result
+/// arrived are dropped along with any stray results.
+fn normalize(messages: Vec<Message>) -> Vec<Message> {
+    let mut messages = merge(messages);
+    let mut answered = HashSet::new();
+    for index in 0..messages.len() {
+        let results: HashSet<String> = messages
+            .get(index + 1)
+            .filter(|next| next.role == Role::User)
+            .map(|next| {
+                next.content
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let previous = std::mem::take(&mut answered);
+        messages[index].content.retain(|block| match block {
+            ContentBlock::ToolUse { id, .. } => results.contains(id) && answered.insert(id.clone()),
+            ContentBlock::ToolResult { tool_use_id, .. } => previous.contains(tool_use_id),
+            _ => true,
+        });
+    }
+
+    let mut messages = merge(messages);
+    while let Some(first) = messages.first_mut() {
+        first
+            .content
+            .retain(|block| !matches!(block, ContentBlock::ToolResult { .. }));
+        if first.role == Role::User && !first.content.is_empty() {
+            break;
+        }
+        messages.remove(0);
+    }
+    messages
+}
+
+/// Drops blank text and folds consecutive messages from the same side into
+/// one, except that a prompt after tool results starts a turn of its own, as
+/// it does when tars itself is interrupted mid-turn.
+fn merge(messages: Vec<Message>) -> Vec<Message> {
+    let mut merged: Vec<Message> = Vec::new();
+    for mut message in messages {
+        message.content.retain(
+            |block| !matches!(block, ContentBlock::Text { text } if text.trim().is_empty()),
+        );
+        if message.content.is_empty() {
+            continue;
+        }
+        match merged.last_mut() {
+            Some(last)
+                if last.role == message.role
+                    && !(message.role == Role::User && has_tool_results(&last.content)) =>
+            {
+                last.content.append(&mut message.content)
+            }
+            _ => merged.push(message),
+        }
+    }
+    merged
+}
+
+/// Replays the messages through the conversation so every turn gets an id.
+fn build(messages: Vec<Message>) -> ImportResult<Conversation> {
+    let mut conversation = Conversation::new();
+    let mut messages = messages.into_iter().peekable();
+    while let Some(message) = messages.next() {
+        match message.role {
+            Role::User => {
+                conversation.finish_turn();
+                conversation.begin_turn(text_content(&message.content))?;
+            }
+            Role::Assistant => {
+                let results = messages
+                    .next_if(|next| next.role == Role::User && has_tool_results(&next.content))
+                    .map(|next| next.content)
+                    .unwrap_or_default();
+                conversation.push_exchange(message.content, results);
+            }
+        }
+    }
+    conversation.finish_turn();
+    Ok(conversation)
+}
+
+fn has_tool_results(content: &[ContentBlock]) -> bool {
+    content
+        .iter()
+        .any(|block| matches!(block, ContentBlock::ToolResult { .. }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_sdk::MessageParam;
+
+    fn roles(conversation: &Conversation) -> Vec<&'static str> {
+        conversation
+            .messages()
+            .iter()
+            .map(|message| match message {
+                MessageParam::User(_) => "user",
+                MessageParam::Assistant(_) => "assistant",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn claude_code_transcripts_drop_unanswered_tool_calls() {
+        let transcript = [
+            r#"{"type":"summary","summary":"Fix the parser"}"#,
+            r#"{"type":"user","message":{"role":"user","content":"fix it"}}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"thinking","thinking":"hm"},{"type":"tool_use","id":"t1","name":"Read","input":{}}]}}"#,
+            r#"{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":[{"type":"text","text":"fn main() {}"}]}]}}"#,
+            r#"{"type":"assistant","isSidechain":true,"message":{"content":[{"type":"text","text":"subagent"}]}}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t2","name":"Edit","input":{}}]}}"#,
+            r#"{"type":"user","message":{"content":"stop, just explain"}}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"It reads main."}]}}"#,
+        ]
+        .join("\n");
+
+        let imported = parse(&transcript).unwrap().pop().unwrap();
+        assert_eq!(imported.title.as_deref(), Some("Fix the parser"));
+        let conversation = build(normalize(imported.messages)).unwrap();
+        assert_eq!(
+            roles(&conversation),
+            ["user", "assistant", "user", "user", "assistant"]
+        );
+        assert_eq!(conversation.turn_id(1), conversation.turn_id(2));
+        assert_ne!(conversation.turn_id(2), conversation.turn_id(3));
+        let text = serde_json::to_string(conversation.messages()).unwrap();
+        assert!(text.contains("fn main() {}"));
+        assert!(!text.contains("\"t2\""));
+        assert!(!text.contains("subagent"));
+    }
+
+    #[test]
+    fn chatgpt_exports_follow_the_current_branch() {
+        let export = r#"[{
+            "title": "Haiku",
+            "current_node": "c",
+            "mapping": {
+                "root": {"message": null, "parent": null},
+                "s": {"message": {"author": {"role": "system"}, "content": {"parts": [""]}}, "parent": "root"},
+                "a": {"message": {"author": {"role": "user"}, "content": {"parts": ["write a haiku"]}}, "parent": "s"},
+                "b": {"message": {"author": {"role": "assistant"}, "content": {"parts": ["first draft"]}}, "parent": "a"},
+                "c": {"message": {"author": {"role": "assistant"}, "content": {"parts": ["regenerated"]}}, "parent": "a"}
+            }
+        }]"#;
+
+        let imported = parse(export).unwrap().pop().unwrap();
+        assert_eq!(imported.title.as_deref(), Some("Haiku"));
+        let conversation = build(normalize(imported.messages)).unwrap();
+        assert_eq!(roles(&conversation), ["user", "assistant"]);
+        let text = serde_json::to_string(conversation.messages()).unwrap();
+        assert!(text.contains("regenerated"));
+        assert!(!text.contains("first draft"));
+    }
+}
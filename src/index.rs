@@ -0,0 +1,857 @@
+//! The workspace index behind `code_outline` and `keyword_search`.
+//!
+//! Each text file under the working directory is split into chunks of
+//! lines, and each chunk is stored as the words of its identifiers and
+//! comments, weighted TF-IDF style when searched, so "where do we retry
+//! requests" finds `retry_after` and `RetryPolicy`. Definitions are picked
+//! out per language by pattern, and the identifiers each source file uses
+//! link it to the files that define them, which ranks files for the repo
+//! map the agent is given each turn. The index is kept in
+//! `~/.tars/index/<workspace>.json` and brought up to date before each
+//! lookup by re-reading only the files whose size or modification time
+//! changed.
+
+use ignore::WalkBuilder;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Instant, UNIX_EPOCH};
+
+use crate::config;
+
+type IndexResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Bumped when the stored format or what is extracted changes, so old
+/// indexes are rebuilt rather than misread.
+const VERSION: u32 = 2;
+/// Files indexed at most; a home directory mistaken for a project stops
+/// here.
+const MAX_FILES: usize = 20_000;
+/// Larger files are generated or data.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+const CHUNK_LINES: usize = 40;
+/// A name defined in more files than this, like `new` or `tests`, says
+/// nothing about which file a reference means.
+const MAX_DEFINING_FILES: usize = 5;
+/// Definitions listed per file in the repo map.
+const MAP_SYMBOLS: usize = 8;
+
+/// Words too common in code to say anything about a chunk.
+const STOP_WORDS: &[&str] = &[
+    "an", "and", "as", "at", "be", "by", "def", "else", "fn", "for", "from", "if", "impl",
+    "import", "in", "is", "it", "let", "mut", "new", "none", "not", "null", "of", "on", "or",
+    "pub", "return", "self", "the", "this", "to", "use", "var", "with",
+];
+
+/// A definition in a source file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Symbol {
+    /// The keyword that introduced it, such as `fn`, `class` or `impl`.
+    pub kind: String,
+    pub name: String,
+    /// 1-based.
+    pub line: usize,
+    /// Columns of indentation, for nesting methods under their type.
+    pub indent: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Chunk {
+    /// 1-based, inclusive.
+    start_line: usize,
+    end_line: usize,
+    /// How often each word occurs in the chunk.
+    terms: HashMap<String, u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedFile {
+    /// Nanoseconds since the epoch.
+    modified: u64,
+    size: u64,
+    symbols: Vec<Symbol>,
+    /// The distinct identifiers a source file uses, for finding the files
+    /// it depends on. Empty for other files.
+    #[serde(default)]
+    references: Vec<String>,
+    /// Empty for binary files, which are kept only so they aren't read
+    /// again.
+    chunks: Vec<Chunk>,
+}
+
+/// What a refresh did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RefreshStats {
+    pub files: usize,
+    pub updated: usize,
+    pub removed: usize,
+    /// Whether `MAX_FILES` cut the walk short.
+    pub truncated: bool,
+}
+
+/// A chunk that matched a search.
+#[derive(Debug, PartialEq)]
+pub struct Hit {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub score: f64,
+    /// The query words found in the chunk, for picking lines to show.
+    pub terms: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepoIndex {
+    version: u32,
+    /// Keyed by path relative to the workspace, with `/` separators.
+    files: BTreeMap<String, IndexedFile>,
+}
+
+impl Default for RepoIndex {
+    fn default() -> Self {
+        RepoIndex {
+            version: VERSION,
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+impl RepoIndex {
+    fn load(path: &Path) -> Self {
+        std::fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<RepoIndex>(&bytes).ok())
+            .filter(|index| index.version == VERSION)
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) -> IndexResult<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Written aside and renamed, so a crash never leaves half an index.
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, serde_json::to_vec(self)?)?;
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// Re-reads the files under `root` that changed since they were
+    /// indexed and forgets the ones that are gone.
+    pub fn refresh(&mut self, root: &Path) -> RefreshStats {
+        let mut stats = RefreshStats::default();
+        let mut seen = HashSet::new();
+        // Hidden files, such as `.env`, stay out: search results are shown
+        // to the model.
+        let walker = WalkBuilder::new(root).hidden(true).build();
+        for entry in walker.flatten() {
+            if !entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file())
+            {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.len() > MAX_FILE_BYTES {
+                continue;
+            }
+            if seen.len() == MAX_FILES {
+                stats.truncated = true;
+                break;
+            }
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            let key = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_nanos() as u64);
+            seen.insert(key.clone());
+            if self
+                .files
+                .get(&key)
+                .is_some_and(|file| file.modified == modified && file.size == metadata.len())
+            {
+                continue;
+            }
+            let Ok(bytes) = std::fs::read(entry.path()) else {
+                continue;
+            };
+            self.files.insert(
+                key.clone(),
+                index_file(&key, &bytes, modified, metadata.len()),
+            );
+            stats.updated += 1;
+        }
+        let before = self.files.len();
+        self.files.retain(|path, _| seen.contains(path));
+        stats.removed = before - self.files.len();
+        stats.files = self.files.len();
+        stats
+    }
+
+    /// The chunks that best match `query`, best first, optionally only
+    /// under `prefix`.
+    pub fn search(&self, query: &str, prefix: Option<&str>, limit: usize) -> Vec<Hit> {
+        let mut query_terms: Vec<String> = terms(query).collect();
+        query_terms.sort();
+        query_terms.dedup();
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+
+        let chunks = || {
+            self.files
+                .iter()
+                .filter(|(path, _)| prefix.is_none_or(|prefix| under(path, prefix)))
+                .flat_map(|(path, file)| file.chunks.iter().map(move |chunk| (path, chunk)))
+        };
+        let total = chunks().count() as f64;
+        let mut frequency: HashMap<&str, usize> = HashMap::new();
+        for (_, chunk) in chunks() {
+            for term in &query_terms {
+                if chunk.terms.contains_key(term) {
+                    *frequency.entry(term).or_default() += 1;
+                }
+            }
+        }
+        let idf = |term: &str| {
+            let containing = frequency.get(term).copied().unwrap_or(0) as f64;
+            (total / (containing + 1.0)).ln() + 1.0
+        };
+
+        let mut hits: Vec<Hit> = chunks()
+            .filter_map(|(path, chunk)| {
+                let found: Vec<String> = query_terms
+                    .iter()
+                    .filter(|term| chunk.terms.contains_key(*term))
+                    .cloned()
+                    .collect();
+                if found.is_empty() {
+                    return None;
+                }
+                let weight: f64 = found
+                    .iter()
+                    .map(|term| (1.0 + (chunk.terms[term] as f64).ln()) * idf(term))
+                    .sum();
+                // Long chunks mention everything; favour focused ones.
+                let length = (chunk.terms.len() as f64).sqrt().max(1.0);
+                // A file named after the query is a good sign.
+                let lower_path = path.to_lowercase();
+                let in_path: f64 = query_terms
+                    .iter()
+                    .filter(|term| lower_path.contains(term.as_str()))
+                    .map(|term| idf(term))
+                    .sum();
+                Some(Hit {
+                    path: path.clone(),
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    score: weight / length * 10.0 + in_path,
+                    terms: found,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.path.cmp(&b.path)));
+        hits.truncate(limit);
+        hits
+    }
+
+    /// The definitions in the file at `path`, or in every file under it.
+    pub fn outline(&self, path: &str) -> Vec<(&str, &[Symbol])> {
+        self.files
+            .iter()
+            .filter(|(file, indexed)| under(file, path) && !indexed.symbols.is_empty())
+            .map(|(file, indexed)| (file.as_str(), indexed.symbols.as_slice()))
+            .collect()
+    }
+
+    /// A compact map of the workspace: its top-level entries, then source
+    /// files ranked by how much the rest of the code uses them, each with
+    /// its main definitions, cut off at about `max_chars`.
+    ///
+    /// Files are ranked PageRank style over the graph of references from
+    /// one file to names defined in another, so a type everything uses
+    /// lifts its file, and the files that use it lift theirs.
+    pub fn map(&self, max_chars: usize) -> String {
+        if self.files.is_empty() {
+            return String::new();
+        }
+        let mut definers: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (path, file) in &self.files {
+            for symbol in file.symbols.iter().filter(|symbol| mapped(symbol)) {
+                let paths = definers.entry(symbol.name.as_str()).or_default();
+                if !paths.contains(&path.as_str()) {
+                    paths.push(path);
+                }
+            }
+        }
+        definers.retain(|_, paths| paths.len() <= MAX_DEFINING_FILES);
+
+        // Edges from a file to each file whose names it uses, and how much
+        // other files use each name. A name shared by several files counts
+        // for each in part.
+        let mut edges: BTreeMap<&str, HashMap<&str, f64>> = BTreeMap::new();
+        let mut uses: HashMap<(&str, &str), f64> = HashMap::new();
+        for (path, file) in &self.files {
+            for name in &file.references {
+                let Some(targets) = definers.get(name.as_str()) else {
+                    continue;
+                };
+                let weight = distinctiveness(name) / targets.len() as f64;
+                for target in targets.iter().filter(|target| **target != path.as_str()) {
+                    *edges.entry(path).or_default().entry(target).or_default() += weight;
+                    *uses.entry((target, name)).or_default() += weight;
+                }
+            }
+        }
+
+        let nodes: Vec<&str> = self
+            .files
+            .iter()
+            .filter(|(_, file)| file.symbols.iter().any(mapped))
+            .map(|(path, _)| path.as_str())
+            .collect();
+        let rank = page_rank(&nodes, &edges);
+        let mut ranked = nodes.clone();
+        ranked.sort_by(|a, b| rank[b].total_cmp(&rank[a]).then(a.cmp(b)));
+
+        let mut lines = vec![top_level(self.files.keys())];
+        let mut used = lines[0].len();
+        for (shown, path) in ranked.iter().enumerate() {
+            let symbols: Vec<&Symbol> = self.files[*path]
+                .symbols
+                .iter()
+                .filter(|symbol| mapped(symbol))
+                .collect();
+            // The names other files use most, then the outermost, shown in
+            // file order.
+            let used_by_others = |symbol: &Symbol| {
+                uses.get(&(*path, symbol.name.as_str()))
+                    .copied()
+                    .unwrap_or(0.0)
+            };
+            let mut picked = symbols.clone();
+            picked.sort_by(|a, b| {
+                used_by_others(b)
+                    .total_cmp(&used_by_others(a))
+                    .then(a.indent.cmp(&b.indent))
+                    .then(a.line.cmp(&b.line))
+            });
+            picked.truncate(MAP_SYMBOLS);
+            picked.sort_by_key(|symbol| symbol.line);
+            let mut line = format!(
+                "{}: {}",
+                path,
+                picked
+                    .iter()
+                    .map(|symbol| format!("{} {}", symbol.kind, symbol.name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            if picked.len() < symbols.len() {
+                line.push_str(", …");
+            }
+            if used + line.len() > max_chars {
+                lines.push(format!(
+                    "…and {} more files; code_outline lists a file's definitions",
+                    ranked.len() - shown
+                ));
+                break;
+            }
+            used += line.len() + 1;
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+
+    pub fn symbol_count(&self) -> usize {
+        self.files.values().map(|file| file.symbols.len()).sum()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.files.values().map(|file| file.chunks.len()).sum()
+    }
+}
+
+/// Whether the indexed `path` is `prefix` or inside it. `.` and an empty
+/// prefix cover everything.
+fn under(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_start_matches("./").trim_end_matches('/');
+    prefix.is_empty()
+        || prefix == "."
+        || path == prefix
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Whether a definition belongs in the repo map: `impl` blocks repeat
+/// their type's name and `mod` lines only point at other files.
+fn mapped(symbol: &Symbol) -> bool {
+    symbol.kind != "impl" && symbol.kind != "mod"
+}
+
+/// How surely a use of `name` refers to its definition: `RetryPolicy` or
+/// `parse_args` almost certainly, a short word like `get` or `render`
+/// hardly.
+fn distinctiveness(name: &str) -> f64 {
+    let compound = name.trim_matches('_').contains('_')
+        || name.starts_with(|c: char| c.is_uppercase())
+        || name.chars().any(|c| c.is_uppercase());
+    if compound || name.len() >= 12 {
+        1.0
+    } else {
+        0.1
+    }
+}
+
+/// The workspace's top-level entries, directories with their file counts.
+fn top_level<'a>(paths: impl Iterator<Item = &'a String>) -> String {
+    let mut files = Vec::new();
+    let mut directories: BTreeMap<&str, usize> = BTreeMap::new();
+    for path in paths {
+        match path.split_once('/') {
+            Some((directory, _)) => *directories.entry(directory).or_default() += 1,
+            None => files.push(path.clone()),
+        }
+    }
+    let entries: Vec<String> = directories
+        .into_iter()
+        .map(|(directory, count)| format!("{}/ ({} files)", directory, count))
+        .chain(files)
+        .collect();
+    format!("Top level: {}", entries.join(", "))
+}
+
+/// Each node's share of a random walk along `edges`, which are weighted
+/// by how many names one file uses from another.
+fn page_rank<'a>(
+    nodes: &[&'a str],
+    edges: &BTreeMap<&'a str, HashMap<&'a str, f64>>,
+) -> HashMap<&'a str, f64> {
+    const DAMPING: f64 = 0.85;
+    const ITERATIONS: usize = 30;
+    let count = nodes.len().max(1) as f64;
+    let mut rank: HashMap<&str, f64> = nodes.iter().map(|node| (*node, 1.0 / count)).collect();
+    for _ in 0..ITERATIONS {
+        let mut next: HashMap<&str, f64> = nodes
+            .iter()
+            .map(|node| (*node, (1.0 - DAMPING) / count))
+            .collect();
+        // Files that use nothing share their rank with everyone.
+        let mut dangling = 0.0;
+        for node in nodes {
+            let targets = edges.get(node).filter(|targets| !targets.is_empty());
+            let Some(targets) = targets else {
+                dangling += rank[node];
+                continue;
+            };
+            let total: f64 = targets.values().sum();
+            for (target, weight) in targets {
+                if let Some(value) = next.get_mut(target) {
+                    *value += DAMPING * rank[node] * weight / total;
+                }
+            }
+        }
+        for value in next.values_mut() {
+            *value += DAMPING * dangling / count;
+        }
+        rank = next;
+    }
+    rank
+}
+
+fn index_file(path: &str, bytes: &[u8], modified: u64, size: u64) -> IndexedFile {
+    let binary = bytes.iter().take(8192).any(|&byte| byte == 0);
+    let text = if binary {
+        String::new()
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    let chunks = lines
+        .chunks(CHUNK_LINES)
+        .enumerate()
+        .map(|(number, chunk)| {
+            let mut counts = HashMap::new();
+            for line in chunk {
+                for term in terms(line) {
+                    *counts.entry(term).or_insert(0) += 1;
+                }
+            }
+            Chunk {
+                start_line: number * CHUNK_LINES + 1,
+                end_line: number * CHUNK_LINES + chunk.len(),
+                terms: counts,
+            }
+        })
+        .filter(|chunk| !chunk.terms.is_empty())
+        .collect();
+    let symbols = symbols(path, &lines);
+    let references = if outlined(path) {
+        let mut references: Vec<String> = text
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|word| word.len() >= 3 && !word.starts_with(|c: char| c.is_ascii_digit()))
+            .map(str::to_string)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        references.sort();
+        references
+    } else {
+        Vec::new()
+    };
+    IndexedFile {
+        modified,
+        size,
+        symbols,
+        references,
+        chunks,
+    }
+}
+
+/// The words in `text`: each identifier lowercased, and for
+/// `parse_args` or `RetryPolicy` also its parts, with common endings
+/// dropped so `retried` finds `retry`.
+fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| word.chars().next().is_some_and(|c| !c.is_ascii_digit()))
+        .flat_map(|word| {
+            let whole = word.to_lowercase();
+            let mut parts: Vec<String> = split_identifier(word)
+                .into_iter()
+                .filter(|part| *part != whole)
+                .collect();
+            parts.push(whole);
+            parts
+        })
+        .map(|term| stem(&term))
+        .filter(|term| term.chars().count() >= 2 && !STOP_WORDS.contains(&term.as_str()))
+}
+
+/// A crude stemmer for English plurals and past tenses: `requests` →
+/// `request`, `retried` → `retry`, `parsing` → `pars`. Queries and
+/// chunks go through the same, so only consistency matters.
+fn stem(term: &str) -> String {
+    for (ending, replacement) in [
+        ("ies", "y"),
+        ("ied", "y"),
+        ("ing", ""),
+        ("ed", ""),
+        ("s", ""),
+    ] {
+        if let Some(root) = term.strip_suffix(ending)
+            && root.chars().count() >= 3
+            && !(ending == "s" && root.ends_with('s'))
+        {
+            return format!("{}{}", root, replacement);
+        }
+    }
+    term.to_string()
+}
+
+/// `parse_args` → `parse`, `args`; `HTTPRetryPolicy` → `http`, `retry`,
+/// `policy`.
+fn split_identifier(word: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    for piece in word.split('_').filter(|piece| !piece.is_empty()) {
+        let chars: Vec<char> = piece.chars().collect();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let boundary = (chars[i].is_uppercase() && chars[i - 1].is_lowercase())
+                || (chars[i].is_uppercase()
+                    && chars[i - 1].is_uppercase()
+                    && chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+            if boundary {
+                parts.push(chars[start..i].iter().collect::<String>().to_lowercase());
+                start = i;
+            }
+        }
+        parts.push(chars[start..].iter().collect::<String>().to_lowercase());
+    }
+    parts
+}
+
+/// Patterns for definitions, by file extension. Each has `indent`, `kind`
+/// and `name` groups.
+static DEFINITIONS: LazyLock<Vec<(&'static [&'static str], Regex)>> = LazyLock::new(|| {
+    let patterns: [(&[&str], &str); 7] = [
+        (
+            &["rs"],
+            r"^(?P<indent>\s*)(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|unsafe|const|extern\s+\S+)\s+)*(?P<kind>fn|struct|enum|trait|type|mod|union|macro_rules!)\s*(?P<name>[A-Za-z_][A-Za-z0-9_]*)",
+        ),
+        (
+            &["rs"],
+            r"^(?P<indent>\s*)(?:unsafe\s+)?(?P<kind>impl)(?:<[^{]*?>)?\s+(?P<name>[^{]+?)\s*(?:\{|where\b|$)",
+        ),
+        (
+            &["py", "pyi"],
+            r"^(?P<indent>\s*)(?:async\s+)?(?P<kind>def|class)\s+(?P<name>[A-Za-z_]\w*)",
+        ),
+        (
+            &["js", "jsx", "mjs", "cjs", "ts", "tsx", "mts", "cts"],
+            r"^(?P<indent>\s*)(?:export\s+)?(?:default\s+)?(?:declare\s+)?(?:abstract\s+)?(?:async\s+)?(?P<kind>function\*?|class|interface|type|enum|namespace)\s+(?P<name>[A-Za-z_$][\w$]*)",
+        ),
+        (
+            &["go"],
+            r"^(?P<indent>)(?P<kind>func|type)\s+(?:\([^)]*\)\s*)?(?P<name>[A-Za-z_]\w*)",
+        ),
+        (
+            &["java", "kt", "kts", "cs", "scala", "swift"],
+            r"^(?P<indent>\s*)(?:(?:public|private|protected|internal|static|final|abstract|sealed|data|open|partial|override|suspend)\s+)*(?P<kind>class|interface|enum|record|object|struct|fun|func)\s+(?P<name>[A-Za-z_]\w*)",
+        ),
+        (
+            &["rb"],
+            r"^(?P<indent>\s*)(?P<kind>def|class|module)\s+(?P<name>[A-Za-z_][\w.?!]*)",
+        ),
+    ];
+    patterns
+        .into_iter()
+        .map(|(extensions, pattern)| (extensions, Regex::new(pattern).unwrap()))
+        .collect()
+});
+
+fn extension(path: &str) -> &str {
+    path.rsplit_once('.').map_or("", |(_, extension)| extension)
+}
+
+/// Whether `path` is in a language definitions are found in.
+fn outlined(path: &str) -> bool {
+    let extension = extension(path);
+    DEFINITIONS
+        .iter()
+        .any(|(extensions, _)| extensions.contains(&extension))
+}
+
+fn symbols(path: &str, lines: &[&str]) -> Vec<Symbol> {
+    let extension = extension(path);
+    let patterns: Vec<&Regex> = DEFINITIONS
+        .iter()
+        .filter(|(extensions, _)| extensions.contains(&extension))
+        .map(|(_, pattern)| pattern)
+        .collect();
+    if patterns.is_empty() {
+        return Vec::new();
+    }
+    let mut symbols = Vec::new();
+    for (number, line) in lines.iter().enumerate() {
+        let Some(captures) = patterns.iter().find_map(|pattern| pattern.captures(line)) else {
+            continue;
+        };
+        let indent = captures["indent"]
+            .chars()
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum();
+        symbols.push(Symbol {
+            kind: captures["kind"].to_string(),
+            name: captures["name"].trim().to_string(),
+            line: number + 1,
+            indent,
+        });
+    }
+    symbols
+}
+
+/// Where the index of `root` is kept.
+fn index_path(root: &Path) -> PathBuf {
+    let hash = blake3::hash(root.to_string_lossy().as_bytes()).to_hex();
+    let name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    config::tars_dir()
+        .join("index")
+        .join(format!("{}-{}.json", name, &hash[..16]))
+}
+
+/// The working directory's index, loaded once per process and refreshed
+/// before every use.
+static CURRENT: Mutex<Option<(PathBuf, RepoIndex)>> = Mutex::new(None);
+
+/// Brings the working directory's index up to date, saving it when
+/// anything changed, and hands it to `f`.
+pub fn with_current<T>(f: impl FnOnce(&RepoIndex, &RefreshStats) -> T) -> IndexResult<T> {
+    let root = std::env::current_dir()?;
+    let mut current = CURRENT.lock().unwrap();
+    if current.as_ref().is_none_or(|(loaded, _)| *loaded != root) {
+        *current = Some((root.clone(), RepoIndex::load(&index_path(&root))));
+    }
+    let (_, index) = current.as_mut().unwrap();
+    let stats = index.refresh(Path::new("."));
+    if stats.updated > 0 || stats.removed > 0 {
+        index.save(&index_path(&root))?;
+    }
+    Ok(f(index, &stats))
+}
+
+/// The working directory's repo map, from its index brought up to date.
+pub fn current_map(max_chars: usize) -> IndexResult<String> {
+    with_current(|index, _| index.map(max_chars))
+}
+
+/// Whether the working directory has been indexed before, so the server
+/// keeps it fresh in the background.
+#[cfg(feature = "server")]
+pub fn current_exists() -> bool {
+    std::env::current_dir().is_ok_and(|root| index_path(&root).exists())
+}
+
+/// `tars index`: builds or updates the working directory's index, and
+/// with `--map` prints the repo map for `map_tokens`.
+pub fn run(rebuild: bool, map_tokens: Option<usize>) -> IndexResult<()> {
+    let root = std::env::current_dir()?;
+    if rebuild {
+        let _ = std::fs::remove_file(index_path(&root));
+        *CURRENT.lock().unwrap() = None;
+    }
+    let started = Instant::now();
+    with_current(|index, stats| {
+        println!(
+            "Indexed {} files in {:.1}s ({} updated, {} removed): {} definitions, {} chunks",
+            stats.files,
+            started.elapsed().as_secs_f64(),
+            stats.updated,
+            stats.removed,
+            index.symbol_count(),
+            index.chunk_count()
+        );
+        if stats.truncated {
+            println!(
+                "Stopped at {} files; run it in the project's directory rather than above it",
+                MAX_FILES
+            );
+        }
+        println!("{}", index_path(&root).display());
+        if let Some(tokens) = map_tokens {
+            println!("\n{}", index.map(tokens * 4));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn files_are_indexed_incrementally_and_searched_by_keyword() {
+        let root = TempDir::new("tars-index");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(
+            root.join("src/retry.rs"),
+            "/// Waits before trying a failed request again.\npub struct RetryPolicy {\n    attempts: u32,\n}\n\nimpl RetryPolicy {\n    pub fn backoff_delay(&self) -> u64 { 1 }\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("src/app.py"),
+            "class Settings:\n    def load_config(self):\n        pass\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("README.md"), "Requests are sent with reqwest.\n").unwrap();
+
+        let mut index = RepoIndex::default();
+        let stats = index.refresh(&root);
+        assert_eq!((stats.files, stats.updated), (3, 3));
+
+        let hits = index.search("how are failed requests retried", None, 5);
+        assert_eq!(hits[0].path, "src/retry.rs");
+        assert_eq!((hits[0].start_line, hits[0].end_line), (1, 8));
+        assert!(index.search("retry", Some("src/app.py"), 5).is_empty());
+
+        let outline = index.outline("src/retry.rs");
+        let names: Vec<(&str, &str, usize)> = outline[0]
+            .1
+            .iter()
+            .map(|symbol| (symbol.kind.as_str(), symbol.name.as_str(), symbol.indent))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("struct", "RetryPolicy", 0),
+                ("impl", "RetryPolicy", 0),
+                ("fn", "backoff_delay", 4)
+            ]
+        );
+        assert_eq!(index.outline("src").len(), 2);
+
+        // Only what changed is read again.
+        std::fs::write(root.join("src/app.py"), "def main():\n    pass\n").unwrap();
+        std::fs::remove_file(root.join("README.md")).unwrap();
+        let stats = index.refresh(&root);
+        assert_eq!((stats.files, stats.updated, stats.removed), (2, 1, 1));
+        assert_eq!(index.outline("src/app.py")[0].1[0].name, "main");
+
+        let path = root.join("index.json");
+        index.save(&path).unwrap();
+        assert_eq!(RepoIndex::load(&path).files.len(), 2);
+    }
+
+    #[test]
+    fn the_repo_map_puts_the_most_used_files_first() {
+        let root = TempDir::new("tars-map");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(
+            root.join("src/policy.rs"),
+            "pub struct RetryPolicy;\n\nimpl RetryPolicy {\n    pub fn backoff_delay(&self) {}\n    fn get(&self) {}\n}\n",
+        )
+        .unwrap();
+        for user in ["client", "server"] {
+            std::fs::write(
+                root.join(format!("src/{}.rs", user)),
+                format!(
+                    "pub fn run_{}(policy: RetryPolicy) {{\n    policy.backoff_delay();\n}}\n",
+                    user
+                ),
+            )
+            .unwrap();
+        }
+        std::fs::write(root.join("Cargo.toml"), "[package]\n").unwrap();
+
+        let mut index = RepoIndex::default();
+        index.refresh(&root);
+        let map = index.map(1000);
+        assert_eq!(
+            map,
+            "Top level: src/ (3 files), Cargo.toml\n\
+             src/policy.rs: struct RetryPolicy, fn backoff_delay, fn get\n\
+             src/client.rs: fn run_client\n\
+             src/server.rs: fn run_server"
+        );
+        assert!(index
+            .map(110)
+            .ends_with("\n…and 2 more files; code_outline lists a file's definitions"));
+        assert_eq!(RepoIndex::default().map(1000), "");
+    }
+
+    #[test]
+    fn hidden_files_stay_out_of_the_index() {
+        let root = TempDir::new("tars-index-hidden");
+        std::fs::create_dir_all(root.join(".github")).unwrap();
+        std::fs::write(root.join(".env"), "API_TOKEN=retry-secret\n").unwrap();
+        std::fs::write(root.join(".github/ci.yml"), "retry: 3\n").unwrap();
+        std::fs::write(root.join("retry.rs"), "fn retry() {}\n").unwrap();
+
+        // An index built before hidden files were left out drops them.
+        let mut index = RepoIndex::default();
+        let env = b"API_TOKEN=retry-secret\n";
+        index.files.insert(
+            ".env".to_string(),
+            index_file(".env", env, 0, env.len() as u64),
+        );
+        let stats = index.refresh(&root);
+        assert_eq!((stats.files, stats.removed), (1, 1));
+        let hits = index.search("retry secret", None, 5);
+        let paths: Vec<&str> = hits.iter().map(|hit| hit.path.as_str()).collect();
+        assert_eq!(paths, ["retry.rs"]);
+    }
+}
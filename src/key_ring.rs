@@ -0,0 +1,158 @@
+use crate::config;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The API keys an agent sends requests with. One key is used until the
+/// API rate-limits it, then the next one takes over.
+///
+/// Keys read from the credentials file follow the file: edits are picked up
+/// on the next request, without restarting the server.
+pub struct KeyRing {
+    state: Mutex<RingState>,
+    source: Option<PathBuf>,
+}
+
+struct RingState {
+    keys: Vec<String>,
+    current: usize,
+    /// Modification time of `source` when the keys were read.
+    modified: Option<SystemTime>,
+}
+
+impl KeyRing {
+    pub fn new(keys: Vec<String>) -> Self {
+        Self {
+            state: Mutex::new(RingState {
+                keys,
+                current: 0,
+                modified: None,
+            }),
+            source: None,
+        }
+    }
+
+    /// The keys from `ANTHROPIC_API_KEY`, or else from the credentials file,
+    /// which is then watched for changes, or else the one in the OS keyring.
+    pub fn from_config() -> Self {
+        let path = config::credentials_path();
+        if config::api_key_from_env() {
+            Self::new(config::api_keys())
+        } else if !path.exists()
+            && let Some(key) = config::keyring_api_key()
+        {
+            Self::new(vec![key])
+        } else {
+            Self::from_file(path)
+        }
+    }
+
+    fn from_file(path: PathBuf) -> Self {
+        let ring = Self {
+            state: Mutex::new(RingState {
+                keys: Vec::new(),
+                current: 0,
+                modified: None,
+            }),
+            source: Some(path),
+        };
+        ring.reload_if_changed(&mut ring.state.lock().unwrap());
+        ring
+    }
+
+    pub fn len(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        self.reload_if_changed(&mut state);
+        state.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The key to send the next request with.
+    pub fn current(&self) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        self.reload_if_changed(&mut state);
+        state.keys.get(state.current).cloned()
+    }
+
+    /// Moves on from `key` after the API rate-limited it. Does nothing if
+    /// another request has already moved on.
+    pub fn rotate(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .keys
+            .get(state.current)
+            .is_some_and(|current| current == key)
+        {
+            state.current = (state.current + 1) % state.keys.len();
+        }
+    }
+
+    fn reload_if_changed(&self, state: &mut RingState) {
+        let Some(path) = &self.source else {
+            return;
+        };
+        let Ok(modified) = std::fs::metadata(path).and_then(|meta| meta.modified()) else {
+            return;
+        };
+        if state.modified == Some(modified) {
+            return;
+        }
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return;
+        };
+        let keys = config::parse_api_keys(&contents);
+        // Stay on the same key if it survived the edit.
+        let current = state
+            .keys
+            .get(state.current)
+            .and_then(|key| keys.iter().position(|new| new == key))
+            .unwrap_or(0);
+        *state = RingState {
+            keys,
+            current,
+            modified: Some(modified),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn rotates_past_rate_limited_keys() {
+        let ring = KeyRing::new(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(ring.current().as_deref(), Some("a"));
+        ring.rotate("a");
+        assert_eq!(ring.current().as_deref(), Some("b"));
+        // A stale rotation from a request that still used "a" is ignored.
+        ring.rotate("a");
+        assert_eq!(ring.current().as_deref(), Some("b"));
+        ring.rotate("b");
+        assert_eq!(ring.current().as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn follows_edits_to_the_credentials_file() {
+        let dir = TempDir::new("tars-keys");
+        let path = dir.join("credentials");
+        std::fs::write(&path, "a\n# spare\nb\n").unwrap();
+        let ring = KeyRing::from_file(path.clone());
+        assert_eq!(ring.len(), 2);
+        ring.rotate("a");
+        assert_eq!(ring.current().as_deref(), Some("b"));
+
+        std::fs::write(&path, "c\nb\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.current().as_deref(), Some("b"));
+        ring.rotate("b");
+        assert_eq!(ring.current().as_deref(), Some("c"));
+    }
+}
@@ -0,0 +1,691 @@
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("tars needs a TLS backend: enable the `rustls` or `native-tls` feature");
+
+mod agent;
+mod ai_sdk;
+pub mod bench;
+#[cfg(feature = "client")]
+mod client;
+mod clock;
+#[cfg(feature = "client")]
+mod commands;
+mod config;
+mod conversation;
+mod daemon;
+mod describe;
+mod export;
+pub mod fuzz;
+mod import;
+mod index;
+mod key_ring;
+mod logging;
+#[cfg(all(feature = "client", feature = "server"))]
+mod onboarding;
+mod prompt;
+mod protocol;
+#[cfg(feature = "client")]
+mod repl;
+mod replay;
+mod response_cache;
+mod schedule;
+#[cfg(feature = "server")]
+mod server;
+mod session_store;
+mod show;
+#[cfg(feature = "client")]
+mod sse;
+mod telemetry;
+#[cfg(test)]
+mod test_support;
+mod tools;
+#[cfg(feature = "client")]
+mod ui;
+#[cfg(feature = "client")]
+mod usage;
+#[cfg(feature = "server")]
+mod webhook;
+#[cfg(feature = "server")]
+mod workspace;
+
+use clap::{Args, Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(name = "tars", version, about = "Terminal-based agent")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[cfg(feature = "client")]
+    #[command(flatten)]
+    client: ClientArgs,
+    /// Config file to use instead of `~/.tars/config.toml`
+    #[arg(long, global = true, env = "TARS_CONFIG", value_name = "PATH")]
+    config: Option<std::path::PathBuf>,
+    /// Model to use instead of the configured one
+    #[arg(long, global = true)]
+    model: Option<String>,
+    /// Most tokens a reply may take [default: `max_tokens` in the config,
+    /// or 4096]
+    #[arg(long, global = true)]
+    max_tokens: Option<u32>,
+    /// Work in this directory instead of the current one
+    #[arg(long, global = true, value_name = "DIR")]
+    cwd: Option<std::path::PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the server that sessions live on
+    #[cfg(feature = "server")]
+    #[command(visible_alias = "serve")]
+    Server(ServerArgs),
+    /// Open the terminal UI on a running server, without starting one
+    #[cfg(feature = "client")]
+    Client(ClientArgs),
+    /// Open the terminal UI on a saved session, the most recent by default
+    #[cfg(feature = "client")]
+    Resume(ResumeArgs),
+    /// Re-run a saved session's prompts at temperature 0 and diff the behavior
+    Replay(ReplayArgs),
+    /// Read a saved session's transcript without resuming it
+    Show(ShowArgs),
+    /// Write a saved session's transcript as Markdown
+    Export(ExportArgs),
+    /// Measure tool latency and agent loop overhead
+    Bench(BenchArgs),
+    /// Convert a Claude Code or ChatGPT export into tars sessions
+    Import(ImportArgs),
+    /// Chat over stdin and stdout without the terminal UI
+    #[cfg(feature = "client")]
+    Repl(ReplArgs),
+    /// Write a commit message for the staged changes, or a PR description
+    Describe(DescribeArgs),
+    /// Build or update the index behind code_outline and keyword_search
+    Index(IndexArgs),
+    /// Manage recurring headless runs
+    #[command(subcommand)]
+    Schedule(ScheduleCommand),
+    /// Run the configured schedules until stopped
+    Daemon(DaemonArgs),
+    /// Show the server's API usage since it started, and how tools fared
+    #[cfg(feature = "client")]
+    Usage(UsageArgs),
+}
+
+#[cfg(feature = "client")]
+#[derive(Args, Clone)]
+struct ClientArgs {
+    #[arg(long)]
+    server: Option<String>,
+    #[arg(long)]
+    token: Option<String>,
+    /// Answer repeated API requests from an on-disk cache, when this starts
+    /// the server (for development)
+    #[arg(long, env = "TARS_CACHE_LLM")]
+    cache_llm: bool,
+    /// Label the session, e.g. `--label project=tars` (repeatable)
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    labels: Vec<(String, String)>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Args)]
+struct ServerArgs {
+    /// Address to listen on [default: `listen` in the config, or
+    /// 127.0.0.1:7331]
+    #[arg(long, env = "TARS_LISTEN")]
+    listen: Option<String>,
+    #[arg(long, env = "TARS_TOKEN")]
+    token: Option<String>,
+    /// Read-only token that may only list sessions and watch their streams
+    /// (repeatable)
+    #[arg(long = "watch-token", env = "TARS_WATCH_TOKENS", value_delimiter = ',')]
+    watch_tokens: Vec<String>,
+    /// TOML file of `[[user]]` entries with their own `name`, `token` and
+    /// `api_key`
+    #[arg(long, env = "TARS_USERS", value_name = "PATH")]
+    users: Option<std::path::PathBuf>,
+    /// Answer repeated API requests from `~/.tars/cache/responses` instead
+    /// of sending them again (for development)
+    #[arg(long, env = "TARS_CACHE_LLM")]
+    cache_llm: bool,
+    /// How request logs are written to stderr; `TARS_LOG` sets the level
+    #[arg(long, env = "TARS_LOG_FORMAT", value_enum, default_value_t)]
+    log_format: logging::LogFormat,
+}
+
+#[cfg(feature = "client")]
+#[derive(Args)]
+struct ResumeArgs {
+    /// Id of the saved session to continue [default: the most recent]
+    session: Option<String>,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+#[derive(Args)]
+struct ReplayArgs {
+    /// Session id or path to a session transcript (.jsonl)
+    session: String,
+    /// Ask the model again and replace the recorded responses
+    #[arg(long)]
+    record: bool,
+}
+
+#[derive(Args)]
+struct ShowArgs {
+    /// Session id or path to a session transcript (.jsonl)
+    session: String,
+    /// Show only these kinds of entry (comma-separated)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    only: Vec<show::EntryKind>,
+    /// Open at this turn, counting from 1
+    #[arg(long)]
+    turn: Option<usize>,
+    /// Print to stdout instead of through $PAGER
+    #[arg(long)]
+    no_pager: bool,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Session id or path to a session transcript (.jsonl)
+    session: String,
+    /// Write to this file instead of stdout
+    #[arg(long, short)]
+    output: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// Samples taken per benchmark
+    #[arg(long, default_value_t = 50)]
+    iterations: usize,
+    /// Files generated in the fixture tree
+    #[arg(long, default_value_t = 2000)]
+    files: usize,
+}
+
+#[derive(Args)]
+struct ImportArgs {
+    /// Claude Code transcript (.jsonl) or ChatGPT `conversations.json`
+    path: std::path::PathBuf,
+}
+
+#[derive(Args)]
+struct IndexArgs {
+    /// Discard the existing index and build it from scratch
+    #[arg(long)]
+    rebuild: bool,
+    /// Print the repo map the agent is given, at most this many tokens
+    #[arg(long, value_name = "TOKENS", num_args = 0..=1, default_missing_value = "1024")]
+    map: Option<usize>,
+}
+
+#[derive(Args)]
+struct DescribeArgs {
+    /// Describe the current branch as a pull request instead
+    #[arg(long)]
+    pr: bool,
+    /// Branch the pull request merges into [default: the remote's default
+    /// branch, or main]
+    #[arg(long, requires = "pr")]
+    base: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum ScheduleCommand {
+    /// Add a schedule to the config
+    Add(ScheduleAddArgs),
+    /// List schedules and how their last run went
+    List,
+    /// Remove a schedule from the config
+    Remove {
+        name: String,
+    },
+    /// Run a schedule now, in the foreground
+    Run {
+        name: String,
+    },
+    /// Show recent runs
+    History {
+        /// Only this schedule's runs
+        name: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+#[derive(Args)]
+struct ScheduleAddArgs {
+    name: String,
+    /// When to run, in local time, e.g. "0 3 * * *" or "@daily"
+    #[arg(long)]
+    cron: String,
+    /// Workspace the agent runs in
+    #[arg(long, default_value = ".")]
+    dir: std::path::PathBuf,
+    /// Shell command run when a run fails, with TARS_SCHEDULE, TARS_ERROR
+    /// and TARS_RUN_LOG set
+    #[arg(long)]
+    on_failure: Option<String>,
+    /// What the agent is asked to do each run
+    prompt: String,
+}
+
+#[derive(Args)]
+struct DaemonArgs {
+    /// How logs are written to stderr; `TARS_LOG` sets the level
+    #[arg(long, env = "TARS_LOG_FORMAT", value_enum, default_value_t)]
+    log_format: logging::LogFormat,
+}
+
+#[cfg(feature = "client")]
+#[derive(Args)]
+struct UsageArgs {
+    /// Also show calls, error rate, mean latency and output tokens per tool
+    #[arg(long)]
+    tools: bool,
+    #[arg(long)]
+    server: Option<String>,
+    #[arg(long, env = "TARS_TOKEN")]
+    token: Option<String>,
+}
+
+#[cfg(feature = "client")]
+#[derive(Args)]
+struct ReplArgs {
+    /// Read line-delimited JSON commands (send, cancel, approve) and write
+    /// events as JSON lines
+    #[arg(long)]
+    json: bool,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+/// Parses `args` into a `Cli`. The client's flags may only come without a
+/// command, while `--model` and the other global flags go on either side of
+/// one, which clap's `args_conflicts_with_subcommands` can't tell apart.
+fn parse_cli<I, T>(args: I) -> Result<Cli, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    use clap::{CommandFactory, FromArgMatches};
+
+    let matches = Cli::command().try_get_matches_from(args)?;
+    #[cfg(feature = "client")]
+    if let Some((name, _)) = matches.subcommand() {
+        let client = ClientArgs::augment_args(clap::Command::new("client"));
+        let given = client.get_arguments().find(|arg| {
+            matches.value_source(arg.get_id().as_str())
+                == Some(clap::parser::ValueSource::CommandLine)
+        });
+        if let Some(arg) = given {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "--{} can't be used with `tars {}`",
+                    arg.get_long().unwrap_or(arg.get_id().as_str()),
+                    name
+                ),
+            ));
+        }
+    }
+    Cli::from_arg_matches(&matches)
+}
+
+/// Runs the command line in `std::env::args`.
+pub async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    dotenvy::dotenv().ok();
+
+    let cli = parse_cli(std::env::args_os()).unwrap_or_else(|err| err.exit());
+    config::set_overrides(config::Overrides {
+        config: cli.config.map(std::path::absolute).transpose()?,
+        model: cli.model,
+        max_tokens: cli.max_tokens,
+    });
+    if let Some(dir) = &cli.cwd {
+        std::env::set_current_dir(dir)
+            .map_err(|err| format!("Cannot work in {}: {}", dir.display(), err))?;
+    }
+    match cli.command {
+        #[cfg(feature = "server")]
+        Some(Command::Server(args)) => {
+            logging::init(args.log_format);
+            let auth_token = server::resolve_token(args.token)?;
+            let users = match args.users {
+                Some(path) => config::load_users(&path)?,
+                None => Vec::new(),
+            };
+            let listen = match args.listen {
+                Some(listen) => listen,
+                None => config::load()?.listen().to_string(),
+            };
+            server::run(server::ServerConfig {
+                listen,
+                auth_token,
+                watch_tokens: args.watch_tokens,
+                users,
+                cache_llm: args.cache_llm,
+            })
+            .await
+        }
+        Some(Command::Replay(args)) => replay::run(&args.session, args.record).await,
+        Some(Command::Show(args)) => {
+            show::run(&args.session, &args.only, args.turn, !args.no_pager)
+        }
+        Some(Command::Export(args)) => export::run(&args.session, args.output.as_deref()),
+        Some(Command::Bench(args)) => {
+            bench::run(bench::BenchConfig {
+                iterations: args.iterations.max(1),
+                files: args.files.max(1),
+            })
+            .await
+        }
+        Some(Command::Import(args)) => import::run(&args.path),
+        Some(Command::Describe(args)) => {
+            describe::run(protocol::DescribeRequest {
+                pr: args.pr,
+                base: args.base,
+            })
+            .await
+        }
+        Some(Command::Index(args)) => index::run(args.rebuild, args.map),
+        Some(Command::Schedule(command)) => match command {
+            ScheduleCommand::Add(args) => schedule::add(config::ScheduleConfig {
+                name: args.name,
+                cron: args.cron,
+                dir: args.dir,
+                prompt: args.prompt,
+                on_failure: args.on_failure,
+            }),
+            ScheduleCommand::List => schedule::list(),
+            ScheduleCommand::Remove { name } => schedule::remove(&name),
+            ScheduleCommand::Run { name } => schedule::run(&name).await,
+            ScheduleCommand::History { name, limit } => schedule::history(name.as_deref(), limit),
+        },
+        Some(Command::Daemon(args)) => {
+            logging::init(args.log_format);
+            daemon::run().await
+        }
+        #[cfg(feature = "client")]
+        Some(Command::Usage(args)) => {
+            let server = match args.server.or_else(|| std::env::var("TARS_SERVER").ok()) {
+                Some(server) => server,
+                None => config::load()?.server_url(),
+            };
+            let token = client::resolve_token(args.token)?;
+            usage::run(&server, &token, args.tools).await
+        }
+        #[cfg(feature = "client")]
+        Some(Command::Repl(args)) => {
+            let (session, _, _) = connect(args.client, true, None).await?;
+            repl::run(session, args.json).await
+        }
+        #[cfg(feature = "client")]
+        Some(Command::Client(args)) => {
+            let (session, _, settings) = connect(args, false, None).await?;
+            run_tui(session, Vec::new(), &settings).await
+        }
+        #[cfg(feature = "client")]
+        Some(Command::Resume(args)) => {
+            let resume = args.session.as_deref().unwrap_or("last");
+            let (session, history, settings) = connect(args.client, true, Some(resume)).await?;
+            run_tui(session, history, &settings).await
+        }
+        #[cfg(feature = "client")]
+        None => {
+            let (session, _, settings) = connect(cli.client, true, None).await?;
+            run_tui(session, Vec::new(), &settings).await
+        }
+        #[cfg(not(feature = "client"))]
+        None => Err(
+            "This build of tars has no terminal UI; see `tars --help` for what it can do".into(),
+        ),
+    }
+}
+
+#[cfg(feature = "client")]
+async fn run_tui(
+    session: client::ClientSession,
+    history: Vec<ai_sdk::MessageParam>,
+    settings: &config::Config,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ui::run_tui(
+        session,
+        history,
+        settings.agent_name().to_string(),
+        settings.model(),
+        settings.compare.clone(),
+        settings.theme,
+    )
+    .await
+}
+
+/// Opens a session on the server, or continues the saved one `resume`
+/// names, first starting a server when `start` allows. Returns the
+/// session's history and the settings too, as onboarding may have just
+/// written them.
+#[cfg(feature = "client")]
+async fn connect(
+    args: ClientArgs,
+    start: bool,
+    resume: Option<&str>,
+) -> Result<
+    (
+        client::ClientSession,
+        Vec<ai_sdk::MessageParam>,
+        config::Config,
+    ),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let base_url = match args.server.or_else(|| std::env::var("TARS_SERVER").ok()) {
+        Some(base_url) => base_url,
+        None => config::load()?.server_url(),
+    };
+
+    let token = args.token.or_else(|| std::env::var("TARS_TOKEN").ok());
+    let auth_token = if start {
+        start_local_server(&base_url, token, args.cache_llm).await?
+    } else {
+        token
+    };
+
+    let auth_token = match auth_token {
+        Some(token) => token,
+        None => client::resolve_token(None)?,
+    };
+    let settings = config::load()?;
+    let config = client::ClientConfig {
+        base_url,
+        token: auth_token,
+        http: settings.network.client()?,
+        labels: args.labels.into_iter().collect(),
+    };
+    let (session, history) = match resume {
+        Some(session_id) => client::ClientSession::resume(config, session_id).await?,
+        None => (client::ClientSession::connect(config).await?, Vec::new()),
+    };
+    Ok((session, history, settings))
+}
+
+/// Starts a server in the background when `base_url` is local and nothing
+/// is listening there, and returns the token to use: the started server's,
+/// or else `token`.
+#[cfg(all(feature = "client", feature = "server"))]
+async fn start_local_server(
+    base_url: &str,
+    token: Option<String>,
+    cache_llm: bool,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(host_port) = host_port_from_base_url(base_url)
+        && is_local_http(base_url)
+        && !is_server_reachable(&host_port).await
+    {
+        onboarding::ensure_api_key().await?;
+        let server_token = server::resolve_token(token)?;
+        spawn_server(host_port.clone(), server_token.clone(), cache_llm);
+        wait_for_server(&host_port).await?;
+        return Ok(Some(server_token));
+    }
+    Ok(token)
+}
+
+/// Without a server to start, the token as given.
+#[cfg(all(feature = "client", not(feature = "server")))]
+async fn start_local_server(
+    _base_url: &str,
+    token: Option<String>,
+    _cache_llm: bool,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(token)
+}
+
+#[cfg(feature = "client")]
+fn parse_label(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err("expected KEY=VALUE".to_string()),
+    }
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+fn host_port_from_base_url(base_url: &str) -> Option<String> {
+    let base = base_url.trim();
+    let without_scheme = base
+        .strip_prefix("http://")
+        .or_else(|| base.strip_prefix("https://"))?;
+    let host_port = without_scheme.split('/').next()?.trim();
+    if host_port.is_empty() {
+        None
+    } else {
+        Some(ensure_port(host_port))
+    }
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+fn ensure_port(host_port: &str) -> String {
+    if host_port.starts_with('[') {
+        if host_port.contains("]:") {
+            host_port.to_string()
+        } else {
+            format!("{}:7331", host_port)
+        }
+    } else if host_port.contains(':') {
+        host_port.to_string()
+    } else {
+        format!("{}:7331", host_port)
+    }
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+fn is_local_http(base_url: &str) -> bool {
+    let base = base_url.trim();
+    let without_scheme = match base.strip_prefix("http://") {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let host_port = without_scheme.split('/').next().unwrap_or("");
+    host_port.starts_with("127.0.0.1")
+        || host_port.starts_with("localhost")
+        || host_port.starts_with("[::1]")
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+async fn is_server_reachable(host_port: &str) -> bool {
+    tokio::net::TcpStream::connect(host_port).await.is_ok()
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+fn spawn_server(listen: String, token: String, cache_llm: bool) {
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build();
+        match runtime {
+            Ok(rt) => {
+                let result = rt.block_on(server::run(server::ServerConfig {
+                    listen,
+                    auth_token: token,
+                    watch_tokens: std::env::var("TARS_WATCH_TOKENS")
+                        .map(|tokens| tokens.split(',').map(str::to_string).collect())
+                        .unwrap_or_default(),
+                    users: Vec::new(),
+                    cache_llm,
+                }));
+                if let Err(err) = result {
+                    eprintln!("tars server stopped: {}", err);
+                }
+            }
+            Err(err) => {
+                eprintln!("failed to start server runtime: {}", err);
+            }
+        }
+    });
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+async fn wait_for_server(host_port: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    for _ in 0..20 {
+        if is_server_reachable(host_port).await {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    Err("Server did not start listening in time".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[cfg(all(feature = "server", feature = "client"))]
+    #[test]
+    fn serve_is_server_and_client_skips_it() {
+        let cli = parse_cli(["tars", "serve", "--listen", "127.0.0.1:9000"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Server(ServerArgs { listen: Some(ref listen), .. })) if listen == "127.0.0.1:9000"
+        ));
+
+        let cli = parse_cli(["tars", "client", "--server", "http://remote:7331"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Client(ClientArgs { server: Some(ref server), .. })) if server == "http://remote:7331"
+        ));
+
+        let cli = parse_cli(["tars", "--server", "http://remote:7331"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.client.server.as_deref(), Some("http://remote:7331"));
+    }
+
+    #[test]
+    fn model_max_tokens_and_cwd_apply_to_any_command() {
+        let cli = parse_cli([
+            "tars",
+            "--model",
+            "sonnet",
+            "index",
+            "--max-tokens",
+            "2000",
+            "--cwd",
+            "/tmp",
+        ])
+        .unwrap();
+        assert!(matches!(cli.command, Some(Command::Index(_))));
+        assert_eq!(cli.model.as_deref(), Some("sonnet"));
+        assert_eq!(cli.max_tokens, Some(2000));
+        assert_eq!(cli.cwd.as_deref(), Some(std::path::Path::new("/tmp")));
+
+        assert!(parse_cli(["tars", "--max-tokens", "lots"]).is_err());
+        #[cfg(feature = "client")]
+        assert!(parse_cli(["tars", "--server", "http://remote:7331", "index"]).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use crate::telemetry;
+use clap::ValueEnum;
+use std::io::IsTerminal;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Filters log records, in `tracing_subscriber`'s `EnvFilter` syntax, e.g.
+/// `TARS_LOG=debug` or `TARS_LOG=tars=info,tower_http=debug`.
+const FILTER_VAR: &str = "TARS_LOG";
+const DEFAULT_FILTER: &str = "info";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// One human-readable line per record.
+    #[default]
+    Text,
+    /// One JSON object per record, for log collectors. A request's
+    /// method, path, session and token are under `span`.
+    Json,
+}
+
+/// Sends log records to stderr for `tars server` and `tars daemon`, and
+/// spans and metrics to an OpenTelemetry collector when one is configured.
+/// The server the TUI starts in the background doesn't log.
+pub fn init(format: LogFormat) {
+    let filter =
+        EnvFilter::try_from_env(FILTER_VAR).unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let logs = tracing_subscriber::fmt::layer()
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_writer(std::io::stderr);
+    let logs = match format {
+        LogFormat::Text => logs.boxed(),
+        LogFormat::Json => logs
+            .json()
+            .flatten_event(true)
+            .with_span_list(false)
+            .boxed(),
+    };
+    let otel = telemetry::layer().unwrap_or_else(|err| {
+        eprintln!("OpenTelemetry export is off: {}", err);
+        None
+    });
+    // Exported spans don't depend on `TARS_LOG`.
+    tracing_subscriber::registry()
+        .with(logs.with_filter(filter))
+        .with(otel.map(|otel| otel.with_filter(LevelFilter::INFO)))
+        .init();
+}
@@ -0,0 +1,185 @@
+use crate::ai_sdk::{check_api_key, KeyCheck};
+use crate::config::{self, Config};
+use std::error::Error;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+type OnboardingResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Providers offered during setup, by their `provider` setting.
+const PROVIDERS: &[(&str, &str)] = &[
+    ("anthropic", "the Anthropic API, with an API key"),
+    ("vertex", "Google Vertex AI, with Google Cloud credentials"),
+];
+
+/// Models offered during setup, with a short note on each.
+const MODELS: &[(&str, &str)] = &[
+    ("claude-haiku-4-5-20251001", "fast and inexpensive"),
+    (
+        "claude-sonnet-4-5-20250929",
+        "balanced speed and capability",
+    ),
+    ("claude-opus-4-1-20250805", "most capable, slowest"),
+];
+
+/// Approval policies offered during setup, as what goes in
+/// `[tools] auto_approve`.
+const APPROVAL_POLICIES: &[(&[&str], &str, &str)] = &[
+    (&[], "ask", "before every edit and command"),
+    (
+        &["create_directory", "edit_file"],
+        "edits",
+        "let file edits run unasked, ask before commands",
+    ),
+    (&["*"], "never ask", "run everything unasked"),
+];
+
+/// Walks a first-time user through choosing a provider, saving an API key
+/// or naming a Google Cloud project, and picking a default model and an
+/// approval policy, then writes `~/.tars/config.toml`.
+fn run() -> OnboardingResult<Config> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut config = config::load()?;
+
+    println!("Welcome to tars! Nothing is set up yet, so let's do that.");
+    println!();
+
+    println!("Provider:");
+    config.provider = PROVIDERS[choose(&mut input, PROVIDERS)?].0.to_string();
+    println!();
+
+    let key = if config.provider == "anthropic" {
+        Some(prompt_api_key()?)
+    } else {
+        println!(
+            "Requests are signed with your Application Default Credentials; run `gcloud auth application-default login` if you haven't."
+        );
+        config.vertex.project = loop {
+            let project = prompt(&mut input, "Google Cloud project: ")?;
+            if !project.is_empty() {
+                break Some(project);
+            }
+            println!("The project can't be empty.");
+        };
+        let region = prompt(&mut input, "Region [global]: ")?;
+        config.vertex.region = (!region.is_empty()).then_some(region);
+        None
+    };
+    println!();
+
+    println!("Default model:");
+    config.model = Some(MODELS[choose(&mut input, MODELS)?].0.to_string());
+    println!();
+
+    println!("Approval before tools change the workspace:");
+    let policies: Vec<(&str, &str)> = APPROVAL_POLICIES
+        .iter()
+        .map(|&(_, name, note)| (name, note))
+        .collect();
+    let (tools, _, _) = APPROVAL_POLICIES[choose(&mut input, &policies)?];
+    config.tools.auto_approve = tools.iter().map(|tool| tool.to_string()).collect();
+
+    let key_location = key.map(|key| config::save_api_key(&key, None)).transpose()?;
+    config::save(&config)?;
+    println!();
+    println!("Saved settings to {}.", config::config_path().display());
+    if let Some(location) = key_location {
+        println!("Saved the key to {}.", location);
+    }
+    println!();
+    Ok(config)
+}
+
+/// Makes sure the local server will start with a usable key: runs setup
+/// when there is none and asks again when the API rejects the saved one. An
+/// unreachable API only warns, since the key may be fine. Vertex AI uses
+/// Google credentials, which are checked on the first request instead.
+pub async fn ensure_api_key() -> OnboardingResult<()> {
+    if config::load()?.provider == "vertex" {
+        return Ok(());
+    }
+    let interactive = io::stdin().is_terminal();
+    let mut key = match config::api_key() {
+        Some(key) => key,
+        None if interactive => {
+            if run()?.provider == "vertex" {
+                return Ok(());
+            }
+            config::api_key().ok_or(config::MISSING_API_KEY)?
+        }
+        None => return Err(config::MISSING_API_KEY.into()),
+    };
+
+    let settings = config::load()?;
+    let client = settings.network.client()?;
+    let base_url = settings.base_url();
+    loop {
+        match check_api_key(&client, &base_url, &key).await {
+            KeyCheck::Valid => return Ok(()),
+            KeyCheck::Unreachable(reason) => {
+                eprintln!(
+                    "Could not reach the API at {} ({}). Starting anyway; turns will fail until it is reachable.",
+                    base_url, reason
+                );
+                return Ok(());
+            }
+            KeyCheck::Rejected(reason) if config::api_key_from_env() => {
+                return Err(
+                    format!("ANTHROPIC_API_KEY was rejected by the API ({})", reason).into(),
+                );
+            }
+            KeyCheck::Rejected(reason) if !interactive => {
+                return Err(format!(
+                    "The saved API key was rejected ({}). Run tars in a terminal to replace it.",
+                    reason
+                )
+                .into());
+            }
+            KeyCheck::Rejected(reason) => {
+                println!("The saved API key was rejected ({}).", reason);
+                let rejected = std::mem::replace(&mut key, prompt_api_key()?);
+                let location = config::save_api_key(&key, Some(&rejected))?;
+                println!("Saved the key to {}.", location);
+            }
+        }
+    }
+}
+
+fn prompt_api_key() -> OnboardingResult<String> {
+    loop {
+        println!("Paste your Anthropic API key (https://console.anthropic.com/settings/keys).");
+        let key = rpassword::prompt_password("API key (hidden): ")?;
+        let key = key.trim().to_string();
+        if key.is_empty() {
+            println!("The key can't be empty.");
+        } else {
+            return Ok(key);
+        }
+    }
+}
+
+/// Lists `items` by number and returns the index of the one chosen, the
+/// first by default.
+fn choose(input: &mut impl BufRead, items: &[(&str, &str)]) -> OnboardingResult<usize> {
+    for (index, (name, note)) in items.iter().enumerate() {
+        println!("  {}) {:<28} {}", index + 1, name, note);
+    }
+    loop {
+        let answer = prompt(input, "Choose [1]: ")?;
+        match answer.parse::<usize>() {
+            _ if answer.is_empty() => return Ok(0),
+            Ok(choice) if (1..=items.len()).contains(&choice) => return Ok(choice - 1),
+            _ => println!("Enter a number from 1 to {}.", items.len()),
+        }
+    }
+}
+
+fn prompt(input: &mut impl BufRead, label: &str) -> OnboardingResult<String> {
+    print!("{}", label);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err("Setup cancelled".into());
+    }
+    Ok(line.trim().to_string())
+}
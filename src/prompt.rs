@@ -0,0 +1,181 @@
+use crate::clock;
+use crate::config::{PromptConfig, PromptSegment};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The base segment unless the config replaces it.
+const BASE_PROMPT: &str = "You are tars, a coding agent working in the user's workspace through the tools you are given. Look at the code before changing it, keep each change as small as the task allows, and check your work with the project's own tests and tools where it has them. When you are done, say plainly what you changed and anything you left undone.";
+
+/// The system prompt's segments, assembled for each request. Files are
+/// read again whenever their size or modification time changes, so an
+/// edited `TARS.md` applies from the next request on.
+pub(crate) struct SystemPrompt {
+    config: PromptConfig,
+    files: Mutex<HashMap<PathBuf, CachedFile>>,
+}
+
+struct CachedFile {
+    len: u64,
+    modified: Option<SystemTime>,
+    contents: String,
+}
+
+impl SystemPrompt {
+    pub(crate) fn new(config: PromptConfig) -> Self {
+        Self {
+            config,
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Each configured segment with something to say, in order, for the
+    /// workspace at `root`.
+    pub(crate) fn segments(&self, root: &Path) -> Vec<String> {
+        self.config
+            .segments
+            .iter()
+            .filter_map(|segment| self.segment(*segment, root))
+            .collect()
+    }
+
+    fn segment(&self, segment: PromptSegment, root: &Path) -> Option<String> {
+        match segment {
+            PromptSegment::Base => Some(
+                self.config
+                    .base
+                    .as_deref()
+                    .unwrap_or(BASE_PROMPT)
+                    .to_string(),
+            ),
+            PromptSegment::Environment => {
+                let today = clock::local_now().date();
+                Some(format!(
+                    "Working directory: {}\nPlatform: {} ({})\nToday's date: {}",
+                    root.display(),
+                    std::env::consts::OS,
+                    std::env::consts::ARCH,
+                    today
+                ))
+            }
+            PromptSegment::Global => {
+                let contents = self.read(&self.config.global_path())?;
+                Some(format!(
+                    "The user's instructions for every project:\n\n{}",
+                    contents
+                ))
+            }
+            PromptSegment::Project => {
+                let instructions: Vec<String> = self
+                    .config
+                    .instructions
+                    .iter()
+                    .filter_map(|path| {
+                        let contents = self.read(&root.join(path))?;
+                        Some(format!(
+                            "Instructions for this project, from {}:\n\n{}",
+                            path.display(),
+                            contents
+                        ))
+                    })
+                    .collect();
+                (!instructions.is_empty()).then(|| instructions.join("\n\n"))
+            }
+            PromptSegment::Memory => {
+                let contents = self.read(&self.config.memory_path())?;
+                Some(format!("Notes kept from earlier sessions:\n\n{}", contents))
+            }
+            PromptSegment::Custom => self.config.custom.clone(),
+        }
+    }
+
+    /// The trimmed contents of `path`, or `None` when it is missing, empty
+    /// or unreadable.
+    fn read(&self, path: &Path) -> Option<String> {
+        let mut files = self.files.lock().unwrap();
+        let Ok(metadata) = std::fs::metadata(path) else {
+            files.remove(path);
+            return None;
+        };
+        let (len, modified) = (metadata.len(), metadata.modified().ok());
+        let reload = match files.get(path) {
+            Some(cached) => cached.len != len || cached.modified != modified,
+            None => true,
+        };
+        if reload {
+            let contents = match std::fs::read_to_string(path) {
+                Ok(contents) => contents.trim().to_string(),
+                Err(err) => {
+                    tracing::warn!("Could not read {}: {}", path.display(), err);
+                    files.remove(path);
+                    return None;
+                }
+            };
+            if files.contains_key(path) {
+                tracing::info!("Reloaded {}", path.display());
+            }
+            files.insert(
+                path.to_path_buf(),
+                CachedFile {
+                    len,
+                    modified,
+                    contents,
+                },
+            );
+        }
+        let contents = &files[path].contents;
+        (!contents.is_empty()).then(|| contents.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn edited_instruction_files_apply_to_the_next_request() {
+        let root = TempDir::new("tars-prompt");
+        let prompt = SystemPrompt::new(PromptConfig {
+            segments: vec![PromptSegment::Custom, PromptSegment::Project],
+            custom: Some("Answer in French.".to_string()),
+            ..PromptConfig::default()
+        });
+        assert_eq!(prompt.segments(&root), ["Answer in French."]);
+
+        std::fs::write(root.join("AGENTS.md"), "Run `just test`.\n").unwrap();
+        assert_eq!(
+            prompt.segments(&root)[1],
+            "Instructions for this project, from AGENTS.md:\n\nRun `just test`."
+        );
+        // A different length is enough to tell, whatever the filesystem's
+        // timestamp resolution.
+        std::fs::write(root.join("AGENTS.md"), "Run `cargo nextest run`.").unwrap();
+        assert!(prompt.segments(&root)[1].ends_with("Run `cargo nextest run`."));
+
+        std::fs::remove_file(root.join("AGENTS.md")).unwrap();
+        assert_eq!(prompt.segments(&root).len(), 1);
+    }
+
+    #[test]
+    fn global_instructions_come_before_the_projects() {
+        let root = TempDir::new("tars-prompt");
+        std::fs::write(root.join("global.md"), "Never push.").unwrap();
+        std::fs::write(root.join("TARS.md"), "Use tabs.").unwrap();
+        std::fs::write(root.join("AGENTS.md"), "Run `just test`.").unwrap();
+        let prompt = SystemPrompt::new(PromptConfig {
+            segments: vec![PromptSegment::Global, PromptSegment::Project],
+            global: Some(root.join("global.md")),
+            ..PromptConfig::default()
+        });
+        assert_eq!(
+            prompt.segments(&root),
+            [
+                "The user's instructions for every project:\n\nNever push.",
+                "Instructions for this project, from TARS.md:\n\nUse tabs.\n\n\
+                 Instructions for this project, from AGENTS.md:\n\nRun `just test`."
+            ]
+        );
+    }
+}
@@ -1,25 +1,417 @@
+#[cfg(any(feature = "server", feature = "client"))]
+use crate::ai_sdk::{MessageParam, ToolChoice};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "server", feature = "client"))]
+use std::collections::BTreeMap;
 
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SessionCreateRequest {
+    /// Free-form tags such as a project name, ticket id or user, for
+    /// finding the session again with `GET /sessions`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Continue a session saved on the server, by id or `last` for the most
+    /// recent, instead of starting an empty one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume: Option<String>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionCreateResponse {
     pub session_id: String,
+    /// The history of a resumed session.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<MessageParam>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionListResponse {
+    pub sessions: Vec<SessionSummary>,
 }
 
+#[cfg(any(feature = "server", feature = "client"))]
 #[derive(Debug, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    /// The user who created the session, on a multi-user server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SendMessageRequest {
     pub content: String,
+    /// Keep this message through history truncation.
+    #[serde(default)]
+    pub pin: bool,
+    /// Text the assistant's reply must start with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefill: Option<String>,
+    /// Tool use for the first reply of the turn; later replies are left to
+    /// the model so a forced tool is not called forever.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    /// Answer the message with each of these models instead, limited to
+    /// read-only tools, for `/compare`. The answers arrive together as one
+    /// `StreamEvent::Comparison` and are not added to the history.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compare: Vec<String>,
+    /// Have this `[profile.<name>]`'s planner model plan the turn first,
+    /// and its executor carry the plan out. The plan arrives as a
+    /// `StreamEvent::Plan`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Run the turn with this model, or an alias such as `sonnet`, instead
+    /// of the server's, as after `/model`. A profile's models take its
+    /// place.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReminderRequest {
+    /// A note for the model, e.g. that files changed outside the session.
+    pub text: String,
+}
+
+/// The user's answer to a `StreamEvent::ApprovalNeeded`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    pub decision: ApprovalDecision,
+    /// Passed on to the model with a denial, e.g. what to do instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    Approve,
+    Deny,
+    /// Approve this call and every later call to the same tool in the
+    /// session.
+    Always,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinResponse {
+    pub text: String,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendMessageResponse {
+    /// Carried by every event the turn produces and by its saved messages.
+    pub turn_id: String,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ForkRequest {
+    /// Copy only the messages before this index, which must start a turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up_to: Option<usize>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RewindResponse {
+    /// The prompt of the removed turn.
+    pub text: String,
+    pub pinned: bool,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveResponse {
+    /// Whether there was anything to save; empty sessions are dropped.
+    pub saved: bool,
+}
+
+/// What `/describe` writes: a commit message for the staged changes, or
+/// with `pr`, a pull request description for the current branch.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DescribeRequest {
+    #[serde(default)]
+    pub pr: bool,
+    /// The branch a pull request would merge into; by default the
+    /// remote's default branch, or `main`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DescribeResponse {
+    pub text: String,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArtifactListResponse {
+    pub artifacts: Vec<ArtifactInfo>,
+}
+
+/// A file saved with the `save_artifact` tool.
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactInfo {
+    pub name: String,
+    /// Relative to the server's working directory.
+    pub path: String,
+    pub size: u64,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthResponse {
+    /// Whether the server can currently reach the model API.
+    pub api_reachable: bool,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageResponse {
+    pub users: Vec<UserUsage>,
+    /// Tool calls made with the same keys, by tool.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolStats>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserUsage {
+    /// `None` for the server's own API key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(flatten)]
+    pub usage: UsageTotals,
+}
+
+/// API usage since the server started.
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// How calls to one tool have gone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolStats {
+    pub name: String,
+    pub calls: u64,
+    /// Calls that ended in an error, `invalid_input` ones included.
+    pub errors: u64,
+    /// Calls turned away because their input didn't match the tool's
+    /// schema, which points at the prompt or the tool's description rather
+    /// than the tool.
+    pub invalid_input: u64,
+    /// Time spent running the tool, over every call that ran.
+    pub total_ms: u64,
+    /// What the tool's output cost the model, at about four characters a
+    /// token.
+    pub output_tokens: u64,
+}
+
+/// The tool calls a session has made.
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionStatsResponse {
+    pub tools: Vec<ToolStats>,
+}
+
+/// How one model response was produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseStats {
+    pub model: String,
+    pub elapsed_ms: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// One model's answer to a compared prompt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparedAnswer {
+    pub model: String,
+    /// The text of every reply, in order.
+    pub text: String,
+    /// How many read-only tool calls the model made on the way.
+    pub tool_calls: usize,
+    /// Totals over every reply; `None` when none arrived.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ResponseStats>,
+    /// Why the model did not finish.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Version of the `EventEnvelope` format, bumped on incompatible changes.
+#[cfg(any(feature = "server", all(test, feature = "client")))]
+pub const EVENT_VERSION: u32 = 1;
+
+/// What the session stream carries: a `StreamEvent` together with what is
+/// needed to order, time and attribute it.
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    pub version: u32,
+    /// Position in the session's stream, counting from 1. A gap means the
+    /// subscriber fell behind and missed events.
+    pub seq: u64,
+    /// When the server emitted the event, in milliseconds since the Unix
+    /// epoch.
+    pub timestamp_ms: u64,
+    pub session_id: String,
+    /// The turn that produced the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turn_id: Option<String>,
+    pub event: StreamEvent,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum StreamEvent {
-    Assistant { text: String },
+    Assistant {
+        text: String,
+        /// Set on the last text block of each model response.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stats: Option<ResponseStats>,
+    },
     ToolCall {
+        /// Matches the `tool_use_id` of the call's `ToolResult`.
+        tool_use_id: String,
         name: String,
         input: serde_json::Value,
     },
-    ToolResult { content: String, is_error: bool },
+    ToolResult {
+        tool_use_id: String,
+        name: String,
+        content: String,
+        is_error: bool,
+    },
+    /// The call with this id waits until the user answers it at
+    /// `POST /sessions/<id>/approvals/<tool_use_id>`. Follows the call's
+    /// `ToolCall`.
+    ApprovalNeeded {
+        tool_use_id: String,
+        name: String,
+        /// The call in one line, e.g. `✏️ edit src/main.rs (+3 -1)`.
+        summary: String,
+    },
     Info { message: String },
+    /// Something the user should notice about a reply, such as it being
+    /// cut short or refused.
+    Warning { message: String },
     Error { message: String },
+    /// The turn could not reach the model API and was rolled back; the
+    /// prompt should be sent again once the API is reachable.
+    Offline { message: String },
+    /// Sent every few seconds while a turn runs, so a quiet turn can be told
+    /// apart from a dead connection.
+    Heartbeat {
+        activity: TurnActivity,
+        /// Time since the turn started.
+        elapsed_ms: u64,
+    },
+    /// A profile's planner broke the turn's message into these steps,
+    /// which its executor carries out next.
+    Plan {
+        steps: Vec<String>,
+        stats: ResponseStats,
+    },
+    /// The agent changed its plan with the `todo` tool; carries the whole
+    /// list.
+    TodoUpdate { items: Vec<TodoItem> },
+    /// A tool changed files; carries the diff of every file changed in the
+    /// session so far.
+    DiffUpdate { files: Vec<FileDiff> },
+    /// Every model's answer to a `/compare` prompt, in the order the
+    /// models were asked.
+    Comparison { answers: Vec<ComparedAnswer> },
+    /// The turn was stopped on request. A prompt that had no reply yet is
+    /// removed from the history.
+    Cancelled,
     Done,
 }
+
+/// The files under the server's working directory, for browsing a
+/// session's workspace.
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceFilesResponse {
+    pub files: Vec<WorkspaceFile>,
+    /// Set when the listing stopped at the server's limit.
+    pub truncated: bool,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceFile {
+    pub path: String,
+    /// The agent read the file this session.
+    pub read: bool,
+    /// The agent changed the file this session.
+    pub modified: bool,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilePreviewResponse {
+    pub path: String,
+    pub content: String,
+    /// Set when only the start of the file was returned.
+    pub truncated: bool,
+}
+
+/// A file changed during a session, as a unified diff against its contents
+/// before the first change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileDiff {
+    pub path: String,
+    pub diff: String,
+}
+
+/// One step of the agent's plan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoItem {
+    /// Numbered from 1 in the order the steps were added.
+    pub id: u32,
+    pub text: String,
+    pub status: TodoStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoStatus {
+    Pending,
+    InProgress,
+    Completed,
+}
+
+/// What a running turn is waiting on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TurnActivity {
+    /// Waiting for the model.
+    Thinking,
+    RunningTool {
+        name: String,
+        /// How far the tool has got, for tools that report it, such as
+        /// `download`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        progress: Option<String>,
+    },
+    /// Waiting for the user to approve a call to this tool.
+    AwaitingApproval { name: String },
+}
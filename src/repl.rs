@@ -0,0 +1,249 @@
+use crate::client::ClientSession;
+use crate::protocol::{
+    ApprovalDecision, ApprovalRequest, EventEnvelope, SendMessageRequest, StreamEvent,
+};
+use crate::schedule::print_event;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::pin::pin;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+type ReplResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// One line of `tars repl --json` input.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ReplCommand {
+    /// Starts a turn; takes the fields of `POST /sessions/<id>/messages`.
+    Send(SendMessageRequest),
+    /// Stops the running turn.
+    Cancel,
+    /// Lets a tool call that is waiting for approval run; with `always`,
+    /// later calls to the same tool too.
+    Approve {
+        tool_use_id: String,
+        #[serde(default)]
+        always: bool,
+    },
+    /// Turns down a tool call that is waiting for approval, telling the
+    /// model `message` when given.
+    Deny {
+        tool_use_id: String,
+        #[serde(default)]
+        message: Option<String>,
+    },
+}
+
+/// One line of `tars repl --json` output.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ReplOutput {
+    /// Written once, before any command is read.
+    Ready { session_id: String },
+    /// A `send` started a turn; its events follow.
+    Sent { turn_id: String },
+    /// A session event, as the stream endpoint delivers it.
+    Event(EventEnvelope),
+    /// A command was malformed or refused. The session carries on.
+    Error { message: String },
+}
+
+/// Drives a session from stdin without a terminal UI.
+///
+/// With `json`, every input line is a `ReplCommand` and every output line a
+/// `ReplOutput`, for scripts and end-to-end tests. Otherwise each line is a
+/// prompt and the replies are printed as plain text, except that while a
+/// tool call waits for approval the next line answers it: `y`, `a` for
+/// always, `n`, or a reason to deny it with. Either way, closing stdin lets
+/// the running turn finish before the process exits.
+pub async fn run(client: ClientSession, json: bool) -> ReplResult<()> {
+    let mut events = pin!(client.subscribe().await?);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdin_open = true;
+    let mut running = false;
+    // The call waiting for approval, for plain-text answers.
+    let mut waiting: Option<String> = None;
+
+    if json {
+        write(&ReplOutput::Ready {
+            session_id: client.session_id().to_string(),
+        });
+    }
+    loop {
+        tokio::select! {
+            line = lines.next_line(), if stdin_open => {
+                let Some(line) = line? else {
+                    stdin_open = false;
+                    if running {
+                        continue;
+                    }
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let result = if json {
+                    match serde_json::from_str(&line) {
+                        Ok(command) => execute(&client, command).await,
+                        Err(err) => Err(format!("invalid command: {}", err).into()),
+                    }
+                } else {
+                    let command = match waiting.take() {
+                        Some(tool_use_id) => answer(tool_use_id, line),
+                        None => ReplCommand::Send(SendMessageRequest {
+                            content: line,
+                            ..Default::default()
+                        }),
+                    };
+                    execute(&client, command).await
+                };
+                match result {
+                    Ok(Some(turn_id)) => {
+                        running = true;
+                        if json {
+                            write(&ReplOutput::Sent { turn_id });
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(err) if json => write(&ReplOutput::Error {
+                        message: err.to_string(),
+                    }),
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+            envelope = events.next() => {
+                let Some(envelope) = envelope else {
+                    return Err("The server closed the session's event stream".into());
+                };
+                let envelope = envelope?;
+                let done = matches!(envelope.event, StreamEvent::Done);
+                match &envelope.event {
+                    StreamEvent::ApprovalNeeded { tool_use_id, .. } => {
+                        waiting = Some(tool_use_id.clone());
+                    }
+                    StreamEvent::ToolResult { .. } | StreamEvent::Done => waiting = None,
+                    _ => {}
+                }
+                if json {
+                    write(&ReplOutput::Event(envelope));
+                } else {
+                    print_event(envelope.event);
+                }
+                if done {
+                    running = false;
+                    if !stdin_open {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs a command, returning the turn id when it started a turn.
+async fn execute(client: &ClientSession, command: ReplCommand) -> ReplResult<Option<String>> {
+    match command {
+        ReplCommand::Send(request) => Ok(Some(client.send_message(request).await?)),
+        ReplCommand::Cancel => {
+            client.cancel().await?;
+            Ok(None)
+        }
+        ReplCommand::Approve {
+            tool_use_id,
+            always,
+        } => {
+            let decision = if always {
+                ApprovalDecision::Always
+            } else {
+                ApprovalDecision::Approve
+            };
+            let request = ApprovalRequest {
+                decision,
+                message: None,
+            };
+            client.answer_approval(&tool_use_id, request).await?;
+            Ok(None)
+        }
+        ReplCommand::Deny {
+            tool_use_id,
+            message,
+        } => {
+            let request = ApprovalRequest {
+                decision: ApprovalDecision::Deny,
+                message,
+            };
+            client.answer_approval(&tool_use_id, request).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// A plain-text line read while a call waits for approval, as the answer
+/// to it.
+fn answer(tool_use_id: String, line: String) -> ReplCommand {
+    match line.trim().to_lowercase().as_str() {
+        "y" | "yes" => ReplCommand::Approve {
+            tool_use_id,
+            always: false,
+        },
+        "a" | "always" => ReplCommand::Approve {
+            tool_use_id,
+            always: true,
+        },
+        "n" | "no" => ReplCommand::Deny {
+            tool_use_id,
+            message: None,
+        },
+        _ => ReplCommand::Deny {
+            tool_use_id,
+            message: Some(line),
+        },
+    }
+}
+
+fn write(output: &ReplOutput) {
+    println!("{}", serde_json::to_string(output).unwrap_or_default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_and_output_are_tagged_by_type() {
+        let command: ReplCommand =
+            serde_json::from_str(r#"{"type":"send","content":"hi","pin":true}"#).unwrap();
+        assert!(matches!(
+            command,
+            ReplCommand::Send(SendMessageRequest { ref content, pin: true, .. }) if content == "hi"
+        ));
+        assert!(matches!(
+            serde_json::from_str(r#"{"type":"cancel"}"#).unwrap(),
+            ReplCommand::Cancel
+        ));
+        assert!(serde_json::from_str::<ReplCommand>(r#"{"type":"send"}"#).is_err());
+        assert!(matches!(
+            serde_json::from_str(r#"{"type":"approve","tool_use_id":"t1"}"#).unwrap(),
+            ReplCommand::Approve { ref tool_use_id, always: false } if tool_use_id == "t1"
+        ));
+        assert!(matches!(
+            answer("t1".to_string(), "use a branch".to_string()),
+            ReplCommand::Deny { message: Some(ref message), .. } if message == "use a branch"
+        ));
+
+        let event = ReplOutput::Event(EventEnvelope {
+            version: 1,
+            seq: 3,
+            timestamp_ms: 0,
+            session_id: "s".to_string(),
+            turn_id: None,
+            event: StreamEvent::Done,
+        });
+        let line = serde_json::to_value(&event).unwrap();
+        assert_eq!(line["type"], "event");
+        assert_eq!(line["seq"], 3);
+        assert_eq!(line["event"]["type"], "done");
+    }
+}
@@ -0,0 +1,519 @@
+use crate::agent::{self, Agent};
+use crate::ai_sdk::{
+    Capabilities, ContentBlock, MessageParam, MessageRequest, MessageResponse, Provider,
+    ProviderResult,
+};
+use crate::config;
+use crate::conversation::{split_turns, text_content, Conversation};
+use crate::key_ring::KeyRing;
+use crate::session_store;
+use crate::tools::ToolContext;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+type ReplayResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// What the agent did in response to one user prompt.
+#[derive(Debug, PartialEq)]
+struct TurnSummary {
+    prompt: String,
+    tool_calls: Vec<String>,
+    reply: String,
+}
+
+/// Re-runs the user prompts of a saved session against the current agent
+/// with temperature 0 and reports where its behavior diverges from the
+/// recording.
+///
+/// The first replay of a session calls the model and records its responses
+/// in `~/.tars/replays/`; later ones are answered from that recording, so
+/// they cost nothing and only the agent's own code can change the outcome.
+/// `record` calls the model again and replaces the recording. Calls that
+/// would change the workspace never run: they get the result the session
+/// saved for the same call. Read-only tools run for real.
+pub async fn run(session: &str, record: bool) -> ReplayResult<()> {
+    let path = session_store::resolve(session);
+    let messages = session_store::load(&path)?;
+    let recorded = summarize(&messages);
+    if recorded.is_empty() {
+        return Err(format!("{} contains no user prompts", path.display()).into());
+    }
+
+    let settings = config::load()?;
+    let tape = tape_path(&path);
+    let exchanges = match record {
+        true => None,
+        false => read_tape(&tape)?,
+    };
+    let keys = KeyRing::from_config();
+    if exchanges.is_none() && settings.provider == "anthropic" && keys.is_empty() {
+        return Err(config::MISSING_API_KEY.into());
+    }
+    let live = agent::backend(&settings, keys)?
+        .with_http_client(settings.network.client()?)
+        .with_request_timeout(settings.network.request_timeout());
+    let replaying = exchanges.is_some();
+    let agent = Agent::with_provider(Recorder::new(live, exchanges))
+        .with_model(settings.model())
+        .with_max_tokens(settings.max_tokens())
+        .with_format(settings.format.clone())
+        .with_guard(settings.guard)
+        .with_review(settings.review.clone())
+        .with_tools(settings.tools.clone())
+        .with_context(settings.context)
+        .with_prompt(settings.prompt.clone())
+        .with_temperature(0.0);
+    let context = ToolContext {
+        recorded: Some(Arc::new(std::sync::Mutex::new(
+            RecordedResults::from_messages(&messages),
+        ))),
+        ..ToolContext::default()
+    };
+    let conversation = Mutex::new(Conversation::new());
+
+    println!(
+        "Replaying {} turn(s) from {}",
+        recorded.len(),
+        path.display()
+    );
+    if replaying {
+        println!("Answering from the recording in {}", tape.display());
+    } else {
+        println!("Recording the model's responses to {}", tape.display());
+    }
+
+    let mut diverged = 0;
+    for (index, expected) in recorded.iter().enumerate() {
+        conversation
+            .lock()
+            .await
+            .begin_turn(expected.prompt.clone())?;
+        let result = agent.run_turn(&conversation, &context, |_| {}).await;
+        let actual = {
+            let mut conversation = conversation.lock().await;
+            conversation.finish_turn();
+            summarize(conversation.messages()).pop()
+        };
+        if let Err(err) = result {
+            // The responses recorded so far were paid for.
+            if !replaying {
+                agent.provider().save(&tape)?;
+            }
+            return Err(err);
+        }
+
+        let actual = actual.ok_or("replayed turn produced no transcript")?;
+        let mut matched = report(index + 1, expected, &actual);
+        for number in agent.provider().take_mismatches() {
+            println!(
+                "  request {}: the conversation differs from the recording",
+                number
+            );
+            matched = false;
+        }
+        if !matched {
+            diverged += 1;
+        }
+    }
+
+    if !replaying {
+        agent.provider().save(&tape)?;
+    }
+    if diverged > 0 {
+        return Err(format!(
+            "replay diverged in {} of {} turn(s)",
+            diverged,
+            recorded.len()
+        )
+        .into());
+    }
+
+    println!("All turns matched");
+    Ok(())
+}
+
+/// One model response in a recording, with a digest of the conversation
+/// that asked for it.
+#[derive(Serialize, Deserialize)]
+struct Exchange {
+    conversation: String,
+    response: serde_json::Value,
+}
+
+/// Stands in for the model during a replay. Recording, it passes requests
+/// on to `live` and keeps the responses; replaying, it answers the n-th
+/// request with the n-th recorded response and never goes online.
+pub(crate) struct Recorder<P> {
+    live: P,
+    /// `None` while recording.
+    recorded: Option<Vec<Exchange>>,
+    /// This run's requests and responses, in order.
+    exchanges: std::sync::Mutex<Vec<Exchange>>,
+    /// Requests whose conversation differs from the recording's, by
+    /// number, not yet reported.
+    mismatches: std::sync::Mutex<Vec<usize>>,
+}
+
+impl<P: Provider> Recorder<P> {
+    fn new(live: P, recorded: Option<Vec<Exchange>>) -> Self {
+        Self {
+            live,
+            recorded,
+            exchanges: std::sync::Mutex::new(Vec::new()),
+            mismatches: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    fn take_mismatches(&self) -> Vec<usize> {
+        std::mem::take(&mut *self.mismatches.lock().unwrap())
+    }
+
+    /// Writes what this run recorded to `path`, one exchange per line, if
+    /// it recorded anything.
+    fn save(&self, path: &Path) -> ReplayResult<()> {
+        if self.exchanges.lock().unwrap().is_empty() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        for exchange in self.exchanges.lock().unwrap().iter() {
+            serde_json::to_writer(&mut file, exchange)?;
+            file.write_all(b"\n")?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl<P: Provider> Provider for Recorder<P> {
+    fn capabilities(&self, model: &str) -> Capabilities {
+        self.live.capabilities(model)
+    }
+
+    fn convert_request(&self, request: &MessageRequest) -> ProviderResult<serde_json::Value> {
+        self.live.convert_request(request)
+    }
+
+    fn convert_response(&self, body: serde_json::Value) -> ProviderResult<MessageResponse> {
+        self.live.convert_response(body)
+    }
+
+    async fn run_inference(&self, request: &MessageRequest) -> ProviderResult<MessageResponse> {
+        // The system prompt carries the date, so only the messages count.
+        let digest = Sha256::digest(serde_json::to_vec(&request.messages)?);
+        let conversation: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let response = match &self.recorded {
+            Some(recorded) => {
+                let number = self.exchanges.lock().unwrap().len() + 1;
+                let exchange = recorded.get(number - 1).ok_or_else(|| {
+                    format!(
+                        "the recording has no response for request {}; run tars replay --record to record it again",
+                        number
+                    )
+                })?;
+                if exchange.conversation != conversation {
+                    self.mismatches.lock().unwrap().push(number);
+                }
+                exchange.response.clone()
+            }
+            None => serde_json::to_value(self.live.run_inference(request).await?)?,
+        };
+        self.exchanges.lock().unwrap().push(Exchange {
+            conversation,
+            response: response.clone(),
+        });
+        Ok(serde_json::from_value(response)?)
+    }
+
+    #[cfg(feature = "server")]
+    async fn reachable(&self) -> bool {
+        self.recorded.is_some() || self.live.reachable().await
+    }
+}
+
+/// The results a session saved for its tool calls, handed out by call
+/// instead of running the tools again.
+#[derive(Debug, Default)]
+pub(crate) struct RecordedResults {
+    /// Tool name, input, result and whether it failed, in call order.
+    calls: Vec<(String, serde_json::Value, String, bool)>,
+}
+
+impl RecordedResults {
+    fn from_messages(messages: &[MessageParam]) -> Self {
+        let mut inputs = std::collections::HashMap::new();
+        let mut calls = Vec::new();
+        for message in messages {
+            let content = match message {
+                MessageParam::User(message) => message.content(),
+                MessageParam::Assistant(message) => message.content(),
+            };
+            for block in content {
+                match block {
+                    ContentBlock::ToolUse { id, name, input } => {
+                        inputs.insert(id.as_str(), (name, input));
+                    }
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        is_error,
+                    } => {
+                        if let Some((name, input)) = inputs.remove(tool_use_id.as_str()) {
+                            calls.push((
+                                name.clone(),
+                                input.clone(),
+                                content.text(),
+                                *is_error == Some(true),
+                            ));
+                        }
+                    }
+                    ContentBlock::Text { .. } => {}
+                }
+            }
+        }
+        Self { calls }
+    }
+
+    /// The saved result of the first unused call to `name` with `input`,
+    /// or an error result when the session never made that call.
+    pub(crate) fn take(&mut self, name: &str, input: &serde_json::Value) -> (String, bool) {
+        match self
+            .calls
+            .iter()
+            .position(|(tool, recorded, _, _)| tool == name && recorded == input)
+        {
+            Some(index) => {
+                let (_, _, content, is_error) = self.calls.remove(index);
+                (content, is_error)
+            }
+            None => (
+                format!(
+                    "Not run: this is a replay, and the recorded session never called {} with this input.",
+                    name
+                ),
+                true,
+            ),
+        }
+    }
+}
+
+/// Where the model's responses for replays of the session at `session`
+/// are kept.
+fn tape_path(session: &Path) -> PathBuf {
+    let name = session.file_stem().unwrap_or_default();
+    config::tars_dir()
+        .join("replays")
+        .join(name)
+        .with_extension("jsonl")
+}
+
+/// The recording at `path`, or `None` when there is none yet.
+fn read_tape(path: &Path) -> ReplayResult<Option<Vec<Exchange>>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("Failed to read {}: {}", path.display(), err).into()),
+    };
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|err| {
+                format!(
+                    "{}:{}: invalid recording: {}",
+                    path.display(),
+                    index + 1,
+                    err
+                )
+                .into()
+            })
+        })
+        .collect::<ReplayResult<Vec<Exchange>>>()
+        .map(Some)
+}
+
+fn summarize(messages: &[MessageParam]) -> Vec<TurnSummary> {
+    split_turns(messages)
+        .into_iter()
+        .map(|turn| {
+            let prompt = match &turn[0] {
+                MessageParam::User(user) => text_content(user.content()),
+                MessageParam::Assistant(_) => String::new(),
+            };
+            let mut tool_calls = Vec::new();
+            let mut reply = String::new();
+            for message in turn {
+                if let MessageParam::Assistant(assistant) = message {
+                    for block in assistant.content() {
+                        if let ContentBlock::ToolUse { name, input, .. } = block {
+                            tool_calls.push(format!("{} {}", name, input));
+                        }
+                    }
+                    reply = text_content(assistant.content());
+                }
+            }
+            TurnSummary {
+                prompt,
+                tool_calls,
+                reply,
+            }
+        })
+        .collect()
+}
+
+/// Prints the comparison for one turn and returns whether it matched.
+fn report(number: usize, expected: &TurnSummary, actual: &TurnSummary) -> bool {
+    let prompt = expected.prompt.lines().next().unwrap_or_default();
+    println!("turn {}: {}", number, prompt);
+
+    let tools_match = expected.tool_calls == actual.tool_calls;
+    if tools_match {
+        println!("  tools: match ({} call(s))", expected.tool_calls.len());
+    } else {
+        println!("  tools: differ");
+        let len = expected.tool_calls.len().max(actual.tool_calls.len());
+        for i in 0..len {
+            match (expected.tool_calls.get(i), actual.tool_calls.get(i)) {
+                (Some(e), Some(a)) if e == a => println!("      {}", e),
+                (e, a) => {
+                    if let Some(e) = e {
+                        println!("    - {}", e);
+                    }
+                    if let Some(a) = a {
+                        println!("    + {}", a);
+                    }
+                }
+            }
+        }
+    }
+
+    let reply_matches = expected.reply == actual.reply;
+    if reply_matches {
+        println!("  reply: match");
+    } else {
+        println!("  reply: differs");
+        for line in expected.reply.lines() {
+            println!("    - {}", line);
+        }
+        for line in actual.reply.lines() {
+            println!("    + {}", line);
+        }
+    }
+
+    tools_match && reply_matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_sdk::{AssistantMessage, ResponseContentBlock, UserMessage};
+    use crate::config::ContextConfig;
+    use crate::test_support::{Scripted, TempDir};
+
+    fn agent(recorder: Recorder<Scripted>) -> Agent<Recorder<Scripted>> {
+        Agent::with_provider(recorder)
+            .with_model("local")
+            .with_context(ContextConfig {
+                repo_map: false,
+                ..ContextConfig::default()
+            })
+    }
+
+    async fn replay_turn(
+        agent: &Agent<Recorder<Scripted>>,
+        prompt: &str,
+        session: &[MessageParam],
+    ) -> TurnSummary {
+        let context = ToolContext {
+            recorded: Some(Arc::new(std::sync::Mutex::new(
+                RecordedResults::from_messages(session),
+            ))),
+            ..ToolContext::default()
+        };
+        let conversation = Mutex::new(Conversation::new());
+        conversation
+            .lock()
+            .await
+            .begin_turn(prompt.to_string())
+            .unwrap();
+        agent
+            .run_turn(&conversation, &context, |_| {})
+            .await
+            .unwrap();
+        let summary = summarize(conversation.lock().await.messages()).pop();
+        summary.unwrap()
+    }
+
+    #[tokio::test]
+    async fn replays_answer_from_the_recording_without_running_edits() {
+        let dir = TempDir::new("tars-replay");
+        let marker = dir.join("marker");
+        let command = serde_json::json!({"command": format!("touch {}", marker.display())});
+        let session = vec![
+            MessageParam::User(UserMessage::from_text("Make a marker".to_string())),
+            MessageParam::Assistant(AssistantMessage::new(vec![ContentBlock::ToolUse {
+                id: "call_1".to_string(),
+                name: "bash".to_string(),
+                input: command.clone(),
+            }])),
+            MessageParam::User(UserMessage::new(vec![ContentBlock::tool_result(
+                "call_1".to_string(),
+                "Exit code: 0".to_string(),
+                false,
+            )])),
+            MessageParam::Assistant(AssistantMessage::new(vec![ContentBlock::Text {
+                text: "Done".to_string(),
+            }])),
+        ];
+        let live = Scripted::new(vec![
+            vec![ResponseContentBlock::ToolUse {
+                id: "call_1".to_string(),
+                name: "bash".to_string(),
+                input: command,
+            }],
+            vec![ResponseContentBlock::Text {
+                text: "Done".to_string(),
+            }],
+        ]);
+
+        let recording = agent(Recorder::new(live, None));
+        let recorded = replay_turn(&recording, "Make a marker", &session).await;
+        assert_eq!(recording.provider().live.requests().len(), 2);
+        assert_eq!(recorded, summarize(&session).pop().unwrap());
+        let tape = dir.join("tape.jsonl");
+        recording.provider().save(&tape).unwrap();
+
+        let replaying = agent(Recorder::new(
+            Scripted::default(),
+            read_tape(&tape).unwrap(),
+        ));
+        let replayed = replay_turn(&replaying, "Make a marker", &session).await;
+        assert_eq!(replaying.provider().live.requests().len(), 0);
+        assert!(replaying.provider().take_mismatches().is_empty());
+        assert_eq!(replayed, recorded);
+        assert!(!marker.exists());
+
+        let diverging = agent(Recorder::new(
+            Scripted::default(),
+            read_tape(&tape).unwrap(),
+        ));
+        replay_turn(&diverging, "Make two markers", &session).await;
+        assert_eq!(diverging.provider().take_mismatches(), vec![1, 2]);
+    }
+
+    #[test]
+    fn calls_the_session_never_made_get_an_error() {
+        let mut results = RecordedResults::default();
+        let (content, is_error) = results.take("bash", &serde_json::json!({"command": "ls"}));
+        assert!(is_error);
+        assert!(content.starts_with("Not run"));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::ai_sdk::{MessageRequest, MessageResponse};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::path::PathBuf;
+
+type CacheResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Messages API responses saved on disk by request, for development: with
+/// `--cache-llm`, re-running the same prompt against the same tools is
+/// answered from `~/.tars/cache/responses` instead of billed again.
+pub struct ResponseCache {
+    dir: PathBuf,
+}
+
+impl ResponseCache {
+    #[cfg(feature = "server")]
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    #[cfg(feature = "server")]
+    pub fn default_dir() -> PathBuf {
+        crate::config::tars_dir().join("cache").join("responses")
+    }
+
+    /// The request's SHA-256: the model, every message, the tool schemas
+    /// and the sampling settings all count.
+    pub(crate) fn key(request: &MessageRequest) -> CacheResult<String> {
+        let digest = Sha256::digest(serde_json::to_vec(request)?);
+        Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    /// A missing or unreadable entry is a miss.
+    pub(crate) fn get(&self, key: &str) -> Option<MessageResponse> {
+        let contents = std::fs::read(self.path(key)).ok()?;
+        serde_json::from_slice(&contents).ok()
+    }
+
+    pub(crate) fn put(&self, key: &str, response: &MessageResponse) -> CacheResult<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        // Write to a sibling file first so a concurrent `get` never reads a
+        // torn entry.
+        let path = self.path(key);
+        let tmp = path.with_extension(format!("json.{}.tmp", uuid::Uuid::new_v4()));
+        std::fs::write(&tmp, serde_json::to_vec(response)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::ai_sdk::{MessageParam, ResponseContentBlock, Usage, UserMessage};
+    use crate::test_support::TempDir;
+
+    fn request(prompt: &str) -> MessageRequest {
+        MessageRequest {
+            model: "claude-haiku-4-5".to_string(),
+            max_tokens: 16,
+            system: None,
+            messages: vec![MessageParam::User(UserMessage::from_text(
+                prompt.to_string(),
+            ))],
+            tools: Vec::new(),
+            temperature: None,
+            tool_choice: None,
+        }
+    }
+
+    #[test]
+    fn responses_are_found_by_request() {
+        let dir = TempDir::new("tars-cache");
+        let cache = ResponseCache::new(dir.to_path_buf());
+        let key = ResponseCache::key(&request("hello")).unwrap();
+        assert_eq!(key, ResponseCache::key(&request("hello")).unwrap());
+        assert_ne!(key, ResponseCache::key(&request("hello!")).unwrap());
+        assert!(cache.get(&key).is_none());
+
+        let response = MessageResponse {
+            id: "msg_1".to_string(),
+            content: vec![ResponseContentBlock::Text {
+                text: "hi".to_string(),
+            }],
+            stop_reason: None,
+            model: "claude-haiku-4-5".to_string(),
+            usage: Usage::default(),
+        };
+        cache.put(&key, &response).unwrap();
+        assert_eq!(cache.get(&key).unwrap().id, "msg_1");
+    }
+}
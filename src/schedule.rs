@@ -0,0 +1,306 @@
+use crate::agent::Agent;
+use crate::clock;
+use crate::config::{self, Config, ScheduleConfig};
+use crate::conversation::Conversation;
+use crate::key_ring::KeyRing;
+use crate::protocol::StreamEvent;
+use crate::session_store;
+use crate::tools::ToolContext;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+mod cron;
+
+pub use cron::Cron;
+
+type ScheduleResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// One finished run, as a line of `~/.tars/schedule/history.jsonl`.
+#[derive(Debug, Serialize, Deserialize)]
+struct RunRecord {
+    schedule: String,
+    /// Local start time, `YYYY-MM-DD HH:MM`.
+    started: String,
+    duration_secs: u64,
+    /// The saved session, for `tars replay` or reading the transcript.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Saves a new schedule to the config, after checking its name, its cron
+/// expression and that the name is free. `dir` is stored as an absolute
+/// path.
+pub fn add(mut schedule: ScheduleConfig) -> ScheduleResult<()> {
+    check_name(&schedule.name)?;
+    Cron::parse(&schedule.cron)?;
+    schedule.dir = std::fs::canonicalize(&schedule.dir)
+        .map_err(|err| format!("{}: {}", schedule.dir.display(), err))?;
+    let mut settings = config::load()?;
+    if settings.schedules.iter().any(|s| s.name == schedule.name) {
+        return Err(format!("A schedule named {:?} already exists", schedule.name).into());
+    }
+    println!(
+        "Added {:?}: {} in {}",
+        schedule.name,
+        schedule.cron,
+        schedule.dir.display()
+    );
+    settings.schedules.push(schedule);
+    config::save(&settings)
+}
+
+/// Names become file names, as in `log_path`, so they are limited to
+/// letters, digits, `-`, `_` and `.`, and can't start with a dot.
+fn check_name(name: &str) -> ScheduleResult<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(format!(
+            "Invalid schedule name {:?}: use letters, digits, '-', '_' and '.', not starting with '.'",
+            name
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub fn remove(name: &str) -> ScheduleResult<()> {
+    let mut settings = config::load()?;
+    let before = settings.schedules.len();
+    settings.schedules.retain(|schedule| schedule.name != name);
+    if settings.schedules.len() == before {
+        return Err(format!("No schedule named {:?}", name).into());
+    }
+    config::save(&settings)
+}
+
+/// Prints every schedule with the outcome of its latest run.
+pub fn list() -> ScheduleResult<()> {
+    let settings = config::load()?;
+    if settings.schedules.is_empty() {
+        println!("No schedules; add one with `tars schedule add`.");
+        return Ok(());
+    }
+    let history = load_history()?;
+    for schedule in &settings.schedules {
+        let last = history
+            .iter()
+            .rev()
+            .find(|run| run.schedule == schedule.name)
+            .map_or("never run".to_string(), describe_run);
+        println!(
+            "{}  {}  {}  ({})\n    {}",
+            schedule.name,
+            schedule.cron,
+            schedule.dir.display(),
+            last,
+            schedule.prompt
+        );
+    }
+    Ok(())
+}
+
+/// Prints the latest `limit` runs, of one schedule or of all of them.
+pub fn history(name: Option<&str>, limit: usize) -> ScheduleResult<()> {
+    let history = load_history()?;
+    let runs: Vec<&RunRecord> = history
+        .iter()
+        .filter(|run| name.is_none_or(|name| run.schedule == name))
+        .collect();
+    for run in &runs[runs.len().saturating_sub(limit)..] {
+        match &run.session_id {
+            Some(session_id) => println!(
+                "{}  {}  {}  session {}",
+                run.started,
+                run.schedule,
+                describe_run(run),
+                session_id
+            ),
+            None => println!("{}  {}  {}", run.started, run.schedule, describe_run(run)),
+        }
+    }
+    Ok(())
+}
+
+fn describe_run(run: &RunRecord) -> String {
+    match &run.error {
+        None => format!("ok in {}s", run.duration_secs),
+        Some(error) => format!("failed after {}s: {}", run.duration_secs, error),
+    }
+}
+
+/// Runs a schedule's prompt once, in the foreground, and records the
+/// outcome. The agent works in the schedule's directory with every tool;
+/// what it does is printed as it happens and the session is saved.
+pub async fn run(name: &str) -> ScheduleResult<()> {
+    let settings = config::load()?;
+    let schedule = settings
+        .schedules
+        .iter()
+        .find(|schedule| schedule.name == name)
+        .ok_or_else(|| format!("No schedule named {:?}", name))?;
+
+    let started = clock::now();
+    let timer = Instant::now();
+    let (session_id, result) = run_prompt(&settings, schedule).await;
+    append_history(&RunRecord {
+        schedule: name.to_string(),
+        started,
+        duration_secs: timer.elapsed().as_secs(),
+        session_id,
+        error: result.as_ref().err().map(|err| err.to_string()),
+    })?;
+    result
+}
+
+/// Returns the id the session was saved under, unless the run failed
+/// before it started.
+async fn run_prompt(
+    settings: &Config,
+    schedule: &ScheduleConfig,
+) -> (Option<String>, ScheduleResult<()>) {
+    // The tools resolve paths against the working directory, and this
+    // process runs nothing else.
+    if let Err(err) = std::env::set_current_dir(&schedule.dir) {
+        return (
+            None,
+            Err(format!("{}: {}", schedule.dir.display(), err).into()),
+        );
+    }
+    let agent = match Agent::from_config(settings, KeyRing::from_config()) {
+        Ok(agent) => agent,
+        Err(err) => return (None, Err(err)),
+    };
+    let agent = agent
+        .with_format(settings.format.clone())
+        .with_guard(settings.guard)
+        .with_review(settings.review.clone())
+        .with_tools(settings.tools.clone())
+        .with_context(settings.context)
+        .with_prompt(settings.prompt.clone());
+
+    let conversation = Mutex::new(Conversation::new());
+    if let Err(err) = conversation
+        .lock()
+        .await
+        .begin_turn(schedule.prompt.clone())
+    {
+        return (None, Err(err.into()));
+    }
+    let result = agent
+        .run_turn(&conversation, &ToolContext::default(), print_event)
+        .await;
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let saved = {
+        let mut conversation = conversation.lock().await;
+        conversation.finish_turn();
+        session_store::save(&session_id, &conversation)
+    };
+    (saved.is_ok().then_some(session_id), result.and(saved))
+}
+
+/// Prints a session event as plain text, for scheduled runs and `tars
+/// repl`.
+pub(crate) fn print_event(event: StreamEvent) {
+    match event {
+        StreamEvent::Assistant { text, .. } => println!("{}", text),
+        StreamEvent::ToolCall { name, input, .. } => println!("→ {} {}", name, input),
+        StreamEvent::ApprovalNeeded { summary, .. } => {
+            eprintln!("approve {}? y, a (always), n, or why not", summary)
+        }
+        StreamEvent::ToolResult {
+            is_error: true,
+            content,
+            ..
+        } => eprintln!("tool error: {}", content),
+        StreamEvent::Warning { message } => eprintln!("warning: {}", message),
+        StreamEvent::Error { message } | StreamEvent::Offline { message } => {
+            eprintln!("error: {}", message)
+        }
+        StreamEvent::Plan { steps, stats } => {
+            println!("── plan ({})", stats.model);
+            for (index, step) in steps.iter().enumerate() {
+                println!("{}. {}", index + 1, step);
+            }
+        }
+        StreamEvent::Comparison { answers } => {
+            for answer in answers {
+                println!("── {}", answer.model);
+                if !answer.text.is_empty() {
+                    println!("{}", answer.text);
+                }
+                if let Some(error) = answer.error {
+                    eprintln!("error: {}", error);
+                }
+            }
+        }
+        StreamEvent::Cancelled => eprintln!("cancelled"),
+        _ => {}
+    }
+}
+
+fn schedule_dir() -> PathBuf {
+    config::tars_dir().join("schedule")
+}
+
+/// Where a schedule's runs write their output when `tars daemon` starts
+/// them.
+pub fn log_path(name: &str) -> PathBuf {
+    schedule_dir().join(format!("{}.log", name))
+}
+
+fn history_path() -> PathBuf {
+    schedule_dir().join("history.jsonl")
+}
+
+fn load_history() -> ScheduleResult<Vec<RunRecord>> {
+    let contents = match std::fs::read_to_string(history_path()) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    // A torn last line from a crash is skipped rather than failing.
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+fn append_history(run: &RunRecord) -> ScheduleResult<()> {
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let mut line = serde_json::to_vec(run)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_must_be_plain_file_names() {
+        for name in ["nightly", "deps-update", "weekly_2.report"] {
+            assert!(check_name(name).is_ok(), "{:?}", name);
+        }
+        for name in ["", "nightly/deps", "../../x", "..", ".hidden", "a\\b", "with space"] {
+            assert!(check_name(name).is_err(), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use time::OffsetDateTime;
+
+/// A five-field cron expression (minute, hour, day of month, month, day of
+/// week) with `*`, lists, ranges and steps, plus the usual `@daily` style
+/// shorthands. Sunday is 0 or 7.
+///
+/// As in classic cron, when both the day of month and the day of week are
+/// restricted, a time matches if either does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl Cron {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "{:?} needs five fields: minute hour day-of-month month day-of-week",
+                expression
+            ));
+        };
+        let mut weekday_bits = field(weekdays, 0, 7, "day of week")?;
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits |= 1;
+        }
+        Ok(Self {
+            minutes: field(minutes, 0, 59, "minute")?,
+            hours: field(hours, 0, 23, "hour")?,
+            days: field(days, 1, 31, "day of month")?,
+            months: field(months, 1, 12, "month")?,
+            weekdays: weekday_bits,
+            days_restricted: !days.starts_with('*'),
+            weekdays_restricted: !weekdays.starts_with('*'),
+        })
+    }
+
+    /// Whether the expression matches `time`'s minute.
+    pub fn matches(&self, time: OffsetDateTime) -> bool {
+        let has = |bits: u64, value: u8| bits & (1 << value) != 0;
+        let day = has(self.days, time.day());
+        let weekday = has(self.weekdays, time.weekday().number_days_from_sunday());
+        let day_matches = if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        };
+        has(self.minutes, time.minute())
+            && has(self.hours, time.hour())
+            && has(self.months, u8::from(time.month()))
+            && day_matches
+    }
+}
+
+/// Parses one field into a bit per allowed value.
+fn field(text: &str, min: u8, max: u8, name: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid {} {:?}", name, text);
+    let mut bits = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u8>().map_err(|_| invalid())?)),
+            None => (part, None),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (
+                    start.parse().map_err(|_| invalid())?,
+                    end.parse().map_err(|_| invalid())?,
+                ),
+                None => {
+                    let start = range.parse().map_err(|_| invalid())?;
+                    // `5/15` means from 5 onwards in steps of 15.
+                    (start, if step.is_some() { max } else { start })
+                }
+            },
+        };
+        if step == Some(0) || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::{Date, Month};
+
+    /// A minute in October 2026, when the 16th is a Friday.
+    fn october(day: u8, hour: u8, minute: u8) -> OffsetDateTime {
+        Date::from_calendar_date(2026, Month::October, day)
+            .unwrap()
+            .with_hms(hour, minute, 0)
+            .unwrap()
+            .assume_utc()
+    }
+
+    #[test]
+    fn expressions_match_their_minutes() {
+        let friday_3am = october(16, 3, 0);
+
+        assert!(Cron::parse("0 3 * * *").unwrap().matches(friday_3am));
+        assert!(!Cron::parse("0 3 * * *").unwrap().matches(october(16, 3, 1)));
+        assert!(Cron::parse("*/15 1-5 * * 1-5").unwrap().matches(friday_3am));
+        assert!(!Cron::parse("0 3 * * 0,6").unwrap().matches(friday_3am));
+        assert!(Cron::parse("0 3 1 * 5").unwrap().matches(friday_3am));
+        assert!(Cron::parse("@daily").unwrap().matches(october(18, 0, 0)));
+        assert!(Cron::parse("0 0 * * 7").unwrap().matches(october(18, 0, 0)));
+
+        for invalid in [
+            "0 3 * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "x * * * *",
+        ] {
+            assert!(Cron::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
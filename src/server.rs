@@ -1,70 +1,362 @@
-use crate::agent::Agent;
-use crate::ai_sdk::{
-    assistant_content_from_response, AssistantMessage, ContentBlock, MessageParam,
-    ResponseContentBlock, UserMessage,
+use crate::agent::{is_network_error, Agent};
+use crate::config::{self, UserConfig, WebhookEvent};
+use crate::conversation::Conversation;
+use crate::describe;
+use crate::export;
+use crate::index;
+use crate::key_ring::KeyRing;
+use crate::protocol::{
+    ApprovalRequest, ArchiveResponse, ArtifactListResponse, ComparedAnswer, DescribeRequest,
+    DescribeResponse, EventEnvelope, FilePreviewResponse, ForkRequest, HealthResponse, PinResponse,
+    ReminderRequest, ResponseStats, RewindResponse, SendMessageRequest, SendMessageResponse,
+    SessionCreateRequest, SessionCreateResponse, SessionListResponse, SessionStatsResponse,
+    SessionSummary, StreamEvent, TurnActivity, UsageResponse, UserUsage, WorkspaceFilesResponse,
+    EVENT_VERSION,
 };
-use crate::protocol::{SendMessageRequest, SessionCreateResponse, StreamEvent};
-use axum::extract::{Path, State};
-use axum::http::header::AUTHORIZATION;
+use crate::response_cache::ResponseCache;
+use crate::session_store;
+use crate::tools::{self, combine_stats, format_patch, get_all_tools, Approvals, ToolContext};
+use crate::webhook::{WebhookPayload, Webhooks};
+use crate::workspace::{self, PreviewError};
+use axum::extract::{Path, Query, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
 use axum::http::{HeaderMap, StatusCode};
 use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::response::IntoResponse;
 use axum::routing::{get, post};
 use axum::Json;
 use futures::StreamExt;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::Infallible;
 use std::error::Error;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{broadcast, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+use tokio::sync::{broadcast, oneshot, Mutex};
+use tokio::time::interval_at;
 use tokio_stream::wrappers::BroadcastStream;
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tower_http::LatencyUnit;
+use tracing::field::Empty;
+use tracing::{Instrument, Level, Span};
 use uuid::Uuid;
 
+/// How often a running turn sends `StreamEvent::Heartbeat`.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How many models `/compare` may ask at once.
+const MAX_COMPARED_MODELS: usize = 3;
+
+/// Told to the model after a turn is cancelled partway through.
+const INTERRUPTED_REMINDER: &str =
+    "The user interrupted your previous turn before it finished. Don't resume it unless asked.";
+
 pub struct ServerConfig {
     pub listen: String,
     pub auth_token: String,
+    /// Tokens that may list sessions and follow their event streams but
+    /// not change them, for demos and audit monitoring. Blank entries, as
+    /// left by a trailing comma in `TARS_WATCH_TOKENS`, are ignored.
+    pub watch_tokens: Vec<String>,
+    /// Users with their own tokens and API keys. With any configured, the
+    /// server's own API key is optional. Their sessions are kept apart, but
+    /// every session's tools work in the server's one working directory.
+    pub users: Vec<UserConfig>,
+    /// Answer repeated requests from the on-disk response cache.
+    pub cache_llm: bool,
 }
 
 struct ServerState {
-    agent: Arc<Agent>,
+    /// Pays for sessions created with the main token; `None` when the
+    /// server has no API key of its own.
+    agent: Option<Arc<Agent>>,
+    /// Keyed by the user's token.
+    users: HashMap<String, Arc<User>>,
     sessions: Mutex<HashMap<String, Arc<SessionState>>>,
     auth_token: String,
+    watch_tokens: Vec<String>,
+    webhooks: Arc<Webhooks>,
+}
+
+impl ServerState {
+    /// Any agent, for checks that don't depend on the API key.
+    fn any_agent(&self) -> Option<&Arc<Agent>> {
+        self.agent
+            .as_ref()
+            .or_else(|| self.users.values().next().map(|user| &user.agent))
+    }
+}
+
+struct User {
+    name: String,
+    /// Uses the user's API key and counts their usage.
+    agent: Arc<Agent>,
+}
+
+/// Whose token a request carries.
+#[derive(Clone)]
+enum Caller {
+    /// The main token, which sees every session.
+    Owner,
+    /// A user's token, which only sees that user's sessions.
+    User(Arc<User>),
+    Watcher,
+}
+
+impl Caller {
+    /// Who made a request, for the logs; never the token itself.
+    fn name(&self) -> &str {
+        match self {
+            Caller::Owner => "owner",
+            Caller::User(user) => &user.name,
+            Caller::Watcher => "watcher",
+        }
+    }
+
+    fn can_access(&self, session: &SessionState) -> bool {
+        match self {
+            Caller::User(user) => session.owner.as_deref() == Some(user.name.as_str()),
+            Caller::Owner | Caller::Watcher => true,
+        }
+    }
+}
+
+/// What a request needs its bearer token to allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    /// Read-only access: listing sessions and following their streams.
+    Watch,
+    /// Everything, including sending messages and changing sessions.
+    Control,
 }
 
 struct SessionState {
-    conversation: Mutex<Vec<MessageParam>>,
-    events: broadcast::Sender<StreamEvent>,
-    running: Mutex<bool>,
+    id: String,
+    /// The user who created the session, on a multi-user server.
+    owner: Option<String>,
+    agent: Arc<Agent>,
+    labels: BTreeMap<String, String>,
+    conversation: Mutex<Conversation>,
+    tools: ToolContext,
+    /// Stops the running turn; `None` between turns.
+    cancel: std::sync::Mutex<Option<oneshot::Sender<()>>>,
+    /// Set while a comparison runs, which keeps the session busy without
+    /// a turn of its own.
+    comparing: AtomicBool,
+    events: broadcast::Sender<EventEnvelope>,
+    /// The `seq` of the last event emitted.
+    seq: AtomicU64,
+    webhooks: Arc<Webhooks>,
+    /// The running turn's latest reply, for the `turn_complete` webhook.
+    last_reply: std::sync::Mutex<Option<String>>,
+}
+
+impl SessionState {
+    /// Wraps `event` in an envelope and broadcasts it to every subscriber.
+    fn emit(&self, turn_id: Option<&str>, event: StreamEvent) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        self.notify(turn_id, timestamp_ms, &event);
+        let _ = self.events.send(EventEnvelope {
+            version: EVENT_VERSION,
+            seq: self.seq.fetch_add(1, Ordering::Relaxed) + 1,
+            timestamp_ms,
+            session_id: self.id.clone(),
+            turn_id: turn_id.map(str::to_string),
+            event,
+        });
+    }
+
+    /// Passes the events webhooks are told about on to them.
+    fn notify(&self, turn_id: Option<&str>, timestamp_ms: u64, event: &StreamEvent) {
+        let (event, message) = match event {
+            StreamEvent::Assistant { text, .. } => {
+                *self.last_reply.lock().unwrap() = Some(text.clone());
+                return;
+            }
+            StreamEvent::Error { message } | StreamEvent::Offline { message } => {
+                (WebhookEvent::Error, Some(message.clone()))
+            }
+            StreamEvent::Done => (
+                WebhookEvent::TurnComplete,
+                self.last_reply.lock().unwrap().take(),
+            ),
+            StreamEvent::ApprovalNeeded { summary, .. } => {
+                (WebhookEvent::ApprovalNeeded, Some(summary.clone()))
+            }
+            _ => return,
+        };
+        self.webhooks.send(WebhookPayload {
+            event,
+            session_id: self.id.clone(),
+            turn_id: turn_id.map(str::to_string),
+            timestamp_ms,
+            labels: self.labels.clone(),
+            message,
+        });
+    }
 }
 
 type ServerResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
 
 pub async fn run(config: ServerConfig) -> ServerResult<()> {
-    let api_key = std::env::var("ANTHROPIC_API_KEY")
-        .map_err(|_| "ANTHROPIC_API_KEY environment variable not set")?;
+    let watch_tokens: Vec<String> = config
+        .watch_tokens
+        .iter()
+        .map(|token| token.trim())
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .collect();
+    check_user_tokens(&config.auth_token, &watch_tokens, &config.users)?;
+    let settings = config::load()?;
+    // Vertex AI signs in with Google credentials, so keys don't apply.
+    let uses_keys = settings.provider == "anthropic";
+    let keys = KeyRing::from_config();
+    if uses_keys && keys.is_empty() && config.users.is_empty() {
+        return Err(config::MISSING_API_KEY.into());
+    }
 
+    let http = settings.network.client()?;
+    let new_agent = |keys: KeyRing| -> ServerResult<Arc<Agent>> {
+        let agent = Agent::from_config(&settings, keys)?
+            .with_format(settings.format.clone())
+            .with_guard(settings.guard)
+            .with_review(settings.review.clone())
+            .with_tools(settings.tools.clone())
+            .with_context(settings.context)
+            .with_prompt(settings.prompt.clone())
+            .with_profiles(settings.profiles.clone());
+        Ok(Arc::new(if config.cache_llm {
+            agent.with_response_cache(ResponseCache::new(ResponseCache::default_dir()))
+        } else {
+            agent
+        }))
+    };
+    let agent = if uses_keys && keys.is_empty() {
+        None
+    } else {
+        Some(new_agent(keys)?)
+    };
+    let mut users = HashMap::new();
+    for user in config.users {
+        let agent = new_agent(KeyRing::new(vec![user.api_key]))?;
+        users.insert(
+            user.token,
+            Arc::new(User {
+                name: user.name,
+                agent,
+            }),
+        );
+    }
     let state = Arc::new(ServerState {
-        agent: Arc::new(Agent::new(api_key)),
+        agent,
+        users,
         sessions: Mutex::new(HashMap::new()),
         auth_token: config.auth_token,
+        watch_tokens,
+        webhooks: Arc::new(Webhooks::new(http.clone(), settings.webhooks.clone())),
     });
 
-    let app = axum::Router::new()
-        .route("/sessions", post(create_session))
-        .route("/sessions/:id/messages", post(send_message))
-        .route("/sessions/:id/stream", get(stream_session))
-        .with_state(state.clone());
+    let app = router(state);
+
+    // Catch an existing index up with what changed while the server was
+    // down, so the first search doesn't wait for it.
+    if index::current_exists() {
+        tokio::task::spawn_blocking(|| {
+            let refreshed = index::with_current(|_, stats| {
+                tracing::info!(
+                    files = stats.files,
+                    updated = stats.updated,
+                    removed = stats.removed,
+                    "workspace index refreshed"
+                );
+            });
+            if let Err(err) = refreshed {
+                tracing::warn!(error = %err, "workspace index refresh failed");
+            }
+        });
+    }
 
     let listener = tokio::net::TcpListener::bind(&config.listen).await?;
-    println!("tars server listening on http://{}", config.listen);
-    println!("auth token stored at {}", token_path().display());
+    eprintln!("tars server listening on http://{}", config.listen);
+    eprintln!("auth token stored at {}", token_path().display());
     axum::serve(listener, app).await?;
 
     Ok(())
 }
 
+/// Refuses user tokens that another caller also has: the main token or a
+/// watch token would be matched first, locking the user out, and two
+/// users with one token would see each other's sessions.
+fn check_user_tokens(
+    auth_token: &str,
+    watch_tokens: &[String],
+    users: &[UserConfig],
+) -> ServerResult<()> {
+    let mut tokens = std::collections::HashSet::new();
+    for user in users {
+        if user.token == auth_token {
+            return Err(format!("User {:?} has the server's main token", user.name).into());
+        }
+        if watch_tokens.contains(&user.token) {
+            return Err(format!("User {:?} has a watch token", user.name).into());
+        }
+        if !tokens.insert(user.token.as_str()) {
+            return Err(format!("User {:?} reuses another user's token", user.name).into());
+        }
+    }
+    Ok(())
+}
+
+/// The API's routes, with each request logged.
+fn router(state: Arc<ServerState>) -> axum::Router {
+    axum::Router::new()
+        .route("/sessions", post(create_session).get(list_sessions))
+        .route("/sessions/:id/messages", post(send_message))
+        .route("/sessions/:id/cancel", post(cancel_turn))
+        .route("/sessions/:id/stream", get(stream_session))
+        .route("/sessions/:id/pin", post(pin_message))
+        .route("/sessions/:id/reminders", post(add_reminder))
+        .route("/sessions/:id/allow/:tool", post(allow_tool))
+        .route(
+            "/sessions/:id/approvals/:tool_use_id",
+            post(answer_approval),
+        )
+        .route("/sessions/:id/rewind", post(rewind_turn))
+        .route("/sessions/:id/fork", post(fork_session))
+        .route("/sessions/:id/archive", post(archive_session))
+        .route("/sessions/:id/artifacts", get(list_artifacts))
+        .route("/sessions/:id/describe", post(describe_changes))
+        .route("/sessions/:id/files", get(list_workspace_files))
+        .route("/sessions/:id/files/*path", get(preview_file))
+        .route("/sessions/:id/stats", get(session_stats))
+        .route("/sessions/:id/turns/:turn/patch", get(turn_patch))
+        .route("/sessions/:id/export", get(export_session))
+        .route("/usage", get(usage))
+        .route("/health", get(health))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(|request: &axum::http::Request<_>| {
+                    tracing::info_span!(
+                        "request",
+                        method = %request.method(),
+                        path = request.uri().path(),
+                        session_id = Empty,
+                        token = Empty,
+                    )
+                })
+                .on_response(
+                    DefaultOnResponse::new()
+                        .level(Level::INFO)
+                        .latency_unit(LatencyUnit::Millis),
+                ),
+        )
+        .with_state(state)
+}
+
 pub fn resolve_token(explicit: Option<String>) -> ServerResult<String> {
     if let Some(token) = explicit {
         write_token_file(&token)?;
@@ -80,27 +372,180 @@ pub fn resolve_token(explicit: Option<String>) -> ServerResult<String> {
     Ok(token)
 }
 
+/// Starts a session, empty or continuing a saved one when the request
+/// names it in `resume`. A resumed session keeps its id, so it goes on
+/// saving to the same transcript; one that is already live is returned as
+/// it is.
 async fn create_session(
     State(state): State<Arc<ServerState>>,
     headers: HeaderMap,
+    payload: Option<Json<SessionCreateRequest>>,
 ) -> Result<Json<SessionCreateResponse>, StatusCode> {
-    authorize(&headers, &state.auth_token)?;
+    let caller = authorize(&headers, &state, Scope::Control)?;
 
-    let session_id = Uuid::new_v4().to_string();
-    let (events, _) = broadcast::channel(200);
-    let session = Arc::new(SessionState {
-        conversation: Mutex::new(Vec::new()),
-        events,
-        running: Mutex::new(false),
-    });
+    // Only a server without an API key of its own can't fund the main
+    // token's sessions.
+    let (owner, agent) = match &caller {
+        Caller::User(user) => (Some(user.name.clone()), Arc::clone(&user.agent)),
+        _ => (None, state.agent.clone().ok_or(StatusCode::FORBIDDEN)?),
+    };
+    let request = payload.map(|Json(request)| request).unwrap_or_default();
+    let Some(resume) = request.resume else {
+        let session_id = Uuid::new_v4().to_string();
+        Span::current().record("session_id", session_id.as_str());
+        insert_session(
+            &state,
+            session_id.clone(),
+            owner,
+            agent,
+            request.labels,
+            Conversation::new(),
+        )
+        .await;
+        return Ok(Json(SessionCreateResponse {
+            session_id,
+            messages: Vec::new(),
+        }));
+    };
+
+    // Transcripts don't record whose they were, so only the main token may
+    // open them.
+    if owner.is_some() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let session_id = match resume.as_str() {
+        "last" => session_store::latest().ok_or(StatusCode::NOT_FOUND)?,
+        id if id.is_empty() || id.contains(['/', '\\', '.']) => {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        id => id.to_string(),
+    };
+    if let Ok(session) = find_session(&state, &caller, &session_id).await {
+        let messages = session.conversation.lock().await.messages().to_vec();
+        return Ok(Json(SessionCreateResponse {
+            session_id,
+            messages,
+        }));
+    }
+    let conversation = session_store::load_conversation(&session_store::session_path(&session_id))
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let messages = conversation.messages().to_vec();
+    insert_session(
+        &state,
+        session_id.clone(),
+        owner,
+        agent,
+        request.labels,
+        conversation,
+    )
+    .await;
+    Ok(Json(SessionCreateResponse {
+        session_id,
+        messages,
+    }))
+}
 
-    state
+/// The caller's sessions currently held in memory, for watchers to pick
+/// from; users see only their own. Query
+/// parameters filter by label, so `?project=tars` lists only sessions
+/// labelled `project=tars`.
+async fn list_sessions(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    Query(filter): Query<HashMap<String, String>>,
+) -> Result<Json<SessionListResponse>, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Watch)?;
+
+    let mut sessions: Vec<SessionSummary> = state
         .sessions
         .lock()
         .await
-        .insert(session_id.clone(), session);
+        .values()
+        .filter(|session| caller.can_access(session))
+        .filter(|session| {
+            filter
+                .iter()
+                .all(|(key, value)| session.labels.get(key) == Some(value))
+        })
+        .map(|session| SessionSummary {
+            session_id: session.id.clone(),
+            owner: session.owner.clone(),
+            labels: session.labels.clone(),
+        })
+        .collect();
+    sessions.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+    Ok(Json(SessionListResponse { sessions }))
+}
 
-    Ok(Json(SessionCreateResponse { session_id }))
+/// Starts a new session with a copy of another's history, so a different
+/// approach can be tried without touching the original.
+async fn fork_session(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+    payload: Option<Json<ForkRequest>>,
+) -> Result<Json<SessionCreateResponse>, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+
+    let up_to = payload.and_then(|Json(request)| request.up_to);
+    let conversation = session
+        .conversation
+        .lock()
+        .await
+        .fork(up_to)
+        .ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    let fork_id = Uuid::new_v4().to_string();
+    if !conversation.messages().is_empty() {
+        session_store::save(&fork_id, &conversation)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    insert_session(
+        &state,
+        fork_id.clone(),
+        session.owner.clone(),
+        Arc::clone(&session.agent),
+        session.labels.clone(),
+        conversation,
+    )
+    .await;
+
+    Ok(Json(SessionCreateResponse {
+        session_id: fork_id,
+        messages: Vec::new(),
+    }))
+}
+
+async fn insert_session(
+    state: &ServerState,
+    session_id: String,
+    owner: Option<String>,
+    agent: Arc<Agent>,
+    labels: BTreeMap<String, String>,
+    conversation: Conversation,
+) {
+    let (events, _) = broadcast::channel(200);
+    let session = Arc::new(SessionState {
+        id: session_id.clone(),
+        owner,
+        agent,
+        labels,
+        conversation: Mutex::new(conversation),
+        tools: ToolContext {
+            artifacts: Some(tools::artifacts_dir(&session_id)),
+            approvals: Arc::new(std::sync::Mutex::new(Approvals::interactive())),
+            ..ToolContext::default()
+        },
+        cancel: std::sync::Mutex::new(None),
+        comparing: AtomicBool::new(false),
+        events,
+        seq: AtomicU64::new(0),
+        webhooks: Arc::clone(&state.webhooks),
+        last_reply: std::sync::Mutex::new(None),
+    });
+
+    state.sessions.lock().await.insert(session_id, session);
 }
 
 async fn send_message(
@@ -108,59 +553,648 @@ async fn send_message(
     Path(session_id): Path<String>,
     headers: HeaderMap,
     Json(payload): Json<SendMessageRequest>,
-) -> Result<StatusCode, StatusCode> {
-    authorize(&headers, &state.auth_token)?;
-
-    let session = {
-        let sessions = state.sessions.lock().await;
-        sessions.get(&session_id).cloned()
+) -> Result<(StatusCode, Json<SendMessageResponse>), StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+    if !payload.compare.is_empty() {
+        return start_comparison(session, payload).await;
     }
-    .ok_or(StatusCode::NOT_FOUND)?;
-
+    if let Some(profile) = &payload.profile
+        && !session.agent.has_profile(profile)
     {
-        let mut running = session.running.lock().await;
-        if *running {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let (profile, model) = (payload.profile, payload.model);
+
+    let turn_id = {
+        let mut conversation = session.conversation.lock().await;
+        if session.comparing.load(Ordering::Relaxed) {
             return Err(StatusCode::CONFLICT);
         }
-        *running = true;
+        let turn_id = conversation
+            .begin_turn(payload.content)
+            .map_err(|_| StatusCode::CONFLICT)?;
+        if payload.pin {
+            conversation.pin_last_user_message();
+        }
+        if let Some(prefill) = payload.prefill {
+            conversation.set_prefill(prefill);
+        }
+        if let Some(tool_choice) = payload.tool_choice {
+            conversation.set_tool_choice(tool_choice);
+        }
+        turn_id
+    };
+    let (cancel, cancelled) = oneshot::channel();
+    *session.cancel.lock().unwrap() = Some(cancel);
+
+    let response = SendMessageResponse {
+        turn_id: turn_id.clone(),
+    };
+    let agent = Arc::clone(&session.agent);
+    let span = tracing::info_span!("turn", session_id = %session.id, turn_id = %turn_id);
+    tokio::spawn(async move {
+        let turn_id = Some(turn_id.as_str());
+        let started = Instant::now();
+        let activity = std::sync::Mutex::new(TurnActivity::Thinking);
+        let emit = |event: StreamEvent| {
+            match &event {
+                StreamEvent::ToolCall { name, .. } => {
+                    *activity.lock().unwrap() = TurnActivity::RunningTool {
+                        name: name.clone(),
+                        progress: None,
+                    };
+                }
+                StreamEvent::ToolResult { .. } => {
+                    *activity.lock().unwrap() = TurnActivity::Thinking;
+                }
+                _ => {}
+            }
+            session.emit(turn_id, event);
+        };
+        let turn = async {
+            match (&profile, &model) {
+                (Some(profile), _) => {
+                    agent
+                        .run_planned_turn(profile, &session.conversation, &session.tools, emit)
+                        .await
+                }
+                (None, Some(model)) => {
+                    agent
+                        .run_turn_on(model, &session.conversation, &session.tools, emit)
+                        .await
+                }
+                (None, None) => {
+                    agent
+                        .run_turn(&session.conversation, &session.tools, emit)
+                        .await
+                }
+            }
+        };
+        let heartbeats = heartbeats(&session, turn_id, started, &activity);
+        let result = tokio::select! {
+            result = turn.instrument(span) => Some(result),
+            _ = heartbeats => unreachable!("heartbeats never stop"),
+            _ = cancelled => None,
+        };
+        // Release the turn before announcing completion so a client reacting
+        // to Done can immediately send the next message. A turn that never
+        // reached the API is rolled back so the client can resend it.
+        let saved = {
+            let mut conversation = session.conversation.lock().await;
+            session.cancel.lock().unwrap().take();
+            let event = match result {
+                // Exchanges are recorded whole, so stopping mid-turn leaves
+                // no tool call without its result. A prompt that got no
+                // reply at all is dropped like an offline one.
+                None => {
+                    if !conversation.abandon_turn() {
+                        conversation.add_reminder(INTERRUPTED_REMINDER.to_string());
+                    }
+                    Some(StreamEvent::Cancelled)
+                }
+                Some(Ok(())) => None,
+                Some(Err(err)) if is_network_error(err.as_ref()) && conversation.abandon_turn() => {
+                    Some(StreamEvent::Offline {
+                        message: err.to_string(),
+                    })
+                }
+                Some(Err(err)) => Some(StreamEvent::Error {
+                    message: err.to_string(),
+                }),
+            };
+            if let Some(event) = event {
+                session.emit(turn_id, event);
+            }
+            if let Some(turn_id) = turn_id {
+                session.tools.changes.lock().unwrap().finish_turn(turn_id);
+            }
+            conversation.finish_turn();
+            session_store::save(&session_id, &conversation)
+        };
+        if let Err(err) = saved {
+            session.emit(
+                turn_id,
+                StreamEvent::Error {
+                    message: format!("Failed to save session: {}", err),
+                },
+            );
+        }
+        session.emit(turn_id, StreamEvent::Done);
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(response)))
+}
+
+/// Sends `StreamEvent::Heartbeat` for a running turn until dropped.
+async fn heartbeats(
+    session: &SessionState,
+    turn_id: Option<&str>,
+    started: Instant,
+    activity: &std::sync::Mutex<TurnActivity>,
+) {
+    let mut ticker = interval_at((started + HEARTBEAT_INTERVAL).into(), HEARTBEAT_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let mut activity = activity.lock().unwrap().clone();
+        if let TurnActivity::RunningTool { name, .. } = &activity
+            && session.tools.approvals.lock().unwrap().waiting()
+        {
+            activity = TurnActivity::AwaitingApproval { name: name.clone() };
+        }
+        if let TurnActivity::RunningTool { progress, .. } = &mut activity {
+            progress.clone_from(&session.tools.progress.lock().unwrap());
+        }
+        session.emit(
+            turn_id,
+            StreamEvent::Heartbeat {
+                activity,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+            },
+        );
     }
+}
+
+/// Answers a message with each of `payload.compare`'s models at once, on
+/// copies of the history, and reports the answers together. The session's
+/// own history is left as it was. Conflicts while a turn or another
+/// comparison is running.
+async fn start_comparison(
+    session: Arc<SessionState>,
+    payload: SendMessageRequest,
+) -> Result<(StatusCode, Json<SendMessageResponse>), StatusCode> {
+    if payload.compare.len() > MAX_COMPARED_MODELS {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let (snapshot, turn_id) = {
+        let conversation = session.conversation.lock().await;
+        if conversation.turn_active() || session.comparing.load(Ordering::Relaxed) {
+            return Err(StatusCode::CONFLICT);
+        }
+        let mut snapshot = conversation.clone();
+        let turn_id = snapshot
+            .begin_turn(payload.content)
+            .map_err(|_| StatusCode::CONFLICT)?;
+        if let Some(prefill) = payload.prefill {
+            snapshot.set_prefill(prefill);
+        }
+        // Set under the conversation's lock, so no turn can start between
+        // the checks above and here.
+        session.comparing.store(true, Ordering::Relaxed);
+        (snapshot, turn_id)
+    };
+    let (cancel, cancelled) = oneshot::channel();
+    *session.cancel.lock().unwrap() = Some(cancel);
 
-    let agent = Arc::clone(&state.agent);
-    let session_clone = Arc::clone(&session);
-    let message = payload.content;
+    let response = SendMessageResponse {
+        turn_id: turn_id.clone(),
+    };
+    let span = tracing::info_span!("comparison", session_id = %session.id, turn_id = %turn_id);
     tokio::spawn(async move {
-        let result = run_agent_loop(agent, session_clone, message).await;
-        if let Err(err) = result {
-            let _ = session.events.send(StreamEvent::Error {
-                message: err.to_string(),
-            });
+        let turn_id = Some(turn_id.as_str());
+        let started = Instant::now();
+        let activity = std::sync::Mutex::new(TurnActivity::Thinking);
+        let answers = futures::future::join_all(
+            payload
+                .compare
+                .iter()
+                .map(|model| compare_model(&session.agent, model, snapshot.clone())),
+        );
+        let heartbeats = heartbeats(&session, turn_id, started, &activity);
+        let answers = tokio::select! {
+            answers = answers.instrument(span) => Some(answers),
+            _ = heartbeats => unreachable!("heartbeats never stop"),
+            _ = cancelled => None,
+        };
+        {
+            // The receiver is gone now, so a closed sender is this
+            // comparison's own; anything else belongs to someone else.
+            let mut cancel = session.cancel.lock().unwrap();
+            if cancel.as_ref().is_some_and(oneshot::Sender::is_closed) {
+                cancel.take();
+            }
         }
-        let _ = session.events.send(StreamEvent::Done);
-        let mut running = session.running.lock().await;
-        *running = false;
+        session.comparing.store(false, Ordering::Relaxed);
+        session.emit(
+            turn_id,
+            match answers {
+                Some(answers) => StreamEvent::Comparison { answers },
+                None => StreamEvent::Cancelled,
+            },
+        );
+        session.emit(turn_id, StreamEvent::Done);
     });
 
+    Ok((StatusCode::ACCEPTED, Json(response)))
+}
+
+/// Runs one model's side of a comparison on its own copy of the history,
+/// with tools that can't change the workspace.
+async fn compare_model(agent: &Agent, model: &str, conversation: Conversation) -> ComparedAnswer {
+    let conversation = Mutex::new(conversation);
+    let mut answer = ComparedAnswer {
+        model: model.to_string(),
+        text: String::new(),
+        tool_calls: 0,
+        stats: None,
+        error: None,
+    };
+    let result = agent
+        .run_read_only_turn(
+            model,
+            &conversation,
+            &ToolContext::default(),
+            |event| match event {
+                StreamEvent::Assistant { text, stats } => {
+                    if !answer.text.is_empty() {
+                        answer.text.push_str("\n\n");
+                    }
+                    answer.text.push_str(text.trim());
+                    if let Some(stats) = stats {
+                        let total = answer.stats.get_or_insert(ResponseStats {
+                            model: stats.model.clone(),
+                            elapsed_ms: 0,
+                            input_tokens: 0,
+                            output_tokens: 0,
+                        });
+                        total.elapsed_ms += stats.elapsed_ms;
+                        total.input_tokens += stats.input_tokens;
+                        total.output_tokens += stats.output_tokens;
+                    }
+                }
+                StreamEvent::ToolCall { .. } => answer.tool_calls += 1,
+                StreamEvent::Warning { message } if answer.error.is_none() => {
+                    answer.error = Some(message);
+                }
+                _ => {}
+            },
+        )
+        .await;
+    if let Err(err) = result {
+        answer.error = Some(err.to_string());
+    }
+    answer
+}
+
+/// Stops the session's running turn, keeping whatever it completed. Conflicts
+/// when no turn is running.
+async fn cancel_turn(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+
+    let cancel = session
+        .cancel
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or(StatusCode::CONFLICT)?;
+    let _ = cancel.send(());
     Ok(StatusCode::ACCEPTED)
 }
 
-async fn stream_session(
+async fn pin_message(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<PinResponse>, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+
+    let text = session
+        .conversation
+        .lock()
+        .await
+        .pin_last_user_message()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(PinResponse { text }))
+}
+
+/// Queues a note that the model sees with its next request but that is
+/// never added to the transcript. Works while a turn is running, in which
+/// case the note arrives with the turn's next model call.
+async fn add_reminder(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<ReminderRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+
+    session.conversation.lock().await.add_reminder(payload.text);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lets the session call a tool that needs approval, such as `edit_file`
+/// or `read_clipboard`, without asking again. Not found for other tools.
+async fn allow_tool(
+    State(state): State<Arc<ServerState>>,
+    Path((session_id, tool)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<StatusCode, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+
+    if !get_all_tools()
+        .iter()
+        .any(|definition| definition.name == tool && definition.needs_approval())
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    session.tools.approvals.lock().unwrap().allow(&tool);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Answers a `StreamEvent::ApprovalNeeded`, letting the call run or
+/// turning it down. Not found when no call with that id is waiting.
+async fn answer_approval(
+    State(state): State<Arc<ServerState>>,
+    Path((session_id, tool_use_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(payload): Json<ApprovalRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+
+    let answered = session
+        .tools
+        .approvals
+        .lock()
+        .unwrap()
+        .answer(&tool_use_id, payload);
+    if !answered {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Drops the last turn so its prompt can be retried or edited. Conflicts
+/// while a turn is running; not found when there is nothing to rewind.
+async fn rewind_turn(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<RewindResponse>, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+
+    let mut conversation = session.conversation.lock().await;
+    if conversation.turn_active() {
+        return Err(StatusCode::CONFLICT);
+    }
+    let (text, pinned) = conversation
+        .rewind_last_turn()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    session_store::save(&session_id, &conversation)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(RewindResponse { text, pinned }))
+}
+
+/// Saves a session and drops it from memory, ending its event stream. A
+/// session with a turn still running can't be archived.
+async fn archive_session(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<ArchiveResponse>, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+
+    let mut sessions = state.sessions.lock().await;
+    let session = sessions
+        .get(&session_id)
+        .filter(|session| caller.can_access(session))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let saved = {
+        let conversation = session.conversation.lock().await;
+        if conversation.turn_active() {
+            return Err(StatusCode::CONFLICT);
+        }
+        let saved = !conversation.messages().is_empty();
+        if saved {
+            session_store::save(&session_id, &conversation)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        saved
+    };
+    sessions.remove(&session_id);
+
+    Ok(Json(ArchiveResponse { saved }))
+}
+
+/// The files the session saved with `save_artifact`.
+async fn list_artifacts(
     State(state): State<Arc<ServerState>>,
     Path(session_id): Path<String>,
     headers: HeaderMap,
+) -> Result<Json<ArtifactListResponse>, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Watch)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+    let artifacts = tools::list_artifacts(&tools::artifacts_dir(&session.id))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(ArtifactListResponse { artifacts }))
+}
+
+/// Writes a commit message or pull request description for the changes in
+/// the server's working directory with the session's agent. Leaves the
+/// session's history alone; unprocessable when there is nothing to describe
+/// or git fails, with the reason in the body.
+async fn describe_changes(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+    payload: Option<Json<DescribeRequest>>,
+) -> Result<Json<DescribeResponse>, (StatusCode, String)> {
+    let caller =
+        authorize(&headers, &state, Scope::Control).map_err(|status| (status, String::new()))?;
+    let session = find_session(&state, &caller, &session_id)
+        .await
+        .map_err(|status| (status, String::new()))?;
+
+    let request = payload.map(|Json(request)| request).unwrap_or_default();
+    match describe::describe(&session.agent, &request, std::path::Path::new(".")).await {
+        Ok(text) => Ok(Json(DescribeResponse { text })),
+        Err(err) => Err((StatusCode::UNPROCESSABLE_ENTITY, err.to_string())),
+    }
+}
+
+/// API usage and tool calls since the server started: everyone's for the
+/// main token, only their own for a user.
+async fn usage(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+) -> Result<Json<UsageResponse>, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+
+    let (users, tools) = match caller {
+        Caller::User(user) => (
+            vec![UserUsage {
+                user: Some(user.name.clone()),
+                usage: user.agent.usage(),
+            }],
+            user.agent.tool_stats(),
+        ),
+        _ => {
+            let mut users: Vec<UserUsage> = state
+                .users
+                .values()
+                .map(|user| UserUsage {
+                    user: Some(user.name.clone()),
+                    usage: user.agent.usage(),
+                })
+                .collect();
+            users.sort_by(|a, b| a.user.cmp(&b.user));
+            if let Some(agent) = &state.agent {
+                users.insert(
+                    0,
+                    UserUsage {
+                        user: None,
+                        usage: agent.usage(),
+                    },
+                );
+            }
+            let agents = state
+                .agent
+                .iter()
+                .chain(state.users.values().map(|user| &user.agent));
+            let tools = combine_stats(agents.flat_map(|agent| agent.tool_stats()));
+            (users, tools)
+        }
+    };
+    Ok(Json(UsageResponse { users, tools }))
+}
+
+/// How the session's tool calls have gone.
+async fn session_stats(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<SessionStatsResponse>, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Watch)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+    let tools = session.tools.usage.lock().unwrap().stats();
+    Ok(Json(SessionStatsResponse { tools }))
+}
+
+/// What turn `turn` of the session changed, counting from 1 or `last`, as
+/// a patch `git am` can apply. No content when the turn changed no files
+/// the tools tracked, or ran before the server started.
+async fn turn_patch(
+    State(state): State<Arc<ServerState>>,
+    Path((session_id, turn)): Path<(String, String)>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, StatusCode> {
-    authorize(&headers, &state.auth_token)?;
+    let caller = authorize(&headers, &state, Scope::Watch)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+    let (turn_id, prompt) = {
+        let conversation = session.conversation.lock().await;
+        let number = match turn.as_str() {
+            "last" => conversation.turn_count(),
+            number => number.parse().map_err(|_| StatusCode::BAD_REQUEST)?,
+        };
+        let (turn_id, prompt) = conversation.turn(number).ok_or(StatusCode::NOT_FOUND)?;
+        (turn_id.to_string(), prompt)
+    };
+    let changes = session.tools.changes.lock().unwrap();
+    let Some(files) = changes.turn_changes(&turn_id) else {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+    let subject: String = prompt
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("Changes from tars")
+        .trim()
+        .chars()
+        .take(72)
+        .collect();
+    let patch = format_patch(&subject, files);
+    Ok(([(CONTENT_TYPE, "text/x-patch; charset=utf-8")], patch).into_response())
+}
 
-    let session = {
-        let sessions = state.sessions.lock().await;
-        sessions.get(&session_id).cloned()
+/// The session's transcript as Markdown, or no content before its first
+/// message.
+async fn export_session(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Watch)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+    let conversation = session.conversation.lock().await;
+    if conversation.messages().is_empty() {
+        return Ok(StatusCode::NO_CONTENT.into_response());
     }
-    .ok_or(StatusCode::NOT_FOUND)?;
+    let markdown = export::markdown(conversation.messages());
+    Ok(([(CONTENT_TYPE, "text/markdown; charset=utf-8")], markdown).into_response())
+}
+
+async fn health(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+) -> Result<Json<HealthResponse>, StatusCode> {
+    authorize(&headers, &state, Scope::Watch)?;
+    let api_reachable = match state.any_agent() {
+        Some(agent) => agent.api_reachable().await,
+        None => false,
+    };
+    Ok(Json(HealthResponse { api_reachable }))
+}
+
+/// Lists the workspace with what the session's tools did to each file.
+async fn list_workspace_files(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<WorkspaceFilesResponse>, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Watch)?;
+    let session = find_session(&state, &caller, &session_id).await?;
+    let files = tokio::task::spawn_blocking(move || workspace::list_files(&session.tools))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(files))
+}
+
+/// The start of a workspace file, for browsing it. Needs full access,
+/// since files can hold what a watcher shouldn't see.
+async fn preview_file(
+    State(state): State<Arc<ServerState>>,
+    Path((session_id, path)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Json<FilePreviewResponse>, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Control)?;
+    find_session(&state, &caller, &session_id).await?;
+    let root = std::env::current_dir().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let preview = tokio::task::spawn_blocking(move || workspace::preview(&root, &path))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match preview {
+        Ok(preview) => Ok(Json(preview)),
+        Err(PreviewError::OutsideWorkspace) => Err(StatusCode::FORBIDDEN),
+        Err(PreviewError::NotFound) => Err(StatusCode::NOT_FOUND),
+        Err(PreviewError::Binary) => Err(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+    }
+}
+
+async fn stream_session(
+    State(state): State<Arc<ServerState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    let caller = authorize(&headers, &state, Scope::Watch)?;
+    let session = find_session(&state, &caller, &session_id).await?;
 
     let stream = BroadcastStream::new(session.events.subscribe()).filter_map(|item| async move {
         match item {
-            Ok(event) => {
-                let data = serde_json::to_string(&event).unwrap_or_default();
-                Some(Ok::<Event, Infallible>(Event::default().data(data)))
+            Ok(envelope) => {
+                let data = serde_json::to_string(&envelope).unwrap_or_default();
+                Some(Ok::<Event, Infallible>(
+                    Event::default().id(envelope.seq.to_string()).data(data),
+                ))
             }
             Err(_) => None,
         }
@@ -173,79 +1207,75 @@ async fn stream_session(
     ))
 }
 
-async fn run_agent_loop(
-    agent: Arc<Agent>,
-    session: Arc<SessionState>,
-    message: String,
-) -> ServerResult<()> {
-    {
-        let mut conversation = session.conversation.lock().await;
-        conversation.push(MessageParam::User(UserMessage::from_text(message)));
-    }
-
-    loop {
-        let conversation = { session.conversation.lock().await.clone() };
-        let response = agent.run_inference(conversation.as_slice()).await?;
-        let mut tool_results: Vec<ContentBlock> = Vec::new();
-
-        for content in &response.content {
-            match content {
-                ResponseContentBlock::Text { text } => {
-                    let _ = session.events.send(StreamEvent::Assistant { text: text.clone() });
-                }
-                ResponseContentBlock::ToolUse { id, name, input } => {
-                    let _ = session.events.send(StreamEvent::ToolCall {
-                        name: name.clone(),
-                        input: input.clone(),
-                    });
-
-                    let result = agent
-                        .execute_tool(id.clone(), name.clone(), input.clone())
-                        .await;
-
-                    let (content, is_error) = match &result {
-                        ContentBlock::ToolResult {
-                            content,
-                            is_error,
-                            ..
-                        } => (content.clone(), is_error.unwrap_or(false)),
-                        _ => (String::new(), false),
-                    };
-
-                    let _ = session.events.send(StreamEvent::ToolResult { content, is_error });
-                    tool_results.push(result);
-                }
-            }
-        }
+/// Checks the bearer token: the main token and user tokens allow
+/// everything, a watch token only `Scope::Watch`. The caller is added to
+/// the request's log span.
+fn authorize(
+    headers: &HeaderMap,
+    state: &ServerState,
+    required: Scope,
+) -> Result<Caller, StatusCode> {
+    let caller = identify(headers, state, required)?;
+    Span::current().record("token", caller.name());
+    Ok(caller)
+}
 
-        let assistant_content = assistant_content_from_response(&response);
-        {
-            let mut conversation = session.conversation.lock().await;
-            conversation.push(MessageParam::Assistant(AssistantMessage::new(
-                assistant_content,
-            )));
-            if !tool_results.is_empty() {
-                conversation.push(MessageParam::User(UserMessage::new(tool_results.clone())));
-            }
-        }
+fn identify(
+    headers: &HeaderMap,
+    state: &ServerState,
+    required: Scope,
+) -> Result<Caller, StatusCode> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .filter(|token| !token.is_empty())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
 
-        if tool_results.is_empty() {
-            break;
-        }
+    if token_matches(token, &state.auth_token) {
+        return Ok(Caller::Owner);
     }
-
-    Ok(())
+    if let Some(user) = state
+        .users
+        .iter()
+        .find_map(|(user_token, user)| token_matches(token, user_token).then_some(user))
+    {
+        return Ok(Caller::User(Arc::clone(user)));
+    }
+    if state
+        .watch_tokens
+        .iter()
+        .any(|watch| token_matches(token, watch))
+    {
+        return if required == Scope::Watch {
+            Ok(Caller::Watcher)
+        } else {
+            Err(StatusCode::FORBIDDEN)
+        };
+    }
+    Err(StatusCode::UNAUTHORIZED)
 }
 
-fn authorize(headers: &HeaderMap, token: &str) -> Result<(), StatusCode> {
-    let header = headers
-        .get(AUTHORIZATION)
-        .and_then(|value| value.to_str().ok());
+/// Compares in constant time, so how long a rejection takes says nothing
+/// about how much of a guessed token was right.
+fn token_matches(token: &str, expected: &str) -> bool {
+    token.as_bytes().ct_eq(expected.as_bytes()).into()
+}
 
-    match header {
-        Some(value) if value == format!("Bearer {}", token) => Ok(()),
-        _ => Err(StatusCode::UNAUTHORIZED),
-    }
+/// Looks up a live session, treating sessions the caller may not see as
+/// missing.
+async fn find_session(
+    state: &ServerState,
+    caller: &Caller,
+    session_id: &str,
+) -> Result<Arc<SessionState>, StatusCode> {
+    Span::current().record("session_id", session_id);
+    let sessions = state.sessions.lock().await;
+    sessions
+        .get(session_id)
+        .filter(|session| caller.can_access(session))
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)
 }
 
 fn read_token_file() -> ServerResult<String> {
@@ -275,9 +1305,442 @@ fn write_token_file(token: &str) -> ServerResult<()> {
 }
 
 fn token_path() -> PathBuf {
-    if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
-        return PathBuf::from(home).join(".tars").join("server.token");
+    config::tars_dir().join("server.token")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_sdk::{Anthropic, Backend};
+    use reqwest::Method;
+
+    const OWNER: &str = "owner-token";
+    const ALICE: &str = "alice-token";
+    const WATCHER: &str = "watch-token";
+
+    /// A server with the main token, a user `alice` and a watch token, whose
+    /// agents ask the model at `base_url`.
+    fn state(base_url: &str) -> ServerState {
+        let agent = |key: &str| {
+            let keys = KeyRing::new(vec![key.to_string()]);
+            Arc::new(Agent::with_provider(Backend::Anthropic(
+                Anthropic::new(keys).with_base_url(base_url),
+            )))
+        };
+        let alice = Arc::new(User {
+            name: "alice".to_string(),
+            agent: agent("alice-key"),
+        });
+        ServerState {
+            agent: Some(agent("owner-key")),
+            users: HashMap::from([(ALICE.to_string(), alice)]),
+            sessions: Mutex::new(HashMap::new()),
+            auth_token: OWNER.to_string(),
+            watch_tokens: vec![WATCHER.to_string()],
+            webhooks: Arc::new(Webhooks::new(reqwest::Client::new(), Vec::new())),
+        }
+    }
+
+    /// Serves `state` on a local port and returns its address.
+    async fn start(state: Arc<ServerState>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(state)).await });
+        format!("http://{}", address)
+    }
+
+    async fn request(method: Method, url: String, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .request(method, url)
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap()
     }
 
-    PathBuf::from("tars.token")
+    async fn create_session(server: &str, token: &str) -> String {
+        let response = request(Method::POST, format!("{}/sessions", server), token).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: SessionCreateResponse = response.json().await.unwrap();
+        body.session_id
+    }
+
+    async fn session(state: &ServerState, id: &str) -> Arc<SessionState> {
+        Arc::clone(&state.sessions.lock().await[id])
+    }
+
+    #[tokio::test]
+    async fn archiving_ends_a_session_unless_a_turn_is_running() {
+        let state = Arc::new(state("http://127.0.0.1:9"));
+        let server = start(Arc::clone(&state)).await;
+        let id = create_session(&server, OWNER).await;
+        let archive = format!("{}/sessions/{}/archive", server, id);
+
+        let session = session(&state, &id).await;
+        session
+            .conversation
+            .lock()
+            .await
+            .begin_turn("running".to_string())
+            .unwrap();
+        let response = request(Method::POST, archive.clone(), OWNER).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        session.conversation.lock().await.abandon_turn();
+
+        let response = request(Method::POST, archive.clone(), ALICE).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = request(Method::POST, archive.clone(), OWNER).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: ArchiveResponse = response.json().await.unwrap();
+        assert!(!body.saved, "an empty session leaves no transcript");
+        assert!(state.sessions.lock().await.is_empty());
+        let response = request(Method::POST, archive, OWNER).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn a_comparison_keeps_the_session_busy_until_it_ends() {
+        // A model that takes every request and never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let model = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+        let state = Arc::new(state(&model));
+        let server = start(Arc::clone(&state)).await;
+        let id = create_session(&server, OWNER).await;
+        let session = session(&state, &id).await;
+        let mut events = session.events.subscribe();
+        let send = |body: serde_json::Value| {
+            reqwest::Client::new()
+                .post(format!("{}/sessions/{}/messages", server, id))
+                .bearer_auth(OWNER)
+                .json(&body)
+                .send()
+        };
+        let compare = serde_json::json!({"content": "hi", "compare": ["haiku", "sonnet"]});
+
+        let response = send(compare.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = send(serde_json::json!({"content": "hi"})).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = send(compare).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let cancel = format!("{}/sessions/{}/cancel", server, id);
+        let response = request(Method::POST, cancel, OWNER).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        while !matches!(events.recv().await.unwrap().event, StreamEvent::Done) {}
+        assert!(!session.comparing.load(Ordering::Relaxed));
+        assert!(!session.conversation.lock().await.turn_active());
+    }
+
+    fn caller(
+        state: &ServerState,
+        authorization: Option<&str>,
+        required: Scope,
+    ) -> Result<String, StatusCode> {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = authorization {
+            headers.insert(AUTHORIZATION, value.parse().unwrap());
+        }
+        identify(&headers, state, required).map(|caller| caller.name().to_string())
+    }
+
+    #[test]
+    fn tokens_allow_only_their_scope() {
+        let mut state = state("http://127.0.0.1:9");
+        let owner = format!("Bearer {}", OWNER);
+        let alice = format!("Bearer {}", ALICE);
+        let watcher = format!("Bearer {}", WATCHER);
+        assert_eq!(
+            caller(&state, Some(&owner), Scope::Control).as_deref(),
+            Ok("owner")
+        );
+        assert_eq!(
+            caller(&state, Some(&alice), Scope::Control).as_deref(),
+            Ok("alice")
+        );
+        assert_eq!(
+            caller(&state, Some(&watcher), Scope::Watch).as_deref(),
+            Ok("watcher")
+        );
+        assert_eq!(
+            caller(&state, Some(&watcher), Scope::Control),
+            Err(StatusCode::FORBIDDEN)
+        );
+
+        assert_eq!(
+            caller(&state, None, Scope::Watch),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            caller(&state, Some(OWNER), Scope::Watch),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            caller(&state, Some("Bearer owner"), Scope::Watch),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            caller(&state, Some(&format!("{}x", owner)), Scope::Watch),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+
+        // A blank token never matches, even a blank entry that slipped in.
+        state.watch_tokens.push(String::new());
+        assert_eq!(
+            caller(&state, Some("Bearer "), Scope::Watch),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[tokio::test]
+    async fn watchers_follow_sessions_but_cannot_change_them() {
+        let state = Arc::new(state("http://127.0.0.1:9"));
+        let server = start(Arc::clone(&state)).await;
+        let id = create_session(&server, OWNER).await;
+        let sessions = format!("{}/sessions", server);
+
+        let response = reqwest::get(&sessions).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = request(Method::GET, sessions.clone(), WATCHER).await;
+        let body: SessionListResponse = response.json().await.unwrap();
+        assert_eq!(body.sessions.len(), 1);
+        assert_eq!(body.sessions[0].session_id, id);
+        let response = request(Method::GET, sessions.clone(), ALICE).await;
+        let body: SessionListResponse = response.json().await.unwrap();
+        assert!(
+            body.sessions.is_empty(),
+            "users see only their own sessions"
+        );
+
+        for (method, path) in [
+            (Method::POST, "".to_string()),
+            (Method::POST, format!("/{}/pin", id)),
+            (Method::POST, format!("/{}/rewind", id)),
+            (Method::POST, format!("/{}/archive", id)),
+            (Method::GET, format!("/{}/files/Cargo.toml", id)),
+        ] {
+            let response = request(method, format!("{}{}", sessions, path), WATCHER).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", path);
+        }
+        let response = request(Method::GET, format!("{}/{}/export", sessions, id), WATCHER).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn events_are_numbered_and_attributed() {
+        let state = state("http://127.0.0.1:9");
+        let agent = Arc::clone(state.agent.as_ref().unwrap());
+        let labels = BTreeMap::new();
+        insert_session(
+            &state,
+            "s1".to_string(),
+            None,
+            agent,
+            labels,
+            Conversation::new(),
+        )
+        .await;
+        let session = session(&state, "s1").await;
+        let mut events = session.events.subscribe();
+
+        session.emit(
+            Some("turn-1"),
+            StreamEvent::Info {
+                message: "working".to_string(),
+            },
+        );
+        session.emit(None, StreamEvent::Done);
+        let first = events.recv().await.unwrap();
+        let second = events.recv().await.unwrap();
+        assert_eq!((first.seq, second.seq), (1, 2));
+        assert!(first.timestamp_ms > 0 && first.timestamp_ms <= second.timestamp_ms);
+
+        let first = serde_json::to_value(&first).unwrap();
+        assert_eq!(first["version"], EVENT_VERSION);
+        assert_eq!(first["session_id"], "s1");
+        assert_eq!(first["turn_id"], "turn-1");
+        assert_eq!(first["event"]["type"], "info");
+        let second = serde_json::to_value(&second).unwrap();
+        assert!(second.get("turn_id").is_none());
+        assert_eq!(second["event"]["type"], "done");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeats_say_what_the_turn_is_doing() {
+        let state = state("http://127.0.0.1:9");
+        let agent = Arc::clone(state.agent.as_ref().unwrap());
+        let labels = BTreeMap::new();
+        insert_session(
+            &state,
+            "s1".to_string(),
+            None,
+            agent,
+            labels,
+            Conversation::new(),
+        )
+        .await;
+        let session = session(&state, "s1").await;
+        let mut events = session.events.subscribe();
+        let activity = std::sync::Mutex::new(TurnActivity::RunningTool {
+            name: "download".to_string(),
+            progress: None,
+        });
+        *session.tools.progress.lock().unwrap() = Some("1.0 MB of 2.0 MB".to_string());
+
+        let beats = heartbeats(&session, Some("turn-1"), Instant::now(), &activity);
+        let mut beats = std::pin::pin!(beats);
+        let mut next_beat = async || {
+            tokio::select! {
+                _ = &mut beats => unreachable!("heartbeats run until dropped"),
+                envelope = events.recv() => envelope.unwrap(),
+            }
+        };
+        let beat = next_beat().await;
+        assert_eq!(beat.turn_id.as_deref(), Some("turn-1"));
+        assert!(matches!(
+            beat.event,
+            StreamEvent::Heartbeat {
+                activity: TurnActivity::RunningTool { name, progress: Some(progress) },
+                ..
+            } if name == "download" && progress == "1.0 MB of 2.0 MB"
+        ));
+
+        let _waiting = session
+            .tools
+            .approvals
+            .lock()
+            .unwrap()
+            .request("call_1", "download");
+        assert!(matches!(
+            next_beat().await.event,
+            StreamEvent::Heartbeat {
+                activity: TurnActivity::AwaitingApproval { name },
+                ..
+            } if name == "download"
+        ));
+    }
+
+    #[tokio::test]
+    async fn sessions_are_listed_by_label() {
+        let server = start(Arc::new(state("http://127.0.0.1:9"))).await;
+        let sessions = format!("{}/sessions", server);
+        let http = reqwest::Client::new();
+        for labels in [
+            serde_json::json!({"project": "tars", "ticket": "T-1"}),
+            serde_json::json!({"project": "tars"}),
+            serde_json::json!({"project": "other"}),
+        ] {
+            let response = http
+                .post(&sessions)
+                .bearer_auth(OWNER)
+                .json(&serde_json::json!({ "labels": labels }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let listed = async |query: &str| {
+            let response = request(Method::GET, format!("{}{}", sessions, query), OWNER).await;
+            response
+                .json::<SessionListResponse>()
+                .await
+                .unwrap()
+                .sessions
+        };
+        assert_eq!(listed("").await.len(), 3);
+        let tars = listed("?project=tars").await;
+        assert_eq!(tars.len(), 2);
+        assert!(tars
+            .iter()
+            .all(|session| session.labels["project"] == "tars"));
+        let ticket = listed("?project=tars&ticket=T-1").await;
+        assert_eq!(ticket.len(), 1);
+        assert_eq!(ticket[0].labels.len(), 2);
+        assert!(listed("?project=nope").await.is_empty());
+    }
+
+    #[test]
+    fn user_tokens_must_be_theirs_alone() {
+        let user = |name: &str, token: &str| UserConfig {
+            name: name.to_string(),
+            token: token.to_string(),
+            api_key: "key".to_string(),
+        };
+        let watchers = [WATCHER.to_string()];
+        let alice = user("alice", ALICE);
+        assert!(check_user_tokens(OWNER, &watchers, &[alice.clone(), user("bob", "b")]).is_ok());
+
+        for (users, reason) in [
+            (vec![user("mallory", OWNER)], "main token"),
+            (vec![user("mallory", WATCHER)], "watch token"),
+            (
+                vec![alice.clone(), user("mallory", ALICE)],
+                "another user's token",
+            ),
+        ] {
+            let err = check_user_tokens(OWNER, &watchers, &users).unwrap_err();
+            assert!(err.to_string().contains(reason), "{}", err);
+            assert!(err.to_string().contains("mallory"), "{}", err);
+        }
+    }
+
+    /// Collects log output for a test.
+    #[derive(Clone, Default)]
+    struct Logs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_are_logged_with_their_session_and_caller() {
+        // As `tars server --log-format json` logs them.
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .flatten_event(true)
+            .with_span_list(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let server = start(Arc::new(state("http://127.0.0.1:9"))).await;
+        let id = create_session(&server, ALICE).await;
+        let stats = format!("{}/sessions/{}/stats", server, id);
+        request(Method::GET, stats.clone(), ALICE).await;
+        request(Method::GET, stats, "wrong").await;
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(!output.contains(ALICE), "tokens are never logged");
+        let records: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .filter(|record: &serde_json::Value| {
+                record["span"]["path"] == format!("/sessions/{}/stats", id)
+            })
+            .collect();
+        assert_eq!(records.len(), 2, "{}", output);
+        assert_eq!(records[0]["status"], 200);
+        assert_eq!(records[0]["span"]["method"], "GET");
+        assert_eq!(records[0]["span"]["session_id"], id.as_str());
+        assert_eq!(records[0]["span"]["token"], "alice");
+        assert!(records[0]["latency"].is_string());
+        assert_eq!(records[1]["status"], 401);
+        assert!(records[1]["span"].get("token").is_none());
+    }
 }
@@ -0,0 +1,162 @@
+use crate::ai_sdk::MessageParam;
+use crate::config;
+use crate::conversation::Conversation;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+type StoreResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// One line of a transcript: the message as sent to the API, tagged with
+/// the turn it belongs to. The tag is absent in older transcripts.
+#[derive(Serialize, Deserialize)]
+struct StoredMessage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    turn_id: Option<String>,
+    #[serde(flatten)]
+    message: MessageParam,
+}
+
+/// Writes a session transcript to `~/.tars/sessions/<id>.jsonl`, one
+/// message per line, replacing any previous copy.
+pub fn save(session_id: &str, conversation: &Conversation) -> StoreResult<()> {
+    let path = session_path(session_id);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Write to a sibling file first so a crash never leaves a torn transcript.
+    let tmp = path.with_extension("jsonl.tmp");
+    let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+    for (index, message) in conversation.messages().iter().enumerate() {
+        let stored = StoredMessage {
+            turn_id: conversation.turn_id(index).map(str::to_string),
+            message: message.clone(),
+        };
+        serde_json::to_writer(&mut file, &stored)?;
+        file.write_all(b"\n")?;
+    }
+    file.flush()?;
+    drop(file);
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> StoreResult<Vec<MessageParam>> {
+    Ok(read(path)?
+        .into_iter()
+        .map(|stored| stored.message)
+        .collect())
+}
+
+/// Reads a transcript back into a conversation that can be continued, with
+/// the turn ids it was saved with.
+#[cfg(feature = "server")]
+pub fn load_conversation(path: &Path) -> StoreResult<Conversation> {
+    let mut messages = Vec::new();
+    let mut turn_ids = BTreeMap::new();
+    let mut previous = None;
+    for stored in read(path)? {
+        if let Some(turn_id) = &stored.turn_id
+            && previous.as_ref() != Some(turn_id)
+        {
+            turn_ids.insert(messages.len(), turn_id.clone());
+        }
+        previous = stored.turn_id;
+        messages.push(stored.message);
+    }
+    Ok(Conversation::restore(messages, turn_ids))
+}
+
+fn read(path: &Path) -> StoreResult<Vec<StoredMessage>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str::<StoredMessage>(line).map_err(|err| {
+                format!("{}:{}: invalid message: {}", path.display(), index + 1, err).into()
+            })
+        })
+        .collect()
+}
+
+/// The id of the session saved most recently, if any.
+#[cfg(feature = "server")]
+pub fn latest() -> Option<String> {
+    std::fs::read_dir(sessions_dir())
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "jsonl"))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max()
+        .and_then(|(_, path)| Some(path.file_stem()?.to_str()?.to_string()))
+}
+
+/// Accepts either a path to a transcript or the id of a saved session.
+pub fn resolve(id_or_path: &str) -> PathBuf {
+    let path = PathBuf::from(id_or_path);
+    if path.exists() {
+        path
+    } else {
+        session_path(id_or_path)
+    }
+}
+
+pub fn session_path(session_id: &str) -> PathBuf {
+    sessions_dir().join(format!("{}.jsonl", session_id))
+}
+
+fn sessions_dir() -> PathBuf {
+    config::tars_dir().join("sessions")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "server")]
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn stored_messages_read_with_and_without_turn_ids() {
+        let tagged = r#"{"turn_id":"t1","role":"user","content":[{"type":"text","text":"hi"}]}"#;
+        let stored: StoredMessage = serde_json::from_str(tagged).unwrap();
+        assert_eq!(stored.turn_id.as_deref(), Some("t1"));
+        assert!(matches!(stored.message, MessageParam::User(_)));
+        assert_eq!(serde_json::to_string(&stored).unwrap(), tagged);
+
+        let legacy = r#"{"role":"assistant","content":[{"type":"text","text":"hello"}]}"#;
+        let stored: StoredMessage = serde_json::from_str(legacy).unwrap();
+        assert_eq!(stored.turn_id, None);
+        assert!(matches!(stored.message, MessageParam::Assistant(_)));
+        assert_eq!(serde_json::to_string(&stored).unwrap(), legacy);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn resumed_conversations_keep_their_turns() {
+        let dir = TempDir::new("tars-session");
+        let path = dir.join("session.jsonl");
+        std::fs::write(
+            &path,
+            [
+                r#"{"turn_id":"t1","role":"user","content":[{"type":"text","text":"hi"}]}"#,
+                r#"{"turn_id":"t1","role":"assistant","content":[{"type":"text","text":"hello"}]}"#,
+                r#"{"turn_id":"t2","role":"user","content":[{"type":"text","text":"bye"}]}"#,
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+        let conversation = load_conversation(&path).unwrap();
+
+        assert_eq!(conversation.messages().len(), 3);
+        assert_eq!(conversation.turn_id(1), Some("t1"));
+        assert_eq!(conversation.turn(2), Some(("t2", "bye".to_string())));
+        assert!(!conversation.turn_active());
+    }
+}
@@ -0,0 +1,214 @@
+use crate::ai_sdk::{ContentBlock, MessageParam};
+use crate::conversation::split_turns;
+use crate::session_store;
+use crate::tools::{get_all_tools, ToolDefinition};
+use clap::ValueEnum;
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+type ShowResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// What an entry of a transcript is, for `tars show --only`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EntryKind {
+    /// The user's prompts.
+    User,
+    /// The assistant's replies.
+    Assistant,
+    /// Tool calls, one line each.
+    Call,
+    /// What the tool calls returned.
+    Result,
+}
+
+/// Prints a saved session for reading, without resuming it: each turn under
+/// a `=== Turn N of M ===` header, limited to the `only` kinds of entry when
+/// any are given. On a terminal the transcript goes through `$PAGER` (`less`
+/// by default), opened at `turn`; elsewhere it starts at `turn`.
+pub fn run(session: &str, only: &[EntryKind], turn: Option<usize>, pager: bool) -> ShowResult<()> {
+    let path = session_store::resolve(session);
+    let messages = session_store::load(&path)?;
+    let turns = split_turns(&messages);
+    if turns.is_empty() {
+        return Err(format!("{} contains no turns", path.display()).into());
+    }
+    let turn = turn.unwrap_or(1);
+    if !(1..=turns.len()).contains(&turn) {
+        return Err(format!("There is no turn {}; the session has {}", turn, turns.len()).into());
+    }
+
+    let pager = pager && io::stdout().is_terminal();
+    let pager_command = pager.then(pager_command).flatten();
+    let jumps = pager_command
+        .as_ref()
+        .is_some_and(|(program, _)| program.rsplit('/').next() == Some("less"));
+    let first = if jumps { 1 } else { turn };
+    let text = render(&turns, only, first);
+    let written = match pager_command {
+        Some((program, mut args)) => {
+            if jumps && turn > 1 {
+                args.push(format!("+/^=== Turn {} of ", turn));
+            }
+            page(&program, &args, &text)
+        }
+        None => io::stdout().lock().write_all(text.as_bytes()),
+    };
+    match written {
+        // The reader quit the pager, or `head`, before the end.
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        written => Ok(written?),
+    }
+}
+
+/// The turns from `first` on, counting from 1, as plain text.
+fn render(turns: &[&[MessageParam]], only: &[EntryKind], first: usize) -> String {
+    let tools = get_all_tools();
+    let shows = |kind| only.is_empty() || only.contains(&kind);
+    let mut text = String::new();
+    for (index, turn) in turns.iter().enumerate().skip(first - 1) {
+        text.push_str(&format!("=== Turn {} of {} ===\n", index + 1, turns.len()));
+        for message in turn.iter() {
+            let (content, user) = match message {
+                MessageParam::User(message) => (message.content(), true),
+                MessageParam::Assistant(message) => (message.content(), false),
+            };
+            for block in content {
+                match block {
+                    ContentBlock::Text { text: body } if body.trim().is_empty() => {}
+                    ContentBlock::Text { text: body } => {
+                        let (kind, label) = if user {
+                            (EntryKind::User, "you:")
+                        } else {
+                            (EntryKind::Assistant, "assistant:")
+                        };
+                        if shows(kind) {
+                            text.push_str(&format!("\n{}\n{}\n", label, indent(body.trim(), 2)));
+                        }
+                    }
+                    ContentBlock::ToolUse { name, input, .. } if shows(EntryKind::Call) => {
+                        text.push_str(&format!("\n→ {}\n", summarize(&tools, name, input)));
+                    }
+                    ContentBlock::ToolUse { .. } => {}
+                    ContentBlock::ToolResult {
+                        content, is_error, ..
+                    } if shows(EntryKind::Result) => {
+                        let label = if *is_error == Some(true) {
+                            "← error:"
+                        } else {
+                            "←"
+                        };
+                        let mut output = content.text().trim().to_string();
+                        let images = content.images().count();
+                        if images > 0 {
+                            output.push_str(&format!("\n({} image(s))", images));
+                        }
+                        text.push_str(&format!("\n{}\n{}\n", label, indent(&output, 4)));
+                    }
+                    ContentBlock::ToolResult { .. } => {}
+                }
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// A call's one-line summary, or its name and input for tools that no
+/// longer exist.
+pub(crate) fn summarize(tools: &[ToolDefinition], name: &str, input: &serde_json::Value) -> String {
+    match tools.iter().find(|tool| tool.name == name) {
+        Some(tool) => (tool.summarize)(input, None),
+        None => format!("🔧 {} {}", name, input),
+    }
+}
+
+fn indent(text: &str, width: usize) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{:width$}{}", "", line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `$PAGER` split into its program and arguments, or `less` when it is
+/// unset. `cat` turns paging off.
+fn pager_command() -> Option<(String, Vec<String>)> {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    let mut words = pager.split_whitespace().map(str::to_string);
+    let program = words.next()?;
+    (program != "cat").then(|| (program, words.collect()))
+}
+
+/// Writes `text` through the pager and waits for the reader to quit it. A
+/// pager that can't be started leaves the text on stdout instead.
+fn page(program: &str, args: &[String], text: &str) -> io::Result<()> {
+    let mut command = Command::new(program);
+    command.args(args).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        // Leave short transcripts on the screen, as git does.
+        command.env("LESS", "FRX");
+    }
+    let Ok(mut child) = command.spawn() else {
+        return io::stdout().lock().write_all(text.as_bytes());
+    };
+    let written = child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(text.as_bytes());
+    child.wait()?;
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_sdk::{AssistantMessage, UserMessage};
+
+    #[test]
+    fn transcripts_render_by_turn_and_kind() {
+        let messages = vec![
+            MessageParam::User(UserMessage::from_text("What's here?".to_string())),
+            MessageParam::Assistant(AssistantMessage::new(vec![
+                ContentBlock::Text {
+                    text: "Looking.".to_string(),
+                },
+                ContentBlock::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "bash".to_string(),
+                    input: serde_json::json!({"command": "ls"}),
+                },
+            ])),
+            MessageParam::User(UserMessage::new(vec![ContentBlock::tool_result(
+                "call_1".to_string(),
+                "Cargo.toml\nsrc".to_string(),
+                false,
+            )])),
+            MessageParam::Assistant(AssistantMessage::new(vec![ContentBlock::Text {
+                text: "A Rust crate.".to_string(),
+            }])),
+            MessageParam::User(UserMessage::from_text("Thanks".to_string())),
+        ];
+        let turns = split_turns(&messages);
+        assert_eq!(
+            render(&turns, &[], 1),
+            "=== Turn 1 of 2 ===\n\nyou:\n  What's here?\n\nassistant:\n  Looking.\n\n\
+             → 💻 ls\n\n←\n    Cargo.toml\n    src\n\nassistant:\n  A Rust crate.\n\n\
+             === Turn 2 of 2 ===\n\nyou:\n  Thanks\n\n"
+        );
+        assert_eq!(
+            render(&turns, &[EntryKind::Call], 1),
+            "=== Turn 1 of 2 ===\n\n→ 💻 ls\n\n=== Turn 2 of 2 ===\n\n"
+        );
+        assert!(render(&turns, &[], 2).starts_with("=== Turn 2 of 2 ==="));
+    }
+}
@@ -0,0 +1,199 @@
+//! Incremental decoder for `text/event-stream` bodies, following the
+//! WHATWG server-sent events parsing rules.
+
+/// One dispatched server-sent event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// The `event:` field, or `"message"` when the event did not set one.
+    pub event: String,
+    /// The `data:` lines joined with newlines.
+    pub data: String,
+}
+
+/// Turns chunks of an event stream into events. Chunks may split lines,
+/// `\r\n` pairs and multi-byte UTF-8 characters anywhere.
+///
+/// `id:` and `retry:` are accepted but not reported, since nothing here
+/// reconnects.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    line: Vec<u8>,
+    /// The previous chunk ended in `\r`, so a leading `\n` belongs to it.
+    after_cr: bool,
+    /// The byte-order mark may only appear at the very start.
+    started: bool,
+    event: String,
+    data: String,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the next chunk of the body and returns the events it completes.
+    pub fn push(&mut self, mut chunk: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+
+        if self.after_cr && !chunk.is_empty() {
+            self.after_cr = false;
+            if chunk[0] == b'\n' {
+                chunk = &chunk[1..];
+            }
+        }
+
+        for (index, &byte) in chunk.iter().enumerate() {
+            match byte {
+                b'\r' => {
+                    self.end_line(&mut events);
+                    if index + 1 == chunk.len() {
+                        self.after_cr = true;
+                    }
+                }
+                b'\n' if index > 0 && chunk[index - 1] == b'\r' => {}
+                b'\n' => self.end_line(&mut events),
+                _ => self.line.push(byte),
+            }
+        }
+
+        events
+    }
+
+    fn end_line(&mut self, events: &mut Vec<SseEvent>) {
+        let mut raw = std::mem::take(&mut self.line);
+        if !self.started {
+            self.started = true;
+            if raw.starts_with(b"\xEF\xBB\xBF") {
+                raw.drain(..3);
+            }
+        }
+        let line = String::from_utf8_lossy(&raw);
+
+        if line.is_empty() {
+            if let Some(event) = self.dispatch() {
+                events.push(event);
+            }
+            return;
+        }
+        if line.starts_with(':') {
+            return;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_ref(), ""),
+        };
+        match field {
+            "event" => self.event = value.to_string(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = std::mem::take(&mut self.event);
+        let mut data = std::mem::take(&mut self.data);
+        if data.is_empty() {
+            return None;
+        }
+        data.pop();
+        Some(SseEvent {
+            event: if event.is_empty() {
+                "message".to_string()
+            } else {
+                event
+            },
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        chunks
+            .iter()
+            .flat_map(|chunk| decoder.push(chunk))
+            .collect()
+    }
+
+    fn message(data: &str) -> SseEvent {
+        SseEvent {
+            event: "message".to_string(),
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn decodes_events_with_and_without_names() {
+        let events = decode(&[b"data: one\n\nevent: ping\ndata: two\n\n"]);
+        assert_eq!(
+            events,
+            vec![
+                message("one"),
+                SseEvent {
+                    event: "ping".to_string(),
+                    data: "two".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn joins_data_lines_with_newlines() {
+        let events = decode(&[b"data: a\ndata:b\ndata\ndata:  c\n\n"]);
+        assert_eq!(events, vec![message("a\nb\n\n c")]);
+    }
+
+    #[test]
+    fn skips_comments_unknown_fields_and_empty_events() {
+        let events =
+            decode(&[b": keep-alive\n\nid: 7\nretry: 10\nfoo: bar\n\nevent: x\n\ndata: y\n\n"]);
+        assert_eq!(events, vec![message("y")]);
+    }
+
+    #[test]
+    fn waits_for_the_blank_line() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: partial\n").is_empty());
+        assert_eq!(decoder.push(b"\n"), vec![message("partial")]);
+    }
+
+    #[test]
+    fn accepts_every_line_ending() {
+        assert_eq!(decode(&[b"data: a\r\n\r\n"]), vec![message("a")]);
+        assert_eq!(decode(&[b"data: a\r\r"]), vec![message("a")]);
+        assert_eq!(
+            decode(&[b"data: a\r\n\ndata: b\n\r\n"]),
+            vec![message("a"), message("b")]
+        );
+    }
+
+    #[test]
+    fn handles_crlf_split_across_chunks() {
+        let events = decode(&[b"data: a\r", b"\n\r", b"\ndata: b\r\n\r\n"]);
+        assert_eq!(events, vec![message("a"), message("b")]);
+    }
+
+    #[test]
+    fn handles_utf8_split_across_chunks() {
+        let text = "data: héllo → ✓\n\n".as_bytes();
+        let chunks: Vec<&[u8]> = text.chunks(1).collect();
+        assert_eq!(decode(&chunks), vec![message("héllo → ✓")]);
+    }
+
+    #[test]
+    fn strips_a_leading_byte_order_mark_only() {
+        let events = decode(&[b"\xEF\xBB", b"\xBFdata: a\n\n"]);
+        assert_eq!(events, vec![message("a")]);
+
+        let events = decode(&[b"data: a\n\n\xEF\xBB\xBFdata: b\n\n"]);
+        assert_eq!(events, vec![message("a")]);
+    }
+}
@@ -0,0 +1,255 @@
+//! OpenTelemetry export for `tars server`, compiled in with the `otel`
+//! feature. Spans come from `tracing`; the metrics are recorded here.
+//! Without the feature, or without an endpoint, everything is a no-op.
+
+use std::time::Duration;
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+pub(crate) type OtelLayer<S> = Box<dyn Layer<S> + Send + Sync>;
+
+/// Variables that turn export on; the exporters read these and the rest of
+/// the standard `OTEL_*` variables themselves.
+#[cfg(feature = "otel")]
+const ENDPOINT_VARS: [&str; 3] = [
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_METRICS_ENDPOINT",
+];
+
+/// Starts exporting over OTLP/HTTP when an endpoint is configured, and
+/// returns the layer that turns spans into traces.
+#[cfg(feature = "otel")]
+pub(crate) fn layer<S>() -> Result<Option<OtelLayer<S>>, Box<dyn std::error::Error + Send + Sync>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::{MetricExporter, SpanExporter};
+    use opentelemetry_sdk::metrics::SdkMeterProvider;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+
+    if !ENDPOINT_VARS
+        .iter()
+        .any(|var| std::env::var_os(var).is_some())
+    {
+        return Ok(None);
+    }
+
+    let mut resource = Resource::builder();
+    if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+        resource = resource.with_service_name("tars");
+    }
+    let resource = resource.build();
+
+    let spans = SpanExporter::builder().with_http().build()?;
+    let tracer = SdkTracerProvider::builder()
+        .with_batch_exporter(spans)
+        .with_resource(resource.clone())
+        .build()
+        .tracer("tars");
+
+    let metrics = MetricExporter::builder().with_http().build()?;
+    opentelemetry::global::set_meter_provider(
+        SdkMeterProvider::builder()
+            .with_periodic_exporter(metrics)
+            .with_resource(resource)
+            .build(),
+    );
+
+    Ok(Some(Box::new(
+        tracing_opentelemetry::layer().with_tracer(tracer),
+    )))
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn layer<S>() -> Result<Option<OtelLayer<S>>, Box<dyn std::error::Error + Send + Sync>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    Ok(None)
+}
+
+#[cfg(feature = "otel")]
+struct Instruments {
+    inference_duration: opentelemetry::metrics::Histogram<f64>,
+    tokens: opentelemetry::metrics::Histogram<u64>,
+    tool_duration: opentelemetry::metrics::Histogram<f64>,
+    tool_output: opentelemetry::metrics::Histogram<u64>,
+}
+
+#[cfg(feature = "otel")]
+impl Instruments {
+    // Names and attributes follow the OpenTelemetry GenAI conventions.
+    fn new(meter: &opentelemetry::metrics::Meter) -> Self {
+        Self {
+            inference_duration: meter
+                .f64_histogram("gen_ai.client.operation.duration")
+                .with_unit("s")
+                .with_description("Time taken by a Messages API request")
+                .build(),
+            tokens: meter
+                .u64_histogram("gen_ai.client.token.usage")
+                .with_unit("{token}")
+                .with_description("Tokens used by a Messages API request")
+                .build(),
+            tool_duration: meter
+                .f64_histogram("tars.tool.duration")
+                .with_unit("s")
+                .with_description("Time taken by a tool call")
+                .build(),
+            tool_output: meter
+                .u64_histogram("tars.tool.output")
+                .with_unit("{token}")
+                .with_description("Estimated tokens of output a tool call gave the model")
+                .build(),
+        }
+    }
+
+    fn inference(&self, model: &str, elapsed: Duration, input_tokens: u64, output_tokens: u64) {
+        use opentelemetry::KeyValue;
+
+        let model = KeyValue::new("gen_ai.request.model", model.to_string());
+        self.inference_duration
+            .record(elapsed.as_secs_f64(), std::slice::from_ref(&model));
+        for (kind, tokens) in [("input", input_tokens), ("output", output_tokens)] {
+            self.tokens.record(
+                tokens,
+                &[model.clone(), KeyValue::new("gen_ai.token.type", kind)],
+            );
+        }
+    }
+
+    fn tool(&self, name: &str, elapsed: Duration, is_error: bool, output_tokens: u64) {
+        use opentelemetry::KeyValue;
+
+        let attributes = [
+            KeyValue::new("tool.name", name.to_string()),
+            KeyValue::new("error", is_error),
+        ];
+        self.tool_duration
+            .record(elapsed.as_secs_f64(), &attributes);
+        self.tool_output.record(output_tokens, &attributes);
+    }
+}
+
+/// Created on first use, after `layer` has installed the meter provider.
+#[cfg(feature = "otel")]
+fn instruments() -> &'static Instruments {
+    static INSTRUMENTS: std::sync::OnceLock<Instruments> = std::sync::OnceLock::new();
+    INSTRUMENTS.get_or_init(|| Instruments::new(&opentelemetry::global::meter("tars")))
+}
+
+/// Records one Messages API request.
+#[cfg(feature = "otel")]
+pub(crate) fn record_inference(
+    model: &str,
+    elapsed: Duration,
+    input_tokens: u64,
+    output_tokens: u64,
+) {
+    instruments().inference(model, elapsed, input_tokens, output_tokens);
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn record_inference(_model: &str, _elapsed: Duration, _input: u64, _output: u64) {}
+
+/// Records one tool call.
+#[cfg(feature = "otel")]
+pub(crate) fn record_tool(name: &str, elapsed: Duration, is_error: bool, output_tokens: u64) {
+    instruments().tool(name, elapsed, is_error, output_tokens);
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn record_tool(_name: &str, _elapsed: Duration, _is_error: bool, _output_tokens: u64) {}
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use super::*;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry::KeyValue;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+
+    /// A histogram's data points, as their attributes and counts.
+    type Points = Vec<(Vec<KeyValue>, u64)>;
+
+    /// Each recorded histogram's name and data points.
+    fn recorded(exporter: &InMemoryMetricExporter) -> Vec<(String, Points)> {
+        let mut recorded = Vec::new();
+        for resource in exporter.get_finished_metrics().unwrap() {
+            for scope in resource.scope_metrics() {
+                for metric in scope.metrics() {
+                    let points = match metric.data() {
+                        AggregatedMetrics::F64(MetricData::Histogram(histogram)) => histogram
+                            .data_points()
+                            .map(|point| (point.attributes().cloned().collect(), point.count()))
+                            .collect(),
+                        AggregatedMetrics::U64(MetricData::Histogram(histogram)) => histogram
+                            .data_points()
+                            .map(|point| (point.attributes().cloned().collect(), point.count()))
+                            .collect(),
+                        other => panic!("unexpected data for {}: {other:?}", metric.name()),
+                    };
+                    recorded.push((metric.name().to_string(), points));
+                }
+            }
+        }
+        recorded.sort_by(|a, b| a.0.cmp(&b.0));
+        recorded
+    }
+
+    #[test]
+    fn inference_and_tool_calls_are_recorded() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let instruments = Instruments::new(&provider.meter("tars"));
+
+        instruments.inference("claude-sonnet-4-5", Duration::from_millis(1200), 1234, 56);
+        instruments.tool("read_file", Duration::from_millis(3), false, 80);
+        instruments.tool("read_file", Duration::from_millis(2), true, 10);
+        provider.force_flush().unwrap();
+
+        let model = KeyValue::new("gen_ai.request.model", "claude-sonnet-4-5");
+        let read_file = KeyValue::new("tool.name", "read_file");
+        let tool_points = vec![
+            (vec![KeyValue::new("error", false), read_file.clone()], 1),
+            (vec![KeyValue::new("error", true), read_file.clone()], 1),
+        ];
+        let mut recorded = recorded(&exporter);
+        for (_, points) in &mut recorded {
+            for (attributes, _) in points.iter_mut() {
+                attributes.sort_by(|a, b| a.key.cmp(&b.key));
+            }
+            points.sort_by_key(|(attributes, _)| format!("{attributes:?}"));
+        }
+        assert_eq!(
+            recorded,
+            vec![
+                (
+                    "gen_ai.client.operation.duration".to_string(),
+                    vec![(vec![model.clone()], 1)]
+                ),
+                (
+                    "gen_ai.client.token.usage".to_string(),
+                    vec![
+                        (
+                            vec![model.clone(), KeyValue::new("gen_ai.token.type", "input")],
+                            1
+                        ),
+                        (
+                            vec![model.clone(), KeyValue::new("gen_ai.token.type", "output")],
+                            1
+                        ),
+                    ]
+                ),
+                ("tars.tool.duration".to_string(), tool_points.clone()),
+                ("tars.tool.output".to_string(), tool_points),
+            ]
+        );
+    }
+}
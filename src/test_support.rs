@@ -0,0 +1,162 @@
+//! Helpers shared by the unit tests.
+
+use crate::ai_sdk::{
+    Capabilities, MessageRequest, MessageResponse, Provider, ProviderResult, ResponseContentBlock,
+    StopReason, Usage,
+};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// A fresh directory under the system temp dir, removed with everything in
+/// it on drop, so a failing test leaves nothing behind.
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates `<temp dir>/<prefix>-<uuid>`.
+    pub(crate) fn new(prefix: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("{}-{}", prefix, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Serves plain HTTP on a local port until the test ends, answering each
+/// request with the status and JSON body `respond` returns for its raw text
+/// (head and body). Returns `http://<address>`.
+pub(crate) async fn serve(respond: impl Fn(&str) -> (u16, String) + Send + 'static) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let request = read_request(&mut socket).await;
+            let (status, body) = respond(&request);
+            let head = format!(
+                "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            let _ = socket.write_all(head.as_bytes()).await;
+            let _ = socket.write_all(body.as_bytes()).await;
+        }
+    });
+    format!("http://{}", address)
+}
+
+/// Reads one request, through to the end of a `Content-Length` body.
+async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+    let mut request = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let read = socket.read(&mut chunk).await.unwrap_or(0);
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..read]);
+        let text = String::from_utf8_lossy(&request);
+        if let Some(end) = text.find("\r\n\r\n") {
+            let length = text[..end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if request.len() >= end + 4 + length {
+                break;
+            }
+        }
+    }
+    String::from_utf8_lossy(&request).into_owned()
+}
+
+/// A model that answers with `script` in order, ending the turn after a
+/// reply without tool calls, and keeps the requests it gets.
+#[derive(Default)]
+pub(crate) struct Scripted {
+    script: Mutex<Vec<Vec<ResponseContentBlock>>>,
+    requests: Mutex<Vec<serde_json::Value>>,
+}
+
+impl Scripted {
+    pub(crate) fn new(script: Vec<Vec<ResponseContentBlock>>) -> Self {
+        Self {
+            script: Mutex::new(script),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn requests(&self) -> Vec<serde_json::Value> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Provider for Scripted {
+    fn capabilities(&self, _model: &str) -> Capabilities {
+        Capabilities {
+            prefill: false,
+            images: false,
+            tool_choice: false,
+        }
+    }
+
+    fn convert_request(&self, request: &MessageRequest) -> ProviderResult<serde_json::Value> {
+        Ok(serde_json::to_value(request)?)
+    }
+
+    fn convert_response(&self, body: serde_json::Value) -> ProviderResult<MessageResponse> {
+        Ok(serde_json::from_value(body)?)
+    }
+
+    async fn run_inference(&self, request: &MessageRequest) -> ProviderResult<MessageResponse> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push(serde_json::to_value(request)?);
+        let mut script = self.script.lock().unwrap();
+        if script.is_empty() {
+            return Err("the script has run out".into());
+        }
+        let content = script.remove(0);
+        let stop_reason = match content.last() {
+            Some(ResponseContentBlock::ToolUse { .. }) => StopReason::ToolUse,
+            _ => StopReason::EndTurn,
+        };
+        Ok(MessageResponse {
+            id: "msg_1".to_string(),
+            content,
+            stop_reason: Some(stop_reason),
+            model: "local".to_string(),
+            usage: Usage::default(),
+        })
+    }
+
+    #[cfg(feature = "server")]
+    async fn reachable(&self) -> bool {
+        true
+    }
+}
@@ -1,27 +1,379 @@
+use std::collections::{BTreeSet, HashMap};
 use std::future::Future;
+use std::path::{Component, Path, PathBuf};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 
+use crate::ai_sdk::ImageSource;
+use crate::protocol::TodoItem;
+use crate::replay::RecordedResults;
+
+mod approval;
+mod archive;
+mod bash;
+mod background;
+mod changes;
+mod code_outline;
+mod check_port;
+mod create_directory;
+mod dependencies;
+mod download;
 mod edit_file;
+mod env;
+mod format;
+mod get_chunk;
+mod guard;
+mod hash_file;
+mod keyword_search;
+mod links;
 mod list_files;
+mod process;
+mod read_clipboard;
 mod read_file;
+mod save_artifact;
+mod schema;
+mod screenshot;
+mod search_files;
+mod stat;
+mod stats;
+mod text;
+mod todo;
+
+pub(crate) use approval::{denial, Approval, Approvals};
+pub(crate) use background::BackgroundProcesses;
+#[cfg(feature = "server")]
+pub(crate) use changes::format_patch;
+pub(crate) use changes::ChangeTracker;
+pub(crate) use format::format_after_edit;
+pub(crate) use guard::{refusal as guard_refusal, Change, TurnEdits, GUARD};
+pub(crate) use links::check_links;
+#[cfg(feature = "server")]
+pub(crate) use save_artifact::{artifacts_dir, list_artifacts};
+pub(crate) use schema::validate_input;
+#[cfg(feature = "server")]
+pub(crate) use stats::combine_stats;
+#[cfg(feature = "client")]
+pub(crate) use stats::stats_table;
+pub(crate) use stats::ToolUsage;
+pub(crate) use todo::NAME as TODO_TOOL;
 
 type ToolHandler = fn(
     serde_json::Value,
+    ToolContext,
 ) -> Pin<
     Box<dyn Future<Output = Result<String, Box<dyn std::error::Error + Send + Sync>>> + Send>,
 >;
 
+/// Renders a one-line transcript entry for a call from its input and, when
+/// it succeeded, its output.
+type ToolSummary = fn(&serde_json::Value, Option<&str>) -> String;
+
 pub(crate) struct ToolDefinition {
     pub(crate) name: &'static str,
     pub(crate) description: &'static str,
     pub(crate) input_schema: serde_json::Value,
     pub(crate) handler: ToolHandler,
+    /// Whether the tool only observes the workspace. Read-only results are
+    /// memoized within a turn until a mutating tool runs.
+    pub(crate) read_only: bool,
+    /// What the user has to turn on in `[tools]` before the tool is
+    /// offered; `None` for tools that are always there.
+    pub(crate) opt_in: Option<OptIn>,
+    pub(crate) summarize: ToolSummary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OptIn {
+    /// `network = true`, for tools that fetch from the internet.
+    Network,
+    /// `clipboard = true`, for reading the user's clipboard.
+    Clipboard,
+    /// `screenshot = true`, for looking at the user's screen.
+    Screenshot,
+    /// `processes = true`, for running commands in the background.
+    Processes,
+}
+
+/// Read-only tools that still ask before each call, because what they read
+/// is private. `auto_approve = ["*"]` doesn't cover them.
+pub(crate) const PRIVATE: &[&str] = &[read_clipboard::NAME];
+
+/// Tools that aren't read-only but change nothing outside the session, so
+/// they never ask.
+const SESSION_ONLY: &[&str] = &[todo::NAME, "check_process"];
+
+impl ToolDefinition {
+    /// Whether each call waits for the user to approve it, unless the
+    /// session or `auto_approve` allows the tool.
+    pub(crate) fn needs_approval(&self) -> bool {
+        self.changes_workspace() || PRIVATE.contains(&self.name)
+    }
+
+    /// Whether a call can change anything outside the session.
+    pub(crate) fn changes_workspace(&self) -> bool {
+        !self.read_only && !SESSION_ONLY.contains(&self.name)
+    }
+}
+
+/// How network tools reach the internet, and where they may go.
+#[derive(Debug, Clone)]
+pub(crate) struct NetworkAccess {
+    /// Built with the agent's network settings, so proxy and certificate
+    /// settings apply, and follows redirects only to allowed hosts.
+    pub(crate) client: reqwest::Client,
+    pub(crate) allowed_hosts: Vec<String>,
+    pub(crate) max_download_bytes: u64,
+}
+
+/// Redirects a network tool follows before giving up, as many as reqwest
+/// follows by default.
+const MAX_REDIRECTS: usize = 10;
+
+impl NetworkAccess {
+    /// Access to `allowed_hosts` through a client built by `builder`, which
+    /// checks every redirect against them as well as the first URL.
+    pub(crate) fn new(
+        builder: reqwest::ClientBuilder,
+        allowed_hosts: Vec<String>,
+        max_download_bytes: u64,
+    ) -> reqwest::Result<Self> {
+        let hosts = allowed_hosts.clone();
+        let policy = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            if host_allowed(&hosts, attempt.url()) {
+                return attempt.follow();
+            }
+            let refusal = format!(
+                "{} redirected to {}, which is not in the allowed_hosts under [tools]",
+                attempt.previous().last().map_or("", |url| url.as_str()),
+                attempt.url().host_str().unwrap_or_default()
+            );
+            attempt.error(refusal)
+        });
+        Ok(Self {
+            client: builder.redirect(policy).build()?,
+            allowed_hosts,
+            max_download_bytes,
+        })
+    }
+
+    /// Whether `url`'s host is in `allowed_hosts`.
+    pub(crate) fn allows(&self, url: &reqwest::Url) -> bool {
+        host_allowed(&self.allowed_hosts, url)
+    }
+}
+
+/// Whether `url`'s host is in `allowed_hosts`, or anything goes. A `*.`
+/// pattern matches subdomains only.
+fn host_allowed(allowed_hosts: &[String], url: &reqwest::Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    allowed_hosts.is_empty()
+        || allowed_hosts.iter().any(|pattern| match pattern.strip_prefix('*') {
+            Some(suffix) => {
+                suffix.starts_with('.') && host.ends_with(&suffix.to_ascii_lowercase())
+            }
+            None => host.eq_ignore_ascii_case(pattern),
+        })
+}
+
+/// State a session's tools keep between calls.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ToolContext {
+    /// The plan kept with the `todo` tool.
+    pub(crate) todos: Arc<Mutex<Vec<TodoItem>>>,
+    /// Where `save_artifact` writes; set for server sessions.
+    pub(crate) artifacts: Option<PathBuf>,
+    /// Files the tools changed, for the session's diff.
+    pub(crate) changes: Arc<Mutex<ChangeTracker>>,
+    /// Paths read-only tools were called with.
+    pub(crate) reads: Arc<Mutex<BTreeSet<PathBuf>>>,
+    /// What the running turn has changed, for the `[guard]` limits.
+    pub(crate) turn_edits: Arc<Mutex<TurnEdits>>,
+    /// How far the running tool has got, e.g. `3.1 MB of 10.0 MB`, for the
+    /// turn's heartbeats.
+    pub(crate) progress: Arc<Mutex<Option<String>>>,
+    /// Calls waiting for the user's approval, and the tools allowed for
+    /// the session.
+    pub(crate) approvals: Arc<Mutex<Approvals>>,
+    /// Images the running tool shows the model with its result.
+    pub(crate) images: Arc<Mutex<Vec<ImageSource>>>,
+    /// Dev servers and watchers started with `start_process`.
+    pub(crate) processes: Arc<Mutex<BackgroundProcesses>>,
+    /// How the session's tool calls have gone, for `/stats`.
+    pub(crate) usage: Arc<Mutex<ToolUsage>>,
+    /// Results from a recorded session that stand in for calls which would
+    /// change the workspace, during `tars replay`.
+    pub(crate) recorded: Option<Arc<Mutex<RecordedResults>>>,
+    /// Set by the agent when `[tools]` turns network tools on.
+    pub(crate) network: Option<NetworkAccess>,
+    /// Variables `[tools]` lets the `env` tool show besides its own list.
+    pub(crate) env_allowlist: Vec<String>,
+    /// How long `bash` lets a command run when the call doesn't say, from
+    /// `[tools]`.
+    pub(crate) bash_timeout_secs: Option<u64>,
+}
+
+/// Results of read-only tool calls made during a single turn, keyed by tool
+/// name and input, so repeated identical calls skip the filesystem.
+#[derive(Default)]
+pub(crate) struct TurnCache {
+    results: HashMap<(String, String), (String, bool)>,
 }
 
+impl TurnCache {
+    pub(crate) fn get(&self, name: &str, input: &serde_json::Value) -> Option<(String, bool)> {
+        self.results
+            .get(&(name.to_string(), input.to_string()))
+            .cloned()
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        name: &str,
+        input: &serde_json::Value,
+        content: String,
+        is_error: bool,
+    ) {
+        self.results
+            .insert((name.to_string(), input.to_string()), (content, is_error));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.results.clear();
+    }
+}
+
+/// Every tool, with its input schema minified.
 pub(crate) fn get_all_tools() -> Vec<ToolDefinition> {
-    vec![
+    let tools = vec![
         read_file::definition(),
+        get_chunk::definition(),
+        bash::definition(),
         list_files::definition(),
+        search_files::definition(),
+        code_outline::definition(),
+        keyword_search::definition(),
         edit_file::definition(),
-    ]
+        create_directory::definition(),
+        stat::definition(),
+        archive::list_definition(),
+        archive::extract_definition(),
+        hash_file::definition(),
+        check_port::definition(),
+        env::definition(),
+        dependencies::definition(),
+        download::definition(),
+        read_clipboard::definition(),
+        screenshot::definition(),
+        background::start_definition(),
+        background::check_definition(),
+        background::stop_definition(),
+        todo::definition(),
+        save_artifact::definition(),
+    ];
+    tools
+        .into_iter()
+        .map(|tool| ToolDefinition {
+            input_schema: schema::minify(tool.input_schema),
+            ..tool
+        })
+        .collect()
+}
+
+/// A tool's path argument with `.` components dropped, so `./src/main.rs`
+/// and `src/main.rs` name the same file.
+pub(crate) fn normalize_path(path: impl AsRef<Path>) -> PathBuf {
+    path.as_ref()
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// Why a file tool can't work on `path`, which exists but isn't a regular
+/// file.
+fn not_a_file(path: &str, metadata: &std::fs::Metadata) -> String {
+    if metadata.is_dir() {
+        format!("{} is a directory", path)
+    } else {
+        format!("{} is not a regular file (a socket, pipe or device)", path)
+    }
+}
+
+/// The `path` argument of a tool call, for summaries.
+fn input_path(input: &serde_json::Value) -> &str {
+    match input["path"].as_str() {
+        Some(path) if !path.is_empty() => path,
+        _ => ".",
+    }
+}
+
+/// Formats a count with thousands separators, e.g. `3,112`.
+pub(crate) fn group_thousands(value: u64) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::new();
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(ch);
+    }
+    grouped
+}
+
+/// Formats a byte count for humans, e.g. `2.1 GB`.
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_all_tools, host_allowed};
+
+    #[test]
+    fn summaries_describe_calls_in_one_line() {
+        let tools = get_all_tools();
+        let summarize = |name: &str, input: serde_json::Value, output: Option<&str>| {
+            let tool = tools.iter().find(|tool| tool.name == name).unwrap();
+            (tool.summarize)(&input, output)
+        };
+
+        let edit = serde_json::json!({ "path": "src/ui.rs", "old_str": "a\nb", "new_str": "c" });
+        assert_eq!(summarize("edit_file", edit.clone(), Some("OK")), "✏️ edit src/ui.rs (+1 −2)");
+        assert_eq!(summarize("edit_file", edit, None), "✏️ edit src/ui.rs");
+
+        let read = serde_json::json!({ "path": "Cargo.toml" });
+        assert_eq!(summarize("read_file", read, Some("a\nb\n")), "📖 read Cargo.toml (2 lines)");
+
+        let list = serde_json::json!({});
+        let output = r#"["a", "b", "…and 5 more (pass cursor: 2 to continue)"]"#;
+        assert_eq!(summarize("list_files", list, Some(output)), "📂 list . (2+ entries)");
+    }
+
+    #[test]
+    fn host_patterns_ignore_case() {
+        let allowed = |patterns: &[&str], url: &str| {
+            let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+            host_allowed(&patterns, &url.parse().unwrap())
+        };
+        assert!(allowed(&[], "https://example.com/"));
+        assert!(allowed(&["GitHub.com"], "https://github.com/"));
+        assert!(allowed(&["*.GitHub.com"], "https://api.GITHUB.com/"));
+        assert!(!allowed(&["*.github.com"], "https://github.com/"));
+        assert!(!allowed(&["*github.com"], "https://evilgithub.com/"));
+        assert!(!allowed(&["github.com"], "https://example.com/"));
+    }
 }
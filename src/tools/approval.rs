@@ -0,0 +1,142 @@
+use std::collections::{BTreeSet, HashMap};
+use tokio::sync::oneshot;
+
+#[cfg(feature = "server")]
+use crate::protocol::ApprovalDecision;
+use crate::protocol::ApprovalRequest;
+
+#[cfg(feature = "server")]
+use super::GUARD;
+
+/// What became of a call that needs approval, before anyone was asked.
+#[derive(Debug)]
+pub(crate) enum Approval {
+    /// The user allowed the tool for the session.
+    Granted,
+    /// No one can answer, so the call can't run.
+    Refused(String),
+    /// Resolves with the user's answer.
+    Pending(oneshot::Receiver<ApprovalRequest>),
+}
+
+/// The session's answers to calls that need the user's approval, and the
+/// calls still waiting for one.
+#[derive(Debug, Default)]
+pub(crate) struct Approvals {
+    /// Whether a client is there to answer; schedules and replays have
+    /// nobody to ask.
+    interactive: bool,
+    /// Tools allowed for the rest of the session, with "always" or
+    /// `/allow`.
+    allowed: BTreeSet<String>,
+    /// Calls waiting for an answer, by tool use id, with their tool.
+    pending: HashMap<String, (String, oneshot::Sender<ApprovalRequest>)>,
+}
+
+impl Approvals {
+    /// For a session a user follows and can answer.
+    #[cfg(feature = "server")]
+    pub(crate) fn interactive() -> Self {
+        Self {
+            interactive: true,
+            ..Self::default()
+        }
+    }
+
+    /// Lets every later call to `tool` run without asking.
+    #[cfg(feature = "server")]
+    pub(crate) fn allow(&mut self, tool: &str) {
+        self.allowed.insert(tool.to_string());
+    }
+
+    /// Asks for approval of the call `tool_use_id` to `tool`, unless the
+    /// session already allowed the tool.
+    pub(crate) fn request(&mut self, tool_use_id: &str, tool: &str) -> Approval {
+        if self.allowed.contains(tool) {
+            return Approval::Granted;
+        }
+        if !self.interactive {
+            return Approval::Refused(format!(
+                "{} needs the user's approval, and nobody can give it in this unattended run. Don't retry it; say what you would have done instead.",
+                tool
+            ));
+        }
+        // Calls whose turn was cancelled while they waited.
+        self.pending.retain(|_, (_, sender)| !sender.is_closed());
+        let (sender, receiver) = oneshot::channel();
+        self.pending
+            .insert(tool_use_id.to_string(), (tool.to_string(), sender));
+        Approval::Pending(receiver)
+    }
+
+    /// Whether a call is waiting for an answer.
+    #[cfg(feature = "server")]
+    pub(crate) fn waiting(&self) -> bool {
+        self.pending.values().any(|(_, sender)| !sender.is_closed())
+    }
+
+    /// Passes the user's answer to the waiting call. False when no call
+    /// with that id is waiting.
+    #[cfg(feature = "server")]
+    pub(crate) fn answer(&mut self, tool_use_id: &str, answer: ApprovalRequest) -> bool {
+        let Some((tool, sender)) = self.pending.remove(tool_use_id) else {
+            return false;
+        };
+        // A change past the `[guard]` limits is asked about every time.
+        if answer.decision == ApprovalDecision::Always && tool != GUARD {
+            self.allowed.insert(tool);
+        }
+        sender.send(answer).is_ok()
+    }
+}
+
+/// What the model is told when the user turns a call down.
+pub(crate) fn denial(answer: &ApprovalRequest) -> String {
+    match answer.message.as_deref().map(str::trim) {
+        Some(message) if !message.is_empty() => {
+            format!("The user denied this call and said: {}", message)
+        }
+        _ => "The user denied this call. Don't retry it unless they ask; say what you meant to do instead.".to_string(),
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_reach_the_waiting_call_and_always_allows_the_tool() {
+        let mut approvals = Approvals::interactive();
+        let Approval::Pending(receiver) = approvals.request("call-1", "edit_file") else {
+            panic!("an interactive session asks");
+        };
+        assert!(!approvals.answer(
+            "call-2",
+            ApprovalRequest {
+                decision: ApprovalDecision::Approve,
+                message: None,
+            }
+        ));
+        assert!(approvals.answer(
+            "call-1",
+            ApprovalRequest {
+                decision: ApprovalDecision::Always,
+                message: None,
+            }
+        ));
+        assert_eq!(receiver.await.unwrap().decision, ApprovalDecision::Always);
+        assert!(matches!(
+            approvals.request("call-3", "edit_file"),
+            Approval::Granted
+        ));
+        assert!(matches!(
+            approvals.request("call-4", "bash"),
+            Approval::Pending(_)
+        ));
+
+        assert!(matches!(
+            Approvals::default().request("call-5", "bash"),
+            Approval::Refused(_)
+        ));
+    }
+}
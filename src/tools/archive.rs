@@ -0,0 +1,262 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use super::{format_size, group_thousands, input_path, text, ToolDefinition};
+
+type ArchiveResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const MAX_LISTED: usize = 1000;
+/// Entries larger than this are cut off when shown, as with read_file.
+const MAX_SHOWN_BYTES: u64 = 256 * 1024;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct ListArchiveInput {
+    #[schemars(description = "The relative path of a .zip (or .whl, .jar), .tar or .tar.gz file")]
+    path: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct ExtractFileInput {
+    #[schemars(description = "The relative path of the archive")]
+    path: String,
+    #[schemars(description = "The entry's path inside the archive, as list_archive shows it")]
+    entry: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+struct Entry {
+    name: String,
+    size: u64,
+    is_dir: bool,
+}
+
+/// Tells archives apart by their first bytes rather than by extension.
+fn format(path: &Path) -> ArchiveResult<Format> {
+    let mut magic = Vec::new();
+    File::open(path)?.take(512).read_to_end(&mut magic)?;
+    if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+        Ok(Format::Zip)
+    } else if magic.starts_with(&[0x1f, 0x8b]) {
+        Ok(Format::TarGz)
+    } else if magic.get(257..262) == Some(b"ustar") {
+        Ok(Format::Tar)
+    } else {
+        Err(format!(
+            "{} is not a zip, tar or gzipped tar archive",
+            path.display()
+        )
+        .into())
+    }
+}
+
+fn tar_archive(path: &Path, format: Format) -> io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = File::open(path)?;
+    let reader: Box<dyn Read> = match format {
+        Format::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+fn entries(path: &Path) -> ArchiveResult<Vec<Entry>> {
+    let mut entries = Vec::new();
+    match format(path)? {
+        Format::Zip => {
+            let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+            for index in 0..archive.len() {
+                let file = archive.by_index(index)?;
+                entries.push(Entry {
+                    name: file.name().to_string(),
+                    size: file.size(),
+                    is_dir: file.is_dir(),
+                });
+            }
+        }
+        format => {
+            for entry in tar_archive(path, format)?.entries()? {
+                let entry = entry?;
+                entries.push(Entry {
+                    name: entry.path()?.to_string_lossy().into_owned(),
+                    size: entry.size(),
+                    is_dir: entry.header().entry_type().is_dir(),
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Up to `limit` bytes of the entry `name`, and its full size.
+fn read_entry(path: &Path, name: &str, limit: u64) -> ArchiveResult<(Vec<u8>, u64)> {
+    let name = name.trim_start_matches("./");
+    let mut bytes = Vec::new();
+    match format(path)? {
+        Format::Zip => {
+            let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+            let file = archive
+                .by_name(name)
+                .map_err(|_| format!("{} has no entry {}", path.display(), name))?;
+            let size = file.size();
+            file.take(limit).read_to_end(&mut bytes)?;
+            Ok((bytes, size))
+        }
+        format => {
+            for entry in tar_archive(path, format)?.entries()? {
+                let entry = entry?;
+                if entry.path()?.to_string_lossy().trim_start_matches("./") != name {
+                    continue;
+                }
+                let size = entry.size();
+                entry.take(limit).read_to_end(&mut bytes)?;
+                return Ok((bytes, size));
+            }
+            Err(format!("{} has no entry {}", path.display(), name).into())
+        }
+    }
+}
+
+fn list(path: &str) -> ArchiveResult<String> {
+    let entries = entries(Path::new(path))?;
+    let total: u64 = entries.iter().map(|entry| entry.size).sum();
+    let mut lines = vec![format!(
+        "{} entries, {} uncompressed",
+        group_thousands(entries.len() as u64),
+        format_size(total)
+    )];
+    for entry in entries.iter().take(MAX_LISTED) {
+        if entry.is_dir {
+            lines.push(format!("{}/", entry.name.trim_end_matches('/')));
+        } else {
+            lines.push(format!("{}  ({})", entry.name, format_size(entry.size)));
+        }
+    }
+    if entries.len() > MAX_LISTED {
+        lines.push(format!(
+            "…and {} more",
+            group_thousands((entries.len() - MAX_LISTED) as u64)
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn extract(path: &str, name: &str) -> ArchiveResult<String> {
+    let (bytes, size) = read_entry(Path::new(path), name, MAX_SHOWN_BYTES)?;
+    if bytes.contains(&0) && !text::is_utf16(&bytes) {
+        return Ok(format!(
+            "[{} is a binary file ({}); its contents are not shown]",
+            name,
+            format_size(size)
+        ));
+    }
+    let contents = text::decode(&bytes).text;
+    if size > MAX_SHOWN_BYTES {
+        return Ok(format!(
+            "{}\n[… cut off after {} of {}]",
+            contents,
+            format_size(MAX_SHOWN_BYTES),
+            format_size(size)
+        ));
+    }
+    Ok(contents)
+}
+
+pub(crate) fn list_definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "list_archive",
+        description: "List the entries of a zip (including .whl and .jar), tar or .tar.gz archive with their sizes, without extracting it.",
+        input_schema: serde_json::to_value(schema_for!(ListArchiveInput)).unwrap(),
+        handler: |input, _| {
+            Box::pin(async move {
+                let input: ListArchiveInput = serde_json::from_value(input)?;
+                tokio::task::spawn_blocking(move || list(&input.path)).await?
+            })
+        },
+        read_only: true,
+        opt_in: None,
+        summarize: |input, _| format!("🗜️ list archive {}", input_path(input)),
+    }
+}
+
+pub(crate) fn extract_definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "extract_file_from_archive",
+        description: "Show the contents of one file inside a zip, tar or .tar.gz archive, without extracting anything to disk. Binary entries are reported by size only, and entries over 256 KB are cut off.",
+        input_schema: serde_json::to_value(schema_for!(ExtractFileInput)).unwrap(),
+        handler: |input, _| {
+            Box::pin(async move {
+                let input: ExtractFileInput = serde_json::from_value(input)?;
+                tokio::task::spawn_blocking(move || extract(&input.path, &input.entry)).await?
+            })
+        },
+        read_only: true,
+        opt_in: None,
+        summarize: |input, _| {
+            format!(
+                "🗜️ read {} from {}",
+                input["entry"].as_str().unwrap_or_default(),
+                input_path(input)
+            )
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::io::Write;
+
+    #[test]
+    fn archives_are_listed_and_read_in_place() {
+        let dir = TempDir::new("tars-archive");
+
+        let wheel = dir.join("pkg-1.0-py3-none-any.whl");
+        let mut zip = zip::ZipWriter::new(File::create(&wheel).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("pkg/", options).unwrap();
+        zip.start_file("pkg/__init__.py", options).unwrap();
+        zip.write_all(b"VERSION = '1.0'\n").unwrap();
+        zip.finish().unwrap();
+
+        let tarball = dir.join("vendor.tgz");
+        let gz = flate2::write::GzEncoder::new(
+            File::create(&tarball).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "lib/a.bin", &[1u8, 0, 2, 3][..])
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let wheel = wheel.to_str().unwrap();
+        assert_eq!(
+            list(wheel).unwrap(),
+            "2 entries, 16 bytes uncompressed\npkg/\npkg/__init__.py  (16 bytes)"
+        );
+        assert_eq!(
+            extract(wheel, "pkg/__init__.py").unwrap(),
+            "VERSION = '1.0'\n"
+        );
+        assert!(extract(wheel, "pkg/missing.py").is_err());
+
+        let tarball = tarball.to_str().unwrap();
+        assert!(list(tarball).unwrap().ends_with("lib/a.bin  (4 bytes)"));
+        assert!(extract(tarball, "lib/a.bin")
+            .unwrap()
+            .contains("binary file (4 bytes)"));
+
+        assert!(list(dir.join("..").to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,480 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Child;
+
+use super::{format_size, OptIn, ToolContext, ToolDefinition};
+
+type BackgroundResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// How many processes a session may have running at once.
+const MAX_RUNNING: usize = 8;
+/// Output kept per process; older output is dropped first.
+const MAX_KEPT_BYTES: usize = 256 * 1024;
+/// Output shown per call. A server that logs every request would otherwise
+/// fill the context; the newest lines are the ones kept.
+const MAX_SHOWN_BYTES: usize = 16 * 1024;
+/// How long `start_process` watches a new process before returning, so a
+/// command that fails at once is reported as failed.
+const STARTUP_WAIT: Duration = Duration::from_secs(2);
+const MAX_WAIT_SECS: u64 = 120;
+/// How long `stop_process` gives a process to exit after SIGTERM before
+/// killing it.
+const STOP_GRACE: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct StartProcessInput {
+    #[schemars(description = "The shell command to run, e.g. `npm run dev`")]
+    command: String,
+    #[schemars(
+        description = "Optional relative directory to run it in; the workspace root when left out"
+    )]
+    cwd: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct CheckProcessInput {
+    #[schemars(
+        description = "The id start_process returned. Leave out to list the session's processes."
+    )]
+    id: Option<u32>,
+    #[schemars(
+        description = "Optional text to wait for in the output, e.g. `ready in` or `Listening on`"
+    )]
+    until: Option<String>,
+    #[schemars(
+        description = "Seconds to wait for `until` to appear or the process to exit; at most 120"
+    )]
+    #[serde(default)]
+    wait_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct StopProcessInput {
+    #[schemars(description = "The id start_process returned")]
+    id: u32,
+}
+
+/// What a process printed, stdout and stderr interleaved as they arrived.
+#[derive(Debug, Default)]
+struct Output {
+    bytes: Vec<u8>,
+    /// Bytes dropped from the front of `bytes` to stay under
+    /// `MAX_KEPT_BYTES`.
+    dropped: usize,
+    /// Offset, counting dropped bytes, up to which the model has seen the
+    /// output.
+    seen: usize,
+}
+
+impl Output {
+    fn push(&mut self, chunk: &[u8]) {
+        self.bytes.extend_from_slice(chunk);
+        if self.bytes.len() > MAX_KEPT_BYTES {
+            let excess = self.bytes.len() - MAX_KEPT_BYTES;
+            self.bytes.drain(..excess);
+            self.dropped += excess;
+        }
+    }
+
+    fn unseen(&self) -> &[u8] {
+        &self.bytes[self.seen.saturating_sub(self.dropped)..]
+    }
+
+    /// The output the model hasn't seen yet, at most `MAX_SHOWN_BYTES` of
+    /// its end, marking it as seen.
+    fn take_unseen(&mut self) -> String {
+        let unseen = self.unseen();
+        let skipped =
+            self.dropped.saturating_sub(self.seen) + unseen.len().saturating_sub(MAX_SHOWN_BYTES);
+        let shown =
+            String::from_utf8_lossy(&unseen[unseen.len().saturating_sub(MAX_SHOWN_BYTES)..])
+                .into_owned();
+        self.seen = self.dropped + self.bytes.len();
+        if skipped > 0 {
+            format!(
+                "[… {} of earlier output left out …]\n{}",
+                format_size(skipped as u64),
+                shown
+            )
+        } else {
+            shown
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BackgroundProcess {
+    command: String,
+    child: Child,
+    /// The process group the command runs in, so stopping it also stops
+    /// whatever it started, like the server under `npm run dev`.
+    #[cfg(unix)]
+    group: Option<i32>,
+    output: Arc<Mutex<Output>>,
+    started: Instant,
+}
+
+impl BackgroundProcess {
+    fn status(&mut self) -> Option<ExitStatus> {
+        self.child.try_wait().ok().flatten()
+    }
+
+    /// `is running for 3m 5s` or `has exited (exit status: 1)`.
+    fn describe(&mut self) -> String {
+        match self.status() {
+            Some(status) => format!("has exited ({})", status),
+            None => format!("is running for {}", format_elapsed(self.started.elapsed())),
+        }
+    }
+
+    #[cfg(unix)]
+    fn signal(&mut self, signal: libc::c_int) {
+        if let Some(group) = self.group {
+            // SAFETY: kill has no memory-safety preconditions.
+            unsafe {
+                libc::kill(-group, signal);
+            }
+        }
+    }
+
+    /// Asks the process to exit, then kills it if it hasn't within
+    /// `STOP_GRACE`.
+    async fn stop(&mut self) -> ExitStatus {
+        #[cfg(unix)]
+        self.signal(libc::SIGTERM);
+        #[cfg(not(unix))]
+        let _ = self.child.start_kill();
+        if let Ok(Ok(status)) = tokio::time::timeout(STOP_GRACE, self.child.wait()).await {
+            return status;
+        }
+        #[cfg(unix)]
+        self.signal(libc::SIGKILL);
+        let _ = self.child.start_kill();
+        self.child.wait().await.unwrap_or_default()
+    }
+}
+
+impl Drop for BackgroundProcess {
+    fn drop(&mut self) {
+        // `kill_on_drop` only reaches the shell; take its children down with
+        // it when the session goes away.
+        #[cfg(unix)]
+        if self.status().is_none() {
+            self.signal(libc::SIGKILL);
+        }
+    }
+}
+
+/// The processes a session's agent started in the background. They are
+/// killed when the session is dropped.
+#[derive(Debug, Default)]
+pub(crate) struct BackgroundProcesses {
+    next_id: u32,
+    processes: BTreeMap<u32, BackgroundProcess>,
+}
+
+impl BackgroundProcesses {
+    fn get(&mut self, id: u32) -> BackgroundResult<&mut BackgroundProcess> {
+        self.processes.get_mut(&id).ok_or_else(|| {
+            format!(
+                "There is no process {}; call check_process without an id to list them",
+                id
+            )
+            .into()
+        })
+    }
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Copies a stream of the child's into `output` until it closes.
+fn collect(mut stream: impl AsyncRead + Unpin + Send + 'static, output: Arc<Mutex<Output>>) {
+    tokio::spawn(async move {
+        let mut buffer = vec![0; 8192];
+        while let Ok(read) = stream.read(&mut buffer).await {
+            if read == 0 {
+                break;
+            }
+            output.lock().unwrap().push(&buffer[..read]);
+        }
+    });
+}
+
+/// A process's status line followed by the output the model hasn't seen.
+fn report(id: u32, process: &mut BackgroundProcess) -> String {
+    let status = process.describe();
+    let output = process.output.lock().unwrap().take_unseen();
+    let output = if output.is_empty() {
+        "No new output.".to_string()
+    } else {
+        format!("New output:\n{}", output)
+    };
+    format!(
+        "Process {} `{}` {}.\n{}",
+        id, process.command, status, output
+    )
+}
+
+async fn start_process_impl(
+    input: serde_json::Value,
+    context: ToolContext,
+) -> BackgroundResult<String> {
+    let input: StartProcessInput = serde_json::from_value(input)?;
+    if input.command.trim().is_empty() {
+        return Err("Invalid input parameters".into());
+    }
+    if let Some(cwd) = &input.cwd
+        && !tokio::fs::metadata(cwd).await?.is_dir()
+    {
+        return Err(format!("{} is not a directory", cwd).into());
+    }
+
+    let id = {
+        let mut processes = context.processes.lock().unwrap();
+        let running = processes
+            .processes
+            .values_mut()
+            .map(BackgroundProcess::status)
+            .filter(Option::is_none)
+            .count();
+        if running >= MAX_RUNNING {
+            return Err(format!(
+                "{} processes are already running; stop one with stop_process first",
+                running
+            )
+            .into());
+        }
+
+        let mut command = if cfg!(windows) {
+            let mut command = tokio::process::Command::new("cmd");
+            command.arg("/C").arg(&input.command);
+            command
+        } else {
+            let mut command = tokio::process::Command::new("sh");
+            command.arg("-c").arg(&input.command);
+            command
+        };
+        if let Some(cwd) = &input.cwd {
+            command.current_dir(cwd);
+        }
+        // Like `run_captured`, the child never shares the terminal.
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        command.process_group(0);
+        let mut child = command.spawn()?;
+
+        let output = Arc::new(Mutex::new(Output::default()));
+        if let Some(stdout) = child.stdout.take() {
+            collect(stdout, output.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            collect(stderr, output.clone());
+        }
+        processes.next_id += 1;
+        let id = processes.next_id;
+        processes.processes.insert(
+            id,
+            BackgroundProcess {
+                command: input.command,
+                #[cfg(unix)]
+                group: child.id().map(|pid| pid as i32),
+                child,
+                output,
+                started: Instant::now(),
+            },
+        );
+        id
+    };
+
+    let deadline = Instant::now() + STARTUP_WAIT;
+    loop {
+        {
+            let mut processes = context.processes.lock().unwrap();
+            let process = processes.get(id)?;
+            if process.status().is_some() || Instant::now() >= deadline {
+                return Ok(report(id, process));
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn check_process_impl(
+    input: serde_json::Value,
+    context: ToolContext,
+) -> BackgroundResult<String> {
+    let input: CheckProcessInput = serde_json::from_value(input)?;
+    let Some(id) = input.id else {
+        let mut processes = context.processes.lock().unwrap();
+        if processes.processes.is_empty() {
+            return Ok("No processes have been started in this session.".to_string());
+        }
+        let lines: Vec<String> = processes
+            .processes
+            .iter_mut()
+            .map(|(id, process)| {
+                let status = process.describe();
+                format!("{}: `{}` {}", id, process.command, status)
+            })
+            .collect();
+        return Ok(lines.join("\n"));
+    };
+
+    let deadline = Instant::now() + Duration::from_secs(input.wait_secs.min(MAX_WAIT_SECS));
+    loop {
+        {
+            let mut processes = context.processes.lock().unwrap();
+            let process = processes.get(id)?;
+            let exited = process.status().is_some();
+            let timed_out = Instant::now() >= deadline;
+            let output = process.output.lock().unwrap();
+            let ready = match &input.until {
+                Some(until) => String::from_utf8_lossy(output.unseen()).contains(until.as_str()),
+                None => false,
+            };
+            drop(output);
+            if exited || timed_out || ready {
+                let mut report = report(id, process);
+                if let Some(until) = &input.until
+                    && !ready
+                {
+                    report.push_str(&format!("\n{:?} has not appeared in the output.", until));
+                }
+                return Ok(report);
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn stop_process_impl(
+    input: serde_json::Value,
+    context: ToolContext,
+) -> BackgroundResult<String> {
+    let input: StopProcessInput = serde_json::from_value(input)?;
+    let mut process = {
+        let mut processes = context.processes.lock().unwrap();
+        processes.get(input.id)?;
+        processes.processes.remove(&input.id).unwrap()
+    };
+    let stopped = match process.status() {
+        Some(status) => format!("had already exited ({})", status),
+        None => format!("was stopped ({})", process.stop().await),
+    };
+    // Give the readers a moment to collect what it printed on the way out.
+    tokio::time::sleep(POLL_INTERVAL).await;
+    let output = process.output.lock().unwrap().take_unseen();
+    let mut report = format!("Process {} `{}` {}.", input.id, process.command, stopped);
+    if !output.is_empty() {
+        report.push_str(&format!("\nLast output:\n{}", output));
+    }
+    Ok(report)
+}
+
+pub(crate) fn start_definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "start_process",
+        description: "Start a long-running shell command in the background, such as a dev server or file watcher, and return its id with the first two seconds of output. Use check_process to follow it and stop_process when done; every process is stopped when the session ends.",
+        input_schema: serde_json::to_value(schema_for!(StartProcessInput)).unwrap(),
+        handler: |input, context| Box::pin(start_process_impl(input, context)),
+        read_only: false,
+        opt_in: Some(OptIn::Processes),
+        summarize: |input, _| format!("🚀 start `{}`", input["command"].as_str().unwrap_or_default()),
+    }
+}
+
+pub(crate) fn check_definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "check_process",
+        description: "Get a background process's status and the output it printed since the last check, optionally waiting for some text such as `Listening on`. Without an id, lists the session's processes.",
+        input_schema: serde_json::to_value(schema_for!(CheckProcessInput)).unwrap(),
+        handler: |input, context| Box::pin(check_process_impl(input, context)),
+        // The output grows between identical calls.
+        read_only: false,
+        opt_in: Some(OptIn::Processes),
+        summarize: |input, _| match input["id"].as_u64() {
+            Some(id) => format!("👀 check process {}", id),
+            None => "👀 list processes".to_string(),
+        },
+    }
+}
+
+pub(crate) fn stop_definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "stop_process",
+        description: "Stop a background process started with start_process, along with anything it started, and return its last output.",
+        input_schema: serde_json::to_value(schema_for!(StopProcessInput)).unwrap(),
+        handler: |input, context| Box::pin(stop_process_impl(input, context)),
+        read_only: false,
+        opt_in: Some(OptIn::Processes),
+        summarize: |input, _| format!("🛑 stop process {}", input["id"].as_u64().unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn processes_run_in_the_background_until_stopped() {
+        let context = ToolContext::default();
+        let script = "echo starting; sleep 3; echo 'ready on :3000'; sleep 30";
+        let output = start_process_impl(json!({ "command": script }), context.clone())
+            .await
+            .unwrap();
+        assert!(output.starts_with("Process 1 `"), "{}", output);
+        assert!(output.contains("is running for"), "{}", output);
+        assert!(output.contains("starting"), "{}", output);
+
+        let output = check_process_impl(
+            json!({ "id": 1, "until": "ready on", "wait_secs": 10 }),
+            context.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(
+            output.ends_with("New output:\nready on :3000\n"),
+            "{}",
+            output
+        );
+        let listed = check_process_impl(json!({}), context.clone())
+            .await
+            .unwrap();
+        assert!(listed.starts_with("1: `echo starting;"), "{}", listed);
+
+        let started = Instant::now();
+        let output = stop_process_impl(json!({ "id": 1 }), context.clone())
+            .await
+            .unwrap();
+        assert!(output.contains("was stopped"), "{}", output);
+        assert!(started.elapsed() < STOP_GRACE);
+        assert!(check_process_impl(json!({ "id": 1 }), context.clone())
+            .await
+            .is_err());
+
+        // A command that fails straight away says so.
+        let output = start_process_impl(json!({ "command": "echo oops >&2; exit 2" }), context)
+            .await
+            .unwrap();
+        assert!(output.contains("has exited (exit status: 2)"), "{}", output);
+        assert!(output.ends_with("oops\n"), "{}", output);
+    }
+}
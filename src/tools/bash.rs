@@ -0,0 +1,275 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{format_size, ToolContext, ToolDefinition};
+
+type BashResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// The timeout when neither the call nor `[tools]` sets one.
+const DEFAULT_TIMEOUT_SECS: u64 = 120;
+/// The longest timeout a call may ask for.
+const MAX_TIMEOUT_SECS: u64 = 600;
+/// Output kept per stream. A failing build's first error and last lines
+/// are what matter, so the middle is left out.
+const MAX_OUTPUT_BYTES: usize = 30 * 1024;
+/// Longest progress line shown while the command runs.
+const MAX_PROGRESS_CHARS: usize = 80;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct BashInput {
+    #[schemars(description = "The command to run, e.g. `cargo test` or `git status`")]
+    command: String,
+    #[schemars(
+        description = "Optional seconds after which the command is killed; at most 600. Long builds may need more than the default."
+    )]
+    timeout_secs: Option<u64>,
+}
+
+/// Kills the command's process group unless it finished, so neither a
+/// timeout nor a cancelled turn leaves its children running.
+struct GroupGuard {
+    #[cfg(unix)]
+    group: Option<i32>,
+    finished: bool,
+}
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if !self.finished
+            && let Some(group) = self.group
+        {
+            // SAFETY: kill has no memory-safety preconditions.
+            unsafe {
+                libc::kill(-group, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// What a command wrote to one stream, bounded as it is read: the first
+/// and last half of `MAX_OUTPUT_BYTES`, and how much was left out between
+/// them, so a command that prints without end can't fill memory.
+#[derive(Debug, Default)]
+struct Output {
+    head: Vec<u8>,
+    tail: Vec<u8>,
+    dropped: usize,
+}
+
+impl Output {
+    fn push(&mut self, chunk: &[u8]) {
+        let room = (MAX_OUTPUT_BYTES / 2).saturating_sub(self.head.len());
+        let (head, rest) = chunk.split_at(room.min(chunk.len()));
+        self.head.extend_from_slice(head);
+        self.tail.extend_from_slice(rest);
+        if self.tail.len() > MAX_OUTPUT_BYTES / 2 {
+            let excess = self.tail.len() - MAX_OUTPUT_BYTES / 2;
+            self.tail.drain(..excess);
+            self.dropped += excess;
+        }
+    }
+
+    fn text(&self) -> String {
+        let head = String::from_utf8_lossy(&self.head);
+        let tail = String::from_utf8_lossy(&self.tail);
+        if self.dropped == 0 {
+            return format!("{}{}", head, tail);
+        }
+        format!(
+            "{}\n[… {} left out …]\n{}",
+            head,
+            format_size(self.dropped as u64),
+            tail
+        )
+    }
+}
+
+/// Reads a stream of the command's into `output` until it closes, showing
+/// its latest line as the tool's progress.
+async fn collect(
+    mut stream: impl AsyncRead + Unpin,
+    output: Arc<Mutex<Output>>,
+    progress: Arc<Mutex<Option<String>>>,
+) {
+    let mut chunk = vec![0; 8192];
+    while let Ok(read) = stream.read(&mut chunk).await {
+        if read == 0 {
+            break;
+        }
+        output.lock().unwrap().push(&chunk[..read]);
+        let text = String::from_utf8_lossy(&chunk[..read]);
+        if let Some(line) = text
+            .lines()
+            .rev()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+        {
+            *progress.lock().unwrap() = Some(line.chars().take(MAX_PROGRESS_CHARS).collect());
+        }
+    }
+}
+
+fn report(status: &str, stdout: &Output, stderr: &Output) -> String {
+    let mut report = status.to_string();
+    for (name, output) in [("stdout", stdout), ("stderr", stderr)] {
+        let text = output.text();
+        if !text.trim().is_empty() {
+            report.push_str(&format!("\n{}:\n{}", name, text.trim_end()));
+        }
+    }
+    report
+}
+
+async fn bash_impl(input: serde_json::Value, context: ToolContext) -> BashResult<String> {
+    let input: BashInput = serde_json::from_value(input)?;
+    if input.command.trim().is_empty() {
+        return Err("Invalid input parameters".into());
+    }
+    let timeout = input
+        .timeout_secs
+        .unwrap_or(context.bash_timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS))
+        .clamp(1, MAX_TIMEOUT_SECS);
+
+    let mut command = if cfg!(windows) {
+        let mut command = tokio::process::Command::new("cmd");
+        command.arg("/C").arg(&input.command);
+        command
+    } else {
+        let mut command = tokio::process::Command::new("bash");
+        command.arg("-c").arg(&input.command);
+        command
+    };
+    // Nothing may wait on the terminal, page its output or color it.
+    command
+        .env("TERM", "dumb")
+        .env("NO_COLOR", "1")
+        .env("PAGER", "cat")
+        .env("GIT_PAGER", "cat")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    command.process_group(0);
+    let mut child = command.spawn().map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => "bash is not installed".to_string(),
+        _ => format!("Could not run bash: {}", err),
+    })?;
+    let mut guard = GroupGuard {
+        #[cfg(unix)]
+        group: child.id().map(|pid| pid as i32),
+        finished: false,
+    };
+
+    let stdout = Arc::new(Mutex::new(Output::default()));
+    let stderr = Arc::new(Mutex::new(Output::default()));
+    let readers = futures::future::join(
+        collect(
+            child.stdout.take().unwrap(),
+            stdout.clone(),
+            context.progress.clone(),
+        ),
+        collect(
+            child.stderr.take().unwrap(),
+            stderr.clone(),
+            context.progress.clone(),
+        ),
+    );
+    let finished = tokio::time::timeout(Duration::from_secs(timeout), async {
+        readers.await;
+        child.wait().await
+    })
+    .await;
+    let stdout = std::mem::take(&mut *stdout.lock().unwrap());
+    let stderr = std::mem::take(&mut *stderr.lock().unwrap());
+    match finished {
+        Ok(status) => {
+            guard.finished = true;
+            Ok(report(&status?.to_string(), &stdout, &stderr))
+        }
+        Err(_) => Err(report(
+            &format!(
+                "Timed out after {}s and was killed; pass a larger timeout_secs if it needs longer",
+                timeout
+            ),
+            &stdout,
+            &stderr,
+        )
+        .into()),
+    }
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "bash",
+        description: "Run a bash command in the working directory, e.g. to build, run tests or use git, and return its exit status, stdout and stderr. Stdin is closed, so interactive commands fail; use start_process for servers that keep running.",
+        input_schema: serde_json::to_value(schema_for!(BashInput)).unwrap(),
+        handler: |input, context| Box::pin(bash_impl(input, context)),
+        read_only: false,
+        opt_in: None,
+        summarize: |input, output| {
+            let command = input["command"].as_str().unwrap_or_default();
+            let mut line = command.lines().next().unwrap_or_default().to_string();
+            if line.chars().count() > 60 || command.lines().nth(1).is_some() {
+                line = format!("{}…", line.chars().take(60).collect::<String>());
+            }
+            match output.and_then(|output| output.lines().next()) {
+                Some("exit status: 0") | None => format!("💻 {}", line),
+                Some(status) if status.starts_with("Timed out") => {
+                    format!("💻 {} (timed out)", line)
+                }
+                Some(status) => format!("💻 {} ({})", line, status),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn commands_report_their_output_and_are_killed_on_timeout() {
+        let context = ToolContext::default();
+        let output = bash_impl(
+            json!({ "command": "echo out; echo err >&2; exit 3" }),
+            context.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(output, "exit status: 3\nstdout:\nout\nstderr:\nerr");
+        assert_eq!(context.progress.lock().unwrap().as_deref(), Some("err"));
+
+        // The background sleep holds the pipes open; the whole group goes.
+        let started = std::time::Instant::now();
+        let err = bash_impl(
+            json!({ "command": "echo partial; sleep 30 & sleep 30", "timeout_secs": 1 }),
+            context,
+        )
+        .await
+        .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            err.to_string(),
+            "Timed out after 1s and was killed; pass a larger timeout_secs if it needs longer\nstdout:\npartial"
+        );
+    }
+
+    #[tokio::test]
+    async fn endless_output_keeps_its_ends() {
+        let output = bash_impl(json!({ "command": "seq 1 200000" }), ToolContext::default())
+            .await
+            .unwrap();
+        assert!(output.starts_with("exit status: 0\nstdout:\n1\n2\n3\n"));
+        assert!(output.ends_with("\n199999\n200000"));
+        assert!(output.contains(" left out …]\n"));
+        assert!(output.len() < MAX_OUTPUT_BYTES + 100, "{}", output.len());
+    }
+}
@@ -0,0 +1,265 @@
+use similar::TextDiff;
+use std::collections::BTreeMap;
+#[cfg(feature = "server")]
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use super::{group_thousands, normalize_path, text};
+use crate::clock;
+use crate::protocol::FileDiff;
+
+/// Files over this size are summarized rather than diffed.
+const MAX_DIFFED_BYTES: u64 = 4 * 1024 * 1024;
+/// How the line standing in for a binary or large file's contents starts.
+const STAND_IN: &str = "Binary or large file, ";
+
+/// Files the tools changed during a session, with their contents from before
+/// the first change, so all of the session's edits can be shown as one diff.
+/// The same is kept for each turn, whose diffs are set aside when it ends.
+/// Only the file tools report their writes, so what commands run through
+/// `bash` or `start_process` change isn't tracked.
+#[derive(Debug, Default)]
+pub(crate) struct ChangeTracker {
+    /// Contents before the first change; `None` for files that didn't exist.
+    originals: BTreeMap<PathBuf, Option<String>>,
+    /// Contents before the running turn first changed each file.
+    turn_originals: BTreeMap<PathBuf, Option<String>>,
+    /// What each finished turn changed, by turn id.
+    #[cfg(feature = "server")]
+    turns: HashMap<String, Vec<FileDiff>>,
+    /// Set when a tracked file may have changed since the last update.
+    changed: bool,
+}
+
+impl ChangeTracker {
+    /// Called before a tool writes to `path`. Remembers the file's contents
+    /// the first time the session, and the turn, touch it.
+    pub(crate) fn track(&mut self, path: &str) {
+        self.changed = true;
+        let path = normalize_path(path);
+        if self.turn_originals.contains_key(&path) {
+            return;
+        }
+        let contents = read_text(&path);
+        self.originals
+            .entry(path.clone())
+            .or_insert_with(|| contents.clone());
+        self.turn_originals.insert(path, contents);
+    }
+
+    /// Sets aside what the turn `turn_id` changed, for `turn_changes`.
+    #[cfg(feature = "server")]
+    pub(crate) fn finish_turn(&mut self, turn_id: &str) {
+        let originals = std::mem::take(&mut self.turn_originals);
+        let files = diff_files(&originals);
+        if !files.is_empty() {
+            self.turns.insert(turn_id.to_string(), files);
+        }
+    }
+
+    /// The diffs of what the turn `turn_id` changed, if it changed anything.
+    #[cfg(feature = "server")]
+    pub(crate) fn turn_changes(&self, turn_id: &str) -> Option<&[FileDiff]> {
+        self.turns.get(turn_id).map(Vec::as_slice)
+    }
+
+    /// The diff of every changed file, if one may have changed since the
+    /// last call.
+    pub(crate) fn take_update(&mut self) -> Option<Vec<FileDiff>> {
+        std::mem::take(&mut self.changed).then(|| self.diff())
+    }
+
+    /// The tracked files that differ from their original contents.
+    #[cfg(feature = "server")]
+    pub(crate) fn modified_paths(&self) -> BTreeSet<PathBuf> {
+        self.originals
+            .iter()
+            .filter(|(path, original)| read_text(path) != **original)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Unified diffs of the tracked files against their original contents,
+    /// leaving out files that are back to how they started.
+    pub(crate) fn diff(&self) -> Vec<FileDiff> {
+        diff_files(&self.originals)
+    }
+}
+
+/// Unified diffs of the files in `originals` against those contents.
+fn diff_files(originals: &BTreeMap<PathBuf, Option<String>>) -> Vec<FileDiff> {
+    originals
+        .iter()
+        .filter_map(|(path, original)| {
+            let current = read_text(path);
+            if current == *original {
+                return None;
+            }
+            let name = path.display().to_string();
+            let old_header = match original {
+                Some(_) => format!("a/{}", name),
+                None => "/dev/null".to_string(),
+            };
+            let new_header = match current {
+                Some(_) => format!("b/{}", name),
+                None => "/dev/null".to_string(),
+            };
+            let diff = TextDiff::from_lines(
+                original.as_deref().unwrap_or_default(),
+                current.as_deref().unwrap_or_default(),
+            )
+            .unified_diff()
+            .header(&old_header, &new_header)
+            .to_string();
+            Some(FileDiff { path: name, diff })
+        })
+        .collect()
+}
+
+/// `files` as a patch in the form `git format-patch` writes it, which
+/// `git am` applies as a commit titled `subject`, and `git apply` or
+/// `patch -p1` as plain changes. Binary and large files, whose diffs only
+/// compare stand-ins, are left out and named in the message instead.
+#[cfg(feature = "server")]
+pub(crate) fn format_patch(subject: &str, files: &[FileDiff]) -> String {
+    let (opaque, files): (Vec<&FileDiff>, Vec<&FileDiff>) =
+        files.iter().partition(|file| is_opaque(file));
+    let mut patch = format!(
+        "From 0000000000000000000000000000000000000000 Mon Sep 17 00:00:00 2001\n\
+         From: tars <tars@localhost>\n\
+         Subject: [PATCH] {}\n\n",
+        subject
+    );
+    if !opaque.is_empty() {
+        patch.push_str("Left out, as binary or too large to diff:\n");
+        for file in opaque {
+            patch.push_str(&format!("  {}\n", file.path));
+        }
+        patch.push('\n');
+    }
+    patch.push_str("---\n");
+    for file in files {
+        patch.push_str(&format!("diff --git a/{0} b/{0}\n", file.path));
+        if file.diff.starts_with("--- /dev/null\n") {
+            patch.push_str("new file mode 100644\n");
+        } else if file.diff.lines().nth(1) == Some("+++ /dev/null") {
+            patch.push_str("deleted file mode 100644\n");
+        }
+        patch.push_str(&file.diff);
+    }
+    patch.push_str("-- \ntars\n");
+    patch
+}
+
+/// Whether either side of `file`'s diff is the stand-in for a binary or
+/// large file rather than its contents.
+#[cfg(feature = "server")]
+fn is_opaque(file: &FileDiff) -> bool {
+    file.diff.lines().any(|line| {
+        line.strip_prefix(['-', '+'])
+            .is_some_and(|line| line.starts_with(STAND_IN))
+    })
+}
+
+/// A file's contents decoded in whatever encoding it uses, or `None` when
+/// it doesn't exist. Binary and very large files, such as downloads, are
+/// stood in for by a line with their size and modification time.
+fn read_text(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let bytes = if metadata.len() > MAX_DIFFED_BYTES {
+        None
+    } else {
+        Some(std::fs::read(path).ok()?)
+    };
+    match bytes {
+        Some(bytes) if !bytes.contains(&0) || text::is_utf16(&bytes) => {
+            Some(text::decode(&bytes).text)
+        }
+        _ => Some(format!(
+            "{}{} bytes, modified {}\n",
+            STAND_IN,
+            group_thousands(metadata.len()),
+            metadata
+                .modified()
+                .map(clock::format_local)
+                .unwrap_or_default()
+        )),
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn diffs_files_against_their_contents_before_the_first_change() {
+        let dir = TempDir::new("tars-changes");
+        let edited = dir.join("edited.txt");
+        let created = dir.join("created.txt");
+        std::fs::write(&edited, "one\ntwo\n").unwrap();
+
+        let mut tracker = ChangeTracker::default();
+        tracker.track(edited.to_str().unwrap());
+        std::fs::write(&edited, "one\n2\n").unwrap();
+        tracker.track(edited.to_str().unwrap());
+        std::fs::write(&edited, "one\n2\nthree\n").unwrap();
+        tracker.track(created.to_str().unwrap());
+        std::fs::write(&created, "new\n").unwrap();
+
+        let files = tracker.take_update().unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].diff.starts_with("--- /dev/null\n"));
+        assert!(files[0].diff.ends_with("+new\n"));
+        assert!(files[1].diff.contains("-two\n+2\n+three\n"));
+        assert!(tracker.take_update().is_none());
+
+        // A file restored to its original contents drops out of the diff.
+        std::fs::write(&edited, "one\ntwo\n").unwrap();
+        tracker.track(edited.to_str().unwrap());
+        assert_eq!(tracker.take_update().unwrap().len(), 1);
+
+        // Each turn's changes are kept apart from the earlier turns'.
+        tracker.finish_turn("t1");
+        tracker.track(edited.to_str().unwrap());
+        std::fs::write(&edited, "one\ntwo\n3\n").unwrap();
+        tracker.finish_turn("t2");
+        assert!(tracker.turn_changes("t0").is_none());
+        let first = format_patch("Add created.txt", tracker.turn_changes("t1").unwrap());
+        assert!(first.contains("Subject: [PATCH] Add created.txt\n"));
+        assert!(first.contains("created.txt\nnew file mode 100644\n--- /dev/null\n"));
+        let second = tracker.turn_changes("t2").unwrap();
+        assert_eq!(second.len(), 1);
+        assert!(second[0].diff.ends_with(" one\n two\n+3\n"));
+    }
+
+    #[test]
+    fn binary_and_large_files_are_named_instead_of_patched() {
+        let dir = TempDir::new("tars-changes-binary");
+        let text = dir.join("notes.txt");
+        let image = dir.join("logo.png");
+        let large = dir.join("data.csv");
+
+        let mut tracker = ChangeTracker::default();
+        for path in [&text, &image, &large] {
+            tracker.track(path.to_str().unwrap());
+        }
+        std::fs::write(&text, "hello\n").unwrap();
+        std::fs::write(&image, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        std::fs::write(&large, "1,2\n".repeat(MAX_DIFFED_BYTES as usize / 4 + 1)).unwrap();
+        tracker.finish_turn("t1");
+
+        let files = tracker.turn_changes("t1").unwrap();
+        assert_eq!(files.len(), 3);
+        let patch = format_patch("Add files", files);
+        let (message, diffs) = patch.split_once("\n---\n").unwrap();
+        assert!(message.ends_with(&format!(
+            "Left out, as binary or too large to diff:\n  {}\n  {}\n",
+            large.display(),
+            image.display()
+        )));
+        assert_eq!(diffs.matches("diff --git ").count(), 1);
+        assert!(diffs.contains("notes.txt\nnew file mode 100644\n"));
+        assert!(!diffs.contains(STAND_IN));
+    }
+}
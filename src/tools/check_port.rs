@@ -0,0 +1,259 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use super::process::run_captured;
+use super::ToolDefinition;
+
+type CheckPortResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const LIST_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct CheckPortInput {
+    #[schemars(description = "The TCP port to check, e.g. 8080")]
+    port: u16,
+}
+
+/// A socket listening on the port, and the process holding it when that
+/// could be found.
+#[derive(Debug)]
+struct Listener {
+    address: SocketAddr,
+    process: Option<(u32, String)>,
+}
+
+/// Listening sockets on `port`, from the kernel's tables in `/proc`.
+#[cfg(target_os = "linux")]
+fn proc_listeners(port: u16) -> std::io::Result<Vec<Listener>> {
+    let mut sockets = Vec::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let Ok(table) = std::fs::read_to_string(table) else {
+            continue;
+        };
+        sockets.extend(
+            table
+                .lines()
+                .skip(1)
+                .filter_map(parse_socket)
+                .filter(|(address, _)| address.port() == port),
+        );
+    }
+    if sockets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Find the processes with the sockets open. Other users' processes
+    // can't be looked into without root, and stay unknown.
+    let mut owners = std::collections::HashMap::new();
+    for entry in std::fs::read_dir("/proc")?.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else {
+                continue;
+            };
+            if let Some(inode) = target
+                .to_str()
+                .and_then(|target| target.strip_prefix("socket:["))
+                .and_then(|target| target.strip_suffix(']'))
+                && sockets.iter().any(|(_, socket)| socket == inode)
+            {
+                owners.insert(inode.to_string(), pid);
+            }
+        }
+    }
+    Ok(sockets
+        .into_iter()
+        .map(|(address, inode)| Listener {
+            address,
+            process: owners.get(&inode).map(|&pid| (pid, command_line(pid))),
+        })
+        .collect())
+}
+
+/// The address and inode of a listening socket in a `/proc/net/tcp` or
+/// `tcp6` line.
+#[cfg(target_os = "linux")]
+fn parse_socket(line: &str) -> Option<(SocketAddr, String)> {
+    const LISTEN: &str = "0A";
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.get(3) != Some(&LISTEN) {
+        return None;
+    }
+    let (address, port) = fields.get(1)?.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    // The address is printed as 32-bit words in the kernel's byte order.
+    let mut bytes = Vec::with_capacity(16);
+    for word in 0..address.len() / 8 {
+        let word = u32::from_str_radix(address.get(word * 8..word * 8 + 8)?, 16).ok()?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+    let ip = match bytes.len() {
+        4 => Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).into(),
+        16 => Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?).into(),
+        _ => return None,
+    };
+    Some((SocketAddr::new(ip, port), fields.get(9)?.to_string()))
+}
+
+#[cfg(target_os = "linux")]
+fn command_line(pid: u32) -> String {
+    let command = std::fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    let command = String::from_utf8_lossy(&command)
+        .split('\0')
+        .filter(|arg| !arg.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if command.is_empty() {
+        // Kernel threads and zombies have no command line.
+        std::fs::read_to_string(format!("/proc/{}/comm", pid))
+            .map(|comm| comm.trim().to_string())
+            .unwrap_or_default()
+    } else if command.chars().count() > 200 {
+        format!("{}…", command.chars().take(200).collect::<String>())
+    } else {
+        command
+    }
+}
+
+/// What the platform's own tool says is listening on `port`, or `None`
+/// when it isn't installed.
+async fn listed_elsewhere(port: u16) -> Option<String> {
+    if cfg!(windows) {
+        let args = ["netstat", "-ano", "-p", "TCP"].map(str::to_string);
+        let output = run_captured(&args, LIST_TIMEOUT).await.ok()?;
+        let suffix = format!(":{}", port);
+        let lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                fields.get(3) == Some(&"LISTENING")
+                    && fields.get(1).is_some_and(|local| local.ends_with(&suffix))
+            })
+            .map(|line| line.trim().to_string())
+            .collect();
+        Some(lines.join("\n"))
+    } else {
+        let args = ["lsof", "-nP", &format!("-iTCP:{}", port), "-sTCP:LISTEN"].map(str::to_string);
+        let output = run_captured(&args, LIST_TIMEOUT).await.ok()?;
+        // lsof exits with 1 when nothing matched.
+        Some(
+            String::from_utf8_lossy(&output.stdout)
+                .trim_end()
+                .to_string(),
+        )
+    }
+}
+
+/// Whether something on this machine accepts connections on `port`.
+async fn accepts_connections(port: u16) -> bool {
+    for ip in [Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()] {
+        let address = SocketAddr::new(ip, port);
+        if let Ok(Ok(_)) =
+            tokio::time::timeout(CONNECT_TIMEOUT, tokio::net::TcpStream::connect(address)).await
+        {
+            return true;
+        }
+    }
+    false
+}
+
+fn describe(port: u16, listeners: &[Listener]) -> String {
+    if listeners.is_empty() {
+        return format!("Nothing is listening on TCP port {}.", port);
+    }
+    let mut lines = vec![format!("TCP port {} is in use:", port)];
+    for listener in listeners {
+        lines.push(match &listener.process {
+            Some((pid, command)) => format!("- {} by pid {}: {}", listener.address, pid, command),
+            None => format!(
+                "- {} by a process tars can't look into (another user's, or outside this container)",
+                listener.address
+            ),
+        });
+    }
+    lines.join("\n")
+}
+
+async fn check_port_impl(input: serde_json::Value) -> CheckPortResult<String> {
+    let input: CheckPortInput = serde_json::from_value(input)?;
+    let port = input.port;
+
+    #[cfg(target_os = "linux")]
+    if std::path::Path::new("/proc/net/tcp").exists() {
+        let listeners = tokio::task::spawn_blocking(move || proc_listeners(port)).await??;
+        return Ok(describe(port, &listeners));
+    }
+
+    match listed_elsewhere(port).await {
+        Some(listed) if listed.is_empty() => Ok(describe(port, &[])),
+        Some(listed) => Ok(format!("TCP port {} is in use:\n{}", port, listed)),
+        None if accepts_connections(port).await => Ok(format!(
+            "Something accepts connections on TCP port {}; install lsof to see what.",
+            port
+        )),
+        None => Ok(format!(
+            "Nothing accepts connections on TCP port {} on localhost.",
+            port
+        )),
+    }
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "check_port",
+        description: "Check whether a local TCP port is being listened on, and by which process (pid and command line). Use it for \"address already in use\" errors or to see whether a dev server came up.",
+        input_schema: serde_json::to_value(schema_for!(CheckPortInput)).unwrap(),
+        handler: |input, _| Box::pin(check_port_impl(input)),
+        read_only: true,
+        opt_in: None,
+        summarize: |input, output| {
+            let port = input["port"].as_u64().unwrap_or_default();
+            match output {
+                Some(output) if output.starts_with("Nothing") => {
+                    format!("🔌 check port {} (free)", port)
+                }
+                Some(_) => format!("🔌 check port {} (in use)", port),
+                None => format!("🔌 check port {}", port),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn listeners_are_found_with_their_process() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let output = check_port_impl(serde_json::json!({ "port": port }))
+            .await
+            .unwrap();
+        let expected = format!(
+            "TCP port {0} is in use:\n- 127.0.0.1:{0} by pid {1}: ",
+            port,
+            std::process::id()
+        );
+        assert!(output.starts_with(&expected), "{}", output);
+
+        drop(listener);
+        let output = check_port_impl(serde_json::json!({ "port": port }))
+            .await
+            .unwrap();
+        assert_eq!(
+            output,
+            format!("Nothing is listening on TCP port {}.", port)
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+use super::{input_path, ToolDefinition};
+use crate::index;
+
+/// Definitions listed before the outline is cut off.
+const MAX_SYMBOLS: usize = 500;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct CodeOutlineInput {
+    #[schemars(
+        description = "A relative file or directory path. A directory lists the definitions of every file under it."
+    )]
+    path: String,
+}
+
+fn outline(input: CodeOutlineInput) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if !std::path::Path::new(&input.path).exists() {
+        return Err(format!("{} does not exist", input.path).into());
+    }
+    index::with_current(|index, _| {
+        let files = index.outline(&input.path);
+        if files.is_empty() {
+            return format!(
+                "No definitions found under {}; only Rust, Python, JavaScript/TypeScript, Go, Java, Kotlin, C#, Scala, Swift and Ruby are outlined",
+                input.path
+            );
+        }
+        let single = files.len() == 1;
+        let mut lines = Vec::new();
+        let mut shown = 0;
+        let total: usize = files.iter().map(|(_, symbols)| symbols.len()).sum();
+        'files: for (path, symbols) in files {
+            if !single {
+                if !lines.is_empty() {
+                    lines.push(String::new());
+                }
+                lines.push(path.to_string());
+            }
+            for symbol in symbols {
+                if shown == MAX_SYMBOLS {
+                    lines.push(format!(
+                        "…and {} more; outline a narrower path",
+                        total - shown
+                    ));
+                    break 'files;
+                }
+                lines.push(format!(
+                    "{:>5}  {}{} {}",
+                    symbol.line,
+                    " ".repeat(symbol.indent.min(24)),
+                    symbol.kind,
+                    symbol.name
+                ));
+                shown += 1;
+            }
+        }
+        lines.join("\n")
+    })
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "code_outline",
+        description: "List the definitions (functions, types, classes, impls, ...) in a file or every file under a directory, with their line numbers and nesting. Cheaper than reading files to find your way around.",
+        input_schema: serde_json::to_value(schema_for!(CodeOutlineInput)).unwrap(),
+        handler: |input, _| {
+            Box::pin(async move {
+                let input: CodeOutlineInput = serde_json::from_value(input)?;
+                tokio::task::spawn_blocking(move || outline(input)).await?
+            })
+        },
+        read_only: true,
+        opt_in: None,
+        summarize: |input, _| format!("🗂️ outline {}", input_path(input)),
+    }
+}
@@ -0,0 +1,40 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+use super::{input_path, ToolDefinition};
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct CreateDirectoryInput {
+    #[schemars(description = "The relative path of the directory to create")]
+    path: String,
+}
+
+async fn create_directory_impl(
+    input: serde_json::Value,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let input: CreateDirectoryInput = serde_json::from_value(input)?;
+    if input.path.is_empty() {
+        return Err("Invalid input parameters".into());
+    }
+    match tokio::fs::metadata(&input.path).await {
+        Ok(metadata) if metadata.is_dir() => {
+            return Ok(format!("{} already exists", input.path));
+        }
+        Ok(_) => return Err(format!("{} exists and is not a directory", input.path).into()),
+        Err(_) => {}
+    }
+    tokio::fs::create_dir_all(&input.path).await?;
+    Ok(format!("Created directory {}", input.path))
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "create_directory",
+        description: "Create a directory, along with any missing parent directories. Succeeds without changes if it already exists. Files can be created with edit_file directly; use this for empty directories when scaffolding a project.",
+        input_schema: serde_json::to_value(schema_for!(CreateDirectoryInput)).unwrap(),
+        handler: |input, _| Box::pin(create_directory_impl(input)),
+        read_only: false,
+        opt_in: None,
+        summarize: |input, _| format!("📁 mkdir {}", input_path(input)),
+    }
+}
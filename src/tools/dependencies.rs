@@ -0,0 +1,631 @@
+use futures::StreamExt;
+use reqwest::header::USER_AGENT;
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{NetworkAccess, ToolContext, ToolDefinition};
+
+type DependenciesResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const MANIFESTS: &[&str] = &["Cargo.toml", "package.json", "pyproject.toml"];
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(15);
+const CONCURRENT_LOOKUPS: usize = 8;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct DependenciesInput {
+    #[schemars(
+        description = "A manifest (Cargo.toml, package.json or pyproject.toml) or a directory holding them; the workspace root when left out"
+    )]
+    path: Option<String>,
+    #[schemars(
+        description = "Also look up each dependency's latest version on crates.io, npm or PyPI. Needs network tools."
+    )]
+    #[serde(default)]
+    latest: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Registry {
+    Crates,
+    Npm,
+    PyPI,
+}
+
+impl Registry {
+    fn name(self) -> &'static str {
+        match self {
+            Registry::Crates => "crates.io",
+            Registry::Npm => "npm",
+            Registry::PyPI => "PyPI",
+        }
+    }
+
+    fn url(self, package: &str) -> String {
+        match self {
+            Registry::Crates => format!("https://crates.io/api/v1/crates/{}", package),
+            Registry::Npm => format!(
+                "https://registry.npmjs.org/{}/latest",
+                package.replace('/', "%2F")
+            ),
+            Registry::PyPI => format!("https://pypi.org/pypi/{}/json", package),
+        }
+    }
+
+    fn latest(self, response: &serde_json::Value) -> Option<String> {
+        let version = match self {
+            Registry::Crates => &response["crate"]["max_stable_version"],
+            Registry::Npm => &response["version"],
+            Registry::PyPI => &response["info"]["version"],
+        };
+        version.as_str().map(str::to_string)
+    }
+}
+
+#[derive(Debug)]
+struct Dependency {
+    name: String,
+    /// The name on the registry, when the manifest renames it.
+    package: String,
+    requirement: String,
+    /// Where it comes from when that isn't the registry, and its features
+    /// or extras.
+    notes: Vec<String>,
+    from_registry: bool,
+}
+
+impl Dependency {
+    fn new(name: &str, requirement: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            package: name.to_string(),
+            requirement: requirement.to_string(),
+            notes: Vec::new(),
+            from_registry: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Manifest {
+    path: PathBuf,
+    registry: Registry,
+    /// Dependency tables in the manifest's order, such as `dependencies` and
+    /// `dev-dependencies`.
+    groups: Vec<(String, Vec<Dependency>)>,
+}
+
+fn strings(value: &toml::Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn cargo_dependency(name: &str, value: &toml::Value) -> Dependency {
+    let Some(table) = value.as_table() else {
+        return Dependency::new(name, value.as_str().unwrap_or("*"));
+    };
+    let mut dependency = Dependency::new(
+        name,
+        table.get("version").and_then(|v| v.as_str()).unwrap_or(""),
+    );
+    if let Some(package) = table.get("package").and_then(|v| v.as_str()) {
+        dependency.package = package.to_string();
+        dependency.notes.push(format!("package {}", package));
+    }
+    if let Some(path) = table.get("path").and_then(|v| v.as_str()) {
+        dependency.notes.push(format!("path {}", path));
+        dependency.from_registry = false;
+    }
+    if let Some(git) = table.get("git").and_then(|v| v.as_str()) {
+        let mut note = format!("git {}", git);
+        for key in ["branch", "tag", "rev"] {
+            if let Some(reference) = table.get(key).and_then(|v| v.as_str()) {
+                note.push_str(&format!(" {} {}", key, reference));
+            }
+        }
+        dependency.notes.push(note);
+        dependency.from_registry = false;
+    }
+    if table.get("workspace").and_then(|v| v.as_bool()) == Some(true) {
+        dependency.notes.push("from the workspace".to_string());
+        dependency.from_registry = false;
+    }
+    if table.get("optional").and_then(|v| v.as_bool()) == Some(true) {
+        dependency.notes.push("optional".to_string());
+    }
+    if let Some(features) = table.get("features").map(strings)
+        && !features.is_empty()
+    {
+        dependency
+            .notes
+            .push(format!("features: {}", features.join(", ")));
+    }
+    dependency
+}
+
+fn parse_cargo(text: &str) -> DependenciesResult<Vec<(String, Vec<Dependency>)>> {
+    let manifest: toml::Table = toml::from_str(text)?;
+    let kinds = ["dependencies", "dev-dependencies", "build-dependencies"];
+    let mut tables = Vec::new();
+    if let Some(workspace) = manifest.get("workspace").and_then(|v| v.as_table())
+        && let Some(table) = workspace.get("dependencies")
+    {
+        tables.push(("workspace.dependencies".to_string(), table));
+    }
+    for kind in kinds {
+        if let Some(table) = manifest.get(kind) {
+            tables.push((kind.to_string(), table));
+        }
+    }
+    if let Some(targets) = manifest.get("target").and_then(|v| v.as_table()) {
+        for (target, table) in targets {
+            for kind in kinds {
+                if let Some(table) = table.get(kind) {
+                    tables.push((format!("target.{}.{}", target, kind), table));
+                }
+            }
+        }
+    }
+    Ok(tables
+        .into_iter()
+        .filter_map(|(group, table)| {
+            let table = table.as_table()?;
+            let dependencies = table
+                .iter()
+                .map(|(name, value)| cargo_dependency(name, value))
+                .collect();
+            Some((group, dependencies))
+        })
+        .collect())
+}
+
+fn parse_package_json(text: &str) -> DependenciesResult<Vec<(String, Vec<Dependency>)>> {
+    let manifest: serde_json::Value = serde_json::from_str(text)?;
+    let kinds = [
+        "dependencies",
+        "devDependencies",
+        "peerDependencies",
+        "optionalDependencies",
+    ];
+    Ok(kinds
+        .into_iter()
+        .filter_map(|kind| {
+            let table = manifest[kind].as_object()?;
+            let dependencies = table
+                .iter()
+                .map(|(name, requirement)| {
+                    let requirement = requirement.as_str().unwrap_or_default();
+                    let mut dependency = Dependency::new(name, requirement);
+                    if let Some((package, version)) = requirement
+                        .strip_prefix("npm:")
+                        .and_then(|alias| alias.rsplit_once('@'))
+                    {
+                        dependency.package = package.to_string();
+                        dependency.requirement = version.to_string();
+                        dependency.notes.push(format!("package {}", package));
+                    } else {
+                        // file:, link:, workspace:, git and URL specifiers.
+                        dependency.from_registry =
+                            !requirement.contains(':') && !requirement.contains('/');
+                    }
+                    dependency
+                })
+                .collect();
+            Some((kind.to_string(), dependencies))
+        })
+        .collect())
+}
+
+/// A PEP 508 requirement such as `requests[socks]>=2.31; python_version > "3.8"`.
+fn pep508_dependency(requirement: &str) -> Option<Dependency> {
+    let requirement = requirement.trim();
+    let end = requirement
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .unwrap_or(requirement.len());
+    if end == 0 {
+        return None;
+    }
+    let (name, rest) = requirement.split_at(end);
+    let mut dependency = Dependency::new(name, rest.trim());
+    // `name @ https://...` is a direct reference.
+    dependency.from_registry = !rest.trim_start().starts_with('@');
+    Some(dependency)
+}
+
+fn poetry_dependencies(table: &toml::Value) -> Vec<Dependency> {
+    let Some(table) = table.as_table() else {
+        return Vec::new();
+    };
+    table
+        .iter()
+        .filter(|(name, _)| name.as_str() != "python")
+        .map(|(name, value)| {
+            let Some(details) = value.as_table() else {
+                return Dependency::new(name, value.as_str().unwrap_or("*"));
+            };
+            let mut dependency = Dependency::new(
+                name,
+                details
+                    .get("version")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or(""),
+            );
+            for key in ["path", "git", "url"] {
+                if let Some(source) = details.get(key).and_then(|v| v.as_str()) {
+                    dependency.notes.push(format!("{} {}", key, source));
+                    dependency.from_registry = false;
+                }
+            }
+            if let Some(extras) = details.get("extras").map(strings)
+                && !extras.is_empty()
+            {
+                dependency
+                    .notes
+                    .push(format!("extras: {}", extras.join(", ")));
+            }
+            dependency
+        })
+        .collect()
+}
+
+fn parse_pyproject(text: &str) -> DependenciesResult<Vec<(String, Vec<Dependency>)>> {
+    let manifest: toml::Table = toml::from_str(text)?;
+    let mut groups = Vec::new();
+    let pep508 = |value: &toml::Value| -> Vec<Dependency> {
+        strings(value)
+            .iter()
+            .filter_map(|requirement| pep508_dependency(requirement))
+            .collect()
+    };
+
+    if let Some(project) = manifest.get("project") {
+        if let Some(dependencies) = project.get("dependencies") {
+            groups.push(("dependencies".to_string(), pep508(dependencies)));
+        }
+        if let Some(extras) = project
+            .get("optional-dependencies")
+            .and_then(|v| v.as_table())
+        {
+            for (extra, dependencies) in extras {
+                groups.push((
+                    format!("optional-dependencies.{}", extra),
+                    pep508(dependencies),
+                ));
+            }
+        }
+    }
+    if let Some(dependency_groups) = manifest.get("dependency-groups").and_then(|v| v.as_table()) {
+        for (group, dependencies) in dependency_groups {
+            groups.push((format!("dependency-groups.{}", group), pep508(dependencies)));
+        }
+    }
+    if let Some(poetry) = manifest
+        .get("tool")
+        .and_then(|tool| tool.get("poetry"))
+        .and_then(|v| v.as_table())
+    {
+        for kind in ["dependencies", "dev-dependencies"] {
+            if let Some(table) = poetry.get(kind) {
+                groups.push((format!("tool.poetry.{}", kind), poetry_dependencies(table)));
+            }
+        }
+        if let Some(poetry_groups) = poetry.get("group").and_then(|v| v.as_table()) {
+            for (group, table) in poetry_groups {
+                if let Some(table) = table.get("dependencies") {
+                    groups.push((
+                        format!("tool.poetry.group.{}.dependencies", group),
+                        poetry_dependencies(table),
+                    ));
+                }
+            }
+        }
+    }
+    Ok(groups)
+}
+
+fn read_manifest(path: &Path) -> DependenciesResult<Manifest> {
+    let text = std::fs::read_to_string(path)?;
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    let (registry, groups) = match file_name {
+        "Cargo.toml" => (Registry::Crates, parse_cargo(&text)),
+        "package.json" => (Registry::Npm, parse_package_json(&text)),
+        "pyproject.toml" => (Registry::PyPI, parse_pyproject(&text)),
+        _ => {
+            return Err(format!(
+                "{} is not a manifest this tool reads ({})",
+                path.display(),
+                MANIFESTS.join(", ")
+            )
+            .into());
+        }
+    };
+    let groups = groups.map_err(|err| format!("Could not parse {}: {}", path.display(), err))?;
+    Ok(Manifest {
+        path: path.to_path_buf(),
+        registry,
+        groups,
+    })
+}
+
+fn read_manifests(path: &str) -> DependenciesResult<Vec<Manifest>> {
+    let path = Path::new(path);
+    if !path.is_dir() {
+        return Ok(vec![read_manifest(path)?]);
+    }
+    let manifests: Vec<Manifest> = MANIFESTS
+        .iter()
+        .map(|name| path.join(name))
+        .filter(|manifest| manifest.is_file())
+        .map(|manifest| read_manifest(&manifest))
+        .collect::<DependenciesResult<_>>()?;
+    if manifests.is_empty() {
+        return Err(format!("There is no {} in {}", MANIFESTS.join(", "), path.display()).into());
+    }
+    Ok(manifests)
+}
+
+/// Each registry package's latest version, or why it couldn't be had.
+async fn latest_versions(
+    manifests: &[Manifest],
+    network: &NetworkAccess,
+    context: &ToolContext,
+) -> HashMap<(Registry, String), Result<String, String>> {
+    let mut packages: Vec<(Registry, String)> = manifests
+        .iter()
+        .flat_map(|manifest| {
+            manifest.groups.iter().flat_map(move |(_, dependencies)| {
+                dependencies
+                    .iter()
+                    .filter(|dependency| dependency.from_registry)
+                    .map(move |dependency| (manifest.registry, dependency.package.clone()))
+            })
+        })
+        .collect();
+    packages.sort_by(|a, b| a.1.cmp(&b.1));
+    packages.dedup();
+
+    let total = packages.len();
+    let mut done = 0;
+    let mut lookups = futures::stream::iter(packages)
+        .map(|(registry, package)| async move {
+            let latest = latest_version(network, registry, &package).await;
+            ((registry, package), latest)
+        })
+        .buffer_unordered(CONCURRENT_LOOKUPS);
+    let mut versions = HashMap::new();
+    while let Some((package, latest)) = lookups.next().await {
+        done += 1;
+        *context.progress.lock().unwrap() = Some(format!("{} of {} looked up", done, total));
+        versions.insert(package, latest);
+    }
+    versions
+}
+
+async fn latest_version(
+    network: &NetworkAccess,
+    registry: Registry,
+    package: &str,
+) -> Result<String, String> {
+    let url = reqwest::Url::parse(&registry.url(package)).map_err(|err| err.to_string())?;
+    if !network.allows(&url) {
+        return Err(format!(
+            "{} is not in the allowed_hosts under [tools]",
+            url.host_str().unwrap_or_default()
+        ));
+    }
+    let response = network
+        .client
+        .get(url)
+        .header(USER_AGENT, concat!("tars/", env!("CARGO_PKG_VERSION")))
+        .timeout(LOOKUP_TIMEOUT)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "{} answered {}",
+            registry.name(),
+            response.status()
+        ));
+    }
+    let body: serde_json::Value = response.json().await.map_err(|err| err.to_string())?;
+    registry
+        .latest(&body)
+        .ok_or_else(|| format!("{} gave no version", registry.name()))
+}
+
+/// ` — latest 1.2.3`, noting when the requirement doesn't allow it. Only
+/// Cargo and npm requirements are checked; PEP 440 ones are left to the
+/// reader.
+fn describe_latest(registry: Registry, requirement: &str, latest: &str) -> String {
+    let allowed = match registry {
+        Registry::Crates | Registry::Npm if !requirement.is_empty() => {
+            match (
+                semver::VersionReq::parse(requirement),
+                semver::Version::parse(latest),
+            ) {
+                (Ok(requirement), Ok(latest)) => Some(requirement.matches(&latest)),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    match allowed {
+        Some(false) => format!(" — latest {}, not allowed by {}", latest, requirement),
+        _ => format!(" — latest {}", latest),
+    }
+}
+
+fn describe(
+    manifests: &[Manifest],
+    versions: &HashMap<(Registry, String), Result<String, String>>,
+) -> String {
+    let mut lines = Vec::new();
+    for manifest in manifests {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(format!(
+            "{} ({})",
+            manifest.path.display(),
+            manifest.registry.name()
+        ));
+        if manifest.groups.is_empty() {
+            lines.push("  no dependencies".to_string());
+        }
+        for (group, dependencies) in &manifest.groups {
+            lines.push(format!("[{}]", group));
+            for dependency in dependencies {
+                let mut line = format!("  {}", dependency.name);
+                if !dependency.requirement.is_empty() {
+                    line.push(' ');
+                    line.push_str(&dependency.requirement);
+                }
+                if !dependency.notes.is_empty() {
+                    line.push_str(&format!(" ({})", dependency.notes.join("; ")));
+                }
+                match versions.get(&(manifest.registry, dependency.package.clone())) {
+                    Some(Ok(latest)) if dependency.from_registry => line.push_str(
+                        &describe_latest(manifest.registry, &dependency.requirement, latest),
+                    ),
+                    Some(Err(err)) if dependency.from_registry => {
+                        line.push_str(&format!(" — latest unknown: {}", err))
+                    }
+                    _ => {}
+                }
+                lines.push(line);
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+async fn dependencies_impl(
+    input: serde_json::Value,
+    context: ToolContext,
+) -> DependenciesResult<String> {
+    let input: DependenciesInput = serde_json::from_value(input)?;
+    let path = input.path.unwrap_or_else(|| ".".to_string());
+    let manifests = tokio::task::spawn_blocking(move || read_manifests(&path)).await??;
+    if !input.latest {
+        return Ok(describe(&manifests, &HashMap::new()));
+    }
+    let Some(network) = context.network.clone() else {
+        return Ok(format!(
+            "{}\n\nLatest versions need network tools; the user can set `network = true` under [tools] in ~/.tars/config.toml.",
+            describe(&manifests, &HashMap::new())
+        ));
+    };
+    let versions = latest_versions(&manifests, &network, &context).await;
+    Ok(describe(&manifests, &versions))
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "dependencies",
+        description: "List the dependencies declared in Cargo.toml, package.json or pyproject.toml with their version requirements, features and sources. With `latest`, also looks up each one's newest release on its registry and flags requirements that don't allow it, for upgrade work.",
+        input_schema: serde_json::to_value(schema_for!(DependenciesInput)).unwrap(),
+        handler: |input, context| Box::pin(dependencies_impl(input, context)),
+        read_only: true,
+        opt_in: None,
+        summarize: |input, _| {
+            let path = input["path"].as_str().unwrap_or(".");
+            if input["latest"].as_bool() == Some(true) {
+                format!("📦 dependencies {} (latest)", path)
+            } else {
+                format!("📦 dependencies {}", path)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[tokio::test]
+    async fn manifests_are_read_and_latest_versions_compared() {
+        let dir = TempDir::new("tars-dependencies");
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            r#"
+[package]
+name = "app"
+
+[dependencies]
+serde = { version = "1", features = ["derive"] }
+local = { path = "../local" }
+
+[target.'cfg(unix)'.dev-dependencies]
+libc = "0.2"
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("package.json"),
+            r#"{ "dependencies": { "react": "^18.2.0", "ui": "workspace:*" } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("pyproject.toml"),
+            r#"
+[project]
+dependencies = ["requests[socks]>=2.31", "tool @ https://example.com/tool.whl"]
+"#,
+        )
+        .unwrap();
+
+        let input = serde_json::json!({ "path": dir.to_str().unwrap(), "latest": true });
+        let output = dependencies_impl(input, ToolContext::default())
+            .await
+            .unwrap();
+        let expected = format!(
+            "{0}/Cargo.toml (crates.io)\n\
+             [dependencies]\n  \
+             local (path ../local)\n  \
+             serde 1 (features: derive)\n\
+             [target.cfg(unix).dev-dependencies]\n  \
+             libc 0.2\n\
+             \n\
+             {0}/package.json (npm)\n\
+             [dependencies]\n  \
+             react ^18.2.0\n  \
+             ui workspace:*\n\
+             \n\
+             {0}/pyproject.toml (PyPI)\n\
+             [dependencies]\n  \
+             requests [socks]>=2.31\n  \
+             tool @ https://example.com/tool.whl\n\
+             \n\
+             Latest versions need network tools",
+            dir.display()
+        );
+        assert!(output.starts_with(&expected), "{}", output);
+
+        assert_eq!(
+            describe_latest(Registry::Crates, "1", "1.0.228"),
+            " — latest 1.0.228"
+        );
+        assert_eq!(
+            describe_latest(Registry::Npm, "^17.0.2", "18.3.1"),
+            " — latest 18.3.1, not allowed by ^17.0.2"
+        );
+        assert_eq!(
+            describe_latest(Registry::PyPI, ">=2.31", "2.32.3"),
+            " — latest 2.32.3"
+        );
+    }
+}
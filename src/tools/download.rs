@@ -0,0 +1,434 @@
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::StatusCode;
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use super::hash_file::{digest, Algorithm};
+use super::{
+    format_size, input_path, not_a_file, NetworkAccess, OptIn, ToolContext, ToolDefinition,
+};
+
+type DownloadResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// How long the server may go quiet before the download is treated as
+/// interrupted.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct DownloadInput {
+    #[schemars(description = "The http or https URL to fetch")]
+    url: String,
+    #[schemars(description = "The relative path to save the file to")]
+    path: String,
+    #[schemars(
+        description = "Optional sha256 the file must have, e.g. from a checksums file or lockfile"
+    )]
+    sha256: Option<String>,
+}
+
+/// Where a download is written until it is complete, and where the next
+/// download to the same path picks up after an interruption.
+fn part_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.part", path))
+}
+
+/// Where the ETag or Last-Modified of the file being downloaded to `path`
+/// is kept, so a resumed download only appends to the same version.
+fn validator_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.part.validator", path))
+}
+
+/// What identifies the version of the file a response carries: its strong
+/// ETag, or else its Last-Modified date. Weak ETags can't be used to resume.
+fn validator(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+        .or_else(|| headers.get(LAST_MODIFIED))
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Fetches `url`, from `offset` on while it still has the version `saved`.
+async fn fetch(
+    network: &NetworkAccess,
+    url: &reqwest::Url,
+    resume: Option<(u64, &str)>,
+) -> DownloadResult<reqwest::Response> {
+    let mut request = network.client.get(url.clone());
+    if let Some((offset, saved)) = resume {
+        request = request
+            .header(RANGE, format!("bytes={}-", offset))
+            .header(IF_RANGE, saved);
+    }
+    request.send().await.map_err(|err| {
+        // A redirect to a host that isn't allowed says so in its source.
+        match std::error::Error::source(&err) {
+            Some(source) if err.is_redirect() => source.to_string().into(),
+            _ => err.into(),
+        }
+    })
+}
+
+async fn download_impl(input: serde_json::Value, context: ToolContext) -> DownloadResult<String> {
+    let input: DownloadInput = serde_json::from_value(input)?;
+    let Some(network) = context.network.clone() else {
+        return Err("Network tools are turned off; set `network = true` under [tools] in ~/.tars/config.toml".into());
+    };
+    if input.path.is_empty() {
+        return Err("Invalid input parameters".into());
+    }
+    let url = reqwest::Url::parse(&input.url)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("Only http and https URLs can be downloaded, not {}", url).into());
+    }
+    if !network.allows(&url) {
+        return Err(format!(
+            "{} is not in the allowed_hosts under [tools]",
+            url.host_str().unwrap_or_default()
+        )
+        .into());
+    }
+    if let Ok(metadata) = tokio::fs::metadata(&input.path).await
+        && !metadata.is_file()
+    {
+        return Err(not_a_file(&input.path, &metadata).into());
+    }
+    if let Some(parent) = Path::new(&input.path).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let part = part_path(&input.path);
+    let saved_validator = validator_path(&input.path);
+    let offset = tokio::fs::metadata(&part)
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    // A partial file whose version isn't known can't be resumed safely, so
+    // it starts over.
+    let saved = match offset {
+        0 => None,
+        _ => tokio::fs::read_to_string(&saved_validator).await.ok(),
+    };
+    let resume = saved.as_deref().map(|saved| (offset, saved));
+    let mut response = fetch(&network, &url, resume).await?;
+    let mut resumed = match response.status() {
+        StatusCode::PARTIAL_CONTENT if saved.is_some() => true,
+        status if status.is_success() => false,
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            tokio::fs::remove_file(&part).await?;
+            let _ = tokio::fs::remove_file(&saved_validator).await;
+            return Err(format!(
+                "{} no longer matches the file at {} and was deleted; download it again",
+                part.display(),
+                url
+            )
+            .into());
+        }
+        status => return Err(format!("{} answered {}", url, status).into()),
+    };
+    // A server that ignores If-Range may send the rest of another version.
+    if resumed && validator(response.headers()) != saved {
+        response = fetch(&network, &url, None).await?;
+        if !response.status().is_success() {
+            return Err(format!("{} answered {}", url, response.status()).into());
+        }
+        resumed = false;
+    }
+    let mut received = if resumed { offset } else { 0 };
+    let total = response.content_length().map(|length| length + received);
+    if let Some(total) = total
+        && total > network.max_download_bytes
+    {
+        return Err(too_large(total, network.max_download_bytes).into());
+    }
+    if !resumed {
+        match validator(response.headers()) {
+            Some(validator) => tokio::fs::write(&saved_validator, validator).await?,
+            None => {
+                let _ = tokio::fs::remove_file(&saved_validator).await;
+            }
+        }
+    }
+    let mut file = if resumed {
+        tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&part)
+            .await?
+    } else {
+        tokio::fs::File::create(&part).await?
+    };
+    let mut body = response.bytes_stream();
+    loop {
+        let chunk = match tokio::time::timeout(IDLE_TIMEOUT, body.next()).await {
+            Ok(None) => break,
+            Ok(Some(Ok(chunk))) => chunk,
+            Ok(Some(Err(err))) => return Err(interrupted(received, &err.to_string()).into()),
+            Err(_) => return Err(interrupted(received, "the server stopped sending").into()),
+        };
+        received += chunk.len() as u64;
+        if received > network.max_download_bytes {
+            drop(file);
+            tokio::fs::remove_file(&part).await?;
+            let _ = tokio::fs::remove_file(&saved_validator).await;
+            return Err(too_large(received, network.max_download_bytes).into());
+        }
+        file.write_all(&chunk).await?;
+        *context.progress.lock().unwrap() = Some(match total {
+            Some(total) => format!("{} of {}", format_size(received), format_size(total)),
+            None => format_size(received),
+        });
+    }
+    file.flush().await?;
+    drop(file);
+    if let Some(total) = total
+        && received < total
+    {
+        return Err(interrupted(received, "the connection closed early").into());
+    }
+
+    let hashed = part.clone();
+    let (sha256, _) =
+        tokio::task::spawn_blocking(move || digest(&hashed, Algorithm::Sha256)).await??;
+    if let Some(expected) = input.sha256 {
+        let expected = Algorithm::Sha256.expected(&expected);
+        if expected != sha256 {
+            tokio::fs::remove_file(&part).await?;
+            let _ = tokio::fs::remove_file(&saved_validator).await;
+            return Err(format!(
+                "The download's sha256 is {}, not the expected {}; it was deleted",
+                sha256, expected
+            )
+            .into());
+        }
+    }
+    tokio::fs::rename(&part, &input.path).await?;
+    let _ = tokio::fs::remove_file(&saved_validator).await;
+
+    let resumed = if resumed {
+        format!(", resumed after {}", format_size(offset))
+    } else {
+        String::new()
+    };
+    Ok(format!(
+        "Downloaded {} to {} ({}{})\nsha256: {}",
+        url,
+        input.path,
+        format_size(received),
+        resumed,
+        sha256
+    ))
+}
+
+fn too_large(size: u64, limit: u64) -> String {
+    format!(
+        "The file is {}, over the {} limit set by max_download_mb under [tools]",
+        format_size(size),
+        format_size(limit)
+    )
+}
+
+fn interrupted(received: u64, reason: &str) -> String {
+    format!(
+        "The download was interrupted after {}: {}. Call download again with the same path to resume it.",
+        format_size(received),
+        reason
+    )
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "download",
+        description: "Download a URL to a file in the workspace, optionally checking its sha256. An interrupted download is kept as `<path>.part` and resumed by the next download to the same path. Returns the file's size and sha256.",
+        input_schema: serde_json::to_value(schema_for!(DownloadInput)).unwrap(),
+        handler: |input, context| Box::pin(download_impl(input, context)),
+        read_only: false,
+        opt_in: Some(OptIn::Network),
+        summarize: |input, _| format!("⬇️ download {}", input_path(input)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use tokio::io::AsyncReadExt;
+
+    /// The version of the file `serve` serves.
+    const ETAG: &str = "\"v1\"";
+
+    /// Serves `body` with `ETAG` over plain HTTP, honouring `Range:
+    /// bytes=N-` and, with `if_range`, `If-Range`. `/moved` redirects to
+    /// the file and `/away` to it under `localhost`.
+    async fn serve(body: &'static [u8], if_range: bool) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let read = socket.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+                let header = |name: &str| {
+                    request
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+                        .map(str::to_string)
+                };
+                let location = if request.starts_with("get /moved ") {
+                    Some(format!("http://{}/file.txt", address))
+                } else if request.starts_with("get /away ") {
+                    Some(format!("http://localhost:{}/file.txt", address.port()))
+                } else {
+                    None
+                };
+                if let Some(location) = location {
+                    let head = format!(
+                        "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        location
+                    );
+                    socket.write_all(head.as_bytes()).await.unwrap();
+                    continue;
+                }
+                let same_version = !if_range || header("if-range").is_none_or(|tag| tag == ETAG);
+                let start = header("range").filter(|_| same_version).and_then(|range| {
+                    range
+                        .strip_prefix("bytes=")?
+                        .split('-')
+                        .next()?
+                        .parse()
+                        .ok()
+                });
+                let head = match start {
+                    Some(start) => format!(
+                        "HTTP/1.1 206 Partial Content\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        ETAG,
+                        body.len() - start
+                    ),
+                    None => format!(
+                        "HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        ETAG,
+                        body.len()
+                    ),
+                };
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(&body[start.unwrap_or(0)..]).await.unwrap();
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    fn context() -> ToolContext {
+        let network = NetworkAccess::new(
+            reqwest::Client::builder(),
+            vec!["127.0.0.1".to_string()],
+            1024,
+        )
+        .unwrap();
+        ToolContext {
+            network: Some(network),
+            ..ToolContext::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn downloads_resume_and_are_verified() {
+        let url = format!("{}/file.txt", serve(b"hello, world\n", true).await);
+        let dir = TempDir::new("tars-download");
+        let path = dir.join("nested/file.txt");
+        let path = path.to_str().unwrap();
+        let sha256 = "853ff93762a06ddbf722c4ebe9ddd66d8f63ddaea97f521c3ecc20da7c976020";
+        let context = context();
+        let input =
+            |sha256: &str| serde_json::json!({ "url": url, "path": path, "sha256": sha256 });
+
+        let output = download_impl(input(sha256), context.clone()).await.unwrap();
+        assert!(
+            output.ends_with(&format!("(13 bytes)\nsha256: {}", sha256)),
+            "{}",
+            output
+        );
+        assert_eq!(std::fs::read(path).unwrap(), b"hello, world\n");
+        assert!(!validator_path(path).exists());
+
+        // A partial file is picked up where it stopped.
+        std::fs::write(part_path(path), b"hello").unwrap();
+        std::fs::write(validator_path(path), ETAG).unwrap();
+        let output = download_impl(input(sha256), context.clone()).await.unwrap();
+        assert!(output.contains("resumed after 5 bytes"), "{}", output);
+        assert_eq!(std::fs::read(path).unwrap(), b"hello, world\n");
+
+        let err = download_impl(input("00"), context.clone())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not the expected 00"), "{}", err);
+        assert!(!part_path(path).exists());
+        assert!(!validator_path(path).exists());
+
+        let offline = ToolContext::default();
+        assert!(download_impl(input(sha256), offline).await.is_err());
+        let elsewhere = serde_json::json!({ "url": "https://example.com/x", "path": path });
+        let err = download_impl(elsewhere, context).await.unwrap_err();
+        assert!(
+            err.to_string().contains("not in the allowed_hosts"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn partial_files_of_another_version_start_over() {
+        let dir = TempDir::new("tars-download-version");
+        let path = dir.join("file.txt");
+        let path = path.to_str().unwrap();
+        let stale = || {
+            std::fs::write(part_path(path), b"HELLO").unwrap();
+            std::fs::write(validator_path(path), "\"v0\"").unwrap();
+        };
+
+        for if_range in [true, false] {
+            let url = format!("{}/file.txt", serve(b"hello, world\n", if_range).await);
+            stale();
+            let input = serde_json::json!({ "url": url, "path": path });
+            let output = download_impl(input, context()).await.unwrap();
+            assert!(!output.contains("resumed"), "{}", output);
+            assert_eq!(std::fs::read(path).unwrap(), b"hello, world\n");
+        }
+
+        // Without a saved version there is nothing to check the rest against.
+        let url = format!("{}/file.txt", serve(b"hello, world\n", true).await);
+        std::fs::write(part_path(path), b"HELLO").unwrap();
+        let input = serde_json::json!({ "url": url, "path": path });
+        let output = download_impl(input, context()).await.unwrap();
+        assert!(!output.contains("resumed"), "{}", output);
+        assert_eq!(std::fs::read(path).unwrap(), b"hello, world\n");
+    }
+
+    #[tokio::test]
+    async fn redirects_stay_within_the_allowed_hosts() {
+        let server = serve(b"hello, world\n", true).await;
+        let dir = TempDir::new("tars-download-redirect");
+        let path = dir.join("file.txt");
+        let path = path.to_str().unwrap();
+
+        let moved = serde_json::json!({ "url": format!("{}/moved", server), "path": path });
+        download_impl(moved, context()).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), b"hello, world\n");
+
+        let away = serde_json::json!({ "url": format!("{}/away", server), "path": path });
+        let err = download_impl(away, context()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}/away redirected to localhost, which is not in the allowed_hosts under [tools]",
+                server
+            )
+        );
+    }
+}
@@ -1,8 +1,15 @@
 use schemars::{schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
 use std::io;
+use tokio::io::AsyncReadExt;
 
-use super::ToolDefinition;
+use super::text;
+use super::{format_size, input_path, not_a_file, ToolDefinition};
+
+/// Files larger than this are refused: rewriting them whole for each edit
+/// is slow, and they are rarely hand-written.
+const MAX_EDIT_BYTES: u64 = 4 * 1024 * 1024;
+const SNIFF_BYTES: u64 = 8 * 1024;
 
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 struct EditFileInput {
@@ -23,23 +30,37 @@ async fn edit_file_impl(
         return Err("Invalid input parameters".into());
     }
 
-    match tokio::fs::read_to_string(&input.path).await {
-        Ok(content) => {
-            let new_content = content.replace(&input.old_str, &input.new_str);
+    if let Ok(metadata) = tokio::fs::metadata(&input.path).await {
+        if !metadata.is_file() {
+            return Err(not_a_file(&input.path, &metadata).into());
+        }
+        check_editable(&input.path, metadata.len()).await?;
+    }
 
-            if !content.contains(&input.old_str) && !input.old_str.is_empty() {
-                return Err("old_str not found in file".into());
+    match tokio::fs::read(&input.path).await {
+        Ok(bytes) => {
+            let decoded = text::decode(&bytes);
+            if decoded.lossy {
+                return Err(format!(
+                    "{} isn't valid {} text, and edit_file would corrupt it; ask the user to \
+                     edit it instead",
+                    input.path,
+                    decoded.format.encoding.name()
+                )
+                .into());
             }
-
-            tokio::fs::write(&input.path, new_content).await?;
+            let new_content = apply_edit(&decoded.text, &input.old_str, &input.new_str)?;
+            let encoded = text::encode(&new_content, decoded.format)
+                .map_err(|err| format!("Can't edit {}: {}", input.path, err))?;
+            tokio::fs::write(&input.path, encoded).await?;
             Ok("OK".to_string())
         }
         Err(e) if e.kind() == io::ErrorKind::NotFound => {
             if input.old_str.is_empty() {
-                if let Some(parent) = std::path::Path::new(&input.path).parent() {
-                    if !parent.as_os_str().is_empty() {
-                        tokio::fs::create_dir_all(parent).await?;
-                    }
+                if let Some(parent) = std::path::Path::new(&input.path).parent()
+                    && !parent.as_os_str().is_empty()
+                {
+                    tokio::fs::create_dir_all(parent).await?;
                 }
                 tokio::fs::write(&input.path, &input.new_str).await?;
                 Ok(format!("Successfully created file {}", input.path))
@@ -51,11 +72,176 @@ async fn edit_file_impl(
     }
 }
 
+/// Refuses files that are too large to rewrite or aren't text, saying what
+/// to do instead.
+async fn check_editable(
+    path: &str,
+    size: u64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if size > MAX_EDIT_BYTES {
+        return Err(format!(
+            "{} is {}, over edit_file's {} limit. Files this large are usually generated or \
+             data; change whatever produces it, or ask the user to make the edit",
+            path,
+            format_size(size),
+            format_size(MAX_EDIT_BYTES)
+        )
+        .into());
+    }
+    let mut sniff = Vec::new();
+    tokio::fs::File::open(path)
+        .await?
+        .take(SNIFF_BYTES)
+        .read_to_end(&mut sniff)
+        .await?;
+    if sniff.contains(&0) && !text::is_utf16(&sniff) {
+        return Err(format!(
+            "{} is a binary file ({}); edit_file only edits text. Change the source it is \
+             built from, or ask the user to replace it",
+            path,
+            format_size(size)
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Replaces the one occurrence of `old_str` in `content`. Overlapping
+/// occurrences count separately: "aa" in "aaa" is just as ambiguous. An
+/// empty `old_str` only matches an empty file.
+fn apply_edit(content: &str, old_str: &str, new_str: &str) -> Result<String, String> {
+    if old_str.is_empty() {
+        return if content.is_empty() {
+            Ok(new_str.to_string())
+        } else {
+            Err("old_str is empty but the file is not".to_string())
+        };
+    }
+    let start = content.find(old_str).ok_or("old_str not found in file")?;
+    let next = start + content[start..].chars().next().map_or(1, char::len_utf8);
+    if content[next..].contains(old_str) {
+        return Err(
+            "old_str matches more than once; include more surrounding text so it matches exactly once"
+                .to_string(),
+        );
+    }
+    Ok(format!(
+        "{}{}{}",
+        &content[..start],
+        new_str,
+        &content[start + old_str.len()..]
+    ))
+}
+
 pub(crate) fn definition() -> ToolDefinition {
     ToolDefinition {
         name: "edit_file",
-        description: "Make edits to a text file.\n\nReplaces 'old_str' with 'new_str' in the given file. 'old_str' and 'new_str' MUST be different from each other.\n\nIf the file specified with path doesn't exist, it will be created.",
+        description: "Make edits to a text file.\n\nReplaces 'old_str' with 'new_str' in the given file. 'old_str' and 'new_str' MUST be different from each other.\n\nIf the file specified with path doesn't exist, it will be created. Binary files and files over 4 MB can't be edited.",
         input_schema: serde_json::to_value(schema_for!(EditFileInput)).unwrap(),
-        handler: |input| Box::pin(edit_file_impl(input)),
+        handler: |input, _| Box::pin(edit_file_impl(input)),
+        read_only: false,
+        opt_in: None,
+        summarize: |input, output| {
+            let path = input_path(input);
+            let lines = |key: &str| input[key].as_str().map_or(0, |s| s.lines().count());
+            match output {
+                Some(output) if output.starts_with("Successfully created") => {
+                    format!("📝 create {} (+{})", path, lines("new_str"))
+                }
+                Some(_) => format!(
+                    "✏️ edit {} (+{} −{})",
+                    path,
+                    lines("new_str"),
+                    lines("old_str")
+                ),
+                None => format!("✏️ edit {}", path),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use proptest::prelude::*;
+
+    #[test]
+    fn edits_need_exactly_one_match() {
+        assert_eq!(apply_edit("a b c", "b", "x").unwrap(), "a x c");
+        assert!(apply_edit("a b b", "b", "x").is_err());
+        assert!(apply_edit("aaa", "aa", "x").is_err());
+        assert!(apply_edit("abc", "z", "x").is_err());
+        assert!(apply_edit("abc", "", "x").is_err());
+        assert_eq!(apply_edit("", "", "x").unwrap(), "x");
+    }
+
+    #[tokio::test]
+    async fn binary_and_oversized_files_are_refused() {
+        let dir = TempDir::new("tars-edit");
+        let edit = |path: &std::path::Path| {
+            edit_file_impl(serde_json::json!({
+                "path": path.to_str().unwrap(),
+                "old_str": "a",
+                "new_str": "b",
+            }))
+        };
+
+        let binary = dir.join("blob.bin");
+        std::fs::write(&binary, b"a\0bc").unwrap();
+        let err = edit(&binary).await.unwrap_err().to_string();
+        assert!(err.contains("is a binary file (4 bytes)"), "{}", err);
+
+        let huge = dir.join("huge.txt");
+        let file = std::fs::File::create(&huge).unwrap();
+        file.set_len(MAX_EDIT_BYTES + 1).unwrap();
+        let err = edit(&huge).await.unwrap_err().to_string();
+        assert!(err.contains("over edit_file's 4.0 MB limit"), "{}", err);
+
+        // Other encodings are edited in place, keeping their line endings.
+        let latin1 = dir.join("latin1.txt");
+        std::fs::write(&latin1, b"s\xfbr\r\nd\xe9j\xe0 a\r\n").unwrap();
+        edit(&latin1).await.unwrap();
+        assert_eq!(
+            std::fs::read(&latin1).unwrap(),
+            b"s\xfbr\r\nd\xe9j\xe0 b\r\n"
+        );
+
+        // Mixed endings stay mixed, whichever is more common.
+        let mixed = dir.join("mixed.txt");
+        std::fs::write(&mixed, "one\r\ntwo\r\nthree\nfour a\r\nfive\n").unwrap();
+        edit(&mixed).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&mixed).unwrap(),
+            "one\r\ntwo\r\nthree\nfour b\r\nfive\n"
+        );
+    }
+
+    proptest! {
+        // A small alphabet so strings overlap and repeat often.
+        #[test]
+        fn edits_round_trip(
+            content in "[ab\n日]{1,12}",
+            start in 0usize..13,
+            len in 1usize..5,
+            new_str in "[abc\n日]{0,4}",
+        ) {
+            let chars: Vec<char> = content.chars().collect();
+            let start = start.min(chars.len() - 1);
+            let old_str: String = chars[start..(start + len).min(chars.len())].iter().collect();
+
+            let matches = (0..chars.len())
+                .filter(|&i| chars[i..].iter().collect::<String>().starts_with(&old_str))
+                .count();
+            let edited = apply_edit(&content, &old_str, &new_str);
+            prop_assert_eq!(edited.is_ok(), matches == 1);
+
+            if let Ok(edited) = edited {
+                prop_assert_eq!(edited.len(), content.len() - old_str.len() + new_str.len());
+                if let Ok(reverted) = apply_edit(&edited, &new_str, &old_str) {
+                    prop_assert_eq!(reverted, content);
+                }
+            }
+        }
     }
 }
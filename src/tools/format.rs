@@ -0,0 +1,96 @@
+use super::process::{run_captured, RunError};
+use crate::config::FormatConfig;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long a formatter may run before it is abandoned.
+const FORMAT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Formatter output passed back to the model is cut off after this many
+/// bytes.
+const MAX_REPORT_BYTES: usize = 4000;
+
+/// Runs the configured formatter on a file the agent just edited.
+///
+/// Returns a note for the model when the formatter changed the file, so it
+/// re-reads before editing again, or when it failed, so it fixes what the
+/// formatter reported. Returns nothing when the file has no formatter, the
+/// formatter isn't installed, or it left the file as it was.
+pub(crate) async fn format_after_edit(format: &FormatConfig, path: &str) -> Option<String> {
+    let args = format.command_for(Path::new(path))?;
+    let before = tokio::fs::read(path).await.ok()?;
+
+    let output = match run_captured(&args, FORMAT_TIMEOUT).await {
+        Ok(output) => output,
+        Err(RunError::NotFound) => return None,
+        Err(RunError::Io(err)) => return Some(format!("Could not run `{}`: {}", args[0], err)),
+        Err(RunError::TimedOut) => {
+            return Some(format!(
+                "`{}` did not finish within {}s",
+                args.join(" "),
+                FORMAT_TIMEOUT.as_secs()
+            ));
+        }
+    };
+
+    if !output.status.success() {
+        return Some(format!(
+            "`{}` failed ({}); fix what it reports:\n{}",
+            args.join(" "),
+            output.status,
+            output.report(MAX_REPORT_BYTES).trim_end()
+        ));
+    }
+
+    let after = tokio::fs::read(path).await.ok()?;
+    (after != before).then(|| {
+        format!(
+            "`{}` reformatted the file; read it again before editing it further.",
+            args[0]
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn format_with(extension: &str, command: &str) -> FormatConfig {
+        FormatConfig {
+            on_edit: true,
+            commands: [(extension.to_string(), command.to_string())].into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_changes_and_failures_only() {
+        let dir = TempDir::new("tars-format");
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, "hello\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        assert_eq!(
+            format_after_edit(&format_with("txt", "true"), path).await,
+            None
+        );
+        assert_eq!(
+            format_after_edit(&format_with("md", "false"), path).await,
+            None
+        );
+        assert_eq!(
+            format_after_edit(&format_with("txt", "no-such-formatter-tars"), path).await,
+            None
+        );
+
+        let failed = format_after_edit(&format_with("txt", "false"), path)
+            .await
+            .unwrap();
+        assert!(failed.starts_with("`false "), "{}", failed);
+
+        let changed = format_after_edit(&format_with("txt", "truncate -s 0"), path)
+            .await
+            .unwrap();
+        assert!(changed.contains("reformatted"), "{}", changed);
+    }
+}
@@ -0,0 +1,308 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::SeekFrom;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
+
+use super::{input_path, not_a_file, text, ToolDefinition};
+
+/// A chunk ends at the first line break after this many bytes, or, in a
+/// line longer than that, after at most twice as many.
+const CHUNK_BYTES: u64 = 48 * 1024;
+/// Lines each chunk repeats from the end of the one before, so code cut at
+/// a boundary is whole in one of them.
+pub(super) const OVERLAP_LINES: usize = 20;
+/// The repeated lines take at most this much of a chunk.
+const OVERLAP_BYTES: u64 = CHUNK_BYTES / 4;
+/// The most of a chunk that is read, and of its text that is shown, should
+/// decoding make it longer than its bytes.
+const MAX_CHUNK_CHARS: usize = 2 * CHUNK_BYTES as usize;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct GetChunkInput {
+    #[schemars(description = "The relative path of the file read_file split into chunks.")]
+    path: String,
+    #[schemars(description = "The chunk to read, counting from 1.")]
+    chunk: usize,
+}
+
+/// A run of whole lines of a large file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Chunk {
+    /// 1-based, inclusive.
+    pub(super) start_line: usize,
+    pub(super) end_line: usize,
+    /// Byte offsets, end exclusive.
+    start: u64,
+    end: u64,
+}
+
+/// Splits a file into chunks of about `CHUNK_BYTES`, each starting a few
+/// lines before the last one ended. Reads the file once, at most
+/// `CHUNK_BYTES` at a time and holding only the last few lines' offsets,
+/// and always gives the same chunks for the same contents. A line too long
+/// for one chunk, such as minified code, is split across several.
+pub(super) async fn chunks(file: &mut tokio::fs::File) -> std::io::Result<Vec<Chunk>> {
+    file.rewind().await?;
+    let mut reader = BufReader::new(file);
+    let mut chunks = Vec::new();
+    // The number and offset of the lines most recently started.
+    let mut recent: VecDeque<(usize, u64)> = VecDeque::new();
+    // The line the next byte is on, and whether it is the line's first.
+    let mut line = 1;
+    let mut line_start = true;
+    let mut last_line = 0;
+    let mut offset = 0;
+    let mut start = (1, 0);
+    loop {
+        let (read, ends_line) = read_piece(&mut reader, CHUNK_BYTES as usize).await?;
+        if read == 0 {
+            break;
+        }
+        if line_start {
+            recent.push_back((line, offset));
+            if recent.len() > OVERLAP_LINES {
+                recent.pop_front();
+            }
+        }
+        last_line = line;
+        offset += read as u64;
+        line_start = ends_line;
+        if ends_line {
+            line += 1;
+        }
+        if offset - start.1 >= CHUNK_BYTES {
+            chunks.push(Chunk {
+                start_line: start.0,
+                end_line: last_line,
+                start: start.1,
+                end: offset,
+            });
+            start = recent
+                .iter()
+                .copied()
+                .find(|&(_, at)| at > start.1 && offset - at <= OVERLAP_BYTES)
+                .unwrap_or((line, offset));
+        }
+    }
+    if chunks.last().is_none_or(|last: &Chunk| last.end < offset) {
+        chunks.push(Chunk {
+            start_line: start.0,
+            end_line: last_line,
+            start: start.1,
+            end: offset,
+        });
+    }
+    Ok(chunks)
+}
+
+/// Reads through the next line break, or at most `limit` bytes of a longer
+/// line, stopping before a UTF-8 character rather than inside one. Returns
+/// how many bytes were read and whether they end the line.
+async fn read_piece(
+    reader: &mut BufReader<&mut tokio::fs::File>,
+    limit: usize,
+) -> std::io::Result<(usize, bool)> {
+    let mut read = 0;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok((read, true));
+        }
+        let room = limit - read;
+        let window = &available[..available.len().min(room)];
+        if let Some(at) = window.iter().position(|&byte| byte == b'\n') {
+            reader.consume(at + 1);
+            return Ok((read + at + 1, true));
+        }
+        if window.len() < room {
+            let taken = window.len();
+            reader.consume(taken);
+            read += taken;
+            continue;
+        }
+        let mut cut = room;
+        if let Some(lead) = window.iter().rposition(|&byte| byte & 0xc0 != 0x80)
+            && lead + utf8_len(window[lead]) > room
+        {
+            cut = lead;
+        }
+        // A character longer than the whole limit is split after all.
+        if cut == 0 && read == 0 {
+            cut = room;
+        }
+        reader.consume(cut);
+        return Ok((read + cut, false));
+    }
+}
+
+/// The length of the UTF-8 sequence `lead` starts, or 1 for a byte that
+/// starts none.
+fn utf8_len(lead: u8) -> usize {
+    match lead {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    }
+}
+
+/// The chunk's lines under a header saying where they are from.
+pub(super) async fn read_chunk(
+    file: &mut tokio::fs::File,
+    path: &str,
+    chunks: &[Chunk],
+    index: usize,
+) -> std::io::Result<String> {
+    let chunk = chunks[index];
+    file.seek(SeekFrom::Start(chunk.start)).await?;
+    let length = (chunk.end - chunk.start).min(MAX_CHUNK_CHARS as u64);
+    let mut bytes = vec![0; length as usize];
+    file.read_exact(&mut bytes).await?;
+    let mut contents = text::decode(&bytes).text;
+    if contents.len() > MAX_CHUNK_CHARS {
+        let mut cut = MAX_CHUNK_CHARS;
+        while !contents.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        contents.truncate(cut);
+        contents.push_str("\n[... the rest of this chunk is not shown ...]");
+    }
+    Ok(format!(
+        "[{}, chunk {} of {}, lines {}–{}]\n{}",
+        path,
+        index + 1,
+        chunks.len(),
+        chunk.start_line,
+        chunk.end_line,
+        contents.trim_end_matches('\n')
+    ))
+}
+
+async fn get_chunk(
+    input: GetChunkInput,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let metadata = tokio::fs::metadata(&input.path).await?;
+    if !metadata.is_file() {
+        return Err(not_a_file(&input.path, &metadata).into());
+    }
+    let mut file = tokio::fs::File::open(&input.path).await?;
+    let mut sniff = vec![0; (8 * 1024).min(metadata.len() as usize)];
+    file.read_exact(&mut sniff).await?;
+    if sniff.contains(&0) {
+        return Err(format!(
+            "{} is binary or UTF-16 and isn't split into chunks",
+            input.path
+        )
+        .into());
+    }
+    let chunks = chunks(&mut file).await?;
+    if input.chunk == 0 || input.chunk > chunks.len() {
+        return Err(format!(
+            "{} has {} chunk{}, numbered from 1",
+            input.path,
+            chunks.len(),
+            if chunks.len() == 1 { "" } else { "s" }
+        )
+        .into());
+    }
+    Ok(read_chunk(&mut file, &input.path, &chunks, input.chunk - 1).await?)
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "get_chunk",
+        description: "Read one chunk of a file too large for read_file, which lists the file's chunks and their line ranges. Chunks are about 48 KB and overlap by a few lines.",
+        input_schema: serde_json::to_value(schema_for!(GetChunkInput)).unwrap(),
+        handler: |input, _| {
+            Box::pin(async move {
+                let input: GetChunkInput = serde_json::from_value(input)?;
+                get_chunk(input).await
+            })
+        },
+        read_only: true,
+        opt_in: None,
+        summarize: |input, output| {
+            let path = input_path(input);
+            let chunk = input["chunk"].as_u64().unwrap_or_default();
+            let lines = output
+                .and_then(|output| output.lines().next())
+                .and_then(|header| header.rsplit_once(", lines "))
+                .map(|(_, lines)| lines.trim_end_matches(']'));
+            match lines {
+                Some(lines) => format!("📖 read {} chunk {} (lines {})", path, chunk, lines),
+                None => format!("📖 read {} chunk {}", path, chunk),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[tokio::test]
+    async fn chunks_overlap_and_cover_the_whole_file() {
+        let dir = TempDir::new("tars-chunk");
+        let path = dir.join("big.log");
+        let contents: String = (1..=100_000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&path, contents).unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let mut file = tokio::fs::File::open(&path).await.unwrap();
+        let chunks = chunks(&mut file).await.unwrap();
+        assert!(chunks.len() > 10);
+        assert_eq!(chunks[0].start_line, 1);
+        assert_eq!(chunks.last().unwrap().end_line, 100_000);
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[1].start_line, pair[0].end_line + 1 - OVERLAP_LINES);
+        }
+
+        let output = get_chunk(GetChunkInput {
+            path: path.clone(),
+            chunk: 2,
+        })
+        .await
+        .unwrap();
+        let header = format!(
+            "[{}, chunk 2 of {}, lines {}–{}]\nline {}\n",
+            path,
+            chunks.len(),
+            chunks[1].start_line,
+            chunks[1].end_line,
+            chunks[1].start_line
+        );
+        assert!(output.starts_with(&header), "{}", &output[..200]);
+        assert!(output.ends_with(&format!("\nline {}", chunks[1].end_line)));
+
+        let err = get_chunk(GetChunkInput { path, chunk: 0 })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("numbered from 1"));
+    }
+
+    #[tokio::test]
+    async fn a_file_on_one_line_is_split_between_characters() {
+        let dir = TempDir::new("tars-chunk");
+        let path = dir.join("min.json");
+        // Three-byte characters, so the byte limits fall inside them.
+        let contents = "日本語".repeat(200_000);
+        std::fs::write(&path, &contents).unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let mut file = tokio::fs::File::open(&path).await.unwrap();
+        let chunks = chunks(&mut file).await.unwrap();
+        assert!(chunks.len() > 10);
+        assert_eq!(chunks.last().unwrap().end, contents.len() as u64);
+        let mut joined = String::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert_eq!((chunk.start_line, chunk.end_line), (1, 1));
+            assert!(chunk.end - chunk.start <= MAX_CHUNK_CHARS as u64);
+            let output = read_chunk(&mut file, &path, &chunks, index).await.unwrap();
+            let (_, text) = output.split_once('\n').unwrap();
+            joined.push_str(text);
+        }
+        assert_eq!(joined, contents);
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use super::normalize_path;
+use crate::config::GuardConfig;
+
+/// The key a breach of the limits is approved under, apart from its tool,
+/// so that allowing a tool for the session doesn't also allow mass changes.
+pub(crate) const GUARD: &str = "guard";
+
+/// A call the `[guard]` limits count.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Change<'a> {
+    /// A change to the file at `path`.
+    File { path: &'a str, removed_lines: usize },
+    /// A shell command, which may change any number of files and counts as
+    /// one more each time.
+    Command,
+}
+
+impl<'a> Change<'a> {
+    /// What a call to `tool` that can change the workspace changes, if
+    /// anything the limits count.
+    pub(crate) fn of(tool: &str, input: &'a serde_json::Value) -> Option<Self> {
+        if matches!(tool, "bash" | "start_process") {
+            return Some(Change::Command);
+        }
+        let path = input["path"].as_str()?;
+        Some(Change::File {
+            path,
+            removed_lines: removed_lines(input),
+        })
+    }
+}
+
+/// What the current turn has changed so far, checked against the
+/// `[guard]` limits before each change so a runaway turn stops and waits
+/// for the user instead of rewriting half the workspace.
+#[derive(Debug, Default)]
+pub(crate) struct TurnEdits {
+    files: BTreeSet<PathBuf>,
+    commands: usize,
+    removed_lines: usize,
+}
+
+impl TurnEdits {
+    /// Forgets the previous turn's changes.
+    pub(crate) fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Whether `change` stays within `limits`, or why it doesn't.
+    pub(crate) fn check(&self, limits: &GuardConfig, change: Change) -> Result<(), String> {
+        let (counted, removed_lines) = match change {
+            Change::File {
+                path,
+                removed_lines,
+            } => (self.files.contains(&normalize_path(path)), removed_lines),
+            Change::Command => (false, 0),
+        };
+        let changes = self.files.len() + self.commands;
+        if limits.max_files > 0 && !counted && changes >= limits.max_files {
+            return Err(format!(
+                "this turn has already changed {} files or run {} commands, the most one turn \
+                 may change without asking",
+                self.files.len(),
+                self.commands
+            ));
+        }
+        if limits.max_removed_lines > 0
+            && self.removed_lines + removed_lines > limits.max_removed_lines
+        {
+            return Err(format!(
+                "this change would bring the lines removed this turn to {}, over the limit of {}",
+                self.removed_lines + removed_lines,
+                limits.max_removed_lines
+            ));
+        }
+        Ok(())
+    }
+
+    /// Counts `change` toward the limits, whether or not it was within them.
+    pub(crate) fn record(&mut self, change: Change) {
+        match change {
+            Change::File {
+                path,
+                removed_lines,
+            } => {
+                self.files.insert(normalize_path(path));
+                self.removed_lines += removed_lines;
+            }
+            Change::Command => self.commands += 1,
+        }
+    }
+}
+
+/// What the model is told when a change past the limits can't be
+/// confirmed, because nobody is there to ask or the user said no.
+pub(crate) fn refusal(reason: &str) -> String {
+    format!(
+        "Refused: {}. Don't try to get around this. Stop here, tell the user what you still \
+         mean to change and why, and ask them to confirm; once they reply you may continue.",
+        reason
+    )
+}
+
+/// How many lines an edit-style call takes out, counting every line it
+/// replaces, so that overwriting a file counts as much as deleting it.
+pub(crate) fn removed_lines(input: &serde_json::Value) -> usize {
+    input["old_str"]
+        .as_str()
+        .map_or(0, |text| text.lines().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_stop_at_the_limits() {
+        let limits = GuardConfig {
+            max_files: 2,
+            max_removed_lines: 10,
+        };
+        let mut edits = TurnEdits::default();
+        let file = |path, removed_lines| Change::File {
+            path,
+            removed_lines,
+        };
+        for change in [file("a.rs", 4), file("./b.rs", 0), file("a.rs", 6)] {
+            edits.check(&limits, change).unwrap();
+            edits.record(change);
+        }
+        // Files already changed this turn don't count again.
+        edits.check(&limits, file("b.rs", 0)).unwrap();
+        assert!(edits
+            .check(&limits, file("c.rs", 0))
+            .unwrap_err()
+            .contains("2 files or run 0 commands"));
+        assert!(edits
+            .check(&limits, file("b.rs", 1))
+            .unwrap_err()
+            .contains("to 11"));
+
+        // Confirmed changes past the limits still count.
+        edits.record(file("c.rs", 1));
+        edits.record(Change::Command);
+        assert!(edits
+            .check(&limits, Change::Command)
+            .unwrap_err()
+            .contains("3 files or run 1 commands"));
+
+        edits.reset();
+        edits.check(&limits, file("c.rs", 10)).unwrap();
+
+        let unlimited = GuardConfig {
+            max_files: 0,
+            max_removed_lines: 0,
+        };
+        assert!(edits.check(&unlimited, file("d.rs", 1000)).is_ok());
+    }
+
+    #[test]
+    fn commands_and_replaced_lines_count() {
+        let bash = serde_json::json!({"command": "rm -rf src"});
+        assert!(matches!(Change::of("bash", &bash), Some(Change::Command)));
+        let server = serde_json::json!({"command": "npm run dev"});
+        assert!(matches!(
+            Change::of("start_process", &server),
+            Some(Change::Command)
+        ));
+        assert!(Change::of("save_artifact", &serde_json::json!({})).is_none());
+
+        // Rewriting lines removes them as much as deleting them does.
+        let edit = serde_json::json!({"path": "a.rs", "old_str": "a\nb\nc", "new_str": "x\ny\nz"});
+        assert!(matches!(
+            Change::of("edit_file", &edit),
+            Some(Change::File {
+                path: "a.rs",
+                removed_lines: 3
+            })
+        ));
+        let create =
+            serde_json::json!({"path": "new.rs", "old_str": "", "new_str": "fn main() {}"});
+        assert_eq!(removed_lines(&create), 0);
+    }
+}
@@ -0,0 +1,176 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+use super::{format_size, group_thousands, input_path, not_a_file, ToolDefinition};
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Algorithm {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// A digest someone gave, lowercased and without the `sha256:` or
+    /// `sha256-` prefix lockfiles put on it, for comparing with `digest`'s.
+    pub(super) fn expected(self, digest: &str) -> String {
+        let digest = digest.trim().to_ascii_lowercase();
+        match digest.strip_prefix(self.name()) {
+            Some(rest) => rest.trim_start_matches([':', '-', '=']).to_string(),
+            None => digest,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct HashFileInput {
+    #[schemars(description = "The relative path of the file to hash")]
+    path: String,
+    #[schemars(description = "sha256 (the default) or blake3")]
+    #[serde(default)]
+    algorithm: Algorithm,
+    #[schemars(
+        description = "Optional hex digest to compare against, e.g. from a checksums file or lockfile"
+    )]
+    expected: Option<String>,
+}
+
+/// Hashes the file in chunks, so large downloads aren't read into memory.
+pub(super) fn digest(path: &Path, algorithm: Algorithm) -> std::io::Result<(String, u64)> {
+    let mut file = std::fs::File::open(path)?;
+    let mut sha256 = Sha256::new();
+    let mut blake3 = blake3::Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        size += read as u64;
+        match algorithm {
+            Algorithm::Sha256 => sha256.update(&buffer[..read]),
+            Algorithm::Blake3 => {
+                blake3.update(&buffer[..read]);
+            }
+        }
+    }
+    let hex = match algorithm {
+        Algorithm::Sha256 => sha256
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+        Algorithm::Blake3 => blake3.finalize().to_hex().to_string(),
+    };
+    Ok((hex, size))
+}
+
+fn hash_file(input: HashFileInput) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let metadata = std::fs::metadata(&input.path)?;
+    if !metadata.is_file() {
+        return Err(not_a_file(&input.path, &metadata).into());
+    }
+    let (hex, size) = digest(Path::new(&input.path), input.algorithm)?;
+    let mut lines = vec![format!("{}: {}", input.algorithm.name(), hex)];
+    lines.push(if size < 1024 {
+        format!("size: {}", format_size(size))
+    } else {
+        format!(
+            "size: {} ({} bytes)",
+            format_size(size),
+            group_thousands(size)
+        )
+    });
+    if let Some(expected) = input.expected {
+        let expected = input.algorithm.expected(&expected);
+        if expected == hex {
+            lines.push("matches the expected digest".to_string());
+        } else {
+            lines.push(format!("does NOT match the expected digest {}", expected));
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "hash_file",
+        description: "Compute a file's sha256 or blake3 digest and size, e.g. to verify a download or a lockfile entry. Pass `expected` to have the digest compared for you.",
+        input_schema: serde_json::to_value(schema_for!(HashFileInput)).unwrap(),
+        handler: |input, _| {
+            Box::pin(async move {
+                let input: HashFileInput = serde_json::from_value(input)?;
+                tokio::task::spawn_blocking(move || hash_file(input)).await?
+            })
+        },
+        read_only: true,
+        opt_in: None,
+        summarize: |input, output| {
+            let verdict = if output.is_some_and(|o| o.contains("does NOT match")) {
+                " (mismatch)"
+            } else {
+                ""
+            };
+            format!("#️⃣ hash {}{}", input_path(input), verdict)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn digests_match_known_values() {
+        let dir = TempDir::new("tars-hash");
+        let file = dir.join("abc.txt");
+        std::fs::write(&file, "abc").unwrap();
+        let path = file.to_str().unwrap().to_string();
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+        let output = hash_file(HashFileInput {
+            path: path.clone(),
+            algorithm: Algorithm::Sha256,
+            expected: Some(format!("sha256:{}", sha256.to_uppercase())),
+        })
+        .unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "sha256: {}\nsize: 3 bytes\nmatches the expected digest",
+                sha256
+            )
+        );
+
+        let output = hash_file(HashFileInput {
+            path,
+            algorithm: Algorithm::Blake3,
+            expected: Some(sha256.to_string()),
+        })
+        .unwrap();
+        assert!(output.starts_with(
+            "blake3: 6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85\n"
+        ));
+        assert!(output.ends_with(&format!("does NOT match the expected digest {}", sha256)));
+
+        assert!(hash_file(HashFileInput {
+            path: dir.to_str().unwrap().to_string(),
+            algorithm: Algorithm::Sha256,
+            expected: None,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+use super::ToolDefinition;
+use crate::index;
+
+const DEFAULT_LIMIT: usize = 8;
+const MAX_LIMIT: usize = 30;
+/// Lines shown per result, preferring those with the query's words.
+const SHOWN_LINES: usize = 6;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct KeywordSearchInput {
+    #[schemars(
+        description = "What you are looking for in plain words or identifiers, e.g. `where are failed requests retried`"
+    )]
+    query: String,
+    #[schemars(description = "Optional relative directory or file to search within")]
+    path: Option<String>,
+    #[schemars(description = "How many results to return. Defaults to 8, at most 30.")]
+    limit: Option<usize>,
+}
+
+fn keyword_search(
+    input: KeywordSearchInput,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let limit = input.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let hits =
+        index::with_current(|index, _| index.search(&input.query, input.path.as_deref(), limit))?;
+    if hits.is_empty() {
+        return Ok(format!(
+            "Nothing in the index matches {:?}; try other words, or search_files for an exact pattern",
+            input.query
+        ));
+    }
+
+    let mut sections = Vec::new();
+    for hit in hits {
+        let text = std::fs::read_to_string(&hit.path).unwrap_or_default();
+        let lines: Vec<(usize, &str)> = text
+            .lines()
+            .enumerate()
+            .skip(hit.start_line - 1)
+            .take(hit.end_line + 1 - hit.start_line)
+            .collect();
+        let mentions = |line: &str| {
+            let line = line.to_lowercase();
+            hit.terms.iter().any(|term| line.contains(term.as_str()))
+        };
+        let mut shown: Vec<&(usize, &str)> = lines
+            .iter()
+            .filter(|(_, line)| mentions(line))
+            .take(SHOWN_LINES)
+            .collect();
+        if shown.is_empty() {
+            shown = lines.iter().take(SHOWN_LINES).collect();
+        }
+        let mut section = vec![format!(
+            "{}:{}-{} (score {:.1})",
+            hit.path, hit.start_line, hit.end_line, hit.score
+        )];
+        section.extend(
+            shown
+                .into_iter()
+                .map(|(number, line)| format!("{:>5}: {}", number + 1, line.trim_end())),
+        );
+        sections.push(section.join("\n"));
+    }
+    Ok(sections.join("\n\n"))
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "keyword_search",
+        description: "Find the code most related to a description, ranked, when you don't know the exact names to grep for. Searches an index of the workspace by keyword, matching the words in identifiers and comments (`retry` finds `RetryPolicy` and `retry_after`), and returns each match's line range with its most relevant lines; read_file the range for more.",
+        input_schema: serde_json::to_value(schema_for!(KeywordSearchInput)).unwrap(),
+        handler: |input, _| {
+            Box::pin(async move {
+                let input: KeywordSearchInput = serde_json::from_value(input)?;
+                tokio::task::spawn_blocking(move || keyword_search(input)).await?
+            })
+        },
+        read_only: true,
+        opt_in: None,
+        summarize: |input, output| {
+            let query = input["query"].as_str().unwrap_or_default();
+            match output {
+                Some(output) => {
+                    let results = output
+                        .lines()
+                        .filter(|line| line.ends_with(')') && line.contains(" (score "))
+                        .count();
+                    format!("🧭 keyword search {:?} ({} results)", query, results)
+                }
+                None => format!("🧭 keyword search {:?}", query),
+            }
+        },
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Refuses a tool path inside the workspace at `root` that reaches outside
+/// it through a symlink, so a link planted in the repository can't point
+/// the tools at `~/.ssh` or `/etc`. Paths that name somewhere outside the
+/// workspace directly aren't this check's concern.
+pub(crate) fn check_links(root: &Path, path: &str) -> Result<(), String> {
+    let Ok(real_root) = root.canonicalize() else {
+        return Ok(());
+    };
+    let Some(lexical) = lexical_join(root, Path::new(path)) else {
+        return Ok(());
+    };
+    if !lexical.starts_with(root) {
+        return Ok(());
+    }
+
+    // The nearest existing ancestor decides where the path really is; the
+    // rest doesn't exist yet and so can't be a link.
+    let mut existing = lexical.as_path();
+    let mut rest = Vec::new();
+    let real = loop {
+        match existing.canonicalize() {
+            Ok(real) => break real,
+            // A dangling link would be followed when written to.
+            Err(_) if existing.is_symlink() => {
+                return Err(format!("{} is a broken symlink; it isn't followed", path));
+            }
+            Err(_) => match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    rest.push(name);
+                    existing = parent;
+                }
+                _ => return Ok(()),
+            },
+        }
+    };
+    let real: PathBuf = real.iter().chain(rest.into_iter().rev()).collect();
+    if real.starts_with(&real_root) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} leads outside the workspace through a symlink (to {}); it isn't followed",
+            path,
+            real.display()
+        ))
+    }
+}
+
+/// `path` resolved against `root` without touching the filesystem, with
+/// `.` and `..` applied. `None` if `..` climbs above the filesystem root.
+fn lexical_join(root: &Path, path: &Path) -> Option<PathBuf> {
+    let mut joined = PathBuf::new();
+    for component in root.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !joined.pop() {
+                    return None;
+                }
+            }
+            other => joined.push(other),
+        }
+    }
+    Some(joined)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn links_out_of_the_workspace_are_refused() {
+        let dir = TempDir::new("tars-links");
+        let root = dir.join("workspace");
+        let outside = dir.join("outside");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret"), "").unwrap();
+        symlink(&outside, root.join("escape")).unwrap();
+        symlink(root.join("src"), root.join("alias")).unwrap();
+
+        assert!(check_links(&root, "src/main.rs").is_ok());
+        assert!(check_links(&root, "./alias/new/file.rs").is_ok());
+        assert!(check_links(&root, "escape").is_err());
+        assert!(check_links(&root, "escape/secret").is_err());
+        assert!(check_links(&root, "escape/not/yet/there.txt").is_err());
+        assert!(check_links(&root, "src/../escape/secret").is_err());
+        symlink(dir.join("missing"), root.join("dangling")).unwrap();
+        assert!(check_links(&root, "dangling").is_err());
+        // Naming an outside path directly is a different matter.
+        assert!(check_links(&root, outside.join("secret").to_str().unwrap()).is_ok());
+        assert!(check_links(&root, "../outside/secret").is_ok());
+    }
+}
@@ -1,44 +1,178 @@
+use ignore::WalkBuilder;
 use schemars::{schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
-use super::ToolDefinition;
+use super::{group_thousands, input_path, ToolDefinition};
+
+const DEFAULT_LIMIT: usize = 200;
+const MAX_LIMIT: usize = 1000;
 
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 struct ListFilesInput {
     #[schemars(description = "Optional relative path to list files from. Defaults to current directory if not provided.")]
     #[serde(default)]
     path: String,
+    #[schemars(description = "How many directory levels to descend. Defaults to 1 (only the immediate children).")]
+    #[serde(default)]
+    depth: Option<usize>,
+    #[schemars(description = "Maximum number of entries to return. Defaults to 200, capped at 1000.")]
+    #[serde(default)]
+    limit: Option<usize>,
+    #[schemars(description = "Number of entries to skip, taken from the previous call's continuation hint.")]
+    #[serde(default)]
+    cursor: Option<usize>,
 }
 
 async fn list_files_impl(
     input: serde_json::Value,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
     let input: ListFilesInput = serde_json::from_value(input)?;
+    tokio::task::spawn_blocking(move || list(input)).await?
+}
+
+fn list(input: ListFilesInput) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
     let dir = if input.path.is_empty() { "." } else { &input.path };
+    let root = Path::new(dir);
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", dir).into());
+    }
 
-    let mut files = Vec::new();
-    let mut entries = tokio::fs::read_dir(dir).await?;
+    let depth = input.depth.unwrap_or(1).max(1);
+    let limit = input.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let cursor = input.cursor.unwrap_or(0);
 
-    while let Some(entry) = entries.next_entry().await? {
-        let path = entry.file_name();
-        let path_str = path.to_string_lossy().to_string();
+    // Entries are visited in sorted order and only the requested window is
+    // materialized; the rest are merely counted for the summary line.
+    let walker = WalkBuilder::new(root)
+        .max_depth(Some(depth))
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .build();
 
-        if entry.file_type().await?.is_dir() {
+    let mut files = Vec::new();
+    let mut total = 0usize;
+    let mut unreadable = Vec::new();
+    for entry in walker {
+        // One unreadable directory shouldn't hide the rest of the tree.
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                unreadable.push(err.to_string());
+                continue;
+            }
+        };
+        if entry.depth() == 0 {
+            continue;
+        }
+        // Sockets, pipes and devices can't be read as files.
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        if !(file_type.is_file() || file_type.is_dir() || file_type.is_symlink()) {
+            continue;
+        }
+        total += 1;
+        if total <= cursor || files.len() >= limit {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let path_str = relative.to_string_lossy().to_string();
+        if file_type.is_dir() {
             files.push(format!("{}/", path_str));
         } else {
             files.push(path_str);
         }
     }
 
-    files.sort();
+    let shown_until = cursor + files.len();
+    if let Some(first) = unreadable.first() {
+        files.push(format!(
+            "…skipped {} unreadable entr{} ({})",
+            unreadable.len(),
+            if unreadable.len() == 1 { "y" } else { "ies" },
+            first
+        ));
+    }
+    if shown_until < total {
+        files.push(format!(
+            "…and {} more (pass cursor: {} to continue)",
+            group_thousands((total - shown_until) as u64),
+            shown_until
+        ));
+    }
+
     serde_json::to_string(&files).map_err(|e| e.into())
 }
 
 pub(crate) fn definition() -> ToolDefinition {
     ToolDefinition {
         name: "list_files",
-        description: "List files and directories at a given path. If no path is provided, lists files in the current directory.",
+        description: "List files and directories at a given path. If no path is provided, lists files in the current directory. Entries ignored by .gitignore are skipped. Large listings are truncated with a summary line; pass the cursor it mentions to see more. Sockets, pipes and devices are left out, and unreadable entries are skipped with a note.",
         input_schema: serde_json::to_value(schema_for!(ListFilesInput)).unwrap(),
-        handler: |input| Box::pin(list_files_impl(input)),
+        handler: |input, _| Box::pin(list_files_impl(input)),
+        read_only: true,
+        opt_in: None,
+        summarize: |input, output| {
+            let path = input_path(input);
+            let Some(entries) = output.and_then(|o| serde_json::from_str::<Vec<String>>(o).ok())
+            else {
+                return format!("📂 list {}", path);
+            };
+            let shown = entries.iter().filter(|entry| !entry.starts_with('…')).count();
+            if entries.iter().any(|entry| entry.starts_with("…and")) {
+                format!("📂 list {} ({}+ entries)", path, shown)
+            } else {
+                format!("📂 list {} ({} entries)", path, shown)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn list_pages_large_directories_with_a_cursor() {
+        let root = TempDir::new("tars-list");
+        std::fs::create_dir_all(root.join("nested")).unwrap();
+        for i in 0..5 {
+            std::fs::write(root.join(format!("file_{}.txt", i)), "").unwrap();
+        }
+        std::fs::write(root.join("nested/inner.txt"), "").unwrap();
+
+        let page = |cursor: Option<usize>, depth: Option<usize>| -> Vec<String> {
+            let output = list(ListFilesInput {
+                path: root.to_string_lossy().to_string(),
+                depth,
+                limit: Some(4),
+                cursor,
+            })
+            .unwrap();
+            serde_json::from_str(&output).unwrap()
+        };
+
+        let first = page(None, None);
+        assert_eq!(first.len(), 5);
+        assert_eq!(first[4], "…and 2 more (pass cursor: 4 to continue)");
+
+        let second = page(Some(4), None);
+        assert_eq!(second, vec!["file_4.txt", "nested/"]);
+
+        let deep = page(Some(4), Some(2));
+        assert_eq!(deep, vec!["file_4.txt", "nested/", "nested/inner.txt"]);
+
+        // Sockets and other special files are left out.
+        #[cfg(unix)]
+        {
+            let _socket = std::os::unix::net::UnixListener::bind(root.join("nested/sock")).unwrap();
+            assert_eq!(page(Some(5), Some(2)), vec!["nested/", "nested/inner.txt"]);
+        }
+
+        assert_eq!(group_thousands(3112), "3,112");
     }
 }
@@ -0,0 +1,92 @@
+use std::io;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+
+/// What a child process printed, for passing back to the model.
+pub(crate) struct ProcessOutput {
+    pub(crate) status: ExitStatus,
+    pub(crate) stdout: Vec<u8>,
+    pub(crate) stderr: Vec<u8>,
+}
+
+impl ProcessOutput {
+    /// Stderr then stdout as text, cut off after `max_bytes`.
+    pub(crate) fn report(&self, max_bytes: usize) -> String {
+        let mut report = String::from_utf8_lossy(&self.stderr).into_owned();
+        report.push_str(&String::from_utf8_lossy(&self.stdout));
+        if report.len() > max_bytes {
+            let mut end = max_bytes;
+            while !report.is_char_boundary(end) {
+                end -= 1;
+            }
+            report.truncate(end);
+            report.push_str("\n…");
+        }
+        report
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum RunError {
+    /// The program is not installed.
+    NotFound,
+    TimedOut,
+    Io(io::Error),
+}
+
+/// Runs `args` for a tool. A child never shares the terminal: its stdin is
+/// closed and both output streams are captured, so nothing it prints can
+/// land on the TUI's screen. It is killed if it outlives `timeout`.
+pub(crate) async fn run_captured(
+    args: &[String],
+    timeout: Duration,
+) -> Result<ProcessOutput, RunError> {
+    let (program, args) = args.split_first().ok_or(RunError::NotFound)?;
+    let mut command = tokio::process::Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    match tokio::time::timeout(timeout, command.output()).await {
+        Ok(Ok(output)) => Ok(ProcessOutput {
+            status: output.status,
+            stdout: output.stdout,
+            stderr: output.stderr,
+        }),
+        Ok(Err(err)) if err.kind() == io::ErrorKind::NotFound => Err(RunError::NotFound),
+        Ok(Err(err)) => Err(RunError::Io(err)),
+        Err(_) => Err(RunError::TimedOut),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn output_is_captured_never_inherited() {
+        // `cat` would wait for the terminal if stdin were inherited.
+        let script = "echo out; echo err >&2; cat; exit 3";
+        let output = run_captured(&args(&["sh", "-c", script]), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.report(100), "err\nout\n");
+        assert_eq!(output.report(2), "er\n…");
+
+        assert!(matches!(
+            run_captured(&args(&["no-such-program-tars"]), Duration::from_secs(5)).await,
+            Err(RunError::NotFound)
+        ));
+        assert!(matches!(
+            run_captured(&args(&["sleep", "5"]), Duration::from_millis(50)).await,
+            Err(RunError::TimedOut)
+        ));
+    }
+}
@@ -0,0 +1,112 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::process::{run_captured, RunError};
+use super::{text, OptIn, ToolDefinition};
+
+pub(crate) const NAME: &str = "read_clipboard";
+
+const CLIPBOARD_TIMEOUT: Duration = Duration::from_secs(5);
+/// Clipboards larger than this keep their start and end, where a stack
+/// trace's error and its innermost frames are.
+const MAX_CLIPBOARD_BYTES: usize = 100 * 1024;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct ReadClipboardInput {}
+
+/// Programs that print the clipboard as text, in the order they are tried.
+fn paste_commands() -> Vec<Vec<&'static str>> {
+    if cfg!(target_os = "macos") {
+        vec![vec!["pbpaste"]]
+    } else if cfg!(windows) {
+        vec![vec![
+            "powershell",
+            "-NoProfile",
+            "-Command",
+            "Get-Clipboard -Raw",
+        ]]
+    } else {
+        let mut commands = Vec::new();
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            commands.push(vec!["wl-paste", "--no-newline", "--type", "text"]);
+        }
+        commands.push(vec!["xclip", "-selection", "clipboard", "-out"]);
+        commands.push(vec!["xsel", "--clipboard", "--output"]);
+        commands
+    }
+}
+
+async fn read_clipboard_impl(
+    input: serde_json::Value,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let _: ReadClipboardInput = serde_json::from_value(input)?;
+    for command in paste_commands() {
+        let args: Vec<String> = command.iter().map(|arg| arg.to_string()).collect();
+        match run_captured(&args, CLIPBOARD_TIMEOUT).await {
+            Ok(output) if output.status.success() => return Ok(clipboard_text(&output.stdout)),
+            Ok(output) => {
+                return Err(format!(
+                    "`{}` failed ({}): {}",
+                    args[0],
+                    output.status,
+                    output.report(1000).trim_end()
+                )
+                .into());
+            }
+            Err(RunError::NotFound) => continue,
+            Err(RunError::TimedOut) => {
+                return Err(format!("`{}` did not answer in time", args[0]).into());
+            }
+            Err(RunError::Io(err)) => {
+                return Err(format!("Could not run `{}`: {}", args[0], err).into());
+            }
+        }
+    }
+    Err("No clipboard program is installed (wl-paste, xclip or xsel)".into())
+}
+
+fn clipboard_text(bytes: &[u8]) -> String {
+    if bytes.iter().all(u8::is_ascii_whitespace) {
+        return "The clipboard is empty, or holds something other than text".to_string();
+    }
+    text::keep_ends(&text::decode(bytes).text, MAX_CLIPBOARD_BYTES)
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: NAME,
+        description: "Read the text on the user's clipboard, e.g. an error they just copied. The user is asked to approve each read unless they allowed it for the session.",
+        input_schema: serde_json::to_value(schema_for!(ReadClipboardInput)).unwrap(),
+        handler: |input, _| Box::pin(read_clipboard_impl(input)),
+        read_only: true,
+        opt_in: Some(OptIn::Clipboard),
+        summarize: |_, output| match output {
+            Some(output) => format!("📋 read clipboard ({} lines)", output.lines().count()),
+            None => "📋 read clipboard".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_clipboard_text_keeps_both_ends() {
+        assert_eq!(
+            clipboard_text(b"panic at main.rs:3\n"),
+            "panic at main.rs:3\n"
+        );
+        assert!(clipboard_text(b" \n").starts_with("The clipboard is empty"));
+        let trace = format!(
+            "Error: boom\n{}  at innermost",
+            "  at frame\n".repeat(20_000)
+        );
+        let shown = clipboard_text(trace.as_bytes());
+        assert!(shown.len() < trace.len());
+        assert!(shown.starts_with("Error: boom\n"));
+        assert!(shown.ends_with("  at innermost"));
+        assert!(shown.contains(" left out …]"));
+    }
+}
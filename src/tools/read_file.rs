@@ -1,7 +1,20 @@
 use schemars::{schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
-use super::ToolDefinition;
+use super::get_chunk::{chunks, read_chunk, OVERLAP_LINES};
+use super::{format_size, input_path, not_a_file, text, ToolDefinition};
+
+/// Files larger than this are split into chunks, and only the first is
+/// returned.
+const MAX_READ_BYTES: u64 = 256 * 1024;
+/// Chunks whose line ranges are listed with the first; beyond that only
+/// their number is given.
+const LISTED_CHUNKS: usize = 40;
+const SNIFF_BYTES: usize = 8 * 1024;
+const EXCERPT_LINES: usize = 100;
+const EXCERPT_BYTES: usize = 16 * 1024;
 
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 struct ReadFileInput {
@@ -13,16 +26,153 @@ async fn read_file_impl(
     input: serde_json::Value,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
     let input: ReadFileInput = serde_json::from_value(input)?;
-    tokio::fs::read_to_string(&input.path)
+    read(&input.path)
         .await
         .map_err(|e| format!("Error reading file: {}", e).into())
 }
 
+async fn read(path: &str) -> std::io::Result<String> {
+    // Opening a pipe would wait for a writer forever.
+    let metadata = tokio::fs::metadata(path).await?;
+    if !metadata.is_file() {
+        return Err(std::io::Error::other(not_a_file(path, &metadata)));
+    }
+    let mut file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+
+    let mut sniff = vec![0; SNIFF_BYTES.min(size as usize)];
+    file.read_exact(&mut sniff).await?;
+    if sniff.contains(&0) && !text::is_utf16(&sniff) {
+        return Ok(format!(
+            "[{} is a binary file ({}); its contents are not shown]",
+            path,
+            format_size(size)
+        ));
+    }
+
+    if size <= MAX_READ_BYTES {
+        file.rewind().await?;
+        let mut contents = Vec::with_capacity(size as usize);
+        file.read_to_end(&mut contents).await?;
+        return Ok(text::decode(&contents).text);
+    }
+    // UTF-16 line breaks are two bytes, which chunks don't split on.
+    if text::is_utf16(&sniff) {
+        return excerpt(&mut file, path, size).await;
+    }
+
+    let chunks = chunks(&mut file).await?;
+    let mut output = format!(
+        "[{} is too large to read at once: {}, {} lines, split into {} chunks that overlap by up to {} lines. This is the first; get_chunk reads the others.]\n",
+        path,
+        format_size(size),
+        chunks.last().map_or(0, |chunk| chunk.end_line),
+        chunks.len(),
+        OVERLAP_LINES
+    );
+    if chunks.len() <= LISTED_CHUNKS {
+        let ranges: Vec<String> = chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| format!("{}: {}–{}", index + 1, chunk.start_line, chunk.end_line))
+            .collect();
+        output.push_str(&format!("[Chunk lines: {}]\n", ranges.join(", ")));
+    }
+    output.push_str(&read_chunk(&mut file, path, &chunks, 0).await?);
+    Ok(output)
+}
+
+/// Summarizes a large UTF-16 file using only bounded reads: the line count is
+/// gathered in fixed-size chunks, and the excerpts come from the first and
+/// last few kilobytes.
+async fn excerpt(file: &mut tokio::fs::File, path: &str, size: u64) -> std::io::Result<String> {
+    file.rewind().await?;
+    let mut lines = 0usize;
+    let mut chunk = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        lines += chunk[..read].iter().filter(|&&b| b == b'\n').count();
+    }
+
+    file.rewind().await?;
+    let mut head = vec![0; EXCERPT_BYTES];
+    file.read_exact(&mut head).await?;
+    let head = text::decode(&head);
+    let encoding = head.format.encoding;
+    let head: Vec<&str> = head.text.lines().take(EXCERPT_LINES).collect();
+
+    file.seek(SeekFrom::End(-(EXCERPT_BYTES as i64))).await?;
+    let mut tail = vec![0; EXCERPT_BYTES];
+    file.read_exact(&mut tail).await?;
+    let (tail, _) = encoding.decode_without_bom_handling(&tail);
+    // The first line of the tail window is most likely partial.
+    let tail: Vec<&str> = tail.lines().skip(1).collect();
+    let tail = &tail[tail.len().saturating_sub(EXCERPT_LINES)..];
+
+    let omitted = lines.saturating_sub(head.len() + tail.len());
+    Ok(format!(
+        "[{} is too large to read in full: {}, {} lines. Showing the first {} and last {} lines.]\n{}\n[... {} lines omitted ...]\n{}",
+        path,
+        format_size(size),
+        lines,
+        head.len(),
+        tail.len(),
+        head.join("\n"),
+        omitted,
+        tail.join("\n")
+    ))
+}
+
 pub(crate) fn definition() -> ToolDefinition {
     ToolDefinition {
         name: "read_file",
-        description: "Read the contents of a given relative file path. Use this when you want to see what's inside a file. Do not use this with directory names.",
+        description: "Read the contents of a given relative file path. Use this when you want to see what's inside a file. Do not use this with directory names. Binary files are reported by size only. Files over 256 KB are split into overlapping chunks of about 48 KB: you get the first and their line ranges, and get_chunk reads the others.",
         input_schema: serde_json::to_value(schema_for!(ReadFileInput)).unwrap(),
-        handler: |input| Box::pin(read_file_impl(input)),
+        handler: |input, _| Box::pin(read_file_impl(input)),
+        read_only: true,
+        opt_in: None,
+        summarize: |input, output| {
+            let path = input_path(input);
+            match output {
+                Some(output) if output.starts_with('[') && output.contains("is a binary file") => {
+                    format!("📖 read {} (binary)", path)
+                }
+                Some(output) if output.starts_with('[') && output.contains(" chunks that overlap") => {
+                    format!("📖 read {} (chunk 1)", path)
+                }
+                Some(output) => format!("📖 read {} ({} lines)", path, output.lines().count()),
+                None => format!("📖 read {}", path),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[tokio::test]
+    async fn read_chunks_large_files_and_summarizes_binary_ones() {
+        let dir = TempDir::new("tars-read");
+
+        let big = dir.join("big.log");
+        let contents: String = (1..=100_000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&big, contents).unwrap();
+        let output = read(big.to_str().unwrap()).await.unwrap();
+        assert!(output.contains("100000 lines, split into"));
+        assert!(output.contains("[Chunk lines: 1: 1–"));
+        assert!(output.contains(", chunk 1 of "));
+        assert!(output.contains("]\nline 1\nline 2\n"));
+        assert!(!output.contains("line 100000"));
+        assert!(output.len() < 64 * 1024);
+
+        let binary = dir.join("blob.bin");
+        std::fs::write(&binary, [0u8, 159, 146, 150]).unwrap();
+        let output = read(binary.to_str().unwrap()).await.unwrap();
+        assert!(output.contains("binary file (4 bytes)"));
     }
 }
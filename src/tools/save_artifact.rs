@@ -0,0 +1,149 @@
+use std::path::Path;
+#[cfg(feature = "server")]
+use std::path::PathBuf;
+
+use base64::Engine;
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+use super::{format_size, ToolContext, ToolDefinition};
+#[cfg(feature = "server")]
+use crate::protocol::ArtifactInfo;
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum Encoding {
+    #[default]
+    Text,
+    Base64,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct SaveArtifactInput {
+    #[schemars(description = "File name for the artifact, e.g. report.md; no directories")]
+    name: String,
+    #[schemars(description = "The artifact's contents")]
+    content: String,
+    #[serde(default)]
+    #[schemars(description = "base64 for binary outputs such as images; text by default")]
+    encoding: Encoding,
+}
+
+/// Where a session's artifacts are kept.
+#[cfg(feature = "server")]
+pub(crate) fn artifacts_dir(session_id: &str) -> PathBuf {
+    Path::new(".tars").join("artifacts").join(session_id)
+}
+
+/// The artifacts saved in `dir`, by name. A missing directory has none.
+#[cfg(feature = "server")]
+pub(crate) fn list_artifacts(dir: &Path) -> std::io::Result<Vec<ArtifactInfo>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut artifacts = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        artifacts.push(ArtifactInfo {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            path: entry.path().display().to_string(),
+            size: metadata.len(),
+        });
+    }
+    artifacts.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(artifacts)
+}
+
+async fn save_artifact_impl(
+    input: serde_json::Value,
+    context: ToolContext,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let input: SaveArtifactInput = serde_json::from_value(input)?;
+    let dir = context
+        .artifacts
+        .ok_or("artifacts can only be saved in a server session")?;
+    save(&dir, input).await
+}
+
+async fn save(
+    dir: &Path,
+    input: SaveArtifactInput,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let name = input.name.trim();
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.contains(['/', '\\'])
+        || Path::new(name).is_absolute()
+    {
+        return Err(format!("{:?} is not a plain file name", input.name).into());
+    }
+    let bytes = match input.encoding {
+        Encoding::Text => input.content.into_bytes(),
+        Encoding::Base64 => base64::engine::general_purpose::STANDARD
+            .decode(input.content.trim())
+            .map_err(|err| format!("content is not valid base64: {}", err))?,
+    };
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(name);
+    tokio::fs::write(&path, &bytes).await?;
+    Ok(format!(
+        "Saved {} ({})",
+        path.display(),
+        format_size(bytes.len() as u64)
+    ))
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "save_artifact",
+        description: "Save a finished output the user asked for, such as a report, a patch or a generated image, as a named artifact of this session. Artifacts are kept apart from the workspace in .tars/artifacts/<session>/ and listed with /artifacts. Saving under an existing name replaces it.",
+        input_schema: serde_json::to_value(schema_for!(SaveArtifactInput)).unwrap(),
+        handler: |input, context| Box::pin(save_artifact_impl(input, context)),
+        read_only: false,
+        opt_in: None,
+        summarize: |input, _| format!("📦 artifact {}", input["name"].as_str().unwrap_or("")),
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[tokio::test]
+    async fn artifacts_are_saved_by_name_and_listed() {
+        let dir = TempDir::new("tars-artifacts");
+        let input = |name: &str, content: &str, encoding| SaveArtifactInput {
+            name: name.to_string(),
+            content: content.to_string(),
+            encoding,
+        };
+
+        assert!(list_artifacts(&dir).unwrap().is_empty());
+        save(&dir, input("report.md", "# Findings\n", Encoding::Text))
+            .await
+            .unwrap();
+        save(&dir, input("pixel.png", "iVBORw0K", Encoding::Base64))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.join("pixel.png")).unwrap(),
+            [0x89, b'P', b'N', b'G', b'\r', b'\n']
+        );
+        for name in ["../escape.md", "nested/report.md", "..", " "] {
+            assert!(save(&dir, input(name, "", Encoding::Text)).await.is_err());
+        }
+
+        let artifacts = list_artifacts(&dir).unwrap();
+        let names: Vec<&str> = artifacts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["pixel.png", "report.md"]);
+        assert_eq!(artifacts[1].size, 11);
+    }
+}
@@ -0,0 +1,350 @@
+use serde_json::{Map, Value};
+
+/// Strips what schemars adds to an input schema that the model has no use
+/// for but pays for in tokens on every request: `$schema`, titles, Rust
+/// integer formats like `uint32` and `null` defaults. References to `$defs`
+/// are inlined, and optional properties lose their `null` alternative,
+/// since leaving them out says the same.
+pub(crate) fn minify(mut schema: Value) -> Value {
+    let definitions = schema
+        .as_object_mut()
+        .and_then(|object| {
+            object
+                .remove("$defs")
+                .or_else(|| object.remove("definitions"))
+        })
+        .and_then(|definitions| match definitions {
+            Value::Object(definitions) => Some(definitions),
+            _ => None,
+        })
+        .unwrap_or_default();
+    minify_value(&mut schema, &definitions);
+    schema
+}
+
+fn minify_value(value: &mut Value, definitions: &Map<String, Value>) {
+    match value {
+        Value::Object(object) => minify_object(object, definitions),
+        Value::Array(items) => {
+            for item in items {
+                minify_value(item, definitions);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn minify_object(object: &mut Map<String, Value>, definitions: &Map<String, Value>) {
+    if let Some(Value::String(reference)) = object.remove("$ref") {
+        let name = reference
+            .strip_prefix("#/$defs/")
+            .or_else(|| reference.strip_prefix("#/definitions/"));
+        match name.and_then(|name| definitions.get(name)) {
+            Some(Value::Object(definition)) => {
+                // Keys next to the reference, like `description`, win.
+                for (key, value) in definition {
+                    object.entry(key.clone()).or_insert_with(|| value.clone());
+                }
+            }
+            _ => {
+                object.insert("$ref".to_string(), Value::String(reference));
+            }
+        }
+    }
+    object.remove("$schema");
+    object.remove("title");
+    if object.get("default") == Some(&Value::Null) {
+        object.remove("default");
+    }
+    if object
+        .get("format")
+        .and_then(Value::as_str)
+        .is_some_and(|format| format.starts_with("int") || format.starts_with("uint"))
+    {
+        object.remove("format");
+    }
+
+    let required: Vec<String> = object
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| {
+            required
+                .iter()
+                .filter_map(|name| name.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(Value::Object(properties)) = object.get_mut("properties") {
+        for (name, property) in properties.iter_mut() {
+            minify_value(property, definitions);
+            if !required.contains(name) {
+                drop_null(property);
+            }
+        }
+    }
+    for (key, value) in object.iter_mut() {
+        if key != "properties" {
+            minify_value(value, definitions);
+        }
+    }
+}
+
+/// `"type": ["string", "null"]` → `"type": "string"`, and the same for an
+/// `anyOf` with a `null` branch.
+fn drop_null(property: &mut Value) {
+    let Some(object) = property.as_object_mut() else {
+        return;
+    };
+    if let Some(Value::Array(types)) = object.get_mut("type") {
+        types.retain(|kind| kind != "null");
+        if types.len() == 1 {
+            let kind = types.remove(0);
+            object.insert("type".to_string(), kind);
+        }
+    }
+    let single = match object.get_mut("anyOf") {
+        Some(Value::Array(branches)) => {
+            branches.retain(|branch| branch.get("type").and_then(Value::as_str) != Some("null"));
+            match branches.as_slice() {
+                [Value::Object(branch)] => Some(branch.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    if let Some(branch) = single {
+        object.remove("anyOf");
+        for (key, value) in branch {
+            object.entry(key).or_insert(value);
+        }
+    }
+}
+
+/// Checks a tool call's input against the tool's schema before the handler
+/// sees it, describing each problem so the model can fix the call, e.g.
+/// "missing field `path`". Covers what tool schemas use: types, required
+/// and nested properties, enums, bounds and array items.
+pub(crate) fn validate_input(schema: &Value, input: &Value) -> Result<(), String> {
+    let mut problems = Vec::new();
+    check(schema, input, "", &mut problems);
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+fn check(schema: &Value, value: &Value, path: &str, problems: &mut Vec<String>) {
+    let name = if path.is_empty() {
+        "the input".to_string()
+    } else {
+        format!("`{}`", path)
+    };
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(kind)) => vec![kind.as_str()],
+        Some(Value::Array(kinds)) => kinds.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|kind| is_type(value, kind)) {
+        let expected: Vec<&str> = types.iter().map(|kind| a_kind(kind)).collect();
+        problems.push(format!(
+            "{} must be {}, not {}",
+            name,
+            expected.join(" or "),
+            a_kind(kind_of(value))
+        ));
+        return;
+    }
+    if let Some(Value::Array(options)) = schema.get("enum")
+        && !options.contains(value)
+    {
+        let options: Vec<String> = options.iter().map(Value::to_string).collect();
+        problems.push(format!("{} must be one of {}", name, options.join(", ")));
+    }
+    if let Some(number) = value.as_f64() {
+        if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64)
+            && number < minimum
+        {
+            problems.push(format!("{} must be at least {}", name, minimum));
+        }
+        if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64)
+            && number > maximum
+        {
+            problems.push(format!("{} must be at most {}", name, maximum));
+        }
+    }
+    let field = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    if let Value::Object(fields) = value {
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        for key in &required {
+            if !fields.contains_key(*key) {
+                problems.push(format!("missing field `{}`", field(key)));
+            }
+        }
+        if let Some(Value::Object(properties)) = schema.get("properties") {
+            for (key, value) in fields {
+                // `minify` dropped the `null` optional fields accept.
+                if value.is_null() && !required.contains(&key.as_str()) {
+                    continue;
+                }
+                if let Some(property) = properties.get(key) {
+                    check(property, value, &field(key), problems);
+                }
+            }
+        }
+    }
+    if let (Value::Array(items), Some(item)) = (value, schema.get("items")) {
+        for (index, value) in items.iter().enumerate() {
+            check(item, value, &format!("{}[{}]", path, index), problems);
+        }
+    }
+    if let Some(Value::Array(branches)) = schema.get("anyOf")
+        && !branches
+            .iter()
+            .any(|branch| validate_input(branch, value).is_ok())
+    {
+        problems.push(format!("{} doesn't match any of its allowed forms", name));
+    }
+}
+
+fn is_type(value: &Value, kind: &str) -> bool {
+    match kind {
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn kind_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// `integer` → `an integer`.
+fn a_kind(kind: &str) -> &str {
+    match kind {
+        "integer" => "an integer",
+        "number" => "a number",
+        "string" => "a string",
+        "boolean" => "a boolean",
+        "array" => "an array",
+        "object" => "an object",
+        _ => kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::get_all_tools;
+    use serde_json::json;
+
+    /// What every request spends on tool schemas, at about four bytes a
+    /// token. Raise it deliberately when adding a tool.
+    const TOKEN_BUDGET: usize = 3_200;
+
+    #[test]
+    fn minified_schemas_still_validate_tool_inputs() {
+        let tools = get_all_tools();
+        let schema = |name: &str| {
+            let tool = tools.iter().find(|tool| tool.name == name).unwrap();
+            jsonschema::validator_for(&tool.input_schema).unwrap()
+        };
+        let valid = [
+            ("read_file", json!({"path": "README.md"})),
+            ("list_files", json!({"path": "src", "depth": 2})),
+            ("hash_file", json!({"path": "a.tar", "algorithm": "blake3"})),
+            ("check_port", json!({"port": 8080})),
+            ("todo", json!({"action": "add", "items": ["Write tests"]})),
+            (
+                "todo",
+                json!({"action": "update", "id": 2, "status": "in_progress"}),
+            ),
+        ];
+        for (name, input) in &valid {
+            assert!(schema(name).is_valid(input), "{} {}", name, input);
+        }
+        let invalid = [
+            ("read_file", json!({})),
+            ("hash_file", json!({"path": "a.tar", "algorithm": "md5"})),
+            ("check_port", json!({"port": 70000})),
+            ("todo", json!({"action": "update", "status": "done"})),
+        ];
+        for (name, input) in &invalid {
+            assert!(!schema(name).is_valid(input), "{} {}", name, input);
+        }
+
+        let mut tokens = 0;
+        for tool in &tools {
+            let text = tool.input_schema.to_string();
+            for noise in ["$ref", "$defs", "$schema", "\"title\"", "uint", "null"] {
+                assert!(!text.contains(noise), "{}: {}", tool.name, text);
+            }
+            tokens += (tool.name.len() + tool.description.len() + text.len()) / 4;
+        }
+        assert!(
+            tokens <= TOKEN_BUDGET,
+            "tools take {} tokens of every request",
+            tokens
+        );
+    }
+
+    #[test]
+    fn invalid_input_is_described_field_by_field() {
+        let tools = get_all_tools();
+        let schema = |name: &str| {
+            &tools
+                .iter()
+                .find(|tool| tool.name == name)
+                .unwrap()
+                .input_schema
+        };
+        assert_eq!(
+            validate_input(schema("read_file"), &json!({"file": "a.rs"})),
+            Err("missing field `path`".to_string())
+        );
+        assert_eq!(
+            validate_input(schema("check_port"), &json!({"port": "8080"})),
+            Err("`port` must be an integer, not a string".to_string())
+        );
+        assert_eq!(
+            validate_input(
+                schema("todo"),
+                &json!({"action": "finish", "items": ["a", 2], "id": -1})
+            ),
+            Err("`action` must be one of \"add\", \"update\", \"complete\"; `id` must be at least 0; `items[1]` must be a string, not an integer".to_string())
+        );
+        assert_eq!(
+            validate_input(schema("bash"), &json!("ls")),
+            Err("the input must be an object, not a string".to_string())
+        );
+        assert_eq!(
+            validate_input(schema("list_files"), &json!({"depth": 2, "limit": null})),
+            Ok(())
+        );
+    }
+}
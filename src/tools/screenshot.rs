@@ -0,0 +1,203 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+
+use super::process::{run_captured, RunError};
+use super::{OptIn, ToolContext, ToolDefinition};
+use crate::ai_sdk::ImageSource;
+
+type ScreenshotResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(15);
+/// The API scales larger images down to this many pixels on the long edge,
+/// so sending more only costs bandwidth.
+const MAX_EDGE: u32 = 1568;
+const JPEG_QUALITY: u8 = 80;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct ScreenshotInput {
+    #[schemars(
+        description = "Optional part of a window's title to capture just that window (X11 only). Captures the whole screen when left out."
+    )]
+    window: Option<String>,
+}
+
+/// Programs that save a screenshot of the whole screen to `file`, in the
+/// order they are tried.
+fn screen_commands(file: &str) -> Vec<Vec<String>> {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+    if cfg!(target_os = "macos") {
+        vec![args(&["screencapture", "-x", "-t", "png", file])]
+    } else if cfg!(windows) {
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms,System.Drawing; \
+             $b = [System.Windows.Forms.SystemInformation]::VirtualScreen; \
+             $i = New-Object System.Drawing.Bitmap $b.Width, $b.Height; \
+             [System.Drawing.Graphics]::FromImage($i).CopyFromScreen($b.Location, [System.Drawing.Point]::Empty, $b.Size); \
+             $i.Save('{}')",
+            file
+        );
+        vec![args(&["powershell", "-NoProfile", "-Command", &script])]
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        vec![
+            args(&["grim", file]),
+            args(&["gnome-screenshot", "-f", file]),
+        ]
+    } else {
+        vec![
+            args(&["import", "-window", "root", file]),
+            args(&["maim", file]),
+            args(&["scrot", "--overwrite", file]),
+            args(&["gnome-screenshot", "-f", file]),
+        ]
+    }
+}
+
+/// Saves a screenshot of the screen, or of the first window whose title
+/// contains `window`, to `file`.
+async fn capture(file: &str, window: Option<&str>) -> ScreenshotResult<()> {
+    let Some(title) = window else {
+        for command in screen_commands(file) {
+            if run(&command).await?.is_some() {
+                return Ok(());
+            }
+        }
+        return Err(
+            "No screenshot program is installed (grim, import, maim, scrot or gnome-screenshot)"
+                .into(),
+        );
+    };
+
+    if cfg!(any(target_os = "macos", windows)) || std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        return Err(
+            "Capturing a single window only works on X11; capture the whole screen instead".into(),
+        );
+    }
+    let search = ["xdotool", "search", "--onlyvisible", "--name", title].map(str::to_string);
+    let ids = run(&search)
+        .await?
+        .ok_or("Capturing a window needs xdotool and ImageMagick's import")?;
+    let id = String::from_utf8_lossy(&ids)
+        .lines()
+        .next()
+        .map(str::to_string)
+        .ok_or_else(|| format!("No window's title contains {:?}", title))?;
+    run(&["import", "-window", &id, file].map(str::to_string))
+        .await?
+        .ok_or("Capturing a window needs xdotool and ImageMagick's import")?;
+    Ok(())
+}
+
+/// Runs a capture program and returns its stdout, or `None` when the
+/// program isn't installed.
+async fn run(args: &[String]) -> ScreenshotResult<Option<Vec<u8>>> {
+    match run_captured(args, CAPTURE_TIMEOUT).await {
+        Ok(output) if output.status.success() => Ok(Some(output.stdout)),
+        Ok(output) => Err(format!(
+            "`{}` failed ({}): {}",
+            args[0],
+            output.status,
+            output.report(1000).trim_end()
+        )
+        .into()),
+        Err(RunError::NotFound) => Ok(None),
+        Err(RunError::TimedOut) => Err(format!("`{}` did not finish in time", args[0]).into()),
+        Err(RunError::Io(err)) => Err(format!("Could not run `{}`: {}", args[0], err).into()),
+    }
+}
+
+/// The screenshot at `file` scaled to fit `MAX_EDGE` and encoded as JPEG,
+/// with a line describing it.
+fn prepare(file: &Path) -> ScreenshotResult<(Vec<u8>, String)> {
+    let image = image::open(file)?;
+    let (width, height) = (image.width(), image.height());
+    let image = if width.max(height) > MAX_EDGE {
+        image.resize(MAX_EDGE, MAX_EDGE, image::imageops::FilterType::Triangle)
+    } else {
+        image
+    };
+    let mut jpeg = Vec::new();
+    let encoder =
+        image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut jpeg), JPEG_QUALITY);
+    image.to_rgb8().write_with_encoder(encoder)?;
+    let size = if image.width() == width {
+        format!("{}×{}", width, height)
+    } else {
+        format!(
+            "{}×{}, scaled from {}×{}",
+            image.width(),
+            image.height(),
+            width,
+            height
+        )
+    };
+    Ok((jpeg, size))
+}
+
+async fn screenshot_impl(
+    input: serde_json::Value,
+    context: ToolContext,
+) -> ScreenshotResult<String> {
+    let input: ScreenshotInput = serde_json::from_value(input)?;
+    let file = std::env::temp_dir().join(format!("tars-screenshot-{}.png", uuid::Uuid::new_v4()));
+    let captured = capture(&file.to_string_lossy(), input.window.as_deref()).await;
+    let prepared = match captured {
+        Ok(()) => {
+            let path = file.clone();
+            tokio::task::spawn_blocking(move || prepare(&path)).await?
+        }
+        Err(err) => Err(err),
+    };
+    let _ = tokio::fs::remove_file(&file).await;
+    let (jpeg, size) = prepared?;
+
+    context
+        .images
+        .lock()
+        .unwrap()
+        .push(ImageSource::base64("image/jpeg", &jpeg));
+    Ok(match input.window {
+        Some(title) => format!("Screenshot of the window {:?} ({})", title, size),
+        None => format!("Screenshot of the screen ({})", size),
+    })
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "screenshot",
+        description: "Take a screenshot of the user's screen, or of one window by title, and look at it. Useful for UI problems the user is looking at.",
+        input_schema: serde_json::to_value(schema_for!(ScreenshotInput)).unwrap(),
+        handler: |input, context| Box::pin(screenshot_impl(input, context)),
+        // The screen changes under the tool, so its results are never
+        // answered from the turn's cache.
+        read_only: false,
+        opt_in: Some(OptIn::Screenshot),
+        summarize: |input, _| match input["window"].as_str() {
+            Some(title) => format!("📸 screenshot {:?}", title),
+            None => "📸 screenshot".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn large_screenshots_are_scaled_to_what_the_api_uses() {
+        let dir = TempDir::new("tars-screenshot");
+        let file = dir.join("screen.png");
+        image::RgbaImage::new(3136, 1960).save(&file).unwrap();
+
+        let (jpeg, size) = prepare(&file).unwrap();
+        assert_eq!(size, "1568×980, scaled from 3136×1960");
+        let scaled = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (1568, 980));
+
+        image::RgbaImage::new(800, 600).save(&file).unwrap();
+        assert_eq!(prepare(&file).unwrap().1, "800×600");
+    }
+}
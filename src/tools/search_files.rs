@@ -0,0 +1,303 @@
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use regex::{Regex, RegexBuilder};
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::{group_thousands, text, ToolDefinition};
+
+type SearchResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+const MAX_CONTEXT: usize = 10;
+/// Files larger than this are generated or data, not code worth searching.
+const MAX_FILE_BYTES: u64 = 4 * 1024 * 1024;
+/// Minified code has lines of whole megabytes; only their start is shown.
+const MAX_LINE_CHARS: usize = 300;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct SearchFilesInput {
+    #[schemars(
+        description = "The regular expression to search for, in Rust regex syntax, e.g. `fn\\s+parse_` or `TODO|FIXME`"
+    )]
+    pattern: String,
+    #[schemars(
+        description = "Optional relative directory or file to search. Defaults to the current directory."
+    )]
+    #[serde(default)]
+    path: String,
+    #[schemars(
+        description = "Optional glob the file paths must match, e.g. `*.rs` or `src/**/*.ts`; `!` in front excludes instead"
+    )]
+    glob: Option<String>,
+    #[schemars(
+        description = "Lines of context to show around each match. Defaults to 0, at most 10."
+    )]
+    #[serde(default)]
+    context: usize,
+    #[schemars(description = "Whether to ignore case")]
+    #[serde(default)]
+    case_insensitive: bool,
+    #[schemars(
+        description = "Maximum number of matching lines to return. Defaults to 100, capped at 1000."
+    )]
+    limit: Option<usize>,
+}
+
+/// The matching lines of one file, ripgrep style: `12:` before a match,
+/// `13-` before a context line and `--` between separate groups. Also says
+/// whether matches were left out because `budget` ran out.
+fn search_file(
+    text: &str,
+    regex: &Regex,
+    context: usize,
+    budget: &mut usize,
+) -> (Vec<String>, bool) {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut shown = Vec::new();
+    let mut next_unshown = 0;
+    for (index, line) in lines.iter().enumerate() {
+        if !regex.is_match(line) {
+            continue;
+        }
+        if *budget == 0 {
+            return (shown, true);
+        }
+        *budget -= 1;
+        let start = index.saturating_sub(context).max(next_unshown);
+        if start > next_unshown && !shown.is_empty() {
+            shown.push("--".to_string());
+        }
+        for (context_index, context_line) in lines.iter().enumerate().take(index).skip(start) {
+            shown.push(format_line(context_index, '-', context_line));
+        }
+        shown.push(format_line(index, ':', line));
+        next_unshown = index + 1;
+        // Trailing context, up to the next match, which is shown as one.
+        let end = (index + 1 + context).min(lines.len());
+        for (context_index, context_line) in lines.iter().enumerate().take(end).skip(index + 1) {
+            if regex.is_match(context_line) {
+                break;
+            }
+            shown.push(format_line(context_index, '-', context_line));
+            next_unshown = context_index + 1;
+        }
+    }
+    (shown, false)
+}
+
+fn format_line(index: usize, separator: char, line: &str) -> String {
+    if line.chars().count() > MAX_LINE_CHARS {
+        let start: String = line.chars().take(MAX_LINE_CHARS).collect();
+        format!("{}{}{}…", index + 1, separator, start)
+    } else {
+        format!("{}{}{}", index + 1, separator, line)
+    }
+}
+
+fn search(input: SearchFilesInput) -> SearchResult<String> {
+    let regex = RegexBuilder::new(&input.pattern)
+        .case_insensitive(input.case_insensitive)
+        .build()
+        .map_err(|err| format!("Invalid pattern: {}", err))?;
+    let root = if input.path.is_empty() {
+        "."
+    } else {
+        &input.path
+    };
+    let root = Path::new(root);
+    if !root.exists() {
+        return Err(format!("{} does not exist", root.display()).into());
+    }
+    let context = input.context.min(MAX_CONTEXT);
+    let limit = input.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut walker = WalkBuilder::new(root);
+    walker
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_path(|a, b| a.cmp(b));
+    if let Some(glob) = &input.glob {
+        let mut overrides = OverrideBuilder::new(root);
+        overrides
+            .add(glob)
+            .map_err(|err| format!("Invalid glob: {}", err))?;
+        walker.overrides(overrides.build()?);
+    }
+
+    let mut output = Vec::new();
+    let mut budget = limit;
+    let mut files = 0;
+    let mut skipped = 0;
+    for entry in walker.build() {
+        let Ok(entry) = entry else {
+            skipped += 1;
+            continue;
+        };
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+        if entry
+            .metadata()
+            .map_or(true, |metadata| metadata.len() > MAX_FILE_BYTES)
+        {
+            skipped += 1;
+            continue;
+        }
+        let Ok(bytes) = std::fs::read(entry.path()) else {
+            skipped += 1;
+            continue;
+        };
+        // Binary files: a NUL byte that isn't UTF-16.
+        if bytes.iter().take(8192).any(|&byte| byte == 0) && !text::is_utf16(&bytes) {
+            continue;
+        }
+        let (found, truncated) =
+            search_file(&text::decode(&bytes).text, &regex, context, &mut budget);
+        if !found.is_empty() {
+            files += 1;
+            if !output.is_empty() {
+                output.push(String::new());
+            }
+            let path = entry.path().strip_prefix(".").unwrap_or(entry.path());
+            output.push(path.display().to_string());
+            output.extend(found);
+        }
+        if truncated {
+            output.push(format!(
+                "…stopped at the limit of {} matching lines; narrow the path or glob, or raise the limit",
+                limit
+            ));
+            break;
+        }
+    }
+
+    let matches = limit - budget;
+    if matches == 0 {
+        output.push(format!("No matches for {:?}", input.pattern));
+    } else {
+        output.push(String::new());
+        output.push(format!(
+            "{} matching line{} in {} file{}",
+            group_thousands(matches as u64),
+            if matches == 1 { "" } else { "s" },
+            files,
+            if files == 1 { "" } else { "s" }
+        ));
+    }
+    if skipped > 0 {
+        output.push(format!(
+            "({} unreadable or over 4 MB skipped)",
+            group_thousands(skipped)
+        ));
+    }
+    Ok(output.join("\n"))
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "search_files",
+        description: "Search file contents for a regular expression, like ripgrep, and return the matching lines with their line numbers, grouped by file. Entries ignored by .gitignore and binary files are skipped. Narrow it with `path` and `glob`; `context` adds lines around each match. Use it to find definitions and usages instead of reading whole files.",
+        input_schema: serde_json::to_value(schema_for!(SearchFilesInput)).unwrap(),
+        handler: |input, _| {
+            Box::pin(async move {
+                let input: SearchFilesInput = serde_json::from_value(input)?;
+                tokio::task::spawn_blocking(move || search(input)).await?
+            })
+        },
+        read_only: true,
+        opt_in: None,
+        summarize: |input, output| {
+            let pattern = input["pattern"].as_str().unwrap_or_default();
+            let path = input["path"].as_str().filter(|path| !path.is_empty());
+            let mut line = format!("🔍 search {:?}", pattern);
+            if let Some(path) = path {
+                line.push_str(&format!(" in {}", path));
+            }
+            if let Some(glob) = input["glob"].as_str() {
+                line.push_str(&format!(" ({})", glob));
+            }
+            match output.and_then(|output| output.lines().rev().find(|l| l.contains(" matching line"))) {
+                Some(found) => format!("{}: {}", line, found),
+                None => line,
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn matches_are_shown_with_line_numbers_and_context() {
+        let dir = TempDir::new("tars-search");
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(
+            dir.join("src/lib.rs"),
+            "use std::io;\n\nfn parse_args() {}\nfn run() {\n    parse_args();\n}\n// end\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes.md"), "parse_args is documented here\n").unwrap();
+        std::fs::write(dir.join("data.bin"), b"parse_args\0\x01").unwrap();
+        std::fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        std::fs::write(dir.join("target/out.rs"), "fn parse_args() {}\n").unwrap();
+        // `ignore` only honours .gitignore inside a git repository.
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        let root = dir.to_str().unwrap().to_string();
+
+        let output = search(SearchFilesInput {
+            pattern: r"parse_\w+".to_string(),
+            path: root.clone(),
+            glob: Some("*.rs".to_string()),
+            context: 1,
+            case_insensitive: false,
+            limit: None,
+        })
+        .unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "{}/src/lib.rs\n2-\n3:fn parse_args() {{}}\n4-fn run() {{\n5:    parse_args();\n6-}}\n\n2 matching lines in 1 file",
+                root
+            )
+        );
+
+        let output = search(SearchFilesInput {
+            pattern: "PARSE_ARGS".to_string(),
+            path: root.clone(),
+            glob: None,
+            context: 0,
+            case_insensitive: true,
+            limit: Some(1),
+        })
+        .unwrap();
+        assert!(
+            output.starts_with(&format!(
+                "{}/notes.md\n1:parse_args is documented here\n…stopped at the limit of 1 matching lines",
+                root
+            )),
+            "{}",
+            output
+        );
+
+        assert!(search(SearchFilesInput {
+            pattern: "(".to_string(),
+            path: root,
+            glob: None,
+            context: 0,
+            case_insensitive: false,
+            limit: None,
+        })
+        .unwrap_err()
+        .to_string()
+        .starts_with("Invalid pattern"));
+    }
+}
@@ -0,0 +1,173 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::fs::Metadata;
+use tokio::io::AsyncReadExt;
+
+use super::{format_size, group_thousands, input_path, text, ToolDefinition};
+use crate::clock;
+
+/// Lines are only counted in files up to this size, so a stat stays cheap.
+const MAX_COUNTED_BYTES: u64 = 64 * 1024 * 1024;
+const SNIFF_BYTES: u64 = 8 * 1024;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct StatInput {
+    #[schemars(description = "The relative path of a file or directory")]
+    path: String,
+}
+
+async fn stat_impl(
+    input: serde_json::Value,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let input: StatInput = serde_json::from_value(input)?;
+    let path = if input.path.is_empty() {
+        "."
+    } else {
+        &input.path
+    };
+    stat(path)
+        .await
+        .map_err(|e| format!("Error reading metadata: {}", e).into())
+}
+
+async fn stat(path: &str) -> std::io::Result<String> {
+    let link = tokio::fs::symlink_metadata(path).await?;
+    let mut lines = vec![format!("path: {}", path)];
+    let metadata = if link.is_symlink() {
+        let target = tokio::fs::read_link(path).await?;
+        lines.push(format!("symlink to: {}", target.display()));
+        tokio::fs::metadata(path).await?
+    } else {
+        link
+    };
+
+    lines.push(format!("type: {}", kind(&metadata)));
+    if metadata.is_file() {
+        let size = metadata.len();
+        lines.push(if size < 1024 {
+            format!("size: {}", format_size(size))
+        } else {
+            format!(
+                "size: {} ({} bytes)",
+                format_size(size),
+                group_thousands(size)
+            )
+        });
+    }
+    if let Ok(modified) = metadata.modified() {
+        lines.push(format!("modified: {}", clock::format_local(modified)));
+    }
+    lines.push(format!("permissions: {}", permissions(&metadata)));
+    if metadata.is_file() {
+        lines.push(contents(path, metadata.len()).await?);
+    }
+    Ok(lines.join("\n"))
+}
+
+fn kind(metadata: &Metadata) -> &'static str {
+    if metadata.is_dir() {
+        "directory"
+    } else if metadata.is_file() {
+        "file"
+    } else {
+        "special file (socket, pipe or device)"
+    }
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = metadata.permissions().mode();
+    let bits: String = [
+        (0o400, 'r'),
+        (0o200, 'w'),
+        (0o100, 'x'),
+        (0o040, 'r'),
+        (0o020, 'w'),
+        (0o010, 'x'),
+        (0o004, 'r'),
+        (0o002, 'w'),
+        (0o001, 'x'),
+    ]
+    .iter()
+    .map(|&(bit, flag)| if mode & bit != 0 { flag } else { '-' })
+    .collect();
+    format!("{} ({:04o})", bits, mode & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn permissions(metadata: &Metadata) -> String {
+    if metadata.permissions().readonly() {
+        "read-only".to_string()
+    } else {
+        "read-write".to_string()
+    }
+}
+
+/// The line count of a text file, or what kind of content it has instead.
+async fn contents(path: &str, size: u64) -> std::io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut sniff = Vec::new();
+    (&mut file)
+        .take(SNIFF_BYTES)
+        .read_to_end(&mut sniff)
+        .await?;
+    if sniff.contains(&0) && !text::is_utf16(&sniff) {
+        return Ok("content: binary".to_string());
+    }
+    if size > MAX_COUNTED_BYTES {
+        return Ok(format!(
+            "lines: not counted (over {})",
+            format_size(MAX_COUNTED_BYTES)
+        ));
+    }
+    let mut bytes = sniff;
+    file.read_to_end(&mut bytes).await?;
+    let decoded = text::decode(&bytes);
+    let count = decoded.text.lines().count();
+    Ok(format!(
+        "lines: {}\nencoding: {}",
+        group_thousands(count as u64),
+        decoded.format.encoding.name()
+    ))
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "stat",
+        description: "Show a file's or directory's metadata without reading it: type, size, modification time, permissions and, for text files, the line count and encoding. Symlinks are shown with their target.",
+        input_schema: serde_json::to_value(schema_for!(StatInput)).unwrap(),
+        handler: |input, _| Box::pin(stat_impl(input)),
+        read_only: true,
+        opt_in: None,
+        summarize: |input, _| format!("🔎 stat {}", input_path(input)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[tokio::test]
+    async fn stat_reports_size_lines_and_kind() {
+        let dir = TempDir::new("tars-stat");
+        let file = dir.join("notes.txt");
+        std::fs::write(&file, "one\ntwo\nthree\n").unwrap();
+
+        let output = stat(file.to_str().unwrap()).await.unwrap();
+        assert!(output.contains("type: file\n"), "{}", output);
+        assert!(output.contains("size: 14 bytes\n"), "{}", output);
+        assert!(output.contains("lines: 3\nencoding: UTF-8"), "{}", output);
+        assert!(output.contains("modified: "), "{}", output);
+
+        let output = stat(dir.to_str().unwrap()).await.unwrap();
+        assert!(output.contains("type: directory"), "{}", output);
+        assert!(!output.contains("lines:"), "{}", output);
+
+        let binary = dir.join("blob.bin");
+        std::fs::write(&binary, [1u8, 0, 2]).unwrap();
+        let output = stat(binary.to_str().unwrap()).await.unwrap();
+        assert!(output.ends_with("content: binary"), "{}", output);
+    }
+}
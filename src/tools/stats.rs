@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[cfg(feature = "client")]
+use super::group_thousands;
+use crate::protocol::ToolStats;
+
+/// Counts of the tool calls made in a session, or with an agent's API key
+/// since the server started.
+#[derive(Debug, Default)]
+pub(crate) struct ToolUsage {
+    tools: BTreeMap<String, ToolStats>,
+}
+
+impl ToolUsage {
+    /// Counts a call that ran, with the output the model was given.
+    pub(crate) fn record(&mut self, name: &str, elapsed: Duration, is_error: bool, output: &str) {
+        let stats = self.entry(name);
+        stats.calls += 1;
+        stats.errors += u64::from(is_error);
+        stats.total_ms += elapsed.as_millis() as u64;
+        stats.output_tokens += output.len() as u64 / 4;
+    }
+
+    /// Counts a call turned away for input that didn't match the schema.
+    pub(crate) fn record_invalid(&mut self, name: &str) {
+        let stats = self.entry(name);
+        stats.calls += 1;
+        stats.errors += 1;
+        stats.invalid_input += 1;
+    }
+
+    fn entry(&mut self, name: &str) -> &mut ToolStats {
+        self.tools
+            .entry(name.to_string())
+            .or_insert_with(|| ToolStats {
+                name: name.to_string(),
+                ..ToolStats::default()
+            })
+    }
+
+    /// Every tool called so far, most called first.
+    #[cfg(feature = "server")]
+    pub(crate) fn stats(&self) -> Vec<ToolStats> {
+        combine_stats(self.tools.values().cloned())
+    }
+}
+
+/// Adds up the stats of each tool, most called first.
+#[cfg(feature = "server")]
+pub(crate) fn combine_stats(stats: impl IntoIterator<Item = ToolStats>) -> Vec<ToolStats> {
+    let mut usage = ToolUsage::default();
+    for stats in stats {
+        let total = usage.entry(&stats.name);
+        total.calls += stats.calls;
+        total.errors += stats.errors;
+        total.invalid_input += stats.invalid_input;
+        total.total_ms += stats.total_ms;
+        total.output_tokens += stats.output_tokens;
+    }
+    let mut stats: Vec<ToolStats> = usage.tools.into_values().collect();
+    stats.sort_by(|a, b| b.calls.cmp(&a.calls).then_with(|| a.name.cmp(&b.name)));
+    stats
+}
+
+/// A row per tool with its calls, error rate, mean latency and output
+/// tokens, under a header.
+#[cfg(feature = "client")]
+pub(crate) fn stats_table(stats: &[ToolStats]) -> String {
+    if stats.is_empty() {
+        return "No tool calls yet".to_string();
+    }
+    let mut table = format!(
+        "{:<20} {:>6} {:>7} {:>8} {:>8} {:>8}",
+        "tool", "calls", "errors", "invalid", "mean ms", "tokens"
+    );
+    for stats in stats {
+        let ran = stats.calls - stats.invalid_input;
+        let mean_ms = stats.total_ms.checked_div(ran).unwrap_or_default();
+        let error_rate = stats.errors as f64 * 100.0 / stats.calls.max(1) as f64;
+        table.push_str(&format!(
+            "\n{:<20} {:>6} {:>6.0}% {:>8} {:>8} {:>8}",
+            stats.name,
+            stats.calls,
+            error_rate,
+            stats.invalid_input,
+            mean_ms,
+            group_thousands(stats.output_tokens)
+        ));
+    }
+    table
+}
+
+#[cfg(all(test, feature = "client", feature = "server"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_add_up_per_tool_across_sessions() {
+        let mut first = ToolUsage::default();
+        first.record("bash", Duration::from_millis(30), false, &"x".repeat(400));
+        first.record("bash", Duration::from_millis(10), true, "exit 1");
+        first.record_invalid("bash");
+        first.record("read_file", Duration::from_millis(2), false, "hi");
+        let mut second = ToolUsage::default();
+        second.record("read_file", Duration::from_millis(4), false, "hello");
+        second.record("read_file", Duration::from_millis(6), false, "");
+
+        let stats = combine_stats(first.stats().into_iter().chain(second.stats()));
+        assert_eq!(
+            stats,
+            [
+                ToolStats {
+                    name: "bash".to_string(),
+                    calls: 3,
+                    errors: 2,
+                    invalid_input: 1,
+                    total_ms: 40,
+                    output_tokens: 101,
+                },
+                ToolStats {
+                    name: "read_file".to_string(),
+                    calls: 3,
+                    errors: 0,
+                    invalid_input: 0,
+                    total_ms: 12,
+                    output_tokens: 1,
+                },
+            ]
+        );
+        let table = stats_table(&stats);
+        let bash = table.lines().nth(1).unwrap();
+        assert_eq!(
+            bash.split_whitespace().collect::<Vec<_>>(),
+            ["bash", "3", "67%", "1", "20", "101"]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+use super::format_size;
+
+/// How a text file is stored on disk, so an edit can be written back the
+/// way the file was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TextFormat {
+    pub(crate) encoding: &'static Encoding,
+    /// The file starts with a byte order mark.
+    bom: bool,
+    /// Every line ends in `\r\n`; the decoded text uses `\n` either way.
+    crlf: bool,
+}
+
+/// A file's contents as text.
+pub(crate) struct Decoded {
+    /// With `\n` line endings.
+    pub(crate) text: String,
+    pub(crate) format: TextFormat,
+    /// Some bytes weren't valid in the detected encoding and were replaced,
+    /// so writing `text` back would change them.
+    pub(crate) lossy: bool,
+}
+
+/// Whether `bytes` start with a UTF-16 byte order mark; such files are
+/// full of NUL bytes but still text.
+pub(crate) fn is_utf16(bytes: &[u8]) -> bool {
+    matches!(Encoding::for_bom(bytes), Some((encoding, _)) if encoding != UTF_8)
+}
+
+/// Decodes a file by its byte order mark, as UTF-8 when it is valid UTF-8,
+/// or else in the encoding the content looks most like, such as
+/// windows-1252 for Latin-1 sources.
+pub(crate) fn decode(bytes: &[u8]) -> Decoded {
+    let (encoding, bom_len) = match Encoding::for_bom(bytes) {
+        Some((encoding, bom_len)) => (encoding, bom_len),
+        None if std::str::from_utf8(bytes).is_ok() => (UTF_8, 0),
+        None => {
+            let mut detector = EncodingDetector::new();
+            detector.feed(bytes, true);
+            (detector.guess(None, true), 0)
+        }
+    };
+    let (text, lossy) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+    // Mixed line endings are left as they are, so an edit doesn't rewrite
+    // lines it never touched.
+    let crlf_count = text.matches("\r\n").count();
+    let crlf = crlf_count > 0 && crlf_count == text.matches('\n').count();
+    let text = if crlf {
+        text.replace("\r\n", "\n")
+    } else {
+        text.into_owned()
+    };
+    Decoded {
+        text,
+        format: TextFormat {
+            encoding,
+            bom: bom_len > 0,
+            crlf,
+        },
+        lossy,
+    }
+}
+
+/// Encodes `text` the way `format` describes, failing when the encoding
+/// can't represent one of its characters.
+pub(crate) fn encode(text: &str, format: TextFormat) -> Result<Vec<u8>, String> {
+    let text = if format.crlf {
+        text.replace('\n', "\r\n")
+    } else {
+        text.to_string()
+    };
+    let mut bytes = Vec::new();
+    // encoding_rs only decodes UTF-16, so its code units are written here.
+    if format.encoding == UTF_16LE || format.encoding == UTF_16BE {
+        let big_endian = format.encoding == UTF_16BE;
+        let mut units = Vec::new();
+        if format.bom {
+            units.push(0xfeff);
+        }
+        units.extend(text.encode_utf16());
+        for unit in units {
+            bytes.extend(if big_endian {
+                unit.to_be_bytes()
+            } else {
+                unit.to_le_bytes()
+            });
+        }
+        return Ok(bytes);
+    }
+    if format.bom && format.encoding == UTF_8 {
+        bytes.extend(b"\xef\xbb\xbf");
+    }
+    let (encoded, _, unmappable) = format.encoding.encode(&text);
+    if unmappable {
+        return Err(format!(
+            "the file is {} and can't store some of the new text's characters",
+            format.encoding.name()
+        ));
+    }
+    bytes.extend_from_slice(&encoded);
+    Ok(bytes)
+}
+
+/// `text` cut to about `max_bytes` by leaving out its middle, since the
+/// start and end of a log or stack trace say the most.
+pub(crate) fn keep_ends(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let mut head = max_bytes / 2;
+    while !text.is_char_boundary(head) {
+        head -= 1;
+    }
+    let mut tail = text.len() - max_bytes / 2;
+    while !text.is_char_boundary(tail) {
+        tail += 1;
+    }
+    format!(
+        "{}\n[… {} left out …]\n{}",
+        &text[..head],
+        format_size((tail - head) as u64),
+        &text[tail..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_round_trip_in_their_encoding_and_line_endings() {
+        let latin1 = b"caf\xe9 cr\xe8me br\xfbl\xe9e\r\nna\xefve fa\xe7ade\r\n";
+        let decoded = decode(latin1);
+        assert_eq!(decoded.text, "café crème brûlée\nnaïve façade\n");
+        assert_eq!(decoded.format.encoding, encoding_rs::WINDOWS_1252);
+        assert!(!decoded.lossy);
+        assert_eq!(encode(&decoded.text, decoded.format).unwrap(), latin1);
+        assert!(encode("日本", decoded.format).is_err());
+
+        let mut utf16: Vec<u8> = vec![0xff, 0xfe];
+        utf16.extend("hé\n".encode_utf16().flat_map(u16::to_le_bytes));
+        assert!(is_utf16(&utf16));
+        let decoded = decode(&utf16);
+        assert_eq!(decoded.text, "hé\n");
+        assert_eq!(encode(&decoded.text, decoded.format).unwrap(), utf16);
+
+        let bom = b"\xef\xbb\xbfplain\n";
+        let decoded = decode(bom);
+        assert_eq!(decoded.text, "plain\n");
+        assert!(!is_utf16(bom));
+        assert_eq!(encode(&decoded.text, decoded.format).unwrap(), bom);
+    }
+}
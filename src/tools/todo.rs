@@ -0,0 +1,167 @@
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+use super::{ToolContext, ToolDefinition};
+use crate::protocol::{TodoItem, TodoStatus};
+
+pub(crate) const NAME: &str = "todo";
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum TodoAction {
+    Add,
+    Update,
+    Complete,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct TodoInput {
+    #[schemars(
+        description = "add: append `items` as pending steps. update: change the `text` and/or `status` of step `id`. complete: mark step `id` completed."
+    )]
+    action: TodoAction,
+    #[serde(default)]
+    #[schemars(description = "Steps to add, in order")]
+    items: Vec<String>,
+    #[schemars(description = "The step to update or complete")]
+    id: Option<u32>,
+    #[schemars(description = "New wording for the step")]
+    text: Option<String>,
+    #[schemars(description = "New status for the step")]
+    status: Option<TodoStatus>,
+}
+
+async fn todo_impl(
+    input: serde_json::Value,
+    context: ToolContext,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let input: TodoInput = serde_json::from_value(input)?;
+    let mut todos = context.todos.lock().unwrap();
+    apply(&mut todos, input)?;
+    Ok(render(&todos))
+}
+
+fn apply(todos: &mut Vec<TodoItem>, input: TodoInput) -> Result<(), String> {
+    match input.action {
+        TodoAction::Add => {
+            if input.items.is_empty() {
+                return Err("add needs at least one entry in items".to_string());
+            }
+            let first_id = todos.iter().map(|item| item.id).max().unwrap_or(0) + 1;
+            todos.extend((first_id..).zip(input.items).map(|(id, text)| TodoItem {
+                id,
+                text,
+                status: TodoStatus::Pending,
+            }));
+        }
+        TodoAction::Update | TodoAction::Complete => {
+            let id = input.id.ok_or("id is required")?;
+            let item = todos
+                .iter_mut()
+                .find(|item| item.id == id)
+                .ok_or_else(|| format!("no step with id {}", id))?;
+            if matches!(input.action, TodoAction::Complete) {
+                item.status = TodoStatus::Completed;
+            } else if input.text.is_none() && input.status.is_none() {
+                return Err("update needs a text or a status".to_string());
+            }
+            if let Some(text) = input.text {
+                item.text = text;
+            }
+            if let Some(status) = input.status {
+                item.status = status;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The list as the model sees it after each change.
+fn render(todos: &[TodoItem]) -> String {
+    let done = todos
+        .iter()
+        .filter(|item| item.status == TodoStatus::Completed)
+        .count();
+    let mut output = format!("Plan ({}/{} done):", done, todos.len());
+    for item in todos {
+        let mark = match item.status {
+            TodoStatus::Pending => ' ',
+            TodoStatus::InProgress => '~',
+            TodoStatus::Completed => 'x',
+        };
+        output.push_str(&format!("\n{}. [{}] {}", item.id, mark, item.text));
+    }
+    output
+}
+
+pub(crate) fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: NAME,
+        description: "Keep a step-by-step plan for multi-step work, visible to the user. Add the steps up front, mark the one you are working on in_progress, and complete each as you finish it. Returns the whole plan.",
+        input_schema: serde_json::to_value(schema_for!(TodoInput)).unwrap(),
+        handler: |input, context| Box::pin(todo_impl(input, context)),
+        read_only: false,
+        opt_in: None,
+        summarize: |input, _| {
+            let id = input["id"].as_u64().unwrap_or_default();
+            match input["action"].as_str() {
+                Some("add") => {
+                    let count = input["items"].as_array().map_or(0, Vec::len);
+                    format!("📋 plan +{} step{}", count, if count == 1 { "" } else { "s" })
+                }
+                Some("complete") => format!("📋 plan: step {} done", id),
+                _ => format!("📋 plan: update step {}", id),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(value: serde_json::Value) -> TodoInput {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn steps_are_added_updated_and_completed() {
+        let mut todos = Vec::new();
+        apply(
+            &mut todos,
+            input(serde_json::json!({ "action": "add", "items": ["read", "edit"] })),
+        )
+        .unwrap();
+        apply(
+            &mut todos,
+            input(serde_json::json!({ "action": "update", "id": 2, "status": "in_progress" })),
+        )
+        .unwrap();
+        apply(
+            &mut todos,
+            input(serde_json::json!({ "action": "complete", "id": 1 })),
+        )
+        .unwrap();
+        apply(
+            &mut todos,
+            input(serde_json::json!({ "action": "add", "items": ["test"] })),
+        )
+        .unwrap();
+
+        assert_eq!(
+            render(&todos),
+            "Plan (1/3 done):\n1. [x] read\n2. [~] edit\n3. [ ] test"
+        );
+        assert!(apply(
+            &mut todos,
+            input(serde_json::json!({ "action": "complete", "id": 9 }))
+        )
+        .is_err());
+        assert!(apply(
+            &mut todos,
+            input(serde_json::json!({ "action": "update", "id": 1 }))
+        )
+        .is_err());
+        assert!(apply(&mut todos, input(serde_json::json!({ "action": "add" }))).is_err());
+    }
+}
@@ -1,36 +1,101 @@
-use crate::client::ClientSession;
-use crate::protocol::StreamEvent;
+use crate::agent::{is_network_error, resolve_model, MODEL_ALIASES};
+use crate::ai_sdk::{ContentBlock, MessageParam, ToolChoice};
+use crate::client::{ClientSession, EventHandler};
+use crate::clock;
+use crate::commands::{self, SlashCommand};
+use crate::config::ColorTheme;
+use crate::conversation::text_content;
+use crate::protocol::{
+    ApprovalDecision, ApprovalRequest, ComparedAnswer, DescribeRequest, FileDiff,
+    FilePreviewResponse, ResponseStats, SendMessageRequest, SessionListResponse, TodoItem,
+    TodoStatus, TurnActivity, WorkspaceFilesResponse,
+};
+use crate::tools::{format_size, get_all_tools, group_thousands, stats_table, ToolDefinition};
+use base64::Engine;
 use crossterm::cursor::MoveTo;
-use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::event::{
+    self, DisableBracketedPaste, EnableBracketedPaste, Event, EventStream, KeyCode, KeyEvent,
+    KeyModifiers, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags,
+};
 use crossterm::execute;
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode, size};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, size, supports_keyboard_enhancement, EnterAlternateScreen,
+    LeaveAlternateScreen,
+};
+use futures::StreamExt;
 use ratatui::backend::CrosstermBackend;
-use ratatui::style::{Color, Modifier, Style};
+use ratatui::layout::{Constraint, Layout, Rect, Size};
+use ratatui::style::{Modifier, Style};
 use ratatui::text::{Line, Span, Text};
 use ratatui::widgets::{Block, Borders, Clear, Paragraph, Widget, Wrap};
 use ratatui::{Frame, Terminal, TerminalOptions, Viewport};
-use std::fmt::Display;
+use std::collections::{HashMap, VecDeque};
 use std::io;
 use std::io::Write;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+mod compare;
+mod file_tree;
+mod input;
+mod palette;
+mod stderr;
+mod theme;
+mod title;
+
+use file_tree::FileTree;
+use input::InputBuffer;
+use palette::{Palette, PaletteAction};
+use stderr::StderrCapture;
+use theme::theme;
+use title::{Activity, TitleUpdater};
 
 type TuiTerminal = Terminal<CrosstermBackend<io::Stdout>>;
 
-const INPUT_HEIGHT: u16 = 6;
+/// Bounds for the inline input viewport, borders included. The viewport
+/// grows with the wrapped input up to `TARS_INPUT_MAX_HEIGHT` rows (default
+/// 10), and never past half the terminal.
+const MIN_INPUT_HEIGHT: u16 = 3;
+const DEFAULT_MAX_INPUT_HEIGHT: u16 = 10;
+const MIN_WIDTH: u16 = 20;
+/// How often to check whether the API is reachable again while offline.
+const CONNECTIVITY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Queued messages shown above the input before the list is summarized.
+const MAX_QUEUED_ROWS: usize = 5;
+/// Rows the expanded plan panel may take, header included.
+const MAX_PLAN_ROWS: usize = 8;
+/// Rows the stderr panel may take, header included.
+const MAX_STDERR_ROWS: usize = 8;
+/// Lines of captured stderr kept for the panel.
+const MAX_STDERR_LINES: usize = 500;
+/// Lines a side pane moves per PageUp/PageDown.
+const PANE_PAGE: u16 = 10;
+/// Columns of the file tree sidebar, borders included.
+const SIDEBAR_WIDTH: u16 = 32;
+/// Files the agent touched that the command palette offers.
+const MAX_RECENT_FILES: usize = 20;
 
 // Restores terminal settings even if the loop exits early.
-struct TerminalGuard;
+struct TerminalGuard {
+    /// Whether keyboard enhancement flags were pushed and need popping.
+    keyboard_enhanced: bool,
+}
 
 impl TerminalGuard {
-    fn new() -> Self {
-        Self
+    fn new(keyboard_enhanced: bool) -> Self {
+        Self { keyboard_enhanced }
     }
 }
 
 impl Drop for TerminalGuard {
     fn drop(&mut self) {
+        if self.keyboard_enhanced {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(io::stdout(), DisableBracketedPaste);
         let _ = disable_raw_mode();
         let _ = io::stdout().flush();
     }
@@ -39,10 +104,35 @@ impl Drop for TerminalGuard {
 #[derive(Debug, Clone)]
 pub enum ChatMessage {
     User(String),
-    Assistant(String),
-    ToolUse { name: String, input: String },
-    ToolResult { content: String, is_error: bool },
+    Assistant {
+        /// The header, e.g. `tars (claude-haiku-4-5):`.
+        label: String,
+        text: String,
+        stats: Option<ResponseStats>,
+    },
+    ToolUse {
+        name: String,
+        input: String,
+    },
+    ToolResult {
+        content: String,
+        is_error: bool,
+    },
+    /// A finished tool call collapsed to its one-line summary.
+    Tool {
+        summary: String,
+        error: Option<String>,
+    },
     Info(String),
+    /// A reply that was cut short or refused.
+    Warning(String),
+    /// Answers to a `/compare` prompt, side by side.
+    Comparison(Vec<ComparedAnswer>),
+    /// A full-width rule marking the start of a session or topic.
+    Separator {
+        title: String,
+        time: String,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -61,35 +151,39 @@ impl LineSpec {
 }
 
 impl ChatMessage {
-    fn line_specs(&self) -> Vec<LineSpec> {
+    fn line_specs(&self, width: u16) -> Vec<LineSpec> {
         match self {
             ChatMessage::User(msg) => {
-                let header_style = Style::default()
-                    .fg(Color::Blue)
-                    .add_modifier(Modifier::BOLD);
-                let body_style = Style::default().fg(Color::Blue);
+                let header_style = theme().user.add_modifier(Modifier::BOLD);
+                let body_style = theme().user;
                 let mut lines = vec![LineSpec::new("You:", header_style)];
                 for line in msg.lines() {
                     lines.push(LineSpec::new(format!("  {}", line), body_style));
                 }
                 lines
             }
-            ChatMessage::Assistant(msg) => {
-                let header_style = Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD);
-                let body_style = Style::default().fg(Color::Yellow);
-                let mut lines = vec![LineSpec::new("Claude:", header_style)];
+            ChatMessage::Assistant {
+                label,
+                text: msg,
+                stats,
+            } => {
+                let header_style = theme().assistant.add_modifier(Modifier::BOLD);
+                let body_style = theme().assistant;
+                let mut lines = vec![LineSpec::new(label.as_str(), header_style)];
                 for line in msg.lines() {
                     lines.push(LineSpec::new(format!("  {}", line), body_style));
                 }
+                if let Some(stats) = stats {
+                    lines.push(LineSpec::new(
+                        format!("  {}", format_stats(stats)),
+                        theme().dim,
+                    ));
+                }
                 lines
             }
             ChatMessage::ToolUse { name, input } => {
-                let header_style = Style::default()
-                    .fg(Color::Green)
-                    .add_modifier(Modifier::BOLD);
-                let body_style = Style::default().fg(Color::Green);
+                let header_style = theme().tool.add_modifier(Modifier::BOLD);
+                let body_style = theme().tool;
                 let input_str = Self::truncate(input, 200, "...\n[truncated]");
                 let mut lines = vec![LineSpec::new(format!("tool: {}(", name), header_style)];
                 for line in input_str.lines() {
@@ -100,9 +194,9 @@ impl ChatMessage {
             }
             ChatMessage::ToolResult { content, is_error } => {
                 let body_style = if *is_error {
-                    Style::default().fg(Color::Red)
+                    theme().error
                 } else {
-                    Style::default().fg(Color::Cyan)
+                    theme().result
                 };
                 let header_style = body_style.add_modifier(Modifier::BOLD);
                 let content_str = Self::truncate(content, 300, "...\n[output truncated]");
@@ -112,39 +206,51 @@ impl ChatMessage {
                 }
                 lines
             }
+            ChatMessage::Tool { summary, error } => match error {
+                None => vec![LineSpec::new(summary.clone(), theme().tool)],
+                Some(error) => vec![LineSpec::new(
+                    format!(
+                        "{} — {}",
+                        summary,
+                        Self::truncate(error.lines().next().unwrap_or_default(), 200, "...")
+                    ),
+                    theme().error,
+                )],
+            },
             ChatMessage::Info(msg) => vec![LineSpec::new(
                 format!("ℹ {}", msg),
-                Style::default()
-                    .fg(Color::Gray)
-                    .add_modifier(Modifier::ITALIC),
+                theme().info.add_modifier(Modifier::ITALIC),
             )],
+            ChatMessage::Warning(msg) => vec![LineSpec::new(
+                format!("⚠ {}", msg),
+                theme().error.add_modifier(Modifier::BOLD),
+            )],
+            ChatMessage::Comparison(answers) => compare::line_specs(answers, width),
+            ChatMessage::Separator { title, time } => {
+                let label = format!("── {} · {} ", title, time);
+                let fill = (width as usize).saturating_sub(label.chars().count());
+                vec![LineSpec::new(
+                    format!("{}{}", label, "─".repeat(fill)),
+                    theme().dim.add_modifier(Modifier::BOLD),
+                )]
+            }
         }
     }
 
-    fn to_text(&self) -> Text<'static> {
+    fn to_text(&self, width: u16) -> Text<'static> {
         let lines = self
-            .line_specs()
+            .line_specs(width)
             .into_iter()
             .map(|spec| Line::from(Span::styled(spec.text, spec.style)))
             .collect::<Vec<_>>();
         Text::from(lines)
     }
 
-    fn plain_lines(&self) -> Vec<String> {
-        self.line_specs()
-            .into_iter()
-            .map(|spec| spec.text)
-            .collect()
-    }
-
+    /// Height of the message once word-wrapped to `width`, matching how the
+    /// paragraph is actually rendered into scrollback.
     fn rendered_height(&self, width: u16) -> u16 {
-        let width = width.max(1) as usize;
-        let mut total = 0usize;
-        for line in self.plain_lines() {
-            let len = line.len().max(1);
-            total += len.div_ceil(width);
-        }
-        total as u16
+        let paragraph = Paragraph::new(self.to_text(width)).wrap(Wrap { trim: false });
+        paragraph.line_count(width.max(1)).min(u16::MAX as usize) as u16
     }
 
     fn truncate(value: &str, max: usize, suffix: &str) -> String {
@@ -159,7 +265,10 @@ impl ChatMessage {
 
 #[derive(Debug)]
 pub enum UiEvent {
-    ApiResponse(String),
+    ApiResponse {
+        text: String,
+        stats: Option<ResponseStats>,
+    },
     ToolCall {
         name: String,
         input: serde_json::Value,
@@ -168,128 +277,152 @@ pub enum UiEvent {
         content: String,
         is_error: bool,
     },
+    /// The pending call waits for the user to approve it.
+    ApprovalNeeded(PendingApproval),
     Error(String),
     Info(String),
+    Warning(String),
+    /// The turn never reached the API; carries the reason.
+    Offline(String),
+    /// The agent's plan, in full, after it changed.
+    TodoUpdate(Vec<TodoItem>),
+    /// Every file changed in the session so far, after a tool changed one.
+    DiffUpdate(Vec<FileDiff>),
+    /// Each model's answer to a `/compare` prompt.
+    Comparison(Vec<ComparedAnswer>),
+    /// The steps a profile's planner drafted for the turn.
+    Plan {
+        steps: Vec<String>,
+        stats: ResponseStats,
+    },
+    /// A fresh listing of the workspace for the file tree.
+    Files(WorkspaceFilesResponse),
+    /// A file opened from the file tree.
+    Preview(FilePreviewResponse),
+    /// The server's live sessions, for the command palette.
+    Sessions(SessionListResponse),
+    /// The API is reachable again after being offline.
+    Online,
+    /// A line written to stderr while the TUI is up.
+    Stderr(String),
+    /// How far the running tool has got, from the turn's heartbeat.
+    ToolProgress(String),
+    /// `/describe` wrote a commit message, or a pull request description
+    /// when `pr` is set.
+    Described {
+        text: String,
+        pr: bool,
+    },
+    /// `/stats` fetched the tool stats of the session and the server.
+    Stats(String),
+    /// `/patch` fetched what a turn changed, as a git patch.
+    Patch(String),
+    /// `/export` fetched the transcript as Markdown.
+    Exported(String),
+    /// `/retry` or `/edit` removed the last turn.
+    Rewound {
+        text: String,
+        pinned: bool,
+        retry: bool,
+    },
+    /// `/new`, `/fork` or the command palette moved the TUI to another
+    /// session. A fresh session starts with an empty history; a fork
+    /// continues the previous one.
+    SessionStarted {
+        client: ClientSession,
+        message: String,
+        fresh: bool,
+        /// Heads the separator in the transcript.
+        title: &'static str,
+    },
+    /// `/tab` or Ctrl+N started a session for a new tab.
+    TabOpened(ClientSession),
+    /// An event for the session in the tab with this id, which is held back
+    /// while another tab is showing.
+    Tab {
+        tab: u64,
+        event: Box<UiEvent>,
+    },
     Done,
-    Quit,
-}
-
-struct InputBuffer {
-    lines: Vec<String>,
-    cursor_x: usize,
-    cursor_y: usize,
+    Terminal(Event),
 }
 
-impl InputBuffer {
-    fn new() -> Self {
-        Self {
-            lines: vec![String::new()],
-            cursor_x: 0,
-            cursor_y: 0,
-        }
-    }
-
-    fn clear(&mut self) {
-        self.lines = vec![String::new()];
-        self.cursor_x = 0;
-        self.cursor_y = 0;
-    }
-
-    fn insert_char(&mut self, c: char) {
-        let line = &mut self.lines[self.cursor_y];
-        if self.cursor_x >= line.len() {
-            line.push(c);
-        } else {
-            line.insert(self.cursor_x, c);
-        }
-        self.cursor_x += 1;
-    }
-
-    fn delete_char(&mut self) {
-        let line = &mut self.lines[self.cursor_y];
-        if self.cursor_x > 0 {
-            line.remove(self.cursor_x - 1);
-            self.cursor_x -= 1;
-        } else if self.cursor_y > 0 {
-            let prev_line = self.lines.remove(self.cursor_y);
-            self.cursor_y -= 1;
-            self.cursor_x = self.lines[self.cursor_y].len();
-            self.lines[self.cursor_y].push_str(&prev_line);
-        }
-    }
-
-    fn new_line(&mut self) {
-        let line = &self.lines[self.cursor_y];
-        let remaining: String = line.chars().skip(self.cursor_x).collect();
-        self.lines[self.cursor_y] = line.chars().take(self.cursor_x).collect();
-        self.lines.insert(self.cursor_y + 1, remaining);
-        self.cursor_y += 1;
-        self.cursor_x = 0;
-    }
-
-    fn move_left(&mut self) {
-        if self.cursor_x > 0 {
-            self.cursor_x -= 1;
-        } else if self.cursor_y > 0 {
-            self.cursor_y -= 1;
-            self.cursor_x = self.lines[self.cursor_y].len();
-        }
-    }
-
-    fn move_right(&mut self) {
-        let line_len = self.lines[self.cursor_y].len();
-        if self.cursor_x < line_len {
-            self.cursor_x += 1;
-        } else if self.cursor_y < self.lines.len() - 1 {
-            self.cursor_y += 1;
-            self.cursor_x = 0;
+impl UiEvent {
+    fn tab(tab: u64, event: UiEvent) -> Self {
+        UiEvent::Tab {
+            tab,
+            event: Box::new(event),
         }
     }
+}
 
-    fn move_up(&mut self) {
-        if self.cursor_y > 0 {
-            self.cursor_y -= 1;
-            self.cursor_x = self.cursor_x.min(self.lines[self.cursor_y].len());
-        }
-    }
+/// A workspace file opened from the file tree, shown read-only.
+struct Preview {
+    path: String,
+    content: String,
+    truncated: bool,
+    /// Lines scrolled past.
+    scroll: u16,
+}
 
-    fn move_down(&mut self) {
-        if self.cursor_y < self.lines.len() - 1 {
-            self.cursor_y += 1;
-            self.cursor_x = self.cursor_x.min(self.lines[self.cursor_y].len());
-        }
-    }
+/// A session open in the TUI. The showing tab's state lives in `App`
+/// itself; the others are parked here until switched to.
+struct Tab {
+    /// Tags the events of the tab's session.
+    id: u64,
+    parked: Option<ParkedSession>,
+}
 
-    fn is_empty(&self) -> bool {
-        self.lines.iter().all(|l| l.is_empty())
-    }
+/// What `App` keeps about a session while its tab isn't showing.
+struct ParkedSession {
+    client: Arc<ClientSession>,
+    stream: Option<JoinHandle<()>>,
+    is_loading: bool,
+    prefill: Option<String>,
+    queued: Vec<SendMessageRequest>,
+    in_flight: Option<SendMessageRequest>,
+    pending_tool: Option<(String, serde_json::Value)>,
+    approval: Option<PendingApproval>,
+    last_tool: Option<FinishedTool>,
+    todos: Vec<TodoItem>,
+    changes: Vec<FileDiff>,
+    /// Events that arrived meanwhile, handled on switching back.
+    backlog: Vec<UiEvent>,
+}
 
-    fn render(&self) -> Text<'static> {
-        if self.is_empty() {
-            return Text::from(Span::styled(
-                "Type your message here...",
-                Style::default().fg(Color::DarkGray),
-            ));
+impl ParkedSession {
+    /// A session that has not been shown yet; its stream starts once it is.
+    fn new(client: Arc<ClientSession>) -> Self {
+        Self {
+            client,
+            stream: None,
+            is_loading: false,
+            prefill: None,
+            queued: Vec::new(),
+            in_flight: None,
+            pending_tool: None,
+            approval: None,
+            last_tool: None,
+            todos: Vec::new(),
+            changes: Vec::new(),
+            backlog: Vec::new(),
         }
-        Text::from(
-            self.lines
-                .iter()
-                .map(|l| Line::from(l.clone()))
-                .collect::<Vec<_>>(),
-        )
     }
 }
 
-impl Default for InputBuffer {
-    fn default() -> Self {
-        Self::new()
-    }
+/// A call waiting for the user to approve it.
+#[derive(Debug, Clone)]
+pub struct PendingApproval {
+    tool_use_id: String,
+    name: String,
+    summary: String,
 }
 
-impl Display for InputBuffer {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.lines.join("\n"))
-    }
+struct FinishedTool {
+    name: String,
+    input: serde_json::Value,
+    content: String,
+    is_error: bool,
 }
 
 pub struct App {
@@ -298,12 +431,97 @@ pub struct App {
     should_quit: bool,
     sender: mpsc::Sender<UiEvent>,
     receiver: mpsc::Receiver<UiEvent>,
+    terminal_input: TerminalInput,
     is_loading: bool,
+    prefill: Option<String>,
+    /// Messages submitted while a turn was running or the API was
+    /// unreachable, sent in order as each turn finishes.
+    queued: Vec<SendMessageRequest>,
+    /// The message whose turn is running, requeued if it never reached the
+    /// API.
+    in_flight: Option<SendMessageRequest>,
+    /// Set when a turn could not reach the API; cleared by the background
+    /// ping once it can again.
+    offline: bool,
+    /// The call whose result is still outstanding; it is rendered together
+    /// with its result.
+    pending_tool: Option<(String, serde_json::Value)>,
+    /// How far the pending call has got, for tools that report it.
+    tool_progress: Option<String>,
+    /// Set while the pending call waits for the user's approval; y, a and
+    /// n answer it.
+    approval: Option<PendingApproval>,
+    /// The most recent finished call, kept in full for Ctrl+O.
+    last_tool: Option<FinishedTool>,
+    tools: Vec<ToolDefinition>,
+    /// The agent's plan, shown above the input.
+    todos: Vec<TodoItem>,
+    /// Whether the plan panel lists every step or just the current one.
+    todos_expanded: bool,
+    /// The most recent lines written to stderr, which is captured so nothing
+    /// printed there lands on the screen.
+    stderr: Vec<String>,
+    /// Whether the stderr panel is open (Ctrl+L).
+    show_stderr: bool,
+    /// Lines captured since the stderr panel was last open.
+    unseen_stderr: usize,
+    /// Files changed this session, shown next to the conversation in the
+    /// split layout.
+    changes: Vec<FileDiff>,
+    /// Whether the changes panel is open (Ctrl+D).
+    show_changes: bool,
+    /// Lines of the changes panel scrolled past.
+    changes_scroll: u16,
+    /// The workspace sidebar, while it is open (Ctrl+F).
+    file_tree: Option<FileTree>,
+    /// Whether arrow keys and Enter go to the file tree rather than the
+    /// input; Tab switches.
+    tree_focused: bool,
+    preview: Option<Preview>,
+    /// The command palette, while it is open (Ctrl+P).
+    palette: Option<Palette>,
+    /// Paths the agent's tools were called with, most recent first.
+    recent_files: Vec<String>,
+    /// What replies are labeled as, from `name` in the config.
+    agent_name: String,
+    /// The model behind the latest reply.
+    model: String,
+    /// The model `/model` picked for the messages sent from then on; the
+    /// server's own until then.
+    chosen_model: Option<String>,
+    /// The models `/compare` asks, from `compare` in the config.
+    compare_models: Vec<String>,
+    /// Whether the split layout is up, for the changes panel or the file
+    /// tree. It takes over the alternate screen, so messages are held back
+    /// from the scrollback meanwhile.
+    split: bool,
+    /// How many of `messages` have been written to the scrollback.
+    printed: usize,
+    /// The queued message highlighted for editing or removal.
+    queue_cursor: Option<usize>,
+    /// Where the queued message being edited in the input came from.
+    editing_queued: Option<usize>,
+    viewport_height: u16,
+    /// The terminal size the viewport was last laid out for.
+    terminal_size: Option<Size>,
+    max_input_height: u16,
     client: Arc<ClientSession>,
+    /// Forwards the session's server events; replaced by `/new`.
+    stream: Option<JoinHandle<()>>,
+    /// Every open session, in tab order; more than one shows a tab bar.
+    tabs: Vec<Tab>,
+    /// The index of the showing tab.
+    active_tab: usize,
+    next_tab_id: u64,
 }
 
 impl App {
-    pub fn new(client: ClientSession) -> Self {
+    pub fn new(
+        client: ClientSession,
+        agent_name: String,
+        model: String,
+        compare_models: Vec<String>,
+    ) -> Self {
         let (sender, receiver) = mpsc::channel(100);
 
         Self {
@@ -312,257 +530,2568 @@ impl App {
             should_quit: false,
             sender,
             receiver,
+            terminal_input: TerminalInput::default(),
             is_loading: false,
+            prefill: None,
+            queued: Vec::new(),
+            in_flight: None,
+            offline: false,
+            pending_tool: None,
+            tool_progress: None,
+            approval: None,
+            last_tool: None,
+            tools: get_all_tools(),
+            todos: Vec::new(),
+            todos_expanded: false,
+            stderr: Vec::new(),
+            show_stderr: false,
+            unseen_stderr: 0,
+            changes: Vec::new(),
+            show_changes: false,
+            changes_scroll: 0,
+            file_tree: None,
+            tree_focused: false,
+            preview: None,
+            palette: None,
+            recent_files: Vec::new(),
+            agent_name,
+            model,
+            chosen_model: None,
+            compare_models,
+            split: false,
+            printed: 0,
+            queue_cursor: None,
+            editing_queued: None,
+            viewport_height: MIN_INPUT_HEIGHT,
+            terminal_size: None,
+            max_input_height: std::env::var("TARS_INPUT_MAX_HEIGHT")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_MAX_INPUT_HEIGHT),
             client: Arc::new(client),
+            stream: None,
+            tabs: vec![Tab {
+                id: 0,
+                parked: None,
+            }],
+            active_tab: 0,
+            next_tab_id: 1,
         }
     }
 
     fn draw(&mut self, f: &mut Frame) {
         let area = f.area();
-        let title = if self.is_loading {
-            " Input (Enter to send, Esc to quit) [Thinking...] "
+        let side_pane = self.preview.is_some() || self.show_changes;
+        // Each pane of the split layout needs the room of the inline one.
+        let panes = if self.split {
+            1 + self.file_tree.is_some() as u16 + side_pane as u16
+        } else {
+            1
+        };
+        let min_width = panes * MIN_WIDTH;
+        if area.width < min_width || area.height < MIN_INPUT_HEIGHT {
+            let placeholder = Paragraph::new(format!(
+                "Terminal too small (need {}x{})",
+                min_width, MIN_INPUT_HEIGHT
+            ))
+            .style(theme().dim)
+            .wrap(Wrap { trim: true });
+            f.render_widget(Clear, area);
+            f.render_widget(placeholder, area);
+            return;
+        }
+
+        if self.split {
+            let mut constraints = Vec::new();
+            if self.file_tree.is_some() {
+                constraints.push(Constraint::Length(SIDEBAR_WIDTH));
+            }
+            constraints.push(Constraint::Min(MIN_WIDTH));
+            if side_pane {
+                constraints.push(Constraint::Percentage(50));
+            }
+            let areas = Layout::horizontal(constraints).split(area);
+            let mut areas = areas.iter().copied();
+            if self.file_tree.is_some()
+                && let Some(sidebar) = areas.next()
+            {
+                self.draw_file_tree(f, sidebar);
+            }
+            let conversation = areas.next().unwrap_or(area);
+            let prompt_height = self.desired_height(conversation.width, conversation.height);
+            let [history, prompt] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(prompt_height)])
+                    .areas(conversation);
+            f.render_widget(self.history_paragraph(history), history);
+            self.draw_prompt(f, prompt);
+            if let Some(pane) = areas.next() {
+                if self.preview.is_some() {
+                    self.draw_preview(f, pane);
+                } else {
+                    self.draw_changes(f, pane);
+                }
+            }
+        } else {
+            self.draw_prompt(f, area);
+        }
+    }
+
+    /// The tab bar, captured stderr, the plan, the queued messages and the
+    /// input box, top to bottom.
+    fn draw_prompt(&self, f: &mut Frame, area: Rect) {
+        let tab_rows = self
+            .tab_rows()
+            .min(area.height.saturating_sub(MIN_INPUT_HEIGHT));
+        if tab_rows > 0 {
+            let tab_area = Rect {
+                height: tab_rows,
+                ..area
+            };
+            f.render_widget(Paragraph::new(self.tab_bar()), tab_area);
+        }
+        let area = Rect {
+            y: area.y + tab_rows,
+            height: area.height - tab_rows,
+            ..area
+        };
+
+        let stderr_rows = self
+            .stderr_rows()
+            .min(area.height.saturating_sub(MIN_INPUT_HEIGHT));
+        if stderr_rows > 0 {
+            let stderr_area = Rect {
+                height: stderr_rows,
+                ..area
+            };
+            f.render_widget(
+                Paragraph::new(self.stderr_text(stderr_rows as usize)),
+                stderr_area,
+            );
+        }
+        let area = Rect {
+            y: area.y + stderr_rows,
+            height: area.height - stderr_rows,
+            ..area
+        };
+
+        let plan_rows = self
+            .plan_rows()
+            .min(area.height.saturating_sub(MIN_INPUT_HEIGHT));
+        if plan_rows > 0 {
+            let plan_area = Rect {
+                height: plan_rows,
+                ..area
+            };
+            f.render_widget(
+                Paragraph::new(self.plan_text(plan_rows as usize)),
+                plan_area,
+            );
+        }
+        let area = Rect {
+            y: area.y + plan_rows,
+            height: area.height - plan_rows,
+            ..area
+        };
+
+        let queue_rows = self
+            .queue_rows()
+            .min(area.height.saturating_sub(MIN_INPUT_HEIGHT));
+        if queue_rows > 0 {
+            let queue_area = Rect {
+                height: queue_rows,
+                ..area
+            };
+            f.render_widget(
+                Paragraph::new(self.queue_text(queue_rows as usize)),
+                queue_area,
+            );
+        }
+        let area = Rect {
+            y: area.y + queue_rows,
+            height: area.height - queue_rows,
+            ..area
+        };
+
+        let palette_rows = self
+            .palette_rows()
+            .min(area.height.saturating_sub(MIN_INPUT_HEIGHT));
+        if let Some(palette) = &self.palette
+            && palette_rows > 0
+        {
+            let palette_area = Rect {
+                height: palette_rows,
+                ..area
+            };
+            f.render_widget(
+                Paragraph::new(palette.render(palette_rows as usize)),
+                palette_area,
+            );
+        }
+        let area = Rect {
+            y: area.y + palette_rows,
+            height: area.height - palette_rows,
+            ..area
+        };
+
+        let title = if let Some(approval) = &self.approval {
+            format!(
+                " Approve {}? y yes, a always, n no (or type why not and Enter) ",
+                approval.summary
+            )
+        } else if self.queue_cursor.is_some() {
+            " Queued (Enter to edit, Delete to remove, Esc to go back) ".to_string()
+        } else if let Some((name, _)) = &self.pending_tool {
+            match &self.tool_progress {
+                Some(progress) => format!(
+                    " Input (Enter to queue, Esc to quit) [Running {}... {}] ",
+                    name, progress
+                ),
+                None => format!(
+                    " Input (Enter to queue, Esc to quit) [Running {}...] ",
+                    name
+                ),
+            }
+        } else if self.offline {
+            format!(
+                " Input (Enter to queue, Esc to quit) [Offline... {} queued] ",
+                self.queued.len()
+            )
+        } else if self.is_loading && !self.queued.is_empty() {
+            format!(
+                " Input (Enter to queue, Esc to quit) [Thinking... {} queued] ",
+                self.queued.len()
+            )
+        } else if self.is_loading {
+            " Input (Enter to queue, Esc to quit) [Thinking...] ".to_string()
         } else {
-            " Input (Enter to send, Esc to quit) "
+            " Input (Enter to send, Esc to quit) ".to_string()
         };
 
-        let input_paragraph = Paragraph::new(self.input.render())
+        let inner_width = area.width.saturating_sub(2);
+        let inner_height = area.height.saturating_sub(2).max(1) as usize;
+        let (text, (cursor_row, cursor_col)) = self.input.render(inner_width);
+        // Scroll so the cursor row stays visible once the box stops growing.
+        let scroll = cursor_row.saturating_sub(inner_height - 1);
+
+        let input_paragraph = Paragraph::new(text)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .title(title)
-                    .border_style(Style::default().fg(Color::DarkGray)),
+                    .border_style(theme().dim),
             )
-            .wrap(Wrap { trim: false });
+            .scroll((scroll as u16, 0));
 
         f.render_widget(input_paragraph, area);
 
-        let cursor_x = (self.input.cursor_x + 1) as u16;
-        let cursor_y = self.input.cursor_y as u16;
-        let x = (area.x + cursor_x).min(area.x + area.width - 2);
-        let y = (area.y + 1 + cursor_y).min(area.y + area.height - 2);
-        f.set_cursor_position((x, y));
-    }
-
-    fn append_message(
-        &mut self,
-        terminal: &mut TuiTerminal,
-        message: ChatMessage,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let width = terminal.size()?.width;
-        let height = message.rendered_height(width).saturating_add(1);
-        let mut text = message.to_text();
-        text.extend(Text::raw("\n"));
-        // Insert above the inline viewport so the log stays in scrollback.
-        terminal.insert_before(height, |buf| {
-            let paragraph = Paragraph::new(text).wrap(Wrap { trim: false });
-            paragraph.render(buf.area, buf);
-        })?;
-        self.messages.push(message);
-        Ok(())
+        let x = area.x + 1 + cursor_col as u16;
+        let y = area.y + 1 + (cursor_row - scroll) as u16;
+        f.set_cursor_position((x.min(area.right() - 2), y.min(area.bottom() - 2)));
     }
 
-    fn handle_events(
-        &mut self,
-        terminal: &mut TuiTerminal,
-    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-        while let Ok(event) = self.receiver.try_recv() {
-            match event {
-                UiEvent::ApiResponse(msg) => {
-                    self.append_message(terminal, ChatMessage::Assistant(msg))?;
-                    self.is_loading = false;
-                }
-                UiEvent::ToolCall { name, input } => {
-                    self.append_message(
-                        terminal,
-                        ChatMessage::ToolUse {
-                            name,
-                            input: serde_json::to_string(&input).unwrap_or_default(),
-                        },
-                    )?;
-                }
-                UiEvent::ToolResult { content, is_error } => {
-                    self.append_message(terminal, ChatMessage::ToolResult { content, is_error })?;
-                }
-                UiEvent::Error(err) => {
-                    self.append_message(terminal, ChatMessage::Info(format!("Error: {}", err)))?;
-                    self.is_loading = false;
-                }
-                UiEvent::Info(msg) => {
-                    self.append_message(terminal, ChatMessage::Info(msg))?;
-                    self.is_loading = false;
-                }
-                UiEvent::Done => {
-                    self.is_loading = false;
-                }
-                UiEvent::Quit => {
-                    self.should_quit = true;
-                    return Ok(false);
-                }
+    /// The end of the conversation, as much of it as fits in `area`.
+    fn history_paragraph(&self, area: Rect) -> Paragraph<'static> {
+        let mut shown = 0;
+        let mut height = 0;
+        for message in self.messages.iter().rev() {
+            if height >= area.height {
+                break;
             }
+            height = height.saturating_add(message.rendered_height(area.width).saturating_add(1));
+            shown += 1;
         }
+        let mut text = Text::default();
+        for message in &self.messages[self.messages.len() - shown..] {
+            text.extend(message.to_text(area.width));
+            text.extend(Text::raw("\n"));
+        }
+        let paragraph = Paragraph::new(text).wrap(Wrap { trim: false });
+        let overflow = paragraph.line_count(area.width.max(1)) as u16;
+        paragraph.scroll((overflow.saturating_sub(area.height), 0))
+    }
 
-        if event::poll(Duration::from_millis(50))?
-            && let Event::Key(key) = event::read()?
-        {
-            if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
-                self.should_quit = true;
-                let _ = self.sender.try_send(UiEvent::Quit);
-                return Ok(false);
+    /// The session's cumulative diff, scrolled with PageUp and PageDown.
+    fn draw_changes(&mut self, f: &mut Frame, area: Rect) {
+        let mut lines = Vec::new();
+        let (mut added, mut removed) = (0, 0);
+        for file in &self.changes {
+            if !lines.is_empty() {
+                lines.push(Line::default());
             }
-
-            match key.code {
-                KeyCode::Esc => {
-                    self.should_quit = true;
-                    let _ = self.sender.try_send(UiEvent::Quit);
-                    return Ok(false);
-                }
-                KeyCode::Enter => {
-                    if key.modifiers.contains(KeyModifiers::SHIFT) {
-                        self.input.new_line();
-                    } else if !self.input.is_empty() {
-                        let msg = self.input.to_string();
-                        if !msg.trim().is_empty() {
-                            self.append_message(terminal, ChatMessage::User(msg.clone()))?;
-                            self.input.clear();
-                            self.is_loading = true;
-                            let client = Arc::clone(&self.client);
-                            let sender = self.sender.clone();
-                            tokio::spawn(async move {
-                                if let Err(err) = client.send_message(msg).await {
-                                    let _ = sender.send(UiEvent::Error(err.to_string())).await;
-                                }
-                            });
-                        }
-                    }
-                }
-                KeyCode::Char(c) => {
-                    self.input.insert_char(c);
-                }
-                KeyCode::Backspace => {
-                    self.input.delete_char();
-                }
-                KeyCode::Left => {
-                    self.input.move_left();
-                }
-                KeyCode::Right => {
-                    self.input.move_right();
-                }
-                KeyCode::Up => {
-                    self.input.move_up();
-                }
-                KeyCode::Down => {
-                    self.input.move_down();
-                }
-                KeyCode::Home => {
-                    self.input.cursor_x = 0;
-                }
-                KeyCode::End => {
-                    self.input.cursor_x = self.input.lines[self.input.cursor_y].len();
-                }
-                _ => {}
+            for line in file.diff.lines() {
+                let style = if line.starts_with("+++") || line.starts_with("---") {
+                    Style::default().add_modifier(Modifier::BOLD)
+                } else if line.starts_with("@@") {
+                    theme().result
+                } else if line.starts_with('+') {
+                    added += 1;
+                    theme().tool
+                } else if line.starts_with('-') {
+                    removed += 1;
+                    theme().error
+                } else {
+                    Style::default()
+                };
+                lines.push(Line::from(Span::styled(line.to_string(), style)));
             }
         }
+        let title = if self.changes.is_empty() {
+            lines.push(Line::from(Span::styled(
+                "No files changed yet.",
+                theme().dim,
+            )));
+            " Changes (Ctrl+D to close) ".to_string()
+        } else {
+            format!(
+                " Changes · {} file{} +{} −{} (Ctrl+D to close) ",
+                self.changes.len(),
+                if self.changes.len() == 1 { "" } else { "s" },
+                added,
+                removed
+            )
+        };
 
-        Ok(true)
+        render_pane(f, area, title, Text::from(lines), &mut self.changes_scroll);
     }
-}
 
-pub fn run_tui(client: ClientSession) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    let (_, rows) = size()?;
-    if rows > 0 {
-        // Push existing screen content into scrollback without clearing it.
-        for _ in 0..rows {
-            writeln!(stdout)?;
+    /// The file opened from the file tree, with line numbers.
+    fn draw_preview(&mut self, f: &mut Frame, area: Rect) {
+        let Some(preview) = &mut self.preview else {
+            return;
+        };
+        let count = preview.content.lines().count();
+        let number_width = count.max(1).to_string().len();
+        let lines: Vec<Line> = preview
+            .content
+            .lines()
+            .enumerate()
+            .map(|(index, line)| {
+                Line::from(vec![
+                    Span::styled(
+                        format!("{:>width$} ", index + 1, width = number_width),
+                        theme().dim,
+                    ),
+                    Span::raw(line.replace('\t', "    ")),
+                ])
+            })
+            .collect();
+        let title = format!(
+            " {} (read-only{}, Esc to close) ",
+            preview.path,
+            if preview.truncated { ", truncated" } else { "" }
+        );
+        render_pane(f, area, title, Text::from(lines), &mut preview.scroll);
+    }
+
+    fn draw_file_tree(&mut self, f: &mut Frame, area: Rect) {
+        let focused = self.tree_focused;
+        let Some(tree) = &mut self.file_tree else {
+            return;
+        };
+        let title = if focused {
+            " Files (Enter to open) "
+        } else {
+            " Files (Tab to browse) "
+        };
+        let text = tree.render(area.height.saturating_sub(2) as usize, focused);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(theme().dim);
+        f.render_widget(Paragraph::new(text).block(block), area);
+    }
+
+    fn activity(&self) -> Activity {
+        if let Some(approval) = &self.approval {
+            return Activity::Approval(approval.name.clone());
+        }
+        match &self.pending_tool {
+            Some((name, _)) => Activity::Running(name.clone()),
+            None if self.is_loading => Activity::Thinking,
+            None if self.offline => Activity::Offline,
+            None => Activity::Idle,
         }
-        stdout.flush()?;
     }
-    execute!(stdout, MoveTo(0, 0))?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::with_options(
-        backend,
-        TerminalOptions {
-            viewport: Viewport::Inline(INPUT_HEIGHT),
-        },
-    )?;
 
-    let mut app = App::new(client);
+    /// Viewport height that fits the tab bar, the stderr panel, the plan, the
+    /// queued messages, the command palette and the wrapped input within the
+    /// configured bounds for a terminal of the given size.
+    fn desired_height(&self, width: u16, rows: u16) -> u16 {
+        let (lines, _) = self.input.visual_lines(width.saturating_sub(2));
+        let max = self.max_input_height.min(rows / 2).max(MIN_INPUT_HEIGHT);
+        let input = (lines.len() as u16 + 2).clamp(MIN_INPUT_HEIGHT, max);
+        let panels = self.tab_rows()
+            + self.stderr_rows()
+            + self.plan_rows()
+            + self.queue_rows()
+            + self.palette_rows();
+        (input + panels).min(rows.max(MIN_INPUT_HEIGHT))
+    }
 
-    let stream_sender = app.sender.clone();
-    let stream_client = Arc::clone(&app.client);
-    let server_info = format!(
-        "Connected to {} (session {})",
-        stream_client.base_url(),
-        stream_client.session_id()
-    );
-    tokio::spawn(async move {
-        let _ = stream_sender.send(UiEvent::Info(server_info)).await;
-        let result = stream_client
-            .stream_events(|event| async {
-                let ui_event = match event {
-                    StreamEvent::Assistant { text } => UiEvent::ApiResponse(text),
-                    StreamEvent::ToolCall { name, input } => UiEvent::ToolCall { name, input },
-                    StreamEvent::ToolResult { content, is_error } => {
-                        UiEvent::ToolResult { content, is_error }
-                    }
-                    StreamEvent::Info { message } => UiEvent::Info(message),
-                    StreamEvent::Error { message } => UiEvent::Error(message),
-                    StreamEvent::Done => UiEvent::Done,
-                };
-                let _ = stream_sender.send(ui_event).await;
-            })
-            .await;
+    fn palette_rows(&self) -> u16 {
+        self.palette.as_ref().map_or(0, Palette::rows)
+    }
 
-        if let Err(err) = result {
-            let _ = stream_sender.send(UiEvent::Error(err.to_string())).await;
-        }
-    });
+    fn tab_rows(&self) -> u16 {
+        (self.tabs.len() > 1) as u16
+    }
 
-    let _guard = TerminalGuard::new();
+    /// One label per tab, the showing one reversed. Other tabs are marked
+    /// while their turn runs (⏳) and when they have unseen output (●).
+    fn tab_bar(&self) -> Text<'static> {
+        let dim = theme().dim;
+        let mut spans = Vec::new();
+        for (index, tab) in self.tabs.iter().enumerate() {
+            let (session_id, mark) = match &tab.parked {
+                None => (self.client.session_id(), ""),
+                Some(parked) if parked.is_loading => (parked.client.session_id(), " ⏳"),
+                Some(parked) if !parked.backlog.is_empty() => (parked.client.session_id(), " ●"),
+                Some(parked) => (parked.client.session_id(), ""),
+            };
+            let label = format!(
+                " {} {}{} ",
+                index + 1,
+                session_id.chars().take(8).collect::<String>(),
+                mark
+            );
+            let style = if index == self.active_tab {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                dim
+            };
+            spans.push(Span::styled(label, style));
+        }
+        spans.push(Span::styled(
+            " Ctrl+N new · Ctrl+Tab or Alt+1-9 switch",
+            dim,
+        ));
+        Text::from(Line::from(spans))
+    }
 
-    terminal.draw(|f| app.draw(f))?;
+    fn stderr_rows(&self) -> u16 {
+        match (self.show_stderr, self.unseen_stderr) {
+            (true, _) => (self.stderr.len() + 1).min(MAX_STDERR_ROWS) as u16,
+            (false, 0) => 0,
+            (false, _) => 1,
+        }
+    }
 
-    while !app.should_quit {
-        if !app.handle_events(&mut terminal)? {
-            break;
+    /// The stderr panel: a header and, when open, the latest lines. Closed,
+    /// it only says how many lines arrived unseen.
+    fn stderr_text(&self, rows: usize) -> Text<'static> {
+        let dim = theme().dim;
+        if !self.show_stderr {
+            return Text::from(Line::from(Span::styled(
+                format!(
+                    "⚠ {} new line{} on stderr (Ctrl+L to show)",
+                    self.unseen_stderr,
+                    if self.unseen_stderr == 1 { "" } else { "s" }
+                ),
+                theme().error,
+            )));
         }
 
-        terminal.draw(|f| app.draw(f))?;
+        let mut lines = vec![Line::from(Span::styled(
+            format!(
+                "stderr · {} line{} (Ctrl+L to hide)",
+                self.stderr.len(),
+                if self.stderr.len() == 1 { "" } else { "s" }
+            ),
+            dim,
+        ))];
+        let shown = rows.saturating_sub(1).min(self.stderr.len());
+        for line in &self.stderr[self.stderr.len() - shown..] {
+            lines.push(Line::from(Span::styled(
+                format!("  {}", ChatMessage::truncate(line, 200, "...")),
+                dim,
+            )));
+        }
+        Text::from(lines)
+    }
 
-        std::thread::sleep(Duration::from_millis(10));
+    fn plan_rows(&self) -> u16 {
+        match self.todos.len() {
+            0 => 0,
+            _ if !self.todos_expanded => 1,
+            steps => (steps + 1).min(MAX_PLAN_ROWS) as u16,
+        }
     }
 
-    terminal.draw(|f| {
-        let area = f.area();
-        f.render_widget(Clear, area);
-        // Place the shell prompt at the top of the cleared inline viewport.
-        f.set_cursor_position((area.x, area.y));
-    })?;
-    disable_raw_mode()?;
-    let mut stdout = io::stdout();
-    stdout.flush()?;
+    /// The plan panel: a header with progress and, when expanded, one line
+    /// per step.
+    fn plan_text(&self, rows: usize) -> Text<'static> {
+        let dim = theme().dim;
+        let done = self
+            .todos
+            .iter()
+            .filter(|item| item.status == TodoStatus::Completed)
+            .count();
+        let progress = format!("☰ Plan {}/{}", done, self.todos.len());
+        if !self.todos_expanded {
+            let current = self
+                .todos
+                .iter()
+                .find(|item| item.status == TodoStatus::InProgress)
+                .or_else(|| {
+                    self.todos
+                        .iter()
+                        .find(|item| item.status == TodoStatus::Pending)
+                })
+                .map_or("all done", |item| item.text.as_str());
+            return Text::from(Line::from(Span::styled(
+                format!(
+                    "{} · {} (Ctrl+T to expand)",
+                    progress,
+                    ChatMessage::truncate(current, 200, "...")
+                ),
+                dim,
+            )));
+        }
 
-    Ok(())
-}
+        let mut lines = vec![Line::from(Span::styled(
+            format!("{} (Ctrl+T to collapse)", progress),
+            dim,
+        ))];
+        let overflow = self.todos.len() + 1 > rows;
+        let shown = rows.saturating_sub(1 + overflow as usize);
+        for item in self.todos.iter().take(shown) {
+            let (mark, style) = match item.status {
+                TodoStatus::Pending => ("○", dim),
+                TodoStatus::InProgress => ("▸", theme().info),
+                TodoStatus::Completed => ("✓", dim.add_modifier(Modifier::CROSSED_OUT)),
+            };
+            lines.push(Line::from(Span::styled(
+                format!(
+                    "  {} {}",
+                    mark,
+                    ChatMessage::truncate(&item.text, 200, "...")
+                ),
+                style,
+            )));
+        }
+        if overflow {
+            lines.push(Line::from(Span::styled(
+                format!("  …and {} more steps", self.todos.len() - shown),
+                dim,
+            )));
+        }
+        Text::from(lines)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::InputBuffer;
+    fn queue_rows(&self) -> u16 {
+        self.queued.len().min(MAX_QUEUED_ROWS) as u16
+    }
 
-    #[test]
-    fn input_buffer_shift_enter_inserts_new_line() {
-        let mut buffer = InputBuffer::new();
-        for ch in "hello".chars() {
-            buffer.insert_char(ch);
+    /// One dimmed line per queued message, with the highlighted one reversed.
+    fn queue_text(&self, rows: usize) -> Text<'static> {
+        let pending = theme().dim.add_modifier(Modifier::ITALIC);
+        let overflow = self.queued.len() > rows;
+        let shown = if overflow { rows - 1 } else { rows };
+        let mut lines: Vec<Line> = self
+            .queued
+            .iter()
+            .take(shown)
+            .enumerate()
+            .map(|(index, request)| {
+                let first_line = request.content.lines().next().unwrap_or_default();
+                let style = if self.queue_cursor == Some(index) {
+                    pending.add_modifier(Modifier::REVERSED)
+                } else {
+                    pending
+                };
+                Line::from(Span::styled(
+                    format!("⏳ {}", ChatMessage::truncate(first_line, 200, "...")),
+                    style,
+                ))
+            })
+            .collect();
+        if overflow {
+            lines.push(Line::from(Span::styled(
+                format!("⏳ …and {} more queued", self.queued.len() - shown),
+                pending,
+            )));
+        }
+        Text::from(lines)
+    }
+
+    fn fit_viewport(
+        &mut self,
+        terminal: &mut TuiTerminal,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Drawing resizes the viewport to a changed terminal, and resizing
+        // an inline viewport queries the cursor.
+        let size = terminal.size()?;
+        if self.terminal_size != Some(size) {
+            self.terminal_input.pause();
+            terminal.autoresize()?;
+            self.terminal_size = Some(size);
+        }
+        let split = self.show_changes || self.file_tree.is_some();
+        if split != self.split {
+            return self.set_split(terminal, split);
+        }
+        if self.split {
+            return Ok(());
         }
-        buffer.new_line();
-        for ch in "world".chars() {
-            buffer.insert_char(ch);
+        let height = self.desired_height(size.width, size.height);
+        if height != self.viewport_height {
+            self.terminal_input.pause();
+            resize_viewport(terminal, height)?;
+            self.viewport_height = height;
         }
+        Ok(())
+    }
+
+    fn append_message(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        message: ChatMessage,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.messages.push(message);
+        if self.split {
+            return Ok(());
+        }
+        self.print_messages(terminal)
+    }
+
+    /// Writes the messages not yet in the scrollback above the inline
+    /// viewport.
+    fn print_messages(
+        &mut self,
+        terminal: &mut TuiTerminal,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let width = terminal.size()?.width;
+        for message in &self.messages[self.printed..] {
+            let height = message.rendered_height(width).saturating_add(1);
+            let mut text = message.to_text(width);
+            text.extend(Text::raw("\n"));
+            // Insert above the inline viewport so the log stays in scrollback.
+            terminal.insert_before(height, |buf| {
+                let paragraph = Paragraph::new(text).wrap(Wrap { trim: false });
+                paragraph.render(buf.area, buf);
+            })?;
+        }
+        self.printed = self.messages.len();
+        Ok(())
+    }
+
+    /// Switches between the inline layout and the split layout, which puts
+    /// the conversation next to the side panes on the alternate screen.
+    /// Messages that arrived meanwhile go to the scrollback on the way back.
+    fn set_split(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        split: bool,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if split == self.split {
+            return Ok(());
+        }
+        self.terminal_input.pause();
+        let viewport = if self.split {
+            execute!(io::stdout(), LeaveAlternateScreen)?;
+            Viewport::Inline(self.viewport_height)
+        } else {
+            // Leaving the alternate screen restores the cursor, so park it
+            // where the inline viewport starts.
+            terminal.draw(|f| {
+                let area = f.area();
+                f.render_widget(Clear, area);
+                f.set_cursor_position((area.x, area.y));
+            })?;
+            execute!(io::stdout(), EnterAlternateScreen)?;
+            Viewport::Fullscreen
+        };
+        *terminal = Terminal::with_options(
+            CrosstermBackend::new(io::stdout()),
+            TerminalOptions { viewport },
+        )?;
+        self.split = !self.split;
+        if !self.split {
+            self.print_messages(terminal)?;
+        }
+        Ok(())
+    }
+
+    /// Opens the file tree and fetches the workspace, or closes it along
+    /// with any preview.
+    fn toggle_file_tree(&mut self) {
+        if self.file_tree.take().is_some() {
+            self.preview = None;
+            self.tree_focused = false;
+        } else {
+            self.file_tree = Some(FileTree::new());
+            self.tree_focused = true;
+            self.refresh_files();
+        }
+    }
+
+    fn spawn_stream(&self) -> JoinHandle<()> {
+        spawn_event_stream(
+            Arc::clone(&self.client),
+            self.sender.clone(),
+            self.tabs[self.active_tab].id,
+        )
+    }
+
+    /// Starts a session in a new tab, next to the others on the server.
+    fn open_tab(&self) {
+        let client = Arc::clone(&self.client);
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let event = match client.new_session().await {
+                Ok(next) => UiEvent::TabOpened(next),
+                Err(err) => UiEvent::Error(err.to_string()),
+            };
+            let _ = sender.send(event).await;
+        });
+    }
+
+    /// Shows the tab at `index`, parking the current one. Its turn keeps
+    /// running on the server meanwhile.
+    fn switch_tab(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        index: usize,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if index == self.active_tab || index >= self.tabs.len() {
+            return Ok(());
+        }
+        let parked = self.park();
+        self.tabs[self.active_tab].parked = Some(parked);
+        self.active_tab = index;
+        let parked = self.tabs[index]
+            .parked
+            .take()
+            .expect("other tabs are parked");
+        self.unpark(terminal, parked)
+    }
+
+    /// Closes the showing tab and switches to its neighbor. The session
+    /// stays on the server, like one switched away from in the palette.
+    fn close_tab(
+        &mut self,
+        terminal: &mut TuiTerminal,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.tabs.len() == 1 {
+            return self.append_message(
+                terminal,
+                ChatMessage::Info("This is the only tab".to_string()),
+            );
+        }
+        if let Some(stream) = self.stream.take() {
+            stream.abort();
+        }
+        let message = format!("Closed the tab for session {}", self.client.session_id());
+        self.tabs.remove(self.active_tab);
+        self.active_tab = self.active_tab.min(self.tabs.len() - 1);
+        let parked = self.tabs[self.active_tab]
+            .parked
+            .take()
+            .expect("other tabs are parked");
+        self.append_message(terminal, ChatMessage::Info(message))?;
+        self.unpark(terminal, parked)
+    }
+
+    /// Returns an event for the showing tab to handle, and holds back one
+    /// for a parked tab until it is switched to. Events for closed tabs are
+    /// dropped.
+    fn route_tab_event(&mut self, tab: u64, event: UiEvent) -> Option<UiEvent> {
+        if tab == self.tabs[self.active_tab].id {
+            return Some(event);
+        }
+        if let Some(parked) = self
+            .tabs
+            .iter_mut()
+            .find(|other| other.id == tab)
+            .and_then(|other| other.parked.as_mut())
+        {
+            // Shown in the tab bar until the backlog is handled.
+            if matches!(event, UiEvent::Done) {
+                parked.is_loading = false;
+            }
+            parked.backlog.push(event);
+        }
+        None
+    }
+
+    fn park(&mut self) -> ParkedSession {
+        self.queue_cursor = None;
+        self.editing_queued = None;
+        self.preview = None;
+        self.changes_scroll = 0;
+        // Caught up on by the session's next heartbeat once it shows again.
+        self.tool_progress = None;
+        ParkedSession {
+            client: Arc::clone(&self.client),
+            stream: self.stream.take(),
+            is_loading: std::mem::take(&mut self.is_loading),
+            prefill: self.prefill.take(),
+            queued: std::mem::take(&mut self.queued),
+            in_flight: self.in_flight.take(),
+            pending_tool: self.pending_tool.take(),
+            approval: self.approval.take(),
+            last_tool: self.last_tool.take(),
+            todos: std::mem::take(&mut self.todos),
+            changes: std::mem::take(&mut self.changes),
+            backlog: Vec::new(),
+        }
+    }
+
+    /// Makes `parked` the showing session and catches up on what it missed.
+    fn unpark(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        parked: ParkedSession,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.client = parked.client;
+        self.stream = Some(parked.stream.unwrap_or_else(|| self.spawn_stream()));
+        self.is_loading = parked.is_loading;
+        self.prefill = parked.prefill;
+        self.queued = parked.queued;
+        self.in_flight = parked.in_flight;
+        self.pending_tool = parked.pending_tool;
+        self.approval = parked.approval;
+        self.last_tool = parked.last_tool;
+        self.todos = parked.todos;
+        self.changes = parked.changes;
+        if self.file_tree.is_some() {
+            self.refresh_files();
+        }
+        self.append_message(
+            terminal,
+            ChatMessage::Separator {
+                title: format!(
+                    "Tab {} · session {}",
+                    self.active_tab + 1,
+                    self.client.session_id()
+                ),
+                time: clock::now(),
+            },
+        )?;
+        for event in parked.backlog {
+            self.handle_ui_event(terminal, event)?;
+        }
+        if !self.is_loading && !self.offline {
+            self.dispatch_next(terminal)?;
+        }
+        Ok(())
+    }
+
+    fn refresh_files(&self) {
+        let client = Arc::clone(&self.client);
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let event = match client.workspace_files().await {
+                Ok(listing) => UiEvent::Files(listing),
+                Err(err) => UiEvent::Error(err.to_string()),
+            };
+            let _ = sender.send(event).await;
+        });
+    }
+
+    fn open_preview(&self, path: String) {
+        let client = Arc::clone(&self.client);
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let event = match client.preview_file(&path).await {
+                Ok(preview) => UiEvent::Preview(preview),
+                Err(err) => UiEvent::Error(err.to_string()),
+            };
+            let _ = sender.send(event).await;
+        });
+    }
+
+    /// Opens the command palette and fetches the server's sessions for it.
+    fn open_palette(&mut self) {
+        self.palette = Some(Palette::new(&self.recent_files));
+        let client = Arc::clone(&self.client);
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            // The palette is still useful without sessions, so a failure is
+            // not reported.
+            if let Ok(listing) = client.list_sessions().await {
+                let _ = sender.send(UiEvent::Sessions(listing)).await;
+            }
+        });
+    }
+
+    /// Handles a key while the command palette is open; typing goes to its
+    /// query rather than the input.
+    fn handle_palette_key(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        key: KeyEvent,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(palette) = &mut self.palette else {
+            return Ok(());
+        };
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => self.palette = None,
+            KeyCode::Char('p') if control => self.palette = None,
+            KeyCode::Up => palette.move_selection(-1),
+            KeyCode::Down => palette.move_selection(1),
+            KeyCode::Backspace => palette.pop(),
+            KeyCode::Char(c) if !control => palette.push(c),
+            KeyCode::Enter => {
+                let action = palette.selected();
+                self.palette = None;
+                if let Some(action) = action {
+                    self.run_palette_action(terminal, action)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn run_palette_action(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        action: PaletteAction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match action {
+            // Commands that take arguments are left in the input to finish.
+            PaletteAction::Command(usage) => match usage.split_once(' ') {
+                Some((name, _)) => {
+                    self.input.clear();
+                    self.input.insert_str(&format!("{} ", name));
+                }
+                None => self.submit(terminal, usage.to_string())?,
+            },
+            PaletteAction::File(path) => {
+                if self.file_tree.is_none() {
+                    self.toggle_file_tree();
+                }
+                self.open_preview(path);
+            }
+            PaletteAction::Session(_) if self.is_loading => self.append_message(
+                terminal,
+                ChatMessage::Info(
+                    "Wait for the current turn to finish before switching sessions".into(),
+                ),
+            )?,
+            PaletteAction::Session(session_id) => {
+                let message = format!(
+                    "Left session {} for {}",
+                    self.client.session_id(),
+                    session_id
+                );
+                let client = self.client.attach(session_id);
+                self.handle_ui_event(
+                    terminal,
+                    UiEvent::SessionStarted {
+                        client,
+                        message,
+                        fresh: true,
+                        title: "Switched session",
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a key while the file tree has focus. Returns false when the
+    /// key should fall through to the input instead.
+    fn handle_tree_key(&mut self, code: KeyCode) -> bool {
+        let Some(tree) = &mut self.file_tree else {
+            return false;
+        };
+        match code {
+            KeyCode::Up => tree.move_selection(-1),
+            KeyCode::Down => tree.move_selection(1),
+            KeyCode::Enter => {
+                if let Some(path) = tree.selected_file().map(str::to_string) {
+                    self.open_preview(path);
+                }
+            }
+            KeyCode::Esc if self.preview.is_some() => self.preview = None,
+            KeyCode::Esc => self.toggle_file_tree(),
+            KeyCode::Tab => self.tree_focused = false,
+            _ => return false,
+        }
+        true
+    }
+
+    fn submit(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        msg: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match commands::parse(&msg) {
+            None => self.send_message(
+                terminal,
+                SendMessageRequest {
+                    content: msg,
+                    ..Default::default()
+                },
+            ),
+            Some(Err(err)) => self.append_message(terminal, ChatMessage::Info(err)),
+            Some(Ok(command)) => self.run_command(terminal, command),
+        }
+    }
+
+    /// Sends the message now, or queues it if a turn is still running or the
+    /// API is unreachable. A queued message that was pulled back for editing
+    /// keeps its place.
+    fn send_message(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        request: SendMessageRequest,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let slot = self.editing_queued.take();
+        if self.is_loading || self.offline {
+            let index = slot.unwrap_or(self.queued.len()).min(self.queued.len());
+            self.queued.insert(index, request);
+            return Ok(());
+        }
+        self.dispatch(terminal, request)
+    }
+
+    fn dispatch(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        mut request: SendMessageRequest,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // A resent message keeps the prefill it was first sent with.
+        if request.prefill.is_none() {
+            request.prefill = self.prefill.take();
+        }
+        if request.model.is_none() {
+            request.model.clone_from(&self.chosen_model);
+        }
+        self.append_message(terminal, ChatMessage::User(request.content.clone()))?;
+        self.is_loading = true;
+        self.in_flight = Some(request.clone());
+        let client = Arc::clone(&self.client);
+        let sender = self.sender.clone();
+        let tab = self.tabs[self.active_tab].id;
+        tokio::spawn(async move {
+            if let Err(err) = client.send_message(request).await {
+                let event = if is_network_error(err.as_ref()) {
+                    UiEvent::Offline(err.to_string())
+                } else {
+                    UiEvent::Error(err.to_string())
+                };
+                let _ = sender.send(UiEvent::tab(tab, event)).await;
+                // No turn started, so nothing else will end this one.
+                let _ = sender.send(UiEvent::tab(tab, UiEvent::Done)).await;
+            }
+        });
+        Ok(())
+    }
+
+    fn dispatch_next(
+        &mut self,
+        terminal: &mut TuiTerminal,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.queued.is_empty() {
+            return Ok(());
+        }
+        let next = self.queued.remove(0);
+        self.queue_cursor = self.queue_cursor.and_then(|index| index.checked_sub(1));
+        self.dispatch(terminal, next)
+    }
+
+    /// A resumed session's history as the transcript would have shown it,
+    /// with each tool call collapsed to its summary.
+    fn history_messages(&self, history: &[MessageParam]) -> Vec<ChatMessage> {
+        let mut calls = HashMap::new();
+        let mut messages = Vec::new();
+        for message in history {
+            match message {
+                MessageParam::User(user) => {
+                    for block in user.content() {
+                        let ContentBlock::ToolResult {
+                            tool_use_id,
+                            content,
+                            is_error,
+                        } = block
+                        else {
+                            continue;
+                        };
+                        let content = content.text();
+                        let is_error = *is_error == Some(true);
+                        messages.push(match calls.remove(tool_use_id.as_str()) {
+                            Some((name, input)) => ChatMessage::Tool {
+                                summary: self.summarize_tool(
+                                    name,
+                                    input,
+                                    (!is_error).then_some(content.as_str()),
+                                ),
+                                error: is_error.then_some(content),
+                            },
+                            None => ChatMessage::ToolResult { content, is_error },
+                        });
+                    }
+                    let text = text_content(user.content());
+                    if !text.trim().is_empty() {
+                        messages.push(ChatMessage::User(text));
+                    }
+                }
+                MessageParam::Assistant(assistant) => {
+                    for block in assistant.content() {
+                        match block {
+                            ContentBlock::Text { text } if !text.trim().is_empty() => {
+                                messages.push(ChatMessage::Assistant {
+                                    label: format!("{}:", self.agent_name),
+                                    text: text.trim().to_string(),
+                                    stats: None,
+                                });
+                            }
+                            ContentBlock::ToolUse { id, name, input } => {
+                                calls.insert(id.as_str(), (name, input));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+        messages
+    }
+
+    fn summarize_tool(
+        &self,
+        name: &str,
+        input: &serde_json::Value,
+        output: Option<&str>,
+    ) -> String {
+        match self.tools.iter().find(|tool| tool.name == name) {
+            Some(tool) => (tool.summarize)(input, output),
+            None => format!(
+                "🔧 {} {}",
+                name,
+                ChatMessage::truncate(&input.to_string(), 80, "...")
+            ),
+        }
+    }
+
+    /// Renders a call that never got a result in its raw form.
+    fn flush_pending_tool(
+        &mut self,
+        terminal: &mut TuiTerminal,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let Some((name, input)) = self.pending_tool.take() {
+            let input = serde_json::to_string(&input).unwrap_or_default();
+            self.append_message(terminal, ChatMessage::ToolUse { name, input })?;
+        }
+        Ok(())
+    }
+
+    /// Prints the raw input and output of the last tool call below its
+    /// summary.
+    fn expand_last_tool(
+        &mut self,
+        terminal: &mut TuiTerminal,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(tool) = &self.last_tool else {
+            return self.append_message(terminal, ChatMessage::Info("No tool calls yet".into()));
+        };
+        let input = serde_json::to_string_pretty(&tool.input).unwrap_or_default();
+        let call = ChatMessage::ToolUse {
+            name: tool.name.clone(),
+            input,
+        };
+        let result = ChatMessage::ToolResult {
+            content: tool.content.clone(),
+            is_error: tool.is_error,
+        };
+        self.append_message(terminal, call)?;
+        self.append_message(terminal, result)
+    }
+
+    /// Answers the pending approval: y approves, a approves and allows the
+    /// tool for the session, n or Esc denies, and Enter denies with the
+    /// typed text as the reason. The letters count only while the input is
+    /// empty. Returns false when the key should fall through to the input.
+    fn handle_approval_key(&mut self, code: KeyCode) -> bool {
+        let empty = self.input.is_empty();
+        let (decision, message) = match code {
+            KeyCode::Char('y') if empty => (ApprovalDecision::Approve, None),
+            KeyCode::Char('a') if empty => (ApprovalDecision::Always, None),
+            KeyCode::Char('n') if empty => (ApprovalDecision::Deny, None),
+            KeyCode::Esc => (ApprovalDecision::Deny, None),
+            KeyCode::Enter if !empty => {
+                let reason = self.input.to_string();
+                self.input.clear();
+                (ApprovalDecision::Deny, Some(reason))
+            }
+            _ => return false,
+        };
+        let Some(approval) = self.approval.take() else {
+            return false;
+        };
+        let client = Arc::clone(&self.client);
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let request = ApprovalRequest { decision, message };
+            if let Err(err) = client.answer_approval(&approval.tool_use_id, request).await {
+                let _ = sender.send(UiEvent::Error(err.to_string())).await;
+            }
+        });
+        true
+    }
+
+    /// Handles a key while a queued message is highlighted. Returns false
+    /// when the key should fall through to the input instead.
+    fn handle_queue_key(&mut self, index: usize, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Up => self.queue_cursor = Some(index.saturating_sub(1)),
+            KeyCode::Down if index + 1 < self.queued.len() => {
+                self.queue_cursor = Some(index + 1);
+            }
+            KeyCode::Down | KeyCode::Esc => self.queue_cursor = None,
+            KeyCode::Enter => {
+                let request = self.queued.remove(index);
+                let draft = if request.pin {
+                    format!("/pin {}", request.content)
+                } else {
+                    request.content
+                };
+                self.input.clear();
+                self.input.insert_str(&draft);
+                self.editing_queued = Some(index);
+                self.queue_cursor = None;
+            }
+            KeyCode::Delete | KeyCode::Backspace => {
+                self.queued.remove(index);
+                self.queue_cursor = match self.queued.len() {
+                    0 => None,
+                    len => Some(index.min(len - 1)),
+                };
+            }
+            _ => {
+                self.queue_cursor = None;
+                return false;
+            }
+        }
+        true
+    }
+
+    fn run_command(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        command: SlashCommand,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match command {
+            SlashCommand::Pin(Some(content)) => self.send_message(
+                terminal,
+                SendMessageRequest {
+                    content,
+                    pin: true,
+                    ..Default::default()
+                },
+            ),
+            SlashCommand::Chat(content) => self.send_message(
+                terminal,
+                SendMessageRequest {
+                    content,
+                    tool_choice: Some(ToolChoice::None),
+                    ..Default::default()
+                },
+            ),
+            SlashCommand::Tool { name, message } => {
+                let tool_choice = match name {
+                    Some(name) if !self.tools.iter().any(|tool| tool.name == name) => {
+                        let names: Vec<&str> = self.tools.iter().map(|tool| tool.name).collect();
+                        return self.append_message(
+                            terminal,
+                            ChatMessage::Info(format!(
+                                "Unknown tool: {} (available: {})",
+                                name,
+                                names.join(", ")
+                            )),
+                        );
+                    }
+                    Some(name) => ToolChoice::Tool { name },
+                    None => ToolChoice::Any,
+                };
+                self.send_message(
+                    terminal,
+                    SendMessageRequest {
+                        content: message,
+                        tool_choice: Some(tool_choice),
+                        ..Default::default()
+                    },
+                )
+            }
+            SlashCommand::Plan { profile, message } => self.send_message(
+                terminal,
+                SendMessageRequest {
+                    content: message,
+                    profile: Some(profile),
+                    ..Default::default()
+                },
+            ),
+            SlashCommand::Compare(_) if self.compare_models.len() < 2 => self.append_message(
+                terminal,
+                ChatMessage::Info(
+                    "List two or three models to compare in ~/.tars/config.toml, e.g. \
+                     compare = [\"claude-haiku-4-5\", \"claude-sonnet-4-5\"]"
+                        .to_string(),
+                ),
+            ),
+            SlashCommand::Compare(content) => self.send_message(
+                terminal,
+                SendMessageRequest {
+                    content,
+                    compare: self.compare_models.iter().take(3).cloned().collect(),
+                    ..Default::default()
+                },
+            ),
+            SlashCommand::New if self.is_loading => self.append_message(
+                terminal,
+                ChatMessage::Info(
+                    "Wait for the current turn to finish before starting a new session".into(),
+                ),
+            ),
+            SlashCommand::New => {
+                let client = Arc::clone(&self.client);
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let started = async {
+                        let archive = client.archive().await?;
+                        let next = client.new_session().await?;
+                        let verb = if archive.saved {
+                            "Archived"
+                        } else {
+                            "Discarded empty"
+                        };
+                        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(UiEvent::SessionStarted {
+                            client: next,
+                            message: format!("{} session {}", verb, client.session_id()),
+                            fresh: true,
+                            title: "New session",
+                        })
+                    };
+                    let event = started
+                        .await
+                        .unwrap_or_else(|err| UiEvent::Error(err.to_string()));
+                    let _ = sender.send(event).await;
+                });
+                Ok(())
+            }
+            SlashCommand::Fork(up_to) => {
+                let client = Arc::clone(&self.client);
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let event = match client.fork(up_to).await {
+                        Ok(fork) => UiEvent::SessionStarted {
+                            message: format!(
+                                "Forked session {} into {}; the original is unchanged",
+                                client.session_id(),
+                                fork.session_id()
+                            ),
+                            client: fork,
+                            fresh: false,
+                            title: "Fork",
+                        },
+                        Err(err) => UiEvent::Error(err.to_string()),
+                    };
+                    let _ = sender.send(event).await;
+                });
+                Ok(())
+            }
+            SlashCommand::Retry | SlashCommand::Edit if self.is_loading => self.append_message(
+                terminal,
+                ChatMessage::Info("Wait for the current turn to finish first".into()),
+            ),
+            SlashCommand::Retry | SlashCommand::Edit => {
+                let retry = command == SlashCommand::Retry;
+                let client = Arc::clone(&self.client);
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let event = match client.rewind().await {
+                        Ok(rewound) => UiEvent::Rewound {
+                            text: rewound.text,
+                            pinned: rewound.pinned,
+                            retry,
+                        },
+                        Err(err) => UiEvent::Error(err.to_string()),
+                    };
+                    let _ = sender.send(event).await;
+                });
+                Ok(())
+            }
+            SlashCommand::Describe { pr, base } => {
+                let client = Arc::clone(&self.client);
+                let sender = self.sender.clone();
+                let tab = self.tabs[self.active_tab].id;
+                tokio::spawn(async move {
+                    let event = match client.describe(DescribeRequest { pr, base }).await {
+                        Ok(described) => UiEvent::Described {
+                            text: described.text,
+                            pr,
+                        },
+                        Err(err) => UiEvent::Error(err.to_string()),
+                    };
+                    let _ = sender.send(UiEvent::tab(tab, event)).await;
+                });
+                self.append_message(
+                    terminal,
+                    ChatMessage::Info(if pr {
+                        "Writing a pull request description…".to_string()
+                    } else {
+                        "Writing a commit message for the staged changes…".to_string()
+                    }),
+                )
+            }
+            SlashCommand::Tab => {
+                self.open_tab();
+                Ok(())
+            }
+            SlashCommand::Close => self.close_tab(terminal),
+            SlashCommand::Topic(title) => self.append_message(
+                terminal,
+                ChatMessage::Separator {
+                    title,
+                    time: clock::now(),
+                },
+            ),
+            SlashCommand::Prefill(prefill) => {
+                let message = match &prefill {
+                    Some(text) => format!("Next reply will start with: {}", text),
+                    None => "Prefill cleared".to_string(),
+                };
+                self.prefill = prefill;
+                self.append_message(terminal, ChatMessage::Info(message))
+            }
+            SlashCommand::Artifacts => {
+                let client = Arc::clone(&self.client);
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let event = match client.artifacts().await {
+                        Ok(artifacts) if artifacts.is_empty() => {
+                            UiEvent::Info("No artifacts saved yet".to_string())
+                        }
+                        Ok(artifacts) => {
+                            let lines: Vec<String> = artifacts
+                                .iter()
+                                .map(|artifact| {
+                                    format!("  {} ({})", artifact.path, format_size(artifact.size))
+                                })
+                                .collect();
+                            UiEvent::Info(format!("Artifacts:\n{}", lines.join("\n")))
+                        }
+                        Err(err) => UiEvent::Error(err.to_string()),
+                    };
+                    let _ = sender.send(event).await;
+                });
+                Ok(())
+            }
+            SlashCommand::Allow(tool) => {
+                let client = Arc::clone(&self.client);
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let event = match client.allow_tool(&tool).await {
+                        Ok(()) => UiEvent::Info(format!("{} is allowed in this session", tool)),
+                        Err(err) => UiEvent::Error(err.to_string()),
+                    };
+                    let _ = sender.send(event).await;
+                });
+                Ok(())
+            }
+            SlashCommand::Stats => {
+                let client = Arc::clone(&self.client);
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let event = match tokio::try_join!(client.stats(), client.usage()) {
+                        Ok((session, server)) => UiEvent::Stats(format!(
+                            "This session:\n{}\n\nSince the server started:\n{}",
+                            stats_table(&session.tools),
+                            stats_table(&server.tools)
+                        )),
+                        Err(err) => UiEvent::Error(err.to_string()),
+                    };
+                    let _ = sender.send(event).await;
+                });
+                Ok(())
+            }
+            SlashCommand::Patch(turn) => {
+                let client = Arc::clone(&self.client);
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let event = match client.turn_patch(turn).await {
+                        Ok(Some(patch)) => UiEvent::Patch(patch),
+                        Ok(None) => UiEvent::Info("That turn changed no files".to_string()),
+                        Err(err) => UiEvent::Error(err.to_string()),
+                    };
+                    let _ = sender.send(event).await;
+                });
+                Ok(())
+            }
+            SlashCommand::Export(path) => {
+                let client = Arc::clone(&self.client);
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let event = match (client.export().await, path) {
+                        (Ok(None), _) => UiEvent::Info("Nothing to export yet".to_string()),
+                        (Ok(Some(markdown)), None) => UiEvent::Exported(markdown),
+                        (Ok(Some(markdown)), Some(path)) => match std::fs::write(&path, markdown) {
+                            Ok(()) => UiEvent::Info(format!("Saved the transcript to {}", path)),
+                            Err(err) => {
+                                UiEvent::Error(format!("Failed to write {}: {}", path, err))
+                            }
+                        },
+                        (Err(err), _) => UiEvent::Error(err.to_string()),
+                    };
+                    let _ = sender.send(event).await;
+                });
+                Ok(())
+            }
+            SlashCommand::Model(None) => {
+                let aliases: Vec<String> = MODEL_ALIASES
+                    .iter()
+                    .map(|(alias, model)| format!("{} = {}", alias, model))
+                    .collect();
+                let model = self.chosen_model.as_deref().unwrap_or(&self.model);
+                self.append_message(
+                    terminal,
+                    ChatMessage::Info(format!("Model: {} ({})", model, aliases.join(", "))),
+                )
+            }
+            SlashCommand::Model(Some(name)) => {
+                let model = resolve_model(&name).to_string();
+                self.model.clone_from(&model);
+                self.chosen_model = Some(model.clone());
+                self.append_message(
+                    terminal,
+                    ChatMessage::Info(format!("Sending the next messages to {}", model)),
+                )
+            }
+            SlashCommand::Pin(None) => {
+                let client = Arc::clone(&self.client);
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let event = match client.pin_last_message().await {
+                        Ok(pinned) => UiEvent::Info(format!(
+                            "Pinned: {}",
+                            ChatMessage::truncate(&pinned.text, 60, "...")
+                        )),
+                        Err(err) => UiEvent::Error(err.to_string()),
+                    };
+                    let _ = sender.send(event).await;
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn handle_ui_event(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        event: UiEvent,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match event {
+            UiEvent::ApiResponse { text, stats } => {
+                if let Some(stats) = &stats {
+                    self.model.clone_from(&stats.model);
+                }
+                let label = reply_label(&self.agent_name, &self.model);
+                self.append_message(terminal, ChatMessage::Assistant { label, text, stats })?;
+            }
+            UiEvent::ToolCall { name, input } => {
+                self.flush_pending_tool(terminal)?;
+                if let Some(path) = input["path"].as_str().filter(|path| !path.is_empty()) {
+                    self.recent_files.retain(|recent| recent != path);
+                    self.recent_files.insert(0, path.to_string());
+                    self.recent_files.truncate(MAX_RECENT_FILES);
+                }
+                self.pending_tool = Some((name, input));
+                self.tool_progress = None;
+            }
+            UiEvent::ToolProgress(progress) => {
+                self.tool_progress = Some(progress);
+            }
+            UiEvent::ApprovalNeeded(approval) => {
+                self.append_message(
+                    terminal,
+                    ChatMessage::Info(format!("Waiting for your approval: {}", approval.summary)),
+                )?;
+                self.approval = Some(approval);
+            }
+            UiEvent::ToolResult { content, is_error } => {
+                // Answered here or by another client.
+                self.approval = None;
+                if self.file_tree.is_some() {
+                    self.refresh_files();
+                }
+                match self.pending_tool.take() {
+                    Some((name, input)) => {
+                        let output = (!is_error).then_some(content.as_str());
+                        let summary = self.summarize_tool(&name, &input, output);
+                        let error = is_error.then(|| content.clone());
+                        self.append_message(terminal, ChatMessage::Tool { summary, error })?;
+                        self.last_tool = Some(FinishedTool {
+                            name,
+                            input,
+                            content,
+                            is_error,
+                        });
+                    }
+                    None => {
+                        self.append_message(
+                            terminal,
+                            ChatMessage::ToolResult { content, is_error },
+                        )?;
+                    }
+                }
+            }
+            UiEvent::Error(err) => {
+                self.flush_pending_tool(terminal)?;
+                self.append_message(terminal, ChatMessage::Info(format!("Error: {}", err)))?;
+            }
+            UiEvent::Info(msg) => {
+                self.append_message(terminal, ChatMessage::Info(msg))?;
+            }
+            UiEvent::Warning(msg) => {
+                self.append_message(terminal, ChatMessage::Warning(msg))?;
+            }
+            UiEvent::Comparison(answers) => {
+                self.append_message(terminal, ChatMessage::Comparison(answers))?;
+            }
+            UiEvent::Plan { steps, stats } => {
+                let text: Vec<String> = steps
+                    .iter()
+                    .enumerate()
+                    .map(|(index, step)| format!("{}. {}", index + 1, step))
+                    .collect();
+                self.append_message(
+                    terminal,
+                    ChatMessage::Assistant {
+                        label: format!("planner ({}):", stats.model),
+                        text: text.join("\n"),
+                        stats: Some(stats),
+                    },
+                )?;
+            }
+            UiEvent::Offline(reason) => {
+                self.flush_pending_tool(terminal)?;
+                if let Some(request) = self.in_flight.take() {
+                    self.queued.insert(0, request);
+                    self.queue_cursor = self.queue_cursor.map(|index| index + 1);
+                }
+                if !self.offline {
+                    self.offline = true;
+                    self.append_message(
+                        terminal,
+                        ChatMessage::Info(format!(
+                            "Offline ({}). Messages will be queued and sent once the API is reachable.",
+                            reason
+                        )),
+                    )?;
+                    spawn_connectivity_check(Arc::clone(&self.client), self.sender.clone());
+                }
+            }
+            UiEvent::TodoUpdate(items) => {
+                self.todos = items;
+            }
+            UiEvent::DiffUpdate(files) => {
+                self.changes = files;
+            }
+            UiEvent::Files(listing) => {
+                if let Some(tree) = &mut self.file_tree {
+                    tree.update(&listing.files, listing.truncated);
+                }
+            }
+            UiEvent::Sessions(listing) => {
+                if let Some(palette) = &mut self.palette {
+                    palette.add_sessions(&listing.sessions, self.client.session_id());
+                }
+            }
+            UiEvent::Preview(preview) => {
+                if self.file_tree.is_some() {
+                    self.preview = Some(Preview {
+                        path: preview.path,
+                        content: preview.content,
+                        truncated: preview.truncated,
+                        scroll: 0,
+                    });
+                }
+            }
+            UiEvent::Stderr(line) => {
+                if self.stderr.len() == MAX_STDERR_LINES {
+                    self.stderr.remove(0);
+                }
+                self.stderr.push(line);
+                if !self.show_stderr {
+                    self.unseen_stderr += 1;
+                }
+            }
+            UiEvent::Online => {
+                self.offline = false;
+                self.append_message(terminal, ChatMessage::Info("Back online.".to_string()))?;
+                if !self.is_loading {
+                    self.dispatch_next(terminal)?;
+                }
+            }
+            UiEvent::Described { text, pr } => {
+                let label = if pr {
+                    "Pull request description:"
+                } else {
+                    "Commit message:"
+                };
+                copy_to_clipboard(&text)?;
+                self.append_message(
+                    terminal,
+                    ChatMessage::Assistant {
+                        label: label.to_string(),
+                        text,
+                        stats: None,
+                    },
+                )?;
+                self.append_message(
+                    terminal,
+                    ChatMessage::Info("Copied to the clipboard".to_string()),
+                )?;
+            }
+            UiEvent::Stats(text) => {
+                self.append_message(
+                    terminal,
+                    ChatMessage::Assistant {
+                        label: "Tool stats:".to_string(),
+                        text,
+                        stats: None,
+                    },
+                )?;
+            }
+            UiEvent::Patch(patch) => {
+                copy_to_clipboard(&patch)?;
+                let files = patch
+                    .lines()
+                    .filter(|line| line.starts_with("diff --git "))
+                    .count();
+                self.append_message(
+                    terminal,
+                    ChatMessage::Info(format!(
+                        "Copied a patch of {} file(s) to the clipboard; `git am` or `git apply` applies it",
+                        files
+                    )),
+                )?;
+            }
+            UiEvent::Exported(markdown) => {
+                copy_to_clipboard(&markdown)?;
+                self.append_message(
+                    terminal,
+                    ChatMessage::Info("Copied the transcript to the clipboard as Markdown".into()),
+                )?;
+            }
+            UiEvent::Rewound {
+                text,
+                pinned,
+                retry: true,
+            } => {
+                self.append_message(
+                    terminal,
+                    ChatMessage::Info("Removed the last turn; retrying".into()),
+                )?;
+                self.send_message(
+                    terminal,
+                    SendMessageRequest {
+                        content: text,
+                        pin: pinned,
+                        ..Default::default()
+                    },
+                )?;
+            }
+            UiEvent::Rewound { text, pinned, .. } => {
+                self.append_message(
+                    terminal,
+                    ChatMessage::Info(
+                        "Removed the last turn; edit the prompt and press Enter to resend".into(),
+                    ),
+                )?;
+                let draft = if pinned {
+                    format!("/pin {}", text)
+                } else {
+                    text
+                };
+                self.input.clear();
+                self.input.insert_str(&draft);
+            }
+            UiEvent::SessionStarted {
+                client,
+                message,
+                fresh,
+                title,
+            } => {
+                if let Some(stream) = self.stream.take() {
+                    stream.abort();
+                }
+                self.client = Arc::new(client);
+                self.stream = Some(self.spawn_stream());
+                if fresh {
+                    self.last_tool = None;
+                    self.prefill = None;
+                    self.todos.clear();
+                    self.changes.clear();
+                    self.preview = None;
+                }
+                if self.file_tree.is_some() {
+                    self.refresh_files();
+                }
+                self.append_message(terminal, ChatMessage::Info(message))?;
+                self.append_message(
+                    terminal,
+                    ChatMessage::Separator {
+                        title: title.to_string(),
+                        time: clock::now(),
+                    },
+                )?;
+            }
+            UiEvent::TabOpened(client) => {
+                let parked = self.park();
+                self.tabs[self.active_tab].parked = Some(parked);
+                self.tabs.push(Tab {
+                    id: self.next_tab_id,
+                    parked: None,
+                });
+                self.next_tab_id += 1;
+                self.active_tab = self.tabs.len() - 1;
+                self.unpark(terminal, ParkedSession::new(Arc::new(client)))?;
+            }
+            UiEvent::Tab { tab, event } => {
+                if let Some(event) = self.route_tab_event(tab, *event) {
+                    return self.handle_ui_event(terminal, event);
+                }
+            }
+            UiEvent::Done => {
+                self.flush_pending_tool(terminal)?;
+                self.approval = None;
+                self.is_loading = false;
+                self.in_flight = None;
+                if !self.offline {
+                    self.dispatch_next(terminal)?;
+                }
+            }
+            UiEvent::Terminal(event) => {
+                self.handle_terminal_event(terminal, event)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_terminal_event(
+        &mut self,
+        terminal: &mut TuiTerminal,
+        event: Event,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let Event::Resize(_, _) = event {
+            // Recompute the inline viewport for the new size and force a full
+            // repaint so the input block is rewrapped at the new width.
+            self.terminal_input.pause();
+            terminal.autoresize()?;
+            terminal.clear()?;
+            return Ok(());
+        }
+
+        if let Event::Paste(text) = event {
+            self.input.insert_str(&text);
+            return Ok(());
+        }
+
+        if let Event::Key(key) = event {
+            if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+                // Ctrl+C copies when there is a selection and quits otherwise.
+                match self.input.copy() {
+                    Some(text) => copy_to_clipboard(&text)?,
+                    None => self.should_quit = true,
+                }
+                return Ok(());
+            }
+
+            if self.approval.is_some() && self.handle_approval_key(key.code) {
+                return Ok(());
+            }
+            if self.palette.is_some() {
+                return self.handle_palette_key(terminal, key);
+            }
+            if self.tree_focused && self.handle_tree_key(key.code) {
+                return Ok(());
+            }
+            if let Some(index) = self.queue_cursor
+                && self.handle_queue_key(index, key.code)
+            {
+                return Ok(());
+            }
+            if key.code == KeyCode::Up && self.input.is_empty() && !self.queued.is_empty() {
+                self.queue_cursor = Some(self.queued.len() - 1);
+                return Ok(());
+            }
+
+            let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+            if matches!(
+                key.code,
+                KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Home
+                    | KeyCode::End
+            ) {
+                self.input.select(shift);
+            }
+
+            let word_jump = KeyModifiers::CONTROL | KeyModifiers::ALT;
+            match key.code {
+                KeyCode::Esc => {
+                    self.should_quit = true;
+                }
+                KeyCode::Enter => {
+                    if key.modifiers.contains(KeyModifiers::SHIFT) {
+                        self.input.new_line();
+                    } else if !self.input.is_empty() {
+                        let msg = self.input.to_string();
+                        if !msg.trim().is_empty() {
+                            self.input.clear();
+                            self.submit(terminal, msg)?;
+                        }
+                    }
+                }
+                KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => match c {
+                    'a' => {
+                        self.input.select(false);
+                        self.input.move_home();
+                    }
+                    'd' => self.show_changes = !self.show_changes,
+                    'e' => {
+                        self.input.select(false);
+                        self.input.move_end();
+                    }
+                    'f' => self.toggle_file_tree(),
+                    'k' => self.input.kill_to_end(),
+                    'n' => self.open_tab(),
+                    'l' => {
+                        self.show_stderr = !self.show_stderr;
+                        self.unseen_stderr = 0;
+                    }
+                    'o' => self.expand_last_tool(terminal)?,
+                    'p' => self.open_palette(),
+                    't' => self.todos_expanded = !self.todos_expanded,
+                    'u' => self.input.kill_line(),
+                    'w' => self.input.delete_word_before(),
+                    'x' => {
+                        if let Some(text) = self.input.cut() {
+                            copy_to_clipboard(&text)?;
+                        }
+                    }
+                    'y' => self.input.yank(),
+                    _ => {}
+                },
+                KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::ALT) => match c {
+                    'b' => {
+                        self.input.select(false);
+                        self.input.move_word_left();
+                    }
+                    'f' => {
+                        self.input.select(false);
+                        self.input.move_word_right();
+                    }
+                    'y' => self.input.yank_pop(),
+                    '1'..='9' => self.switch_tab(terminal, c as usize - '1' as usize)?,
+                    _ => {}
+                },
+                KeyCode::Char(c) => {
+                    self.input.insert_char(c);
+                }
+                KeyCode::PageUp | KeyCode::PageDown if self.split => {
+                    let scroll = match &mut self.preview {
+                        Some(preview) => &mut preview.scroll,
+                        None => &mut self.changes_scroll,
+                    };
+                    *scroll = if key.code == KeyCode::PageUp {
+                        scroll.saturating_sub(PANE_PAGE)
+                    } else {
+                        scroll.saturating_add(PANE_PAGE)
+                    };
+                }
+                // Only terminals that report keyboard enhancements tell
+                // Ctrl+Tab apart from Tab.
+                KeyCode::Tab | KeyCode::BackTab
+                    if key.modifiers.contains(KeyModifiers::CONTROL) =>
+                {
+                    let back =
+                        key.code == KeyCode::BackTab || key.modifiers.contains(KeyModifiers::SHIFT);
+                    let count = self.tabs.len();
+                    let step = if back { count - 1 } else { 1 };
+                    self.switch_tab(terminal, (self.active_tab + step) % count)?;
+                }
+                KeyCode::Tab if self.file_tree.is_some() => self.tree_focused = true,
+                KeyCode::Backspace => {
+                    self.input.delete_char();
+                }
+                KeyCode::Delete => {
+                    self.input.delete_forward();
+                }
+                KeyCode::Left if key.modifiers.intersects(word_jump) => {
+                    self.input.move_word_left();
+                }
+                KeyCode::Right if key.modifiers.intersects(word_jump) => {
+                    self.input.move_word_right();
+                }
+                KeyCode::Left => {
+                    self.input.move_left();
+                }
+                KeyCode::Right => {
+                    self.input.move_right();
+                }
+                KeyCode::Up => {
+                    self.input.move_up();
+                }
+                KeyCode::Down => {
+                    self.input.move_down();
+                }
+                KeyCode::Home => {
+                    self.input.move_home();
+                }
+                KeyCode::End => {
+                    self.input.move_end();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Dimmed footer for an assistant message, e.g.
+/// `claude-haiku-4-5 · 2.4s · 1,234 in / 56 out`.
+/// What a reply is headed with in the transcript, e.g. `tars (claude-haiku-4-5):`.
+fn reply_label(agent_name: &str, model: &str) -> String {
+    format!("{} ({}):", agent_name, model)
+}
+
+fn format_stats(stats: &ResponseStats) -> String {
+    format!(
+        "{} · {:.1}s · {} in / {} out",
+        stats.model,
+        stats.elapsed_ms as f64 / 1000.0,
+        group_thousands(stats.input_tokens),
+        group_thousands(stats.output_tokens)
+    )
+}
+
+/// Puts text on the system clipboard with an OSC 52 escape, which most
+/// terminals honor, including over SSH.
+fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", encoded)?;
+    stdout.flush()
+}
+
+/// Renders `text` in a bordered pane, scrolled down `scroll` lines. The
+/// scroll is clamped so the last line can't leave the bottom of the pane.
+fn render_pane(f: &mut Frame, area: Rect, title: String, text: Text<'static>, scroll: &mut u16) {
+    let paragraph = Paragraph::new(text).wrap(Wrap { trim: false });
+    let lines = paragraph.line_count(area.width.saturating_sub(2).max(1)) as u16;
+    *scroll = (*scroll).min(lines.saturating_sub(area.height.saturating_sub(2)));
+    let paragraph = paragraph
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(theme().dim),
+        )
+        .scroll((*scroll, 0));
+    f.render_widget(paragraph, area);
+}
+
+/// Replaces the inline viewport with one of a different height. Ratatui
+/// fixes the height of an inline viewport at creation, so the old one is
+/// cleared and a new terminal is anchored where it started.
+fn resize_viewport(
+    terminal: &mut TuiTerminal,
+    height: u16,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    terminal.clear()?;
+    *terminal = Terminal::with_options(
+        CrosstermBackend::new(io::stdout()),
+        TerminalOptions {
+            viewport: Viewport::Inline(height),
+        },
+    )?;
+    Ok(())
+}
+
+/// Announces the session, then forwards its server events to the UI until
+/// the stream ends. `tab` is the tab the session is open in.
+fn spawn_event_stream(
+    client: Arc<ClientSession>,
+    sender: mpsc::Sender<UiEvent>,
+    tab: u64,
+) -> JoinHandle<()> {
+    let server_info = format!(
+        "Connected to {} (session {})",
+        client.base_url(),
+        client.session_id()
+    );
+    tokio::spawn(async move {
+        let mut forwarder = UiForwarder { sender, tab };
+        forwarder.forward(UiEvent::Info(server_info)).await;
+        let result = client.handle_events(&mut forwarder).await;
+
+        if let Err(err) = result {
+            forwarder.forward(UiEvent::Error(err.to_string())).await;
+        }
+    })
+}
+
+/// Turns server events into `UiEvent`s for the UI loop, tagged with the tab
+/// whose session they belong to.
+struct UiForwarder {
+    sender: mpsc::Sender<UiEvent>,
+    tab: u64,
+}
+
+impl UiForwarder {
+    async fn forward(&self, event: UiEvent) {
+        let _ = self.sender.send(UiEvent::tab(self.tab, event)).await;
+    }
+}
+
+impl EventHandler for UiForwarder {
+    async fn on_assistant(&mut self, text: String, stats: Option<ResponseStats>) {
+        self.forward(UiEvent::ApiResponse { text, stats }).await;
+    }
+
+    async fn on_tool_call(&mut self, _tool_use_id: String, name: String, input: serde_json::Value) {
+        self.forward(UiEvent::ToolCall { name, input }).await;
+    }
+
+    async fn on_tool_result(
+        &mut self,
+        _tool_use_id: String,
+        _name: String,
+        content: String,
+        is_error: bool,
+    ) {
+        self.forward(UiEvent::ToolResult { content, is_error })
+            .await;
+    }
+
+    async fn on_approval_needed(&mut self, tool_use_id: String, name: String, summary: String) {
+        self.forward(UiEvent::ApprovalNeeded(PendingApproval {
+            tool_use_id,
+            name,
+            summary,
+        }))
+        .await;
+    }
+
+    async fn on_info(&mut self, message: String) {
+        self.forward(UiEvent::Info(message)).await;
+    }
+
+    async fn on_warning(&mut self, message: String) {
+        self.forward(UiEvent::Warning(message)).await;
+    }
+
+    async fn on_error(&mut self, message: String) {
+        self.forward(UiEvent::Error(message)).await;
+    }
+
+    async fn on_offline(&mut self, message: String) {
+        self.forward(UiEvent::Offline(message)).await;
+    }
+
+    async fn on_heartbeat(&mut self, activity: TurnActivity, _elapsed_ms: u64) {
+        if let TurnActivity::RunningTool {
+            progress: Some(progress),
+            ..
+        } = activity
+        {
+            self.forward(UiEvent::ToolProgress(progress)).await;
+        }
+    }
+
+    async fn on_todo_update(&mut self, items: Vec<TodoItem>) {
+        self.forward(UiEvent::TodoUpdate(items)).await;
+    }
+
+    async fn on_diff_update(&mut self, files: Vec<FileDiff>) {
+        self.forward(UiEvent::DiffUpdate(files)).await;
+    }
+
+    async fn on_comparison(&mut self, answers: Vec<ComparedAnswer>) {
+        self.forward(UiEvent::Comparison(answers)).await;
+    }
+
+    async fn on_plan(&mut self, steps: Vec<String>, stats: ResponseStats) {
+        self.forward(UiEvent::Plan { steps, stats }).await;
+    }
+
+    async fn on_cancelled(&mut self) {
+        self.forward(UiEvent::Warning("Turn cancelled".to_string()))
+            .await;
+    }
+
+    async fn on_done(&mut self) {
+        self.forward(UiEvent::Done).await;
+    }
+}
+
+/// Pings the server until it reports the API reachable again, then sends
+/// `UiEvent::Online`.
+fn spawn_connectivity_check(client: Arc<ClientSession>, sender: mpsc::Sender<UiEvent>) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(CONNECTIVITY_CHECK_INTERVAL).await;
+            if client
+                .health()
+                .await
+                .is_ok_and(|health| health.api_reachable)
+            {
+                let _ = sender.send(UiEvent::Online).await;
+                return;
+            }
+        }
+    });
+}
+
+/// Reads terminal input on its own thread and forwards it to the UI loop as
+/// `UiEvent::Terminal`, so waiting for keys never blocks rendering or the
+/// runtime's tasks. The thread starts when the UI first waits for input.
+///
+/// crossterm's `EventStream` holds its input lock while it waits for a key,
+/// and querying the cursor position needs that lock. Recreating the inline
+/// viewport and clearing or resizing it query the cursor, so the UI pauses
+/// the reader, which drops its stream and releases the lock, before any of
+/// them, and resumes it when it next waits for input.
+#[derive(Default)]
+struct TerminalInput {
+    control: Option<mpsc::UnboundedSender<ReaderControl>>,
+    paused: bool,
+}
+
+enum ReaderControl {
+    /// Stop reading, and answer once the lock is released.
+    Pause(std::sync::mpsc::SyncSender<()>),
+    Resume,
+}
+
+impl TerminalInput {
+    fn pause(&mut self) {
+        let Some(control) = &self.control else {
+            return;
+        };
+        if self.paused {
+            return;
+        }
+        self.paused = true;
+        let (done, stopped) = std::sync::mpsc::sync_channel(1);
+        if control.send(ReaderControl::Pause(done)).is_ok() {
+            let _ = stopped.recv();
+        }
+    }
+
+    fn resume(&mut self, sender: &mpsc::Sender<UiEvent>) {
+        match &self.control {
+            Some(control) if self.paused => {
+                self.paused = false;
+                let _ = control.send(ReaderControl::Resume);
+            }
+            Some(_) => {}
+            None => self.control = Some(spawn_terminal_reader(sender.clone())),
+        }
+    }
+}
+
+/// Starts the reader thread, reading until paused. It stops once the UI
+/// loop is gone.
+fn spawn_terminal_reader(sender: mpsc::Sender<UiEvent>) -> mpsc::UnboundedSender<ReaderControl> {
+    let (control, mut commands) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        futures::executor::block_on(async move {
+            let mut events = Some(EventStream::new());
+            // Read but not yet forwarded; a full channel mustn't keep a
+            // pause waiting.
+            let mut pending: VecDeque<UiEvent> = VecDeque::new();
+            loop {
+                tokio::select! {
+                    command = commands.recv() => match command {
+                        Some(ReaderControl::Pause(done)) => {
+                            if events.take().is_some() {
+                                drain_terminal_input(&mut pending);
+                            }
+                            let _ = done.send(());
+                        }
+                        Some(ReaderControl::Resume) => {
+                            events.get_or_insert_with(EventStream::new);
+                        }
+                        None => break,
+                    },
+                    Some(event) = next_event(&mut events) => pending.push_back(match event {
+                        Ok(event) => UiEvent::Terminal(event),
+                        Err(err) => UiEvent::Error(format!("Terminal input failed: {}", err)),
+                    }),
+                    permit = sender.reserve(), if !pending.is_empty() => match permit {
+                        Ok(permit) => permit.send(pending.pop_front().unwrap()),
+                        Err(_) => break,
+                    },
+                }
+            }
+        })
+    });
+    control
+}
+
+async fn next_event(events: &mut Option<EventStream>) -> Option<io::Result<Event>> {
+    match events {
+        Some(events) => events.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Dropping the stream wakes crossterm's reader. When the reader wasn't
+/// waiting, the wake stays pending and the next cursor query would take it
+/// for a timeout, so poll until it's used up. Polling only gets that far
+/// once the keys already read are taken out.
+fn drain_terminal_input(pending: &mut VecDeque<UiEvent>) {
+    while let Ok(true) = event::poll(Duration::ZERO) {
+        match event::read() {
+            Ok(event) => pending.push_back(UiEvent::Terminal(event)),
+            Err(_) => break,
+        }
+    }
+}
+
+/// Runs the TUI on `client`'s session. Replies are labeled with
+/// `agent_name` and the model that wrote them, `model` until the first
+/// reply says otherwise.
+pub async fn run_tui(
+    client: ClientSession,
+    history: Vec<MessageParam>,
+    agent_name: String,
+    model: String,
+    compare_models: Vec<String>,
+    palette: ColorTheme,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    theme::init(palette);
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    // Pastes arrive as one event instead of a burst of keystrokes, so
+    // newlines in pasted text do not submit the prompt.
+    execute!(stdout, EnableBracketedPaste)?;
+    // Lets terminals that support it report Ctrl+Tab, which switches tabs,
+    // as something other than Tab.
+    let keyboard_enhanced = supports_keyboard_enhancement().unwrap_or(false);
+    if keyboard_enhanced {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        )?;
+    }
+    let (_, rows) = size()?;
+    if rows > 0 {
+        // Push existing screen content into scrollback without clearing it.
+        for _ in 0..rows {
+            writeln!(stdout)?;
+        }
+        stdout.flush()?;
+    }
+    execute!(stdout, MoveTo(0, 0))?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::with_options(
+        backend,
+        TerminalOptions {
+            viewport: Viewport::Inline(MIN_INPUT_HEIGHT),
+        },
+    )?;
+
+    let mut app = App::new(client, agent_name, model, compare_models);
+
+    app.stream = Some(app.spawn_stream());
+
+    for message in app.history_messages(&history) {
+        app.append_message(&mut terminal, message)?;
+    }
+    let title = if history.is_empty() {
+        "New session"
+    } else {
+        "Resumed session"
+    };
+    app.append_message(
+        &mut terminal,
+        ChatMessage::Separator {
+            title: title.to_string(),
+            time: clock::now(),
+        },
+    )?;
+
+    let _guard = TerminalGuard::new(keyboard_enhanced);
+    let stderr = StderrCapture::start(app.sender.clone());
+    let mut title = TitleUpdater::new()?;
+    title.set(app.activity())?;
+
+    terminal.draw(|f| app.draw(f))?;
+
+    // Everything arrives as a UiEvent, so the loop only wakes (and redraws)
+    // when there is something to handle.
+    while !app.should_quit {
+        app.terminal_input.resume(&app.sender);
+        let Some(event) = app.receiver.recv().await else {
+            break;
+        };
+        app.handle_ui_event(&mut terminal, event)?;
+        app.fit_viewport(&mut terminal)?;
+        title.set(app.activity())?;
+        terminal.draw(|f| app.draw(f))?;
+    }
+    drop(stderr);
+    title.restore()?;
+    app.terminal_input.pause();
+    app.set_split(&mut terminal, false)?;
+
+    terminal.draw(|f| {
+        let area = f.area();
+        f.render_widget(Clear, area);
+        // Place the shell prompt at the top of the cleared inline viewport.
+        f.set_cursor_position((area.x, area.y));
+    })?;
+    let mut stdout = io::stdout();
+    execute!(stdout, DisableBracketedPaste)?;
+    disable_raw_mode()?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reply_label, theme, App, ChatMessage, ParkedSession, Tab, UiEvent};
+    use crate::client::{ClientConfig, ClientSession};
+    use crate::protocol::ResponseStats;
+    use crate::test_support::serve;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn rendered_height_accounts_for_word_wrapping() {
+        let message = ChatMessage::User("aaaa bbbb cccc".to_string());
+        // "You:" plus "  aaaa bbbb cccc" wrapped at word boundaries.
+        assert_eq!(message.rendered_height(80), 2);
+        assert_eq!(message.rendered_height(8), 4);
+    }
+
+    #[test]
+    fn separator_fills_the_width() {
+        let separator = ChatMessage::Separator {
+            title: "Refactor".to_string(),
+            time: "2026-10-16 09:30".to_string(),
+        };
+        let lines = separator.line_specs(40);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0]
+            .text
+            .starts_with("── Refactor · 2026-10-16 09:30 ─"));
+        assert_eq!(lines[0].text.chars().count(), 40);
+        assert_eq!(separator.rendered_height(40), 1);
+    }
+
+    #[test]
+    fn assistant_messages_end_with_a_dim_usage_footer() {
+        let message = ChatMessage::Assistant {
+            label: "tars:".to_string(),
+            text: "Done.".to_string(),
+            stats: Some(ResponseStats {
+                model: "claude-sonnet-4-5".to_string(),
+                elapsed_ms: 2_400,
+                input_tokens: 1_234,
+                output_tokens: 56,
+            }),
+        };
+        let lines = message.line_specs(80);
+        assert_eq!(lines.len(), 3);
+        let footer = &lines[2];
+        assert_eq!(
+            footer.text,
+            "  claude-sonnet-4-5 · 2.4s · 1,234 in / 56 out"
+        );
+        assert_eq!(footer.style, theme().dim);
+    }
+
+    #[test]
+    fn assistant_messages_without_stats_have_no_footer() {
+        let message = ChatMessage::Assistant {
+            label: "tars:".to_string(),
+            text: "Done.".to_string(),
+            stats: None,
+        };
+        assert_eq!(message.line_specs(80).len(), 2);
+    }
+
+    #[test]
+    fn replies_are_labeled_with_the_agent_and_model() {
+        assert_eq!(
+            reply_label("tars", "claude-haiku-4-5-20251001"),
+            "tars (claude-haiku-4-5-20251001):"
+        );
+        assert_eq!(
+            reply_label("jarvis", "gemini-2.5-pro"),
+            "jarvis (gemini-2.5-pro):"
+        );
+    }
+
+    /// An app showing session "showing-session", with "background-session"
+    /// parked in a second tab.
+    async fn two_tabs() -> App {
+        let base_url = serve(|_| (201, r#"{"session_id":"showing-session"}"#.to_string())).await;
+        let client = ClientSession::connect(ClientConfig {
+            base_url,
+            token: "token".to_string(),
+            http: reqwest::Client::new(),
+            labels: BTreeMap::new(),
+        })
+        .await
+        .unwrap();
+        let background = client.attach("background-session".to_string());
+        let mut app = App::new(client, "tars".to_string(), "model".to_string(), Vec::new());
+        app.tabs.push(Tab {
+            id: app.next_tab_id,
+            parked: Some(ParkedSession::new(Arc::new(background))),
+        });
+        app.next_tab_id += 1;
+        app
+    }
+
+    fn tab_labels(app: &App) -> Vec<String> {
+        app.tab_bar().lines[0]
+            .spans
+            .iter()
+            .map(|span| span.content.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn hidden_tabs_hold_their_events_until_shown() {
+        let mut app = two_tabs().await;
+        assert!(matches!(
+            app.route_tab_event(0, UiEvent::Online),
+            Some(UiEvent::Online)
+        ));
+
+        app.tabs[1].parked.as_mut().unwrap().is_loading = true;
+        assert!(app
+            .route_tab_event(1, UiEvent::Stderr("working".to_string()))
+            .is_none());
+        assert_eq!(tab_labels(&app)[..2], [" 1 showing- ", " 2 backgrou ⏳ "]);
+
+        assert!(app.route_tab_event(1, UiEvent::Done).is_none());
+        assert_eq!(tab_labels(&app)[1], " 2 backgrou ● ");
+        let parked = app.tabs[1].parked.as_ref().unwrap();
+        assert!(!parked.is_loading);
+        assert!(matches!(
+            parked.backlog[..],
+            [UiEvent::Stderr(_), UiEvent::Done]
+        ));
 
-        assert_eq!(buffer.to_string(), "hello\nworld");
-        assert_eq!(buffer.lines.len(), 2);
-        assert_eq!(buffer.cursor_y, 1);
+        // A closed tab's stream may still deliver an event or two.
+        assert!(app.route_tab_event(7, UiEvent::Done).is_none());
+        assert_eq!(app.tabs[1].parked.as_ref().unwrap().backlog.len(), 2);
     }
 }
@@ -0,0 +1,202 @@
+use super::theme::theme;
+use super::LineSpec;
+use crate::protocol::ComparedAnswer;
+use crate::tools::group_thousands;
+use ratatui::style::Modifier;
+
+/// Columns narrower than this are hard to read, so the answers are stacked
+/// instead.
+const MIN_COLUMN_WIDTH: usize = 24;
+const COLUMN_GAP: &str = " │ ";
+
+/// Lays `/compare` answers out in one column per model, each headed by the
+/// model and ending with its timing and usage. Falls back to one answer
+/// after another when `width` can't fit the columns.
+pub(super) fn line_specs(answers: &[ComparedAnswer], width: u16) -> Vec<LineSpec> {
+    let width = width as usize;
+    let count = answers.len().max(1);
+    let gaps = COLUMN_GAP.chars().count() * (count - 1);
+    let column = width.saturating_sub(gaps) / count;
+    if count == 1 || column < MIN_COLUMN_WIDTH {
+        return answers
+            .iter()
+            .flat_map(|answer| {
+                let [header, body, footer] = sections(answer, width.max(1));
+                let mut lines: Vec<LineSpec> = header
+                    .into_iter()
+                    .map(|line| LineSpec::new(line, header_style()))
+                    .collect();
+                lines.extend(
+                    body.into_iter()
+                        .map(|line| LineSpec::new(line, theme().assistant)),
+                );
+                lines.extend(
+                    footer
+                        .into_iter()
+                        .map(|line| LineSpec::new(line, theme().dim)),
+                );
+                lines
+            })
+            .collect();
+    }
+
+    let columns: Vec<[Vec<String>; 3]> = answers
+        .iter()
+        .map(|answer| sections(answer, column))
+        .collect();
+    let mut lines = Vec::new();
+    for (section, style) in [
+        (0, header_style()),
+        (1, theme().assistant),
+        (2, theme().dim),
+    ] {
+        let rows = columns
+            .iter()
+            .map(|sections| sections[section].len())
+            .max()
+            .unwrap_or(0);
+        for row in 0..rows {
+            let cells: Vec<String> = columns
+                .iter()
+                .map(|sections| {
+                    pad(
+                        sections[section].get(row).map_or("", String::as_str),
+                        column,
+                    )
+                })
+                .collect();
+            lines.push(LineSpec::new(cells.join(COLUMN_GAP), style));
+        }
+    }
+    lines
+}
+
+fn header_style() -> ratatui::style::Style {
+    theme().assistant.add_modifier(Modifier::BOLD)
+}
+
+/// An answer's header, body and footer, wrapped to `width`.
+fn sections(answer: &ComparedAnswer, width: usize) -> [Vec<String>; 3] {
+    let header = format!("{} ", answer.model);
+    let fill = width.saturating_sub(header.chars().count());
+    let header = vec![format!("{}{}", header, "─".repeat(fill))];
+
+    let mut body = wrap(&answer.text, width);
+    if let Some(error) = &answer.error {
+        if !body.is_empty() {
+            body.push(String::new());
+        }
+        body.extend(wrap(&format!("⚠ {}", error), width));
+    }
+    if body.is_empty() {
+        body.push("(no answer)".to_string());
+    }
+
+    let mut footer = Vec::new();
+    if let Some(stats) = &answer.stats {
+        footer.push(format!(
+            "{:.1}s · {} in / {} out",
+            stats.elapsed_ms as f64 / 1000.0,
+            group_thousands(stats.input_tokens),
+            group_thousands(stats.output_tokens)
+        ));
+    }
+    if answer.tool_calls > 0 {
+        footer.push(format!(
+            "{} tool call{}",
+            answer.tool_calls,
+            if answer.tool_calls == 1 { "" } else { "s" }
+        ));
+    }
+    let footer = wrap(&footer.join(" · "), width);
+    [header, body, footer]
+}
+
+/// Breaks `text` into rows of at most `width` characters, at spaces where
+/// possible.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut rows = Vec::new();
+    for line in text.lines() {
+        let mut row = String::new();
+        let mut row_len = 0;
+        for word in line.split(' ') {
+            let mut word: Vec<char> = word.chars().collect();
+            if row_len > 0 && row_len + 1 + word.len() > width {
+                rows.push(std::mem::take(&mut row));
+                row_len = 0;
+            }
+            if row_len > 0 {
+                row.push(' ');
+                row_len += 1;
+            }
+            while row_len + word.len() > width {
+                let rest = word.split_off(width - row_len);
+                row.extend(word);
+                rows.push(std::mem::take(&mut row));
+                row_len = 0;
+                word = rest;
+            }
+            row_len += word.len();
+            row.extend(word);
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+fn pad(text: &str, width: usize) -> String {
+    let fill = width.saturating_sub(text.chars().count());
+    format!("{}{}", text, " ".repeat(fill))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(model: &str, text: &str) -> ComparedAnswer {
+        ComparedAnswer {
+            model: model.to_string(),
+            text: text.to_string(),
+            tool_calls: 0,
+            stats: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn answers_sit_side_by_side_when_they_fit() {
+        let answers = [
+            answer("claude-haiku-4-5", "Use a HashMap keyed by path."),
+            answer(
+                "claude-sonnet-4-5",
+                "A BTreeMap keeps the paths sorted, which the listing needs.",
+            ),
+        ];
+        let lines = line_specs(&answers, 61);
+        assert!(lines[0].text.starts_with("claude-haiku-4-5 ───"));
+        assert!(lines[0].text.contains(" │ claude-sonnet-4-5 ───"));
+        assert!(lines.iter().all(|line| line.text.chars().count() == 61));
+        assert_eq!(
+            lines[1].text,
+            format!(
+                "{} │ {}",
+                pad("Use a HashMap keyed by path.", 29),
+                pad("A BTreeMap keeps the paths", 29)
+            )
+        );
+
+        let stacked = line_specs(&answers, 40);
+        assert!(stacked[0].text.starts_with("claude-haiku-4-5 ─"));
+        assert!(stacked.iter().all(|line| line.text.chars().count() <= 40));
+        assert!(stacked
+            .iter()
+            .any(|line| line.text.starts_with("claude-sonnet-4-5 ─")));
+    }
+
+    #[test]
+    fn wrap_breaks_at_spaces_and_splits_long_words() {
+        assert_eq!(wrap("one two three", 7), ["one two", "three"]);
+        assert_eq!(wrap("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("a\n\nb", 4), ["a", "", "b"]);
+    }
+}
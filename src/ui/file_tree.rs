@@ -0,0 +1,175 @@
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+
+use super::theme::theme;
+use crate::protocol::WorkspaceFile;
+
+/// The workspace as an indented tree for the sidebar, with the files the
+/// agent read marked ✓ and the ones it changed marked ✱.
+pub(super) struct FileTree {
+    rows: Vec<TreeRow>,
+    selected: usize,
+    /// The first row shown, moved just enough to keep the selection visible.
+    top: usize,
+    /// Whether the server cut the listing short.
+    truncated: bool,
+}
+
+#[derive(Debug)]
+struct TreeRow {
+    depth: usize,
+    name: String,
+    /// The file's path; `None` for directories.
+    path: Option<String>,
+    read: bool,
+    modified: bool,
+}
+
+impl FileTree {
+    pub(super) fn new() -> Self {
+        Self {
+            rows: Vec::new(),
+            selected: 0,
+            top: 0,
+            truncated: false,
+        }
+    }
+
+    /// Rebuilds the rows from a listing sorted by path, keeping the selected
+    /// file selected if it is still there.
+    pub(super) fn update(&mut self, files: &[WorkspaceFile], truncated: bool) {
+        let selected = self.selected_file().map(str::to_string);
+        self.rows = rows(files);
+        self.truncated = truncated;
+        self.selected = selected
+            .and_then(|path| {
+                self.rows
+                    .iter()
+                    .position(|row| row.path.as_deref() == Some(path.as_str()))
+            })
+            .unwrap_or(self.selected)
+            .min(self.rows.len().saturating_sub(1));
+    }
+
+    pub(super) fn move_selection(&mut self, delta: isize) {
+        let last = self.rows.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// The path of the selected row, unless it is a directory.
+    pub(super) fn selected_file(&self) -> Option<&str> {
+        self.rows.get(self.selected)?.path.as_deref()
+    }
+
+    /// The rows that fit in `height`, the selected one reversed.
+    pub(super) fn render(&mut self, height: usize, focused: bool) -> Text<'static> {
+        if self.rows.is_empty() {
+            return Text::from(Span::styled("Loading…", theme().dim));
+        }
+        let height = height.max(1);
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + height {
+            self.top = self.selected + 1 - height;
+        }
+
+        let mut lines: Vec<Line> = self
+            .rows
+            .iter()
+            .enumerate()
+            .skip(self.top)
+            .take(height)
+            .map(|(index, row)| {
+                let (marker, style) = if row.modified {
+                    (" ✱", theme().assistant)
+                } else if row.read {
+                    (" ✓", theme().tool)
+                } else if row.path.is_none() {
+                    ("", theme().user)
+                } else {
+                    ("", Style::default())
+                };
+                let style = if index == self.selected && focused {
+                    style.add_modifier(Modifier::REVERSED)
+                } else {
+                    style
+                };
+                Line::from(Span::styled(
+                    format!("{}{}{}", "  ".repeat(row.depth), row.name, marker),
+                    style,
+                ))
+            })
+            .collect();
+        if self.truncated && self.top + height >= self.rows.len() && lines.len() < height {
+            lines.push(Line::from(Span::styled("…listing truncated", theme().dim)));
+        }
+        Text::from(lines)
+    }
+}
+
+/// One row per file, preceded by a row for each directory it opens.
+fn rows(files: &[WorkspaceFile]) -> Vec<TreeRow> {
+    let mut rows = Vec::new();
+    let mut open: Vec<&str> = Vec::new();
+    for file in files {
+        let mut parts: Vec<&str> = file.path.split('/').collect();
+        let name = parts.pop().unwrap_or_default();
+        let common = open
+            .iter()
+            .zip(&parts)
+            .take_while(|(open, part)| open == part)
+            .count();
+        open.truncate(common);
+        for dir in &parts[common..] {
+            rows.push(TreeRow {
+                depth: open.len(),
+                name: format!("{}/", dir),
+                path: None,
+                read: false,
+                modified: false,
+            });
+            open.push(dir);
+        }
+        rows.push(TreeRow {
+            depth: parts.len(),
+            name: name.to_string(),
+            path: Some(file.path.clone()),
+            read: file.read,
+            modified: file.modified,
+        });
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_are_nested_under_their_directories() {
+        let file = |path: &str| WorkspaceFile {
+            path: path.to_string(),
+            read: path.ends_with("ui.rs"),
+            modified: false,
+        };
+        let files = ["Cargo.toml", "src/main.rs", "src/ui/input.rs", "src/ui.rs"].map(file);
+        let rows: Vec<String> = rows(&files)
+            .iter()
+            .map(|row| {
+                let marker = if row.read { " ✓" } else { "" };
+                format!("{}{}{}", "  ".repeat(row.depth), row.name, marker)
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "Cargo.toml",
+                "src/",
+                "  main.rs",
+                "  ui/",
+                "    input.rs",
+                "  ui.rs ✓"
+            ]
+        );
+    }
+}
@@ -0,0 +1,680 @@
+use super::theme::theme;
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+/// How many kills are remembered for yanking.
+const KILL_RING_SIZE: usize = 16;
+
+/// A (line, column) position in the buffer, in characters.
+type Position = (usize, usize);
+
+/// One wrapped row: the line it comes from and its start and end columns.
+type Segment = (usize, usize, usize);
+
+/// A multi-line prompt being edited. `cursor_x` counts characters, not
+/// bytes, so non-ASCII input edits cleanly.
+///
+/// Selection follows the mark/point model: `mark` anchors one end and the
+/// cursor is the other. Killed and copied text goes onto a small kill ring
+/// that Ctrl+Y yanks back and Alt+Y rotates through.
+pub(super) struct InputBuffer {
+    lines: Vec<String>,
+    cursor_x: usize,
+    cursor_y: usize,
+    mark: Option<Position>,
+    kill_ring: VecDeque<String>,
+    last_yank: Option<Yank>,
+}
+
+/// Where the most recent yank landed, so Alt+Y can swap it for an older kill.
+struct Yank {
+    start: Position,
+    end: Position,
+    index: usize,
+}
+
+impl InputBuffer {
+    pub(super) fn new() -> Self {
+        Self {
+            lines: vec![String::new()],
+            cursor_x: 0,
+            cursor_y: 0,
+            mark: None,
+            kill_ring: VecDeque::new(),
+            last_yank: None,
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.lines = vec![String::new()];
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+        self.mark = None;
+        self.last_yank = None;
+    }
+
+    pub(super) fn insert_char(&mut self, c: char) {
+        self.delete_selection();
+        let index = self.byte_index(self.cursor_x);
+        self.lines[self.cursor_y].insert(index, c);
+        self.cursor_x += 1;
+    }
+
+    /// Inserts text that may span several lines, replacing any selection.
+    pub(super) fn insert_str(&mut self, text: &str) {
+        self.delete_selection();
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let mut pieces = text.split('\n');
+        let first = pieces.next().unwrap_or_default();
+        let index = self.byte_index(self.cursor_x);
+        let tail = self.lines[self.cursor_y].split_off(index);
+        self.lines[self.cursor_y].push_str(first);
+        self.cursor_x += first.chars().count();
+        for piece in pieces {
+            self.cursor_y += 1;
+            self.lines.insert(self.cursor_y, piece.to_string());
+            self.cursor_x = piece.chars().count();
+        }
+        self.lines[self.cursor_y].push_str(&tail);
+    }
+
+    /// Starts a selection at the cursor when `extend` is set and none is
+    /// active, or drops the selection otherwise. Call before moving.
+    pub(super) fn select(&mut self, extend: bool) {
+        if !extend {
+            self.mark = None;
+        } else if self.mark.is_none() {
+            self.mark = Some(self.cursor());
+        }
+    }
+
+    /// The selected range in document order, if it is non-empty.
+    fn selection(&self) -> Option<(Position, Position)> {
+        let mark = self.mark?;
+        let cursor = self.cursor();
+        match mark.cmp(&cursor) {
+            std::cmp::Ordering::Less => Some((mark, cursor)),
+            std::cmp::Ordering::Greater => Some((cursor, mark)),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    pub(super) fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection()?;
+        Some(self.text_between(start, end))
+    }
+
+    /// Copies the selection onto the kill ring and returns it.
+    pub(super) fn copy(&mut self) -> Option<String> {
+        let text = self.selected_text()?;
+        self.push_kill(text.clone());
+        self.mark = None;
+        Some(text)
+    }
+
+    /// Removes the selection onto the kill ring and returns it.
+    pub(super) fn cut(&mut self) -> Option<String> {
+        let text = self.delete_selection()?;
+        self.push_kill(text.clone());
+        Some(text)
+    }
+
+    /// Inserts the most recent kill at the cursor.
+    pub(super) fn yank(&mut self) {
+        let Some(text) = self.kill_ring.front().cloned() else {
+            return;
+        };
+        self.delete_selection();
+        let start = self.cursor();
+        self.insert_str(&text);
+        self.last_yank = Some(Yank {
+            start,
+            end: self.cursor(),
+            index: 0,
+        });
+    }
+
+    /// Replaces the text just yanked with the next older kill. Does nothing
+    /// unless the cursor still sits at the end of an unchanged yank.
+    pub(super) fn yank_pop(&mut self) {
+        let Some(yank) = self.last_yank.take() else {
+            return;
+        };
+        let unchanged = self.cursor() == yank.end
+            && self.kill_ring.get(yank.index).map(String::as_str)
+                == Some(self.text_between(yank.start, yank.end).as_str());
+        if !unchanged || self.kill_ring.len() < 2 {
+            return;
+        }
+        let index = (yank.index + 1) % self.kill_ring.len();
+        self.remove_between(yank.start, yank.end);
+        let text = self.kill_ring[index].clone();
+        self.insert_str(&text);
+        self.last_yank = Some(Yank {
+            start: yank.start,
+            end: self.cursor(),
+            index,
+        });
+    }
+
+    pub(super) fn delete_char(&mut self) {
+        if self.delete_selection().is_some() {
+            return;
+        }
+        if self.cursor_x > 0 {
+            self.move_left();
+            self.delete_forward();
+        } else if self.cursor_y > 0 {
+            self.move_left();
+            self.join_next_line();
+        }
+    }
+
+    /// Deletes the character under the cursor, joining the next line when
+    /// the cursor is at the end of its line.
+    pub(super) fn delete_forward(&mut self) {
+        if self.delete_selection().is_some() {
+            return;
+        }
+        if self.cursor_x < self.line_len() {
+            let index = self.byte_index(self.cursor_x);
+            self.lines[self.cursor_y].remove(index);
+        } else {
+            self.join_next_line();
+        }
+    }
+
+    /// Kills from the start of the previous word up to the cursor, like
+    /// readline's Ctrl+W.
+    pub(super) fn delete_word_before(&mut self) {
+        if self.cursor_x == 0 {
+            self.delete_char();
+            return;
+        }
+        let end = self.cursor();
+        self.cursor_x = self.word_start_before(self.cursor_x);
+        self.kill_between(self.cursor(), end);
+    }
+
+    /// Kills from the start of the line up to the cursor, like readline's
+    /// Ctrl+U.
+    pub(super) fn kill_line(&mut self) {
+        let end = self.cursor();
+        self.cursor_x = 0;
+        self.kill_between(self.cursor(), end);
+    }
+
+    /// Kills from the cursor to the end of the line, like readline's Ctrl+K.
+    pub(super) fn kill_to_end(&mut self) {
+        let end = (self.cursor_y, self.line_len());
+        self.kill_between(self.cursor(), end);
+    }
+
+    pub(super) fn new_line(&mut self) {
+        self.delete_selection();
+        let line = &self.lines[self.cursor_y];
+        let remaining: String = line.chars().skip(self.cursor_x).collect();
+        self.lines[self.cursor_y] = line.chars().take(self.cursor_x).collect();
+        self.lines.insert(self.cursor_y + 1, remaining);
+        self.cursor_y += 1;
+        self.cursor_x = 0;
+    }
+
+    pub(super) fn move_left(&mut self) {
+        if self.cursor_x > 0 {
+            self.cursor_x -= 1;
+        } else if self.cursor_y > 0 {
+            self.cursor_y -= 1;
+            self.cursor_x = self.line_len();
+        }
+    }
+
+    pub(super) fn move_right(&mut self) {
+        if self.cursor_x < self.line_len() {
+            self.cursor_x += 1;
+        } else if self.cursor_y < self.lines.len() - 1 {
+            self.cursor_y += 1;
+            self.cursor_x = 0;
+        }
+    }
+
+    /// Moves to the start of the current or previous word, crossing onto the
+    /// previous line from column zero.
+    pub(super) fn move_word_left(&mut self) {
+        if self.cursor_x == 0 {
+            self.move_left();
+        } else {
+            self.cursor_x = self.word_start_before(self.cursor_x);
+        }
+    }
+
+    /// Moves past the end of the current or next word, crossing onto the
+    /// next line from the end of this one.
+    pub(super) fn move_word_right(&mut self) {
+        let chars: Vec<char> = self.lines[self.cursor_y].chars().collect();
+        if self.cursor_x >= chars.len() {
+            self.move_right();
+            return;
+        }
+        let mut x = self.cursor_x;
+        while x < chars.len() && !is_word_char(chars[x]) {
+            x += 1;
+        }
+        while x < chars.len() && is_word_char(chars[x]) {
+            x += 1;
+        }
+        self.cursor_x = x;
+    }
+
+    pub(super) fn move_up(&mut self) {
+        if self.cursor_y > 0 {
+            self.cursor_y -= 1;
+            self.cursor_x = self.cursor_x.min(self.line_len());
+        }
+    }
+
+    pub(super) fn move_down(&mut self) {
+        if self.cursor_y < self.lines.len() - 1 {
+            self.cursor_y += 1;
+            self.cursor_x = self.cursor_x.min(self.line_len());
+        }
+    }
+
+    pub(super) fn move_home(&mut self) {
+        self.cursor_x = 0;
+    }
+
+    pub(super) fn move_end(&mut self) {
+        self.cursor_x = self.line_len();
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.lines.iter().all(|l| l.is_empty())
+    }
+
+    /// Splits the buffer into rows of at most `width` characters and returns
+    /// them with the cursor's row and column in that wrapped layout.
+    pub(super) fn visual_lines(&self, width: u16) -> (Vec<String>, (usize, usize)) {
+        let (segments, cursor) = self.wrap(width);
+        let rows = segments
+            .into_iter()
+            .map(|(y, start, end)| {
+                self.lines[y]
+                    .chars()
+                    .skip(start)
+                    .take(end - start)
+                    .collect()
+            })
+            .collect();
+        (rows, cursor)
+    }
+
+    pub(super) fn render(&self, width: u16) -> (Text<'static>, (usize, usize)) {
+        if self.is_empty() {
+            let placeholder = Text::from(Span::styled("Type your message here...", theme().dim));
+            return (placeholder, (0, 0));
+        }
+        let (segments, cursor) = self.wrap(width);
+        let selection = self.selection();
+        let rows = segments
+            .into_iter()
+            .map(|(y, start, end)| {
+                let chars: Vec<char> = self.lines[y].chars().collect();
+                let text = |from: usize, to: usize| chars[from..to].iter().collect::<String>();
+                // Clip the selection to this row and split the row around it.
+                let (sel_start, sel_end) = match selection {
+                    Some(((sy, sx), (ey, ex))) if sy <= y && y <= ey => {
+                        let from = if y == sy { sx } else { 0 };
+                        let to = if y == ey { ex } else { chars.len() };
+                        (from.clamp(start, end), to.clamp(start, end))
+                    }
+                    _ => (end, end),
+                };
+                Line::from(vec![
+                    Span::raw(text(start, sel_start)),
+                    Span::styled(
+                        text(sel_start, sel_end),
+                        Style::default().add_modifier(Modifier::REVERSED),
+                    ),
+                    Span::raw(text(sel_end, end)),
+                ])
+            })
+            .collect::<Vec<_>>();
+        (Text::from(rows), cursor)
+    }
+
+    /// Lays the buffer out in rows of at most `width` characters, plus the
+    /// cursor's row and column in that layout.
+    fn wrap(&self, width: u16) -> (Vec<Segment>, (usize, usize)) {
+        let width = width.max(1) as usize;
+        let mut segments = Vec::new();
+        let mut cursor = (0, 0);
+        for (y, line) in self.lines.iter().enumerate() {
+            let len = line.chars().count();
+            if y == self.cursor_y {
+                cursor = (
+                    segments.len() + self.cursor_x / width,
+                    self.cursor_x % width,
+                );
+            }
+            if len == 0 {
+                segments.push((y, 0, 0));
+            }
+            for start in (0..len).step_by(width) {
+                segments.push((y, start, (start + width).min(len)));
+            }
+            // A cursor sitting just past a full row wraps onto a fresh one.
+            if y == self.cursor_y
+                && len > 0
+                && self.cursor_x == len
+                && self.cursor_x.is_multiple_of(width)
+            {
+                segments.push((y, len, len));
+            }
+        }
+        (segments, cursor)
+    }
+
+    fn cursor(&self) -> Position {
+        (self.cursor_y, self.cursor_x)
+    }
+
+    fn line_len(&self) -> usize {
+        self.lines[self.cursor_y].chars().count()
+    }
+
+    fn byte_index(&self, char_index: usize) -> usize {
+        let line = &self.lines[self.cursor_y];
+        line.char_indices()
+            .nth(char_index)
+            .map_or(line.len(), |(index, _)| index)
+    }
+
+    fn word_start_before(&self, end: usize) -> usize {
+        let chars: Vec<char> = self.lines[self.cursor_y].chars().collect();
+        let mut x = end;
+        while x > 0 && !is_word_char(chars[x - 1]) {
+            x -= 1;
+        }
+        while x > 0 && is_word_char(chars[x - 1]) {
+            x -= 1;
+        }
+        x
+    }
+
+    fn text_between(&self, start: Position, end: Position) -> String {
+        (start.0..=end.0)
+            .map(|y| {
+                let from = if y == start.0 { start.1 } else { 0 };
+                let line = self.lines[y].chars().skip(from);
+                if y == end.0 {
+                    line.take(end.1 - from).collect()
+                } else {
+                    line.collect()
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Removes the text between two ordered positions and leaves the cursor
+    /// at `start`.
+    fn remove_between(&mut self, start: Position, end: Position) -> String {
+        let removed = self.text_between(start, end);
+        let head: String = self.lines[start.0].chars().take(start.1).collect();
+        let tail: String = self.lines[end.0].chars().skip(end.1).collect();
+        self.lines.drain(start.0 + 1..=end.0);
+        self.lines[start.0] = head + &tail;
+        (self.cursor_y, self.cursor_x) = start;
+        self.mark = None;
+        removed
+    }
+
+    /// Removes the selection, if any, and ends selecting either way.
+    fn delete_selection(&mut self) -> Option<String> {
+        let selection = self.selection();
+        self.mark = None;
+        let (start, end) = selection?;
+        Some(self.remove_between(start, end))
+    }
+
+    fn kill_between(&mut self, start: Position, end: Position) {
+        if start != end {
+            let killed = self.remove_between(start, end);
+            self.push_kill(killed);
+        }
+    }
+
+    fn push_kill(&mut self, text: String) {
+        self.kill_ring.push_front(text);
+        self.kill_ring.truncate(KILL_RING_SIZE);
+    }
+
+    fn join_next_line(&mut self) {
+        if self.cursor_y + 1 < self.lines.len() {
+            let next = self.lines.remove(self.cursor_y + 1);
+            self.lines[self.cursor_y].push_str(&next);
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Default for InputBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for InputBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InputBuffer;
+    use proptest::prelude::*;
+
+    fn buffer(text: &str) -> InputBuffer {
+        let mut buffer = InputBuffer::new();
+        for ch in text.chars() {
+            buffer.insert_char(ch);
+        }
+        buffer
+    }
+
+    #[test]
+    fn input_buffer_shift_enter_inserts_new_line() {
+        let mut buffer = buffer("hello");
+        buffer.new_line();
+        for ch in "world".chars() {
+            buffer.insert_char(ch);
+        }
+
+        assert_eq!(buffer.to_string(), "hello\nworld");
+        assert_eq!(buffer.lines.len(), 2);
+        assert_eq!(buffer.cursor_y, 1);
+    }
+
+    #[test]
+    fn input_buffer_wraps_visual_lines_and_cursor() {
+        let mut buffer = buffer("abcdefgh");
+        let (rows, cursor) = buffer.visual_lines(4);
+        assert_eq!(rows, vec!["abcd", "efgh", ""]);
+        assert_eq!(cursor, (2, 0));
+
+        buffer.new_line();
+        buffer.insert_char('x');
+        let (rows, cursor) = buffer.visual_lines(5);
+        assert_eq!(rows, vec!["abcde", "fgh", "x"]);
+        assert_eq!(cursor, (2, 1));
+    }
+
+    #[test]
+    fn input_buffer_edits_by_word_and_line() {
+        let mut buffer = buffer("café au lait");
+        buffer.move_word_left();
+        assert_eq!(buffer.cursor_x, 8);
+        buffer.move_word_left();
+        buffer.move_word_left();
+        assert_eq!(buffer.cursor_x, 0);
+        buffer.move_word_right();
+        assert_eq!(buffer.cursor_x, 4);
+
+        buffer.move_end();
+        buffer.delete_word_before();
+        assert_eq!(buffer.to_string(), "café au ");
+        buffer.move_home();
+        buffer.delete_forward();
+        assert_eq!(buffer.to_string(), "afé au ");
+
+        buffer.move_end();
+        buffer.new_line();
+        buffer.insert_char('x');
+        buffer.kill_line();
+        assert_eq!(buffer.to_string(), "afé au \n");
+        buffer.delete_word_before();
+        assert_eq!(buffer.to_string(), "afé au ");
+    }
+
+    #[test]
+    fn input_buffer_cuts_selections_and_yanks_kills() {
+        let mut buffer = buffer("one two");
+        buffer.insert_str("\nthree");
+        buffer.select(true);
+        buffer.move_up();
+        buffer.move_word_left();
+        assert_eq!(buffer.selected_text().as_deref(), Some("two\nthree"));
+
+        assert_eq!(buffer.cut().as_deref(), Some("two\nthree"));
+        assert_eq!(buffer.to_string(), "one ");
+        buffer.delete_word_before();
+        assert_eq!(buffer.to_string(), "");
+
+        buffer.yank();
+        assert_eq!(buffer.to_string(), "one ");
+        buffer.yank_pop();
+        assert_eq!(buffer.to_string(), "two\nthree");
+        buffer.insert_char('!');
+        buffer.yank_pop();
+        assert_eq!(buffer.to_string(), "two\nthree!");
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Insert(char),
+        InsertStr(String),
+        Select(bool),
+        Copy,
+        Cut,
+        Yank,
+        YankPop,
+        DeleteChar,
+        DeleteForward,
+        DeleteWordBefore,
+        KillLine,
+        KillToEnd,
+        NewLine,
+        Left,
+        Right,
+        WordLeft,
+        WordRight,
+        Up,
+        Down,
+        Home,
+        End,
+        Clear,
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            prop::sample::select(vec!['a', 'b', ' ', '_', 'é', '日', '🦀']).prop_map(Op::Insert),
+            "[ab é\r\n🦀]{0,6}".prop_map(Op::InsertStr),
+            any::<bool>().prop_map(Op::Select),
+            Just(Op::Copy),
+            Just(Op::Cut),
+            Just(Op::Yank),
+            Just(Op::YankPop),
+            Just(Op::DeleteChar),
+            Just(Op::DeleteForward),
+            Just(Op::DeleteWordBefore),
+            Just(Op::KillLine),
+            Just(Op::KillToEnd),
+            Just(Op::NewLine),
+            Just(Op::Left),
+            Just(Op::Right),
+            Just(Op::WordLeft),
+            Just(Op::WordRight),
+            Just(Op::Up),
+            Just(Op::Down),
+            Just(Op::Home),
+            Just(Op::End),
+            Just(Op::Clear),
+        ]
+    }
+
+    fn apply(buffer: &mut InputBuffer, op: Op) {
+        match op {
+            Op::Insert(c) => buffer.insert_char(c),
+            Op::InsertStr(text) => buffer.insert_str(&text),
+            Op::Select(extend) => buffer.select(extend),
+            Op::Copy => {
+                buffer.copy();
+            }
+            Op::Cut => {
+                buffer.cut();
+            }
+            Op::Yank => buffer.yank(),
+            Op::YankPop => buffer.yank_pop(),
+            Op::DeleteChar => buffer.delete_char(),
+            Op::DeleteForward => buffer.delete_forward(),
+            Op::DeleteWordBefore => buffer.delete_word_before(),
+            Op::KillLine => buffer.kill_line(),
+            Op::KillToEnd => buffer.kill_to_end(),
+            Op::NewLine => buffer.new_line(),
+            Op::Left => buffer.move_left(),
+            Op::Right => buffer.move_right(),
+            Op::WordLeft => buffer.move_word_left(),
+            Op::WordRight => buffer.move_word_right(),
+            Op::Up => buffer.move_up(),
+            Op::Down => buffer.move_down(),
+            Op::Home => buffer.move_home(),
+            Op::End => buffer.move_end(),
+            Op::Clear => buffer.clear(),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn input_buffer_keeps_cursor_and_lines_consistent(
+            ops in prop::collection::vec(op(), 0..60),
+            width in 1u16..8,
+        ) {
+            let mut buffer = InputBuffer::new();
+            for op in ops {
+                apply(&mut buffer, op);
+
+                let len = |y: usize| buffer.lines[y].chars().count();
+                prop_assert!(!buffer.lines.is_empty());
+                prop_assert!(buffer.lines.iter().all(|line| !line.contains(['\n', '\r'])));
+                prop_assert!(buffer.cursor_y < buffer.lines.len());
+                prop_assert!(buffer.cursor_x <= len(buffer.cursor_y));
+                if let Some((y, x)) = buffer.mark {
+                    prop_assert!(y < buffer.lines.len() && x <= len(y));
+                }
+                prop_assert_eq!(buffer.to_string().split('\n').count(), buffer.lines.len());
+
+                let (rows, (row, column)) = buffer.visual_lines(width);
+                prop_assert!(row < rows.len());
+                prop_assert!(column < width as usize);
+                buffer.render(width);
+            }
+        }
+    }
+}
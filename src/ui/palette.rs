@@ -0,0 +1,219 @@
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+
+use super::theme::theme;
+use crate::commands::COMMANDS;
+use crate::protocol::SessionSummary;
+
+/// Matches shown below the query at a time.
+const MAX_MATCHES: usize = 8;
+
+/// What picking a palette entry does.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum PaletteAction {
+    /// A slash command, by its usage line.
+    Command(&'static str),
+    /// A file the agent touched, opened in the file tree's preview.
+    File(String),
+    /// Another live session on the server, by id.
+    Session(String),
+}
+
+struct PaletteItem {
+    label: String,
+    detail: String,
+    action: PaletteAction,
+}
+
+/// The Ctrl+P palette: every slash command, the files the agent recently
+/// touched and the server's other sessions, narrowed by a fuzzy query.
+pub(super) struct Palette {
+    query: String,
+    items: Vec<PaletteItem>,
+    /// Indices into `items` that match the query, best first.
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+impl Palette {
+    pub(super) fn new(recent_files: &[String]) -> Self {
+        let commands = COMMANDS.iter().map(|command| PaletteItem {
+            label: command.usage.to_string(),
+            detail: command.summary.to_string(),
+            action: PaletteAction::Command(command.usage),
+        });
+        let files = recent_files.iter().map(|path| PaletteItem {
+            label: path.clone(),
+            detail: "recent file".to_string(),
+            action: PaletteAction::File(path.clone()),
+        });
+        let mut palette = Self {
+            query: String::new(),
+            items: commands.chain(files).collect(),
+            matches: Vec::new(),
+            selected: 0,
+        };
+        palette.refilter();
+        palette
+    }
+
+    /// Adds the server's live sessions other than `current`, once they have
+    /// been fetched.
+    pub(super) fn add_sessions(&mut self, sessions: &[SessionSummary], current: &str) {
+        for session in sessions {
+            if session.session_id == current {
+                continue;
+            }
+            let mut detail = vec!["session".to_string()];
+            detail.extend(session.owner.clone());
+            detail.extend(
+                session
+                    .labels
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value)),
+            );
+            self.items.push(PaletteItem {
+                label: session.session_id.clone(),
+                detail: detail.join(" · "),
+                action: PaletteAction::Session(session.session_id.clone()),
+            });
+        }
+        self.refilter();
+    }
+
+    pub(super) fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.refilter();
+    }
+
+    pub(super) fn pop(&mut self) {
+        self.query.pop();
+        self.refilter();
+    }
+
+    pub(super) fn move_selection(&mut self, delta: isize) {
+        let last = self.matches.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    pub(super) fn selected(&self) -> Option<PaletteAction> {
+        let index = *self.matches.get(self.selected)?;
+        Some(self.items[index].action.clone())
+    }
+
+    /// Rows the palette takes: the query line, then the matches.
+    pub(super) fn rows(&self) -> u16 {
+        (1 + self.matches.len().min(MAX_MATCHES)) as u16
+    }
+
+    pub(super) fn render(&self, rows: usize) -> Text<'static> {
+        let mut lines = vec![Line::from(vec![
+            Span::styled("› ", theme().user),
+            Span::raw(self.query.clone()),
+            Span::styled(
+                if self.query.is_empty() {
+                    " type to filter commands, files and sessions (Esc to close)"
+                } else {
+                    ""
+                },
+                theme().dim,
+            ),
+        ])];
+        let shown = rows.saturating_sub(1);
+        // Keep the selection in view when it is past the first page.
+        let first = (self.selected + 1).saturating_sub(shown);
+        for (position, &index) in self.matches.iter().enumerate().skip(first).take(shown) {
+            let item = &self.items[index];
+            let selected = position == self.selected;
+            let style = |style: Style| {
+                if selected {
+                    style.add_modifier(Modifier::REVERSED)
+                } else {
+                    style
+                }
+            };
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {}", item.label), style(Style::default())),
+                Span::styled(format!("  {}", item.detail), style(theme().dim)),
+            ]));
+        }
+        if self.matches.is_empty() {
+            lines.push(Line::from(Span::styled("  no matches", theme().dim)));
+        }
+        Text::from(lines)
+    }
+
+    fn refilter(&mut self) {
+        let mut scored: Vec<(i32, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                let score = fuzzy_score(&self.query, &item.label)
+                    .max(fuzzy_score(&self.query, &item.detail).map(|score| score - 10))?;
+                Some((score, index))
+            })
+            .collect();
+        // Stable, so equal scores keep commands, files and sessions in order.
+        scored.sort_by_key(|(score, _)| -score);
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.selected = 0;
+    }
+}
+
+/// Scores `candidate` against `query` as a case-insensitive subsequence,
+/// favoring runs of consecutive characters and matches at word starts.
+/// `None` when the candidate lacks some character of the query.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut candidate = candidate.chars().map(|c| c.to_ascii_lowercase());
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+    for wanted in query.chars().map(|c| c.to_ascii_lowercase()) {
+        loop {
+            let c = candidate.next()?;
+            let word_start = previous.is_none_or(|p| !p.is_alphanumeric());
+            let matched = c == wanted;
+            if matched {
+                score += 1;
+                if previous_matched {
+                    score += 5;
+                }
+                if word_start {
+                    score += 3;
+                }
+            }
+            previous = Some(c);
+            previous_matched = matched;
+            if matched {
+                break;
+            }
+        }
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_matches_prefer_word_starts_and_runs() {
+        assert_eq!(fuzzy_score("xyz", "/fork"), None);
+        assert!(fuzzy_score("fk", "/fork").is_some());
+        assert!(fuzzy_score("ret", "/retry") > fuzzy_score("ret", "/prefill [text]"));
+        assert!(fuzzy_score("ui", "src/ui.rs") > fuzzy_score("ui", "src/build.rs"));
+
+        let mut palette = Palette::new(&["src/ui.rs".to_string()]);
+        for c in "ui".chars() {
+            palette.push(c);
+        }
+        assert_eq!(
+            palette.selected(),
+            Some(PaletteAction::File("src/ui.rs".to_string()))
+        );
+        palette.pop();
+        palette.pop();
+        assert_eq!(palette.selected(), Some(PaletteAction::Command("/new")));
+    }
+}
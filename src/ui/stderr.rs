@@ -0,0 +1,120 @@
+use super::UiEvent;
+use tokio::sync::mpsc;
+
+/// Points stderr at a pipe for as long as the TUI is up, so stray writes
+/// from the in-process server, a library or a child that inherited it
+/// can't scribble over the screen. Each line arrives as
+/// `UiEvent::Stderr`; stderr is pointed back at the terminal on drop or
+/// panic, so panic messages still show.
+pub(super) struct StderrCapture {
+    _private: (),
+}
+
+impl StderrCapture {
+    /// Returns `None` if stderr could not be redirected, in which case it is
+    /// left alone.
+    pub(super) fn start(sender: mpsc::Sender<UiEvent>) -> Option<Self> {
+        imp::start(sender).then_some(Self { _private: () })
+    }
+}
+
+impl Drop for StderrCapture {
+    fn drop(&mut self) {
+        imp::restore();
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::UiEvent;
+    use std::fs::File;
+    use std::io::{BufRead, BufReader};
+    use std::os::fd::FromRawFd;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::Once;
+    use tokio::sync::mpsc;
+
+    /// The terminal's stderr while it is redirected, or -1.
+    static SAVED: AtomicI32 = AtomicI32::new(-1);
+
+    pub(super) fn start(sender: mpsc::Sender<UiEvent>) -> bool {
+        let mut fds = [-1; 2];
+        // Safety: plain fd juggling; every fd opened here is either closed on
+        // failure or owned by the reader thread or `SAVED`. The read end and
+        // the saved fd are close-on-exec so children never hold them.
+        let reader = unsafe {
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return false;
+            }
+            let [read, write] = fds;
+            let saved = libc::fcntl(libc::STDERR_FILENO, libc::F_DUPFD_CLOEXEC, 0);
+            if saved < 0
+                || libc::fcntl(read, libc::F_SETFD, libc::FD_CLOEXEC) != 0
+                || libc::dup2(write, libc::STDERR_FILENO) < 0
+            {
+                for fd in [read, write, saved] {
+                    if fd >= 0 {
+                        libc::close(fd);
+                    }
+                }
+                return false;
+            }
+            libc::close(write);
+            SAVED.store(saved, Ordering::SeqCst);
+            File::from_raw_fd(read)
+        };
+
+        static HOOK: Once = Once::new();
+        HOOK.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                restore();
+                previous(info);
+            }));
+        });
+
+        // A plain thread: the read blocks until stderr is restored and the
+        // last writer is gone.
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                }
+                let text = String::from_utf8_lossy(&line).trim_end().to_string();
+                if sender.blocking_send(UiEvent::Stderr(text)).is_err() {
+                    break;
+                }
+            }
+        });
+        true
+    }
+
+    /// Points stderr back at the terminal, if it is redirected.
+    pub(super) fn restore() {
+        let saved = SAVED.swap(-1, Ordering::SeqCst);
+        if saved >= 0 {
+            // Safety: `saved` came from `start` and is closed exactly once,
+            // since the swap hands it to a single caller.
+            unsafe {
+                libc::dup2(saved, libc::STDERR_FILENO);
+                libc::close(saved);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use super::UiEvent;
+    use tokio::sync::mpsc;
+
+    pub(super) fn start(_sender: mpsc::Sender<UiEvent>) -> bool {
+        false
+    }
+
+    pub(super) fn restore() {}
+}
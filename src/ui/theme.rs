@@ -0,0 +1,142 @@
+use crate::config::ColorTheme;
+use ratatui::style::{Color, Style};
+use std::sync::OnceLock;
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum ColorSupport {
+    None,
+    Basic,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Reads the usual conventions: `NO_COLOR` disables color, `CLICOLOR=0`
+    /// disables it unless `CLICOLOR_FORCE` is set, and `COLORTERM`/`TERM`
+    /// advertise truecolor or 256-color support.
+    fn detect(var: impl Fn(&str) -> Option<String>) -> Self {
+        let set = |name: &str| var(name).is_some_and(|value| !value.is_empty());
+        if set("NO_COLOR") {
+            return Self::None;
+        }
+        let forced = var("CLICOLOR_FORCE").is_some_and(|value| !value.is_empty() && value != "0");
+        let term = var("TERM").unwrap_or_default();
+        if !forced && (var("CLICOLOR").as_deref() == Some("0") || term == "dumb") {
+            return Self::None;
+        }
+
+        let colorterm = var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            Self::TrueColor
+        } else if term.contains("256color") {
+            Self::Ansi256
+        } else {
+            Self::Basic
+        }
+    }
+}
+
+/// Foreground styles for each kind of transcript content, picked for the
+/// configured palette and the terminal's color support. Without color every
+/// style is plain, so only modifiers such as bold and reverse remain.
+pub(super) struct Theme {
+    pub(super) user: Style,
+    pub(super) assistant: Style,
+    pub(super) tool: Style,
+    pub(super) result: Style,
+    pub(super) error: Style,
+    pub(super) info: Style,
+    pub(super) dim: Style,
+}
+
+impl Theme {
+    fn new(palette: ColorTheme, support: ColorSupport) -> Self {
+        let support = match palette {
+            ColorTheme::Plain => ColorSupport::None,
+            ColorTheme::Dark | ColorTheme::Light => support,
+        };
+        // Each role: truecolor, 256-color index, then the basic ANSI color.
+        let fg = |rgb: (u8, u8, u8), indexed: u8, basic: Color| {
+            let color = match support {
+                ColorSupport::None => return Style::default(),
+                ColorSupport::Basic => basic,
+                ColorSupport::Ansi256 => Color::Indexed(indexed),
+                ColorSupport::TrueColor => Color::Rgb(rgb.0, rgb.1, rgb.2),
+            };
+            Style::default().fg(color)
+        };
+        if palette == ColorTheme::Light {
+            return Self {
+                user: fg((64, 120, 242), 27, Color::Blue),
+                assistant: fg((152, 104, 1), 130, Color::Yellow),
+                tool: fg((80, 161, 79), 28, Color::Green),
+                result: fg((1, 132, 188), 31, Color::Cyan),
+                error: fg((228, 86, 73), 160, Color::Red),
+                info: fg((56, 58, 66), 238, Color::Black),
+                dim: fg((160, 161, 167), 247, Color::DarkGray),
+            };
+        }
+        Self {
+            user: fg((97, 175, 239), 75, Color::Blue),
+            assistant: fg((229, 192, 123), 179, Color::Yellow),
+            tool: fg((152, 195, 121), 114, Color::Green),
+            result: fg((86, 182, 194), 73, Color::Cyan),
+            error: fg((224, 108, 117), 168, Color::Red),
+            info: fg((171, 178, 191), 249, Color::Gray),
+            dim: fg((92, 99, 112), 241, Color::DarkGray),
+        }
+    }
+}
+
+/// Draws with `palette` from now on; only the first call has an effect.
+pub(super) fn init(palette: ColorTheme) {
+    THEME.get_or_init(|| {
+        Theme::new(
+            palette,
+            ColorSupport::detect(|name| std::env::var(name).ok()),
+        )
+    });
+}
+
+/// The theme for this process: the dark palette unless `init` picked
+/// another, with colors detected from the environment.
+pub(super) fn theme() -> &'static Theme {
+    init(ColorTheme::default());
+    THEME.get().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ColorSupport;
+
+    fn detect(vars: &[(&str, &str)]) -> ColorSupport {
+        ColorSupport::detect(|name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn color_support_follows_environment_conventions() {
+        assert_eq!(detect(&[("TERM", "xterm")]), ColorSupport::Basic);
+        assert_eq!(detect(&[("TERM", "xterm-256color")]), ColorSupport::Ansi256);
+        assert_eq!(
+            detect(&[("TERM", "xterm-256color"), ("COLORTERM", "truecolor")]),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            detect(&[("TERM", "xterm-256color"), ("NO_COLOR", "1")]),
+            ColorSupport::None
+        );
+        assert_eq!(detect(&[("TERM", "dumb")]), ColorSupport::None);
+        assert_eq!(detect(&[("CLICOLOR", "0")]), ColorSupport::None);
+        assert_eq!(
+            detect(&[("CLICOLOR", "0"), ("CLICOLOR_FORCE", "1")]),
+            ColorSupport::Basic
+        );
+    }
+}
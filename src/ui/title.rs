@@ -0,0 +1,138 @@
+use std::io::{self, Write};
+
+/// What the session is doing, as shown in the terminal title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Activity {
+    Idle,
+    Thinking,
+    Running(String),
+    /// A call to this tool waits for the user's approval.
+    Approval(String),
+    Offline,
+}
+
+impl Activity {
+    fn label(&self) -> String {
+        match self {
+            Activity::Idle => "tars".to_string(),
+            Activity::Thinking => "tars — thinking".to_string(),
+            Activity::Running(tool) => format!("tars — running {}", tool),
+            Activity::Approval(tool) => format!("tars — approve {}?", tool),
+            Activity::Offline => "tars — offline".to_string(),
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        match self {
+            Activity::Idle => "idle",
+            Activity::Thinking => "thinking",
+            Activity::Running(_) => "running",
+            Activity::Approval(_) => "approval",
+            Activity::Offline => "offline",
+        }
+    }
+}
+
+/// Keeps the terminal title in step with the session so it can be found
+/// from a window or tmux pane list.
+///
+/// The title is set with OSC 2, plus the `ESC k` window-name form under
+/// tmux and screen. With `TARS_TMUX_STATUS=1` inside tmux the pane option
+/// `@tars-status` is also set, for use in `status-format` and hooks.
+pub(super) struct TitleUpdater {
+    current: Option<Activity>,
+    multiplexer: bool,
+    tmux_status: bool,
+}
+
+impl TitleUpdater {
+    /// Saves the existing title so `restore` can put it back.
+    pub(super) fn new() -> io::Result<Self> {
+        let term = std::env::var("TERM").unwrap_or_default();
+        let in_tmux = std::env::var_os("TMUX").is_some();
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[22;0t")?;
+        stdout.flush()?;
+        Ok(Self {
+            current: None,
+            multiplexer: in_tmux || term.starts_with("screen") || term.starts_with("tmux"),
+            tmux_status: in_tmux && std::env::var("TARS_TMUX_STATUS").is_ok_and(|v| v == "1"),
+        })
+    }
+
+    pub(super) fn set(&mut self, activity: Activity) -> io::Result<()> {
+        if self.current.as_ref() == Some(&activity) {
+            return Ok(());
+        }
+        let mut stdout = io::stdout();
+        stdout.write_all(title_sequence(&activity.label(), self.multiplexer).as_bytes())?;
+        stdout.flush()?;
+        if self.tmux_status {
+            set_tmux_status(activity.status());
+        }
+        self.current = Some(activity);
+        Ok(())
+    }
+
+    pub(super) fn restore(&mut self) -> io::Result<()> {
+        if self.tmux_status {
+            set_tmux_status("");
+        }
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[23;0t")?;
+        stdout.flush()
+    }
+}
+
+/// The escapes that set the title to `label`.
+fn title_sequence(label: &str, multiplexer: bool) -> String {
+    let mut sequence = format!("\x1b]2;{}\x07", label);
+    if multiplexer {
+        sequence.push_str(&format!("\x1bk{}\x1b\\", label));
+    }
+    sequence
+}
+
+/// Best effort: a missing tmux binary only loses the status option.
+fn set_tmux_status(status: &str) {
+    let mut command = tokio::process::Command::new("tmux");
+    command.args(["set-option", "-p"]);
+    if let Ok(pane) = std::env::var("TMUX_PANE") {
+        command.args(["-t", &pane]);
+    }
+    let _ = command
+        .args(["@tars-status", status])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_name_the_activity() {
+        assert_eq!(Activity::Idle.label(), "tars");
+        assert_eq!(Activity::Thinking.label(), "tars — thinking");
+        assert_eq!(
+            Activity::Running("bash".to_string()).label(),
+            "tars — running bash"
+        );
+        assert_eq!(
+            Activity::Approval("edit_file".to_string()).label(),
+            "tars — approve edit_file?"
+        );
+        assert_eq!(Activity::Offline.status(), "offline");
+    }
+
+    #[test]
+    fn multiplexers_also_get_the_window_name() {
+        assert_eq!(title_sequence("tars", false), "\x1b]2;tars\x07");
+        assert_eq!(
+            title_sequence("tars", true),
+            "\x1b]2;tars\x07\x1bktars\x1b\\"
+        );
+    }
+}
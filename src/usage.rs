@@ -0,0 +1,32 @@
+use crate::client;
+use crate::config;
+use crate::tools::{group_thousands, stats_table};
+use std::error::Error;
+
+type UsageResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Prints what the server's API keys have been used for since it started,
+/// for `tars usage`, and with `tools`, how each tool's calls have gone.
+pub async fn run(server: &str, token: &str, tools: bool) -> UsageResult<()> {
+    let settings = config::load()?;
+    let usage = client::usage(&settings.network.client()?, server, token).await?;
+
+    println!(
+        "{:<16} {:>9} {:>14} {:>14}",
+        "user", "requests", "input tokens", "output tokens"
+    );
+    for user in &usage.users {
+        println!(
+            "{:<16} {:>9} {:>14} {:>14}",
+            user.user.as_deref().unwrap_or("(server key)"),
+            group_thousands(user.usage.requests),
+            group_thousands(user.usage.input_tokens),
+            group_thousands(user.usage.output_tokens)
+        );
+    }
+    if tools {
+        println!();
+        println!("{}", stats_table(&usage.tools));
+    }
+    Ok(())
+}
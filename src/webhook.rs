@@ -0,0 +1,99 @@
+use crate::config::{WebhookConfig, WebhookEvent};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+
+/// What a webhook receives, as the JSON body of a POST.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload {
+    pub event: WebhookEvent,
+    pub session_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turn_id: Option<String>,
+    /// When the server emitted the event, in milliseconds since the Unix
+    /// epoch.
+    pub timestamp_ms: u64,
+    pub labels: BTreeMap<String, String>,
+    /// The turn's last reply for `turn_complete`, the error for `error`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// The configured webhooks, shared by every session on the server.
+pub struct Webhooks {
+    http: reqwest::Client,
+    hooks: Vec<WebhookConfig>,
+}
+
+impl Webhooks {
+    pub fn new(http: reqwest::Client, hooks: Vec<WebhookConfig>) -> Self {
+        Self { http, hooks }
+    }
+
+    /// Posts `payload` to every webhook that wants its event, in the
+    /// background. Failures are logged and not retried.
+    pub fn send(&self, payload: WebhookPayload) {
+        let hooks: Vec<WebhookConfig> = self
+            .hooks
+            .iter()
+            .filter(|hook| hook.events.is_empty() || hook.events.contains(&payload.event))
+            .cloned()
+            .collect();
+        if hooks.is_empty() {
+            return;
+        }
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(err) => {
+                tracing::warn!(error = %err, "could not encode webhook payload");
+                return;
+            }
+        };
+        let http = self.http.clone();
+        tokio::spawn(async move {
+            for hook in hooks {
+                let mut request = http
+                    .post(&hook.url)
+                    .header("Content-Type", "application/json")
+                    .header("X-Tars-Event", payload.event.as_str());
+                if let Some(secret) = &hook.secret {
+                    request = request.header("X-Tars-Signature-256", signature(secret, &body));
+                }
+                match request.body(body.clone()).send().await {
+                    Ok(response) if response.status().is_success() => {}
+                    Ok(response) => {
+                        tracing::warn!(url = %hook.url, status = %response.status(), "webhook rejected")
+                    }
+                    Err(err) => tracing::warn!(url = %hook.url, error = %err, "webhook failed"),
+                }
+            }
+        });
+    }
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body` keyed with `secret`, the
+/// scheme GitHub uses, so receivers can check the payload came from this
+/// server.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_is_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
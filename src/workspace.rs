@@ -0,0 +1,196 @@
+use crate::protocol::{FilePreviewResponse, WorkspaceFile, WorkspaceFilesResponse};
+use crate::tools::{check_links, normalize_path, ToolContext};
+use ignore::WalkBuilder;
+use std::io::Read;
+use std::path::{Component, Path};
+
+/// Files listed for browsing before the listing is cut off.
+const MAX_FILES: usize = 5000;
+/// Bytes of a file returned for a preview.
+const MAX_PREVIEW_BYTES: usize = 256 * 1024;
+
+/// Why a file can't be previewed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PreviewError {
+    /// The path is absolute or leaves the working directory, directly or
+    /// through a symlink.
+    OutsideWorkspace,
+    /// Missing, or not among the files `list_files` shows.
+    NotFound,
+    /// The file isn't text.
+    Binary,
+}
+
+/// The files under the working directory, skipping what `.gitignore`
+/// ignores, marked with what the session's tools did to them.
+pub fn list_files(context: &ToolContext) -> WorkspaceFilesResponse {
+    let reads = context.reads.lock().unwrap().clone();
+    let modified = context.changes.lock().unwrap().modified_paths();
+
+    let walker = walker(Path::new("."))
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .build();
+    let mut files = Vec::new();
+    let mut truncated = false;
+    for entry in walker.flatten() {
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+        if files.len() == MAX_FILES {
+            truncated = true;
+            break;
+        }
+        let path = normalize_path(entry.path());
+        files.push(WorkspaceFile {
+            path: path.to_string_lossy().to_string(),
+            read: reads.contains(&path),
+            modified: modified.contains(&path),
+        });
+    }
+    WorkspaceFilesResponse { files, truncated }
+}
+
+/// What `list_files` walks from `dir`: everything but `.git` and what the
+/// ignore files exclude.
+fn walker(dir: &Path) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(dir);
+    builder
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git");
+    builder
+}
+
+/// The start of a text file under `root`, the working directory, if
+/// `list_files` would list it.
+pub fn preview(root: &Path, path: &str) -> Result<FilePreviewResponse, PreviewError> {
+    let relative = Path::new(path);
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(PreviewError::OutsideWorkspace);
+    }
+    check_links(root, path).map_err(|_| PreviewError::OutsideWorkspace)?;
+    if !listed(root, relative) {
+        return Err(PreviewError::NotFound);
+    }
+
+    let mut bytes = Vec::new();
+    std::fs::File::open(root.join(relative))
+        .and_then(|file| {
+            file.take(MAX_PREVIEW_BYTES as u64 + 1)
+                .read_to_end(&mut bytes)
+        })
+        .map_err(|_| PreviewError::NotFound)?;
+    let truncated = bytes.len() > MAX_PREVIEW_BYTES;
+    bytes.truncate(MAX_PREVIEW_BYTES);
+    if bytes.contains(&0) {
+        return Err(PreviewError::Binary);
+    }
+    Ok(FilePreviewResponse {
+        path: path.to_string(),
+        content: String::from_utf8_lossy(&bytes).to_string(),
+        truncated,
+    })
+}
+
+/// Whether each step of `relative`, from the top directory down to the
+/// file, is one the walker would enter. A directory's ignore rules don't
+/// reach paths given below it, so every level is checked.
+fn listed(root: &Path, relative: &Path) -> bool {
+    let mut dir = root.to_path_buf();
+    for component in relative.components() {
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        let child = dir.join(name);
+        let found = walker(&dir)
+            .max_depth(Some(1))
+            .build()
+            .flatten()
+            .any(|entry| entry.depth() == 1 && entry.path() == child);
+        if !found {
+            return false;
+        }
+        dir = child;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn previews_stay_inside_the_workspace() {
+        let root = Path::new(".");
+        assert_eq!(
+            preview(root, "../etc/passwd").unwrap_err(),
+            PreviewError::OutsideWorkspace
+        );
+        assert_eq!(
+            preview(root, "/etc/passwd").unwrap_err(),
+            PreviewError::OutsideWorkspace
+        );
+        assert_eq!(
+            preview(root, "missing.txt").unwrap_err(),
+            PreviewError::NotFound
+        );
+        assert!(preview(root, "Cargo.toml")
+            .unwrap()
+            .content
+            .contains("[package]"));
+    }
+
+    #[test]
+    fn previews_show_only_what_the_listing_shows() {
+        let dir = TempDir::new("tars-preview");
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::create_dir_all(dir.join("target/debug")).unwrap();
+        std::fs::write(dir.join(".gitignore"), "target/\n.env\n").unwrap();
+        std::fs::write(dir.join(".env"), "SECRET=1\n").unwrap();
+        std::fs::write(dir.join(".git/config"), "[core]\n").unwrap();
+        std::fs::write(dir.join("target/debug/notes.txt"), "built\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "kept\n").unwrap();
+        std::fs::write(dir.join("large.txt"), "x".repeat(MAX_PREVIEW_BYTES + 10)).unwrap();
+        std::fs::write(dir.join("image.png"), b"\x89PNG\x00\x00").unwrap();
+
+        assert_eq!(preview(&dir, "notes.txt").unwrap().content, "kept\n");
+        assert_eq!(
+            preview(&dir, ".gitignore").unwrap().content,
+            "target/\n.env\n"
+        );
+        for ignored in [".env", ".git/config", "target/debug/notes.txt"] {
+            assert_eq!(
+                preview(&dir, ignored).unwrap_err(),
+                PreviewError::NotFound,
+                "{}",
+                ignored
+            );
+        }
+        let large = preview(&dir, "large.txt").unwrap();
+        assert!(large.truncated);
+        assert_eq!(large.content.len(), MAX_PREVIEW_BYTES);
+        assert_eq!(
+            preview(&dir, "image.png").unwrap_err(),
+            PreviewError::Binary
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn previews_do_not_follow_links_out_of_the_workspace() {
+        let dir = TempDir::new("tars-preview-link");
+        let outside = TempDir::new("tars-preview-outside");
+        std::fs::write(outside.join("secret"), "hunter2\n").unwrap();
+        std::os::unix::fs::symlink(outside.join("secret"), dir.join("secret")).unwrap();
+        assert_eq!(
+            preview(&dir, "secret").unwrap_err(),
+            PreviewError::OutsideWorkspace
+        );
+    }
+}